@@ -858,7 +858,7 @@ Future<Uint8List> getOurChatFile(
     }) as ResponseStream<DownloadResponse>;
     List<int> data = [];
     for (DownloadResponse piece in await res.toList()) {
-      data.addAll(piece.data);
+      data.addAll(piece.content);
     }
 
     manager.putFile(
@@ -40,6 +40,14 @@ pub const fn default_add_friend_request_expiry() -> Duration {
     Duration::from_days(3)
 }
 
+pub const fn default_password_reset_expiry() -> Duration {
+    Duration::from_mins(30)
+}
+
+pub const fn default_password_reset_interval() -> Duration {
+    Duration::from_mins(1)
+}
+
 // define ID type to fit many types of databases
 impl_newtype_int!(ID, u64,);
 impl_redis_value_from_for_newint!(ID);
@@ -214,12 +222,9 @@ pub fn default_user_files_store_limit() -> Size {
     Size::from_mebibytes(100)
 }
 
-pub const fn default_leader_node() -> bool {
-    true
-}
-
-pub const fn default_single_instance() -> bool {
-    true
+/// How long the leader holds its lease without renewing it
+pub const fn default_leader_lease_duration() -> Duration {
+    Duration::from_secs(30)
 }
 
 pub const fn default_http_run_migration() -> bool {
@@ -246,6 +251,22 @@ pub const fn default_room_key_duration() -> Duration {
     Duration::from_days(30)
 }
 
+pub const fn default_scheduled_msg_check_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+pub const fn default_expired_msg_clean_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub const fn default_access_token_expire() -> Duration {
+    Duration::from_mins(15)
+}
+
+pub const fn default_refresh_token_expire() -> Duration {
+    Duration::from_days(30)
+}
+
 pub const fn default_keep_voip_room_keep_duration() -> Duration {
     Duration::from_mins(10)
 }
@@ -306,6 +327,8 @@ pub static VERSION_SPLIT: LazyLock<ServerVersion> = LazyLock::new(|| {
 });
 
 pub const JWT_HEADER: &str = "authorization";
+/// Metadata naming the device when logging in
+pub const DEVICE_NAME_HEADER: &str = "device-name";
 
 // OAuth defaults
 pub const fn default_oauth_enable() -> bool {
@@ -320,6 +343,22 @@ pub fn default_oauth_github_client_secret() -> String {
     "".to_string()
 }
 
+pub fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
+pub fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+pub const fn default_s3_path_style() -> bool {
+    true
+}
+
 pub const fn default_require_email_verification() -> bool {
     false
 }
@@ -3,6 +3,7 @@
 use serde::{Deserialize, Serialize};
 
 pub const VERIFY_QUEUE: &str = "email_verify";
+pub const PASSWORD_RESET_QUEUE: &str = "password_reset";
 
 #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
 pub struct VerifyRecord {
@@ -15,3 +16,10 @@ impl VerifyRecord {
         Self { token, email }
     }
 }
+
+/// A password reset email to be sent by the http server
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
+pub struct PasswordResetRecord {
+    pub token: String,
+    pub email: String,
+}
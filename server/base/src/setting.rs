@@ -54,6 +54,17 @@ pub struct UserSetting {
         default = "crate::constants::default_add_friend_request_expiry"
     )]
     pub add_friend_request_expiry: Duration,
+    #[serde(
+        with = "humantime_serde",
+        default = "crate::constants::default_password_reset_expiry"
+    )]
+    pub password_reset_expiry: Duration,
+    /// Minimum interval between two password reset emails of one account
+    #[serde(
+        with = "humantime_serde",
+        default = "crate::constants::default_password_reset_interval"
+    )]
+    pub password_reset_interval: Duration,
 }
 
 impl Setting for UserSetting {}
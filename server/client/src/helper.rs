@@ -35,8 +35,9 @@ pub async fn get_hash_from_download(
     use sha3::{Digest, Sha3_256};
     let mut hasher = Sha3_256::new();
     while let Some(stream) = content.next().await {
-        let stream = stream?;
-        hasher.update(stream.data);
+        if let Some(content) = stream?.content() {
+            hasher.update(content);
+        }
     }
     let hash = format!("{:x}", hasher.finalize());
     Ok(hash)
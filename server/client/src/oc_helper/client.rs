@@ -419,6 +419,26 @@ impl TestApp {
         ret.try_into().unwrap()
     }
 
+    /// Launch another instance of the server sharing the database and the rabbitmq vhost of this
+    /// one, returning its shared data and the handle to shut it down.
+    pub async fn launch_another_instance(&self) -> anyhow::Result<(Arc<SharedData>, ShutdownSdr)> {
+        let mut application =
+            Application::build(ArgsParser::test(), self.app_config.clone()).await?;
+        let shared = application.shared.clone();
+        let abort_handle = application.get_abort_handle();
+        let notifier = application.started_notify.clone();
+        tokio::spawn(async move {
+            application.run_forever().await.unwrap();
+        });
+        notifier.notified().await;
+        Ok((shared, abort_handle))
+    }
+
+    /// Get the handle to shut the server instance down without dropping its database
+    pub fn shutdown_handle(&self) -> ShutdownSdr {
+        self.server_drop_handle.clone()
+    }
+
     pub async fn get_id(&mut self, ocid: OCID) -> Result<ID, tonic::Status> {
         let id: ID = self
             .basic_service()
@@ -478,6 +498,11 @@ impl TestApp {
         &self,
         url: impl AsRef<str>,
     ) -> Result<reqwest::Response, reqwest::Error> {
+        self.http_client.get(self.http_url(url)).send().await
+    }
+
+    /// The full url of `url` on the http server, for building requests by hand
+    pub fn http_url(&self, url: impl AsRef<str>) -> String {
         let mut base_url = self.app_config.http_cfg.base_url();
         if let Some(host) = base_url.host()
             && (host == "0.0.0.0" || host == "127.0.0.1")
@@ -487,10 +512,7 @@ impl TestApp {
             parts.authority = Some(format!("localhost:{port}").parse().unwrap());
             base_url = http::Uri::from_parts(parts).unwrap();
         }
-        self.http_client
-            .get(format!("{}{}", base_url, url.as_ref()))
-            .send()
-            .await
+        format!("{}{}", base_url, url.as_ref())
     }
 
     pub async fn verify(&mut self, token: &str) -> Result<reqwest::Response, reqwest::Error> {
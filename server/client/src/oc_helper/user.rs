@@ -9,10 +9,11 @@ use bytes::Bytes;
 use migration::predefined::PredefinedServerManagementRole;
 use pb::service::auth::authorize::v1::{AuthRequest, auth_request};
 use pb::service::auth::register::v1::RegisterRequest;
+use pb::service::auth::token::v1::RefreshTokenRequest;
 use pb::service::basic::v1::TimestampRequest;
 use pb::service::basic::v1::basic_service_client::BasicServiceClient;
 use pb::service::ourchat::delete::v1::DeleteFileRequest;
-use pb::service::ourchat::download::v1::{DownloadRequest, DownloadResponse};
+use pb::service::ourchat::download::v1::{DownloadHeader, DownloadRequest, DownloadResponse};
 use pb::service::ourchat::get_account_info;
 use pb::service::ourchat::get_account_info::v1::{GetAccountInfoRequest, GetAccountInfoResponse};
 use pb::service::ourchat::msg_delivery::v1::{
@@ -53,6 +54,7 @@ pub struct TestUser {
     pub id: ID,
     pub port: u16,
     pub token: String,
+    pub refresh_token: String,
     pub clients: Clients,
     pub rpc_url: String,
     pub oc_server: Option<OCClient>,
@@ -104,6 +106,7 @@ impl TestUser {
             // reserved
             ocid: OCID::default(),
             token: String::default(),
+            refresh_token: String::default(),
             oc_server: None,
             server_manage_client: None,
             id: ID::default(),
@@ -138,6 +141,14 @@ impl TestUser {
         user.ocid = OCID(ret.ocid);
         user.id = ID(ret.id);
         user.token = ret.token;
+        user.refresh_token = ret.refresh_token;
+        Self::connect(user).await?;
+        user.has_registered = true;
+        Ok(())
+    }
+
+    /// Connect the clients which carry the token of the user
+    async fn connect(user: &mut TestUser) -> Result<(), ClientErr> {
         let mut tls_config = None;
         if user.tls.is_tls_on()? {
             let client_cert =
@@ -185,7 +196,6 @@ impl TestUser {
                 Ok(req)
             }),
         ));
-        user.has_registered = true;
         Ok(())
     }
 
@@ -248,6 +258,20 @@ impl TestUser {
         };
         let ret = self.clients.auth.auth(login_req).await?.into_inner();
         self.token = ret.token.clone();
+        self.refresh_token = ret.refresh_token;
+        Self::connect(self).await?;
+        Ok(())
+    }
+
+    /// Get a new token with the refresh token, the clients use the new token afterwards
+    pub async fn refresh(&mut self) -> Result<(), ClientErr> {
+        let req = RefreshTokenRequest {
+            refresh_token: self.refresh_token.clone(),
+        };
+        let ret = self.clients.auth.refresh_token(req).await?.into_inner();
+        self.token = ret.token;
+        self.refresh_token = ret.refresh_token;
+        Self::connect(self).await?;
         Ok(())
     }
 
@@ -319,19 +343,74 @@ impl TestUser {
         let mut files_part = self.download_file_as_iter(key).await?;
         let mut file_download = Vec::new();
         while let Some(part) = files_part.next().await {
-            let part = part?;
-            file_download.extend_from_slice(&part.data);
+            if let Some(content) = part?.content() {
+                file_download.extend_from_slice(&content);
+            }
         }
         Ok(file_download)
     }
 
+    /// Download `length` bytes from `offset`, returning the header frame sent first as well
+    pub async fn download_file_range(
+        &mut self,
+        key: impl Into<String>,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(DownloadHeader, Vec<u8>), tonic::Status> {
+        self.download_with_header(DownloadRequest {
+            key: key.into(),
+            offset,
+            length,
+            thumbnail: None,
+        })
+        .await
+    }
+
+    /// Download the thumbnail large enough for `side` pixels, or the file if it has none
+    pub async fn download_thumbnail(
+        &mut self,
+        key: impl Into<String>,
+        side: u32,
+    ) -> Result<(DownloadHeader, Vec<u8>), tonic::Status> {
+        self.download_with_header(DownloadRequest {
+            key: key.into(),
+            offset: 0,
+            length: None,
+            thumbnail: Some(side),
+        })
+        .await
+    }
+
+    async fn download_with_header(
+        &mut self,
+        request: DownloadRequest,
+    ) -> Result<(DownloadHeader, Vec<u8>), tonic::Status> {
+        let mut files_part = self.oc().download(request).await?.into_inner();
+        let header = files_part
+            .next()
+            .await
+            .transpose()?
+            .and_then(DownloadResponse::header)
+            .ok_or_else(|| tonic::Status::internal("missing download header"))?;
+        let mut file_download = Vec::new();
+        while let Some(part) = files_part.next().await {
+            if let Some(content) = part?.content() {
+                file_download.extend_from_slice(&content);
+            }
+        }
+        Ok((header, file_download))
+    }
+
     pub async fn download_file_as_iter(
         &mut self,
         key: impl Into<String>,
     ) -> Result<Streaming<DownloadResponse>, tonic::Status> {
         let files_part = self
             .oc()
-            .download(DownloadRequest { key: key.into() })
+            .download(DownloadRequest {
+                key: key.into(),
+                ..Default::default()
+            })
             .await?;
         // Allow
         Ok(files_part.into_inner())
@@ -362,9 +441,14 @@ impl TestUser {
                 size,
                 auto_clean: true,
                 session_id: session_id.map(|x| x.0),
+                content_type: None,
             })
             .await?
             .into_inner();
+        // The server has the content already
+        if let Some(key) = start_response.key {
+            return Ok(key);
+        }
 
         let upload_id = start_response.upload_id;
         let chunk_size = start_response.chunk_size as usize;
@@ -414,6 +498,10 @@ impl TestUser {
             is_encrypted,
             markdown_text: markdown_text.into(),
             involved_files,
+            reply_to_msg_id: None,
+            thread_root_id: None,
+            send_at: None,
+            ttl: None,
         };
         Ok(self.oc().send_msg(req).await?)
     }
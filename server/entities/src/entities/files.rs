@@ -12,6 +12,12 @@ pub struct Model {
     pub path: String,
     pub user_id: i64,
     pub session_id: Option<i64>,
+    pub size: Option<i64>,
+    pub hash: String,
+    pub content_type: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub thumbnail_type: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
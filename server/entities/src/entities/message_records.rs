@@ -14,12 +14,37 @@ pub struct Model {
     pub time: DateTimeWithTimeZone,
     pub is_encrypted: bool,
     pub is_all_user: bool,
+    pub reply_to_msg_id: Option<i64>,
+    pub thread_root_id: Option<i64>,
+    pub expire_time: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::announcement_msg::Entity")]
     AnnouncementMsg,
+    #[sea_orm(has_many = "super::message_reactions::Entity")]
+    MessageReactions,
+    #[sea_orm(has_many = "super::message_revisions::Entity")]
+    MessageRevisions,
+    #[sea_orm(has_one = "super::pinned_messages::Entity")]
+    PinnedMessages,
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ReplyToMsgId",
+        to = "Column::MsgId",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    SelfRef2,
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ThreadRootId",
+        to = "Column::MsgId",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    SelfRef1,
     #[sea_orm(
         belongs_to = "super::session::Entity",
         from = "Column::SessionId",
@@ -44,6 +69,24 @@ impl Related<super::announcement_msg::Entity> for Entity {
     }
 }
 
+impl Related<super::message_reactions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MessageReactions.def()
+    }
+}
+
+impl Related<super::message_revisions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MessageRevisions.def()
+    }
+}
+
+impl Related<super::pinned_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PinnedMessages.def()
+    }
+}
+
 impl Related<super::session::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Session.def()
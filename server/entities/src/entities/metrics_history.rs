@@ -99,6 +99,8 @@ pub struct Model {
     pub tokio_task_mean_slow_poll_duration: Option<i64>,
     pub tokio_task_mean_short_delay_duration: Option<i64>,
     pub tokio_task_mean_long_delay_duration: Option<i64>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub node_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
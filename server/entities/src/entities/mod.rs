@@ -4,23 +4,36 @@ pub mod prelude;
 
 pub mod announcement;
 pub mod announcement_msg;
+pub mod device_keys;
+pub mod file_blobs;
 pub mod files;
 pub mod friend;
+pub mod leader_lease;
+pub mod login_devices;
 pub mod manager_role_relation;
+pub mod message_reactions;
 pub mod message_records;
+pub mod message_revisions;
 pub mod metrics_history;
+pub mod oauth_identities;
+pub mod one_time_prekeys;
 pub mod permission;
+pub mod pinned_messages;
 pub mod role;
 pub mod role_permissions;
 pub mod rtc_room;
+pub mod scheduled_messages;
 pub mod server_management_permission;
 pub mod server_management_role;
 pub mod server_management_role_permissions;
+pub mod server_status;
 pub mod session;
 pub mod session_invitation;
 pub mod session_relation;
+pub mod totp_recovery_codes;
 pub mod user;
 pub mod user_contact_info;
+pub mod user_key_history;
 pub mod user_role_relation;
 pub mod user_status;
 pub mod webrtc_room;
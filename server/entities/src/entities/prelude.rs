@@ -2,23 +2,36 @@
 
 pub use super::announcement::Entity as Announcement;
 pub use super::announcement_msg::Entity as AnnouncementMsg;
+pub use super::device_keys::Entity as DeviceKeys;
+pub use super::file_blobs::Entity as FileBlobs;
 pub use super::files::Entity as Files;
 pub use super::friend::Entity as Friend;
+pub use super::leader_lease::Entity as LeaderLease;
+pub use super::login_devices::Entity as LoginDevices;
 pub use super::manager_role_relation::Entity as ManagerRoleRelation;
+pub use super::message_reactions::Entity as MessageReactions;
 pub use super::message_records::Entity as MessageRecords;
+pub use super::message_revisions::Entity as MessageRevisions;
 pub use super::metrics_history::Entity as MetricsHistory;
+pub use super::oauth_identities::Entity as OauthIdentities;
+pub use super::one_time_prekeys::Entity as OneTimePrekeys;
 pub use super::permission::Entity as Permission;
+pub use super::pinned_messages::Entity as PinnedMessages;
 pub use super::role::Entity as Role;
 pub use super::role_permissions::Entity as RolePermissions;
 pub use super::rtc_room::Entity as RtcRoom;
+pub use super::scheduled_messages::Entity as ScheduledMessages;
 pub use super::server_management_permission::Entity as ServerManagementPermission;
 pub use super::server_management_role::Entity as ServerManagementRole;
 pub use super::server_management_role_permissions::Entity as ServerManagementRolePermissions;
+pub use super::server_status::Entity as ServerStatus;
 pub use super::session::Entity as Session;
 pub use super::session_invitation::Entity as SessionInvitation;
 pub use super::session_relation::Entity as SessionRelation;
+pub use super::totp_recovery_codes::Entity as TotpRecoveryCodes;
 pub use super::user::Entity as User;
 pub use super::user_contact_info::Entity as UserContactInfo;
+pub use super::user_key_history::Entity as UserKeyHistory;
 pub use super::user_role_relation::Entity as UserRoleRelation;
 pub use super::user_status::Entity as UserStatus;
 pub use super::webrtc_room::Entity as WebrtcRoom;
@@ -17,6 +17,7 @@ pub struct Model {
     pub e2ee_on: bool,
     pub room_key_time: DateTimeWithTimeZone,
     pub leaving_to_process: bool,
+    pub message_ttl: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -27,6 +28,8 @@ pub enum Relation {
     Friend,
     #[sea_orm(has_many = "super::message_records::Entity")]
     MessageRecords,
+    #[sea_orm(has_many = "super::pinned_messages::Entity")]
+    PinnedMessages,
     #[sea_orm(
         belongs_to = "super::role::Entity",
         from = "Column::DefaultRole",
@@ -35,6 +38,8 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Role,
+    #[sea_orm(has_many = "super::scheduled_messages::Entity")]
+    ScheduledMessages,
     #[sea_orm(has_many = "super::session_invitation::Entity")]
     SessionInvitation,
     #[sea_orm(has_many = "super::session_relation::Entity")]
@@ -61,12 +66,24 @@ impl Related<super::message_records::Entity> for Entity {
     }
 }
 
+impl Related<super::pinned_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PinnedMessages.def()
+    }
+}
+
 impl Related<super::role::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Role.def()
     }
 }
 
+impl Related<super::scheduled_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ScheduledMessages.def()
+    }
+}
+
 impl Related<super::session_invitation::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SessionInvitation.def()
@@ -23,12 +23,13 @@ pub struct Model {
     pub deleted_at: Option<DateTimeWithTimeZone>,
     #[sea_orm(column_type = "VarBinary(StringLen::None)")]
     pub public_key: Vec<u8>,
-    #[sea_orm(unique)]
-    pub github_id: Option<String>,
-    pub oauth_provider: Option<String>,
     pub email_verified: bool,
     pub public_update_time: DateTimeWithTimeZone,
     pub update_time: DateTimeWithTimeZone,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub files_limit: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -37,14 +38,26 @@ pub enum Relation {
     Announcement,
     #[sea_orm(has_many = "super::files::Entity")]
     Files,
+    #[sea_orm(has_many = "super::login_devices::Entity")]
+    LoginDevices,
     #[sea_orm(has_one = "super::manager_role_relation::Entity")]
     ManagerRoleRelation,
+    #[sea_orm(has_many = "super::message_reactions::Entity")]
+    MessageReactions,
     #[sea_orm(has_many = "super::message_records::Entity")]
     MessageRecords,
+    #[sea_orm(has_many = "super::oauth_identities::Entity")]
+    OauthIdentities,
+    #[sea_orm(has_many = "super::pinned_messages::Entity")]
+    PinnedMessages,
     #[sea_orm(has_many = "super::role::Entity")]
     Role,
+    #[sea_orm(has_many = "super::scheduled_messages::Entity")]
+    ScheduledMessages,
     #[sea_orm(has_many = "super::session_relation::Entity")]
     SessionRelation,
+    #[sea_orm(has_many = "super::totp_recovery_codes::Entity")]
+    TotpRecoveryCodes,
     #[sea_orm(has_many = "super::user_role_relation::Entity")]
     UserRoleRelation,
     #[sea_orm(has_many = "super::webrtc_room_member::Entity")]
@@ -63,30 +76,66 @@ impl Related<super::files::Entity> for Entity {
     }
 }
 
+impl Related<super::login_devices::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginDevices.def()
+    }
+}
+
 impl Related<super::manager_role_relation::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ManagerRoleRelation.def()
     }
 }
 
+impl Related<super::message_reactions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MessageReactions.def()
+    }
+}
+
 impl Related<super::message_records::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::MessageRecords.def()
     }
 }
 
+impl Related<super::oauth_identities::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OauthIdentities.def()
+    }
+}
+
+impl Related<super::pinned_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PinnedMessages.def()
+    }
+}
+
 impl Related<super::role::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Role.def()
     }
 }
 
+impl Related<super::scheduled_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ScheduledMessages.def()
+    }
+}
+
 impl Related<super::session_relation::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SessionRelation.def()
     }
 }
 
+impl Related<super::totp_recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TotpRecoveryCodes.def()
+    }
+}
+
 impl Related<super::user_role_relation::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserRoleRelation.def()
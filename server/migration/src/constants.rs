@@ -1,2 +1,3 @@
 pub const OCID_MAX_LEN: usize = 50;
 pub const USERNAME_MAX_LEN: usize = 60;
+pub const EMOJI_MAX_LEN: usize = 64;
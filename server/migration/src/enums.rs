@@ -22,6 +22,9 @@ pub enum User {
     GithubId,
     OauthProvider,
     EmailVerified,
+    TotpSecret,
+    TotpEnabled,
+    FilesLimit,
 }
 
 #[derive(DeriveIden)]
@@ -39,6 +42,7 @@ pub enum SessionRelation {
     SessionId,
     UserId,
     DisplayName,
+    LastReadMsgId,
 }
 
 #[derive(DeriveIden)]
@@ -56,6 +60,7 @@ pub enum Session {
     E2EEOn,
     RoomKeyTime,
     LeavingToProcess,
+    MessageTtl,
 }
 
 #[derive(DeriveIden)]
@@ -79,6 +84,12 @@ pub enum Files {
     Path,
     UserId,
     SessionId,
+    Size,
+    Hash,
+    ContentType,
+    Width,
+    Height,
+    ThumbnailType,
 }
 
 #[derive(DeriveIden)]
@@ -219,6 +230,9 @@ pub enum MessageRecords {
     Time,
     IsEncrypted,
     IsAllUser,
+    ReplyToMsgId,
+    ThreadRootId,
+    ExpireTime,
 }
 
 #[derive(DeriveIden)]
@@ -314,4 +328,135 @@ pub enum MetricsHistory {
     TokioTaskMeanSlowPollDuration,
     TokioTaskMeanShortDelayDuration,
     TokioTaskMeanLongDelayDuration,
+    NodeId,
+}
+
+#[derive(DeriveIden)]
+pub enum MessageRevisions {
+    Table,
+    Id,
+    MsgId,
+    MsgData,
+    Revision,
+    EditedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum MessageReactions {
+    Table,
+    MsgId,
+    UserId,
+    Emoji,
+    CreatedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum PinnedMessages {
+    Table,
+    MsgId,
+    SessionId,
+    PinnedBy,
+    PinnedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum ScheduledMessages {
+    Table,
+    Id,
+    MsgData,
+    SenderId,
+    SessionId,
+    IsEncrypted,
+    SendAt,
+    CreatedTime,
+    Ttl,
+}
+
+#[derive(DeriveIden)]
+pub enum TotpRecoveryCodes {
+    Table,
+    UserId,
+    CodeHash,
+}
+
+#[derive(DeriveIden)]
+pub enum LoginDevices {
+    Table,
+    Sid,
+    UserId,
+    DeviceName,
+    UserAgent,
+    Ip,
+    CreatedTime,
+    LastUsedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum OauthIdentities {
+    Table,
+    Provider,
+    Subject,
+    UserId,
+    Email,
+    CreatedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum FileBlobs {
+    Table,
+    Hash,
+    Path,
+    Size,
+    RefCount,
+    CreatedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum DeviceKeys {
+    Table,
+    Sid,
+    UserId,
+    IdentityKey,
+    SignedPrekeyId,
+    SignedPrekey,
+    SignedPrekeySignature,
+    UpdatedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum OneTimePrekeys {
+    Table,
+    Sid,
+    KeyId,
+    PublicKey,
+}
+
+#[derive(DeriveIden)]
+pub enum UserKeyHistory {
+    Table,
+    Id,
+    UserId,
+    PublicKey,
+    CreatedTime,
+    DeviceId,
+}
+
+#[derive(DeriveIden)]
+pub enum ServerStatus {
+    Table,
+    Id,
+    Maintaining,
+    Reason,
+    StartTime,
+    EndTime,
+    UpdatedTime,
+}
+
+#[derive(DeriveIden)]
+pub enum LeaderLease {
+    Table,
+    Id,
+    NodeId,
+    AcquiredTime,
+    ExpireTime,
 }
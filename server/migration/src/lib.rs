@@ -23,6 +23,28 @@ pub mod m20251205_000011_server_management_permissions_extended;
 mod m20251230_090033_add_session_in_file_load;
 mod m20251231_120000_add_assign_role_permission;
 pub mod m20260220_120000_create_metrics_history_table;
+mod m20261018_000001_message_revisions;
+mod m20261018_000002_message_threads;
+mod m20261018_000003_message_reactions;
+mod m20261018_000004_session_history_index;
+mod m20261018_000005_read_receipts;
+mod m20261018_000006_message_search;
+mod m20261018_000007_pinned_messages;
+mod m20261018_000008_scheduled_messages;
+mod m20261018_000009_message_ttl;
+mod m20261018_000010_totp;
+mod m20261018_000011_login_devices;
+mod m20261018_000012_oauth_identities;
+mod m20261018_000013_file_metadata;
+mod m20261018_000014_media_metadata;
+mod m20261018_000015_files_limit;
+mod m20261018_000016_file_blobs;
+mod m20261018_000017_device_keys;
+mod m20261018_000018_user_key_history;
+mod m20261018_000019_server_status;
+mod m20261018_000020_leader_lease;
+mod m20261018_000021_device_key_history;
+mod m20261018_000022_metrics_node_id;
 
 pub struct Migrator;
 
@@ -44,6 +66,28 @@ impl MigratorTrait for Migrator {
             Box::new(m20251230_090033_add_session_in_file_load::Migration),
             Box::new(m20251231_120000_add_assign_role_permission::Migration),
             Box::new(m20260220_120000_create_metrics_history_table::Migration),
+            Box::new(m20261018_000001_message_revisions::Migration),
+            Box::new(m20261018_000002_message_threads::Migration),
+            Box::new(m20261018_000003_message_reactions::Migration),
+            Box::new(m20261018_000004_session_history_index::Migration),
+            Box::new(m20261018_000005_read_receipts::Migration),
+            Box::new(m20261018_000006_message_search::Migration),
+            Box::new(m20261018_000007_pinned_messages::Migration),
+            Box::new(m20261018_000008_scheduled_messages::Migration),
+            Box::new(m20261018_000009_message_ttl::Migration),
+            Box::new(m20261018_000010_totp::Migration),
+            Box::new(m20261018_000011_login_devices::Migration),
+            Box::new(m20261018_000012_oauth_identities::Migration),
+            Box::new(m20261018_000013_file_metadata::Migration),
+            Box::new(m20261018_000014_media_metadata::Migration),
+            Box::new(m20261018_000015_files_limit::Migration),
+            Box::new(m20261018_000016_file_blobs::Migration),
+            Box::new(m20261018_000017_device_keys::Migration),
+            Box::new(m20261018_000018_user_key_history::Migration),
+            Box::new(m20261018_000019_server_status::Migration),
+            Box::new(m20261018_000020_leader_lease::Migration),
+            Box::new(m20261018_000021_device_key_history::Migration),
+            Box::new(m20261018_000022_metrics_node_id::Migration),
         ]
     }
 }
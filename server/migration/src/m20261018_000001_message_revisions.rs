@@ -0,0 +1,59 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{MessageRecords, MessageRevisions};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MessageRevisions::Table)
+                    .if_not_exists()
+                    .col(
+                        big_integer(MessageRevisions::Id)
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(big_integer(MessageRevisions::MsgId))
+                    // the content of the message before this edit
+                    .col(json_binary(MessageRevisions::MsgData))
+                    .col(big_integer(MessageRevisions::Revision))
+                    .col(
+                        timestamp_with_time_zone(MessageRevisions::EditedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(MessageRevisions::Table, MessageRevisions::MsgId)
+                            .to(MessageRecords::Table, MessageRecords::MsgId)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_message_revisions_msg_id")
+                    .table(MessageRevisions::Table)
+                    .col(MessageRevisions::MsgId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MessageRevisions::Table).to_owned())
+            .await
+    }
+}
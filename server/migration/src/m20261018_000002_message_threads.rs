@@ -0,0 +1,75 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::MessageRecords;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+pub static REPLY_TO_FOREIGN_KEY: &str = "fk_message_records_reply_to_msg_id";
+pub static THREAD_ROOT_FOREIGN_KEY: &str = "fk_message_records_thread_root_id";
+pub static THREAD_ROOT_INDEX: &str = "idx_message_records_thread_root_id";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessageRecords::Table)
+                    .add_column(big_integer_null(MessageRecords::ReplyToMsgId))
+                    .add_column(big_integer_null(MessageRecords::ThreadRootId))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name(REPLY_TO_FOREIGN_KEY)
+                            .from_tbl(MessageRecords::Table)
+                            .from_col(MessageRecords::ReplyToMsgId)
+                            .to_tbl(MessageRecords::Table)
+                            .to_col(MessageRecords::MsgId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name(THREAD_ROOT_FOREIGN_KEY)
+                            .from_tbl(MessageRecords::Table)
+                            .from_col(MessageRecords::ThreadRootId)
+                            .to_tbl(MessageRecords::Table)
+                            .to_col(MessageRecords::MsgId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name(THREAD_ROOT_INDEX)
+                    .table(MessageRecords::Table)
+                    .col(MessageRecords::ThreadRootId)
+                    .col(MessageRecords::MsgId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name(THREAD_ROOT_INDEX).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessageRecords::Table)
+                    .drop_foreign_key(THREAD_ROOT_FOREIGN_KEY)
+                    .drop_foreign_key(REPLY_TO_FOREIGN_KEY)
+                    .drop_column(MessageRecords::ThreadRootId)
+                    .drop_column(MessageRecords::ReplyToMsgId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::constants::EMOJI_MAX_LEN;
+use crate::enums::{MessageReactions, MessageRecords, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MessageReactions::Table)
+                    .if_not_exists()
+                    .col(big_integer(MessageReactions::MsgId))
+                    .col(big_unsigned(MessageReactions::UserId))
+                    .col(string_len(MessageReactions::Emoji, EMOJI_MAX_LEN as u32))
+                    .col(
+                        timestamp_with_time_zone(MessageReactions::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(MessageReactions::Table, MessageReactions::MsgId)
+                            .to(MessageRecords::Table, MessageRecords::MsgId)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(MessageReactions::Table, MessageReactions::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // a user can react to a message with the same emoji only once
+                    .primary_key(
+                        Index::create()
+                            .col(MessageReactions::MsgId)
+                            .col(MessageReactions::UserId)
+                            .col(MessageReactions::Emoji),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MessageReactions::Table).to_owned())
+            .await
+    }
+}
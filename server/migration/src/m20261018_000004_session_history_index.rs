@@ -0,0 +1,34 @@
+use sea_orm_migration::prelude::*;
+
+use crate::enums::MessageRecords;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // used to page through the history of a single session
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_message_records_session_id_time")
+                    .table(MessageRecords::Table)
+                    .col(MessageRecords::SessionId)
+                    .col(MessageRecords::Time)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_message_records_session_id_time")
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,32 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::SessionRelation;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // No foreign key here, the read position should survive the deletion of the message
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SessionRelation::Table)
+                    .add_column(big_integer_null(SessionRelation::LastReadMsgId))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SessionRelation::Table)
+                    .drop_column(SessionRelation::LastReadMsgId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
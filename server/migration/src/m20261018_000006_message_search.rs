@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        // The vector is always NULL for encrypted messages, so they can never be matched.
+        // It is maintained by the database and deliberately left out of the entity.
+        conn.execute_unprepared(
+            r#"
+ALTER TABLE message_records ADD COLUMN IF NOT EXISTS search_vector tsvector
+GENERATED ALWAYS AS (
+    CASE WHEN is_encrypted THEN NULL
+    ELSE to_tsvector('simple', COALESCE(msg_data -> 'Msg' ->> 'markdown_text', ''))
+    END
+) STORED;
+        "#,
+        )
+        .await?;
+
+        conn.execute_unprepared(
+            r#"
+CREATE INDEX IF NOT EXISTS idx_message_records_search_vector
+ON message_records USING GIN (search_vector);
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            r#"
+DROP INDEX IF EXISTS idx_message_records_search_vector;
+ALTER TABLE message_records DROP COLUMN IF EXISTS search_vector;
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
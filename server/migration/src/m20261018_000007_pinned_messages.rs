@@ -0,0 +1,99 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{MessageRecords, PinnedMessages, Session, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PinnedMessages::Table)
+                    .if_not_exists()
+                    .col(big_integer(PinnedMessages::MsgId).primary_key())
+                    .col(big_unsigned(PinnedMessages::SessionId))
+                    .col(big_unsigned_null(PinnedMessages::PinnedBy))
+                    .col(
+                        timestamp_with_time_zone(PinnedMessages::PinnedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(PinnedMessages::Table, PinnedMessages::MsgId)
+                            .to(MessageRecords::Table, MessageRecords::MsgId)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(PinnedMessages::Table, PinnedMessages::SessionId)
+                            .to(Session::Table, Session::SessionId)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(PinnedMessages::Table, PinnedMessages::PinnedBy)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_pinned_messages_session_id")
+                    .table(PinnedMessages::Table)
+                    .col(PinnedMessages::SessionId)
+                    .to_owned(),
+            )
+            .await?;
+
+        let conn = manager.get_connection();
+
+        // Insert new PinMsg permission
+        conn.execute_unprepared(
+            r#"
+INSERT INTO permission (id, description) VALUES
+(15, 'pin msg')
+ON CONFLICT (id) DO NOTHING;
+        "#,
+        )
+        .await?;
+
+        // Link permission to owner (role_id = 3) and admin (role_id = 2)
+        conn.execute_unprepared(
+            r#"
+INSERT INTO role_permissions (role_id, permission_id) VALUES
+(3, 15), (2, 15)
+ON CONFLICT (role_id, permission_id) DO NOTHING;
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            r#"
+DELETE FROM role_permissions WHERE permission_id = 15;
+DELETE FROM permission WHERE id = 15;
+        "#,
+        )
+        .await?;
+
+        manager
+            .drop_table(Table::drop().table(PinnedMessages::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,79 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{ScheduledMessages, Session, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScheduledMessages::Table)
+                    .if_not_exists()
+                    .col(
+                        big_integer(ScheduledMessages::Id)
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(json_binary(ScheduledMessages::MsgData))
+                    .col(big_unsigned(ScheduledMessages::SenderId))
+                    .col(big_unsigned(ScheduledMessages::SessionId))
+                    .col(boolean(ScheduledMessages::IsEncrypted).default(false))
+                    .col(timestamp_with_time_zone(ScheduledMessages::SendAt))
+                    .col(
+                        timestamp_with_time_zone(ScheduledMessages::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScheduledMessages::Table, ScheduledMessages::SenderId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ScheduledMessages::Table, ScheduledMessages::SessionId)
+                            .to(Session::Table, Session::SessionId)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The delivery job scans for the due messages
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_scheduled_messages_send_at")
+                    .table(ScheduledMessages::Table)
+                    .col(ScheduledMessages::SendAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_scheduled_messages_sender_id")
+                    .table(ScheduledMessages::Table)
+                    .col(ScheduledMessages::SenderId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ScheduledMessages::Table).to_owned())
+            .await
+    }
+}
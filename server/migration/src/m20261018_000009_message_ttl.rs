@@ -0,0 +1,114 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{MessageRecords, ScheduledMessages, Session};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Seconds, NULL means the messages are kept forever
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .add_column(big_integer_null(Session::MessageTtl))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessageRecords::Table)
+                    .add_column(timestamp_with_time_zone_null(MessageRecords::ExpireTime))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_message_records_expire_time")
+                    .table(MessageRecords::Table)
+                    .col(MessageRecords::ExpireTime)
+                    .to_owned(),
+            )
+            .await?;
+
+        // The ttl requested by the sender is applied when the message is delivered
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ScheduledMessages::Table)
+                    .add_column(big_integer_null(ScheduledMessages::Ttl))
+                    .to_owned(),
+            )
+            .await?;
+
+        let conn = manager.get_connection();
+
+        // Insert new SetMessageTtl permission
+        conn.execute_unprepared(
+            r#"
+INSERT INTO permission (id, description) VALUES
+(16, 'set message ttl')
+ON CONFLICT (id) DO NOTHING;
+        "#,
+        )
+        .await?;
+
+        // Link permission to owner (role_id = 3) and admin (role_id = 2)
+        conn.execute_unprepared(
+            r#"
+INSERT INTO role_permissions (role_id, permission_id) VALUES
+(3, 16), (2, 16)
+ON CONFLICT (role_id, permission_id) DO NOTHING;
+        "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+
+        conn.execute_unprepared(
+            r#"
+DELETE FROM role_permissions WHERE permission_id = 16;
+DELETE FROM permission WHERE id = 16;
+        "#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ScheduledMessages::Table)
+                    .drop_column(ScheduledMessages::Ttl)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessageRecords::Table)
+                    .drop_column(MessageRecords::ExpireTime)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .drop_column(Session::MessageTtl)
+                    .to_owned(),
+            )
+            .await
+    }
+}
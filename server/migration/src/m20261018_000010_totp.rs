@@ -0,0 +1,61 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{TotpRecoveryCodes, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The secret is stored on EnableTotp, TOTP is required only after it is confirmed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(text_null(User::TotpSecret))
+                    .add_column(boolean(User::TotpEnabled).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TotpRecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(big_unsigned(TotpRecoveryCodes::UserId))
+                    .col(string(TotpRecoveryCodes::CodeHash))
+                    .primary_key(
+                        Index::create()
+                            .col(TotpRecoveryCodes::UserId)
+                            .col(TotpRecoveryCodes::CodeHash),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(TotpRecoveryCodes::Table, TotpRecoveryCodes::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TotpRecoveryCodes::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::TotpEnabled)
+                    .drop_column(User::TotpSecret)
+                    .to_owned(),
+            )
+            .await
+    }
+}
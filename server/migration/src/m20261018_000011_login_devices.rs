@@ -0,0 +1,60 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{LoginDevices, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // One row per login, keyed by the session id carried in its tokens
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginDevices::Table)
+                    .if_not_exists()
+                    .col(string(LoginDevices::Sid).primary_key())
+                    .col(big_unsigned(LoginDevices::UserId))
+                    .col(string(LoginDevices::DeviceName))
+                    .col(text_null(LoginDevices::UserAgent))
+                    .col(string_null(LoginDevices::Ip))
+                    .col(
+                        timestamp_with_time_zone(LoginDevices::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        timestamp_with_time_zone(LoginDevices::LastUsedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(LoginDevices::Table, LoginDevices::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_login_devices_user_id")
+                    .table(LoginDevices::Table)
+                    .col(LoginDevices::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginDevices::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,124 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{OauthIdentities, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // An identity belongs to one account, an account has at most one identity per provider
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthIdentities::Table)
+                    .if_not_exists()
+                    .col(string(OauthIdentities::Provider))
+                    .col(string(OauthIdentities::Subject))
+                    .col(big_unsigned(OauthIdentities::UserId))
+                    .col(string_null(OauthIdentities::Email))
+                    .col(
+                        timestamp_with_time_zone(OauthIdentities::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(OauthIdentities::Provider)
+                            .col(OauthIdentities::Subject),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(OauthIdentities::Table, OauthIdentities::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_oauth_identities_user_id_provider")
+                    .table(OauthIdentities::Table)
+                    .col(OauthIdentities::UserId)
+                    .col(OauthIdentities::Provider)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Move the GitHub accounts over before dropping their columns
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(OauthIdentities::Table)
+                    .columns([
+                        OauthIdentities::Provider,
+                        OauthIdentities::Subject,
+                        OauthIdentities::UserId,
+                        OauthIdentities::Email,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .expr(Expr::val("github"))
+                            .column(User::GithubId)
+                            .column(User::Id)
+                            .column(User::Email)
+                            .from(User::Table)
+                            .and_where(Expr::col(User::GithubId).is_not_null())
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Custom(e.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::GithubId)
+                    .drop_column(User::OauthProvider)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(string_null(User::GithubId).unique_key())
+                    .add_column(string_null(User::OauthProvider))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(User::Table)
+                    .value(
+                        User::GithubId,
+                        Expr::cust(
+                            r#"(SELECT "subject" FROM "oauth_identities" WHERE "oauth_identities"."user_id" = "user"."id" AND "oauth_identities"."provider" = 'github')"#,
+                        ),
+                    )
+                    .value(User::OauthProvider, Expr::val("github"))
+                    .and_where(Expr::cust(
+                        r#"EXISTS (SELECT 1 FROM "oauth_identities" WHERE "oauth_identities"."user_id" = "user"."id" AND "oauth_identities"."provider" = 'github')"#,
+                    ))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OauthIdentities::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,51 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::Files;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The size of the files uploaded before is read from the disk when needed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .add_column(big_integer_null(Files::Size))
+                    .add_column(string_null(Files::Hash))
+                    .add_column(string_null(Files::ContentType))
+                    .to_owned(),
+            )
+            .await?;
+        // The keys are a 20 characters prefix followed by the hex hash of the file
+        manager
+            .get_connection()
+            .execute_unprepared(r#"UPDATE "files" SET "hash" = substring("key" from 21)"#)
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .modify_column(string(Files::Hash))
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .drop_column(Files::Size)
+                    .drop_column(Files::Hash)
+                    .drop_column(Files::ContentType)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
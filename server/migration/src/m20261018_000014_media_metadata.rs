@@ -0,0 +1,38 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::Files;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Set for the images only, the thumbnail type is null if no thumbnail was generated
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .add_column(integer_null(Files::Width))
+                    .add_column(integer_null(Files::Height))
+                    .add_column(string_null(Files::ThumbnailType))
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .drop_column(Files::Width)
+                    .drop_column(Files::Height)
+                    .drop_column(Files::ThumbnailType)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
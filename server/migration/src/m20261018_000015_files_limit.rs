@@ -0,0 +1,68 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Null means the `user_files_limit` of the server config
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(big_integer_null(User::FilesLimit))
+                    .to_owned(),
+            )
+            .await?;
+
+        let conn = manager.get_connection();
+        conn.execute_unprepared(
+            r#"
+INSERT INTO server_management_permission (id, name, description) VALUES
+(10, 'manage_storage_quota', 'set the files limit of users')
+ON CONFLICT (id) DO NOTHING;
+        "#,
+        )
+        .await?;
+        // Link permission to admin role (role_id = 1)
+        conn.execute_unprepared(
+            r#"
+INSERT INTO server_management_role_permissions (role_id, permission_id) VALUES
+(1, 10)
+ON CONFLICT (role_id, permission_id) DO NOTHING;
+        "#,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        conn.execute_unprepared(
+            r#"
+DELETE FROM server_management_role_permissions
+WHERE permission_id = 10 AND role_id = 1;
+        "#,
+        )
+        .await?;
+        conn.execute_unprepared(
+            r#"
+DELETE FROM server_management_permission WHERE id = 10;
+        "#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::FilesLimit)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{FileBlobs, Files};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // One stored object per content, shared by the files uploaded with the same hash
+        manager
+            .create_table(
+                Table::create()
+                    .table(FileBlobs::Table)
+                    .if_not_exists()
+                    .col(string(FileBlobs::Hash).primary_key())
+                    .col(string(FileBlobs::Path))
+                    .col(big_integer(FileBlobs::Size))
+                    .col(big_integer(FileBlobs::RefCount))
+                    .col(
+                        timestamp_with_time_zone(FileBlobs::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Looking for the files of a hash when it is uploaded again
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_files_hash")
+                    .table(Files::Table)
+                    .col(Files::Hash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .if_exists()
+                    .name("idx_files_hash")
+                    .table(Files::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(FileBlobs::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,91 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{DeviceKeys, LoginDevices, OneTimePrekeys, User};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The keys go away with the device when it is logged out
+        manager
+            .create_table(
+                Table::create()
+                    .table(DeviceKeys::Table)
+                    .if_not_exists()
+                    .col(string(DeviceKeys::Sid).primary_key())
+                    .col(big_unsigned(DeviceKeys::UserId))
+                    .col(binary(DeviceKeys::IdentityKey))
+                    .col(big_unsigned(DeviceKeys::SignedPrekeyId))
+                    .col(binary(DeviceKeys::SignedPrekey))
+                    .col(binary(DeviceKeys::SignedPrekeySignature))
+                    .col(
+                        timestamp_with_time_zone(DeviceKeys::UpdatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DeviceKeys::Table, DeviceKeys::Sid)
+                            .to(LoginDevices::Table, LoginDevices::Sid)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(DeviceKeys::Table, DeviceKeys::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_device_keys_user_id")
+                    .table(DeviceKeys::Table)
+                    .col(DeviceKeys::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OneTimePrekeys::Table)
+                    .if_not_exists()
+                    .col(string(OneTimePrekeys::Sid))
+                    .col(big_unsigned(OneTimePrekeys::KeyId))
+                    .col(binary(OneTimePrekeys::PublicKey))
+                    .primary_key(
+                        Index::create()
+                            .col(OneTimePrekeys::Sid)
+                            .col(OneTimePrekeys::KeyId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(OneTimePrekeys::Table, OneTimePrekeys::Sid)
+                            .to(DeviceKeys::Table, DeviceKeys::Sid)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OneTimePrekeys::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(DeviceKeys::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,79 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::{User, UserKeyHistory};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserKeyHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        big_integer(UserKeyHistory::Id)
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(big_unsigned(UserKeyHistory::UserId))
+                    .col(binary(UserKeyHistory::PublicKey))
+                    .col(
+                        timestamp_with_time_zone(UserKeyHistory::CreatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(UserKeyHistory::Table, UserKeyHistory::UserId)
+                            .to(User::Table, User::Id)
+                            .on_update(ForeignKeyAction::Cascade)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_key_history_user_id")
+                    .table(UserKeyHistory::Table)
+                    .col(UserKeyHistory::UserId)
+                    .col(UserKeyHistory::CreatedTime)
+                    .to_owned(),
+            )
+            .await?;
+
+        // The keys the users have now are the first of their history
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(UserKeyHistory::Table)
+                    .columns([
+                        UserKeyHistory::UserId,
+                        UserKeyHistory::PublicKey,
+                        UserKeyHistory::CreatedTime,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .column(User::Id)
+                            .column(User::PublicKey)
+                            .column(User::Time)
+                            .from(User::Table)
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Custom(e.to_string()))?
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserKeyHistory::Table).to_owned())
+            .await
+    }
+}
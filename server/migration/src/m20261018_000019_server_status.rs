@@ -0,0 +1,36 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::ServerStatus;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A single row, shared by every instance of the server
+        manager
+            .create_table(
+                Table::create()
+                    .table(ServerStatus::Table)
+                    .if_not_exists()
+                    .col(integer(ServerStatus::Id).primary_key())
+                    .col(boolean(ServerStatus::Maintaining).default(false))
+                    .col(text(ServerStatus::Reason).default(""))
+                    .col(timestamp_with_time_zone_null(ServerStatus::StartTime))
+                    .col(timestamp_with_time_zone_null(ServerStatus::EndTime))
+                    .col(
+                        timestamp_with_time_zone(ServerStatus::UpdatedTime)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ServerStatus::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,31 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::LeaderLease;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A single row, held by the instance running the background jobs
+        manager
+            .create_table(
+                Table::create()
+                    .table(LeaderLease::Table)
+                    .if_not_exists()
+                    .col(integer(LeaderLease::Id).primary_key())
+                    .col(text(LeaderLease::NodeId))
+                    .col(timestamp_with_time_zone(LeaderLease::AcquiredTime))
+                    .col(timestamp_with_time_zone(LeaderLease::ExpireTime))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LeaderLease::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::UserKeyHistory;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Null for the public key of the account, otherwise the identity key of the device. The
+        // history outlives the device, so it isn't a foreign key.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserKeyHistory::Table)
+                    .add_column(text_null(UserKeyHistory::DeviceId))
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(UserKeyHistory::Table)
+                    .and_where(Expr::col(UserKeyHistory::DeviceId).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserKeyHistory::Table)
+                    .drop_column(UserKeyHistory::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::enums::MetricsHistory;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Every node saves its own snapshot, the snapshots saved before are left without a node
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MetricsHistory::Table)
+                    .add_column(text_null(MetricsHistory::NodeId))
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MetricsHistory::Table)
+                    .drop_column(MetricsHistory::NodeId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
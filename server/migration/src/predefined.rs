@@ -26,6 +26,8 @@ pub enum PredefinedPermissions {
     UnmuteUser = 12,
     AcceptJoinRequest = 13,
     E2eeizeAndDee2eeizeSession = 14,
+    PinMsg = 15,
+    SetMessageTtl = 16,
     // Add other permissions as needed
 }
 
@@ -49,6 +51,7 @@ pub enum PredefinedServerManagementPermission {
     ViewUsers = 7,
     ManageSessions = 8,
     AssignRole = 9,
+    ManageStorageQuota = 10,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
@@ -78,6 +78,46 @@ fn main() -> anyhow::Result<()> {
             "service.ourchat.msg_delivery.recall.v1.RecallNotification",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
+        .type_attribute(
+            "service.ourchat.msg_delivery.edit.v1.EditNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.reaction.v1.ReactionNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.read.v1.ReadReceiptNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.typing.v1.TypingNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.pin.v1.PinNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.expire.v1.ExpireNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.device.v1.NewLoginNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.device.v1.PrekeysLowNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.device.v1.PublicKeyChangedNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "service.ourchat.msg_delivery.announcement.v1.MaintenanceNotification",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
         .type_attribute(
             "google.protobuf.Timestamp",
             "#[derive(serde::Serialize, serde::Deserialize)]",
@@ -14,8 +14,26 @@ pub mod email_verify {
     }
 }
 
+pub mod password_reset {
+    pub mod v1 {
+        include!("../generated/service.auth.password_reset.v1.rs");
+    }
+}
+
 pub mod authorize {
     pub mod v1 {
         include!("../generated/service.auth.authorize.v1.rs");
     }
 }
+
+pub mod token {
+    pub mod v1 {
+        include!("../generated/service.auth.token.v1.rs");
+    }
+}
+
+pub mod totp {
+    pub mod v1 {
+        include!("../generated/service.auth.totp.v1.rs");
+    }
+}
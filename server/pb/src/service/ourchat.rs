@@ -6,9 +6,55 @@ pub mod v1 {
     include!("../generated/service.ourchat.v1.rs");
 }
 
+pub mod file_info {
+    pub mod v1 {
+        include!("../generated/service.ourchat.file_info.v1.rs");
+    }
+}
+
 pub mod download {
     pub mod v1 {
         include!("../generated/service.ourchat.download.v1.rs");
+
+        impl DownloadResponse {
+            pub fn header(self) -> Option<DownloadHeader> {
+                match self.data? {
+                    download_response::Data::Header(header) => Some(header),
+                    _ => None,
+                }
+            }
+
+            pub fn content(self) -> Option<prost::bytes::Bytes> {
+                match self.data? {
+                    download_response::Data::Content(content) => Some(content),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+pub mod change_password {
+    pub mod v1 {
+        include!("../generated/service.ourchat.change_password.v1.rs");
+    }
+}
+
+pub mod device {
+    pub mod v1 {
+        include!("../generated/service.ourchat.device.v1.rs");
+    }
+}
+
+pub mod oauth {
+    pub mod v1 {
+        include!("../generated/service.ourchat.oauth.v1.rs");
+    }
+}
+
+pub mod storage {
+    pub mod v1 {
+        include!("../generated/service.ourchat.storage.v1.rs");
     }
 }
 
@@ -128,6 +174,12 @@ pub mod set_account_info {
     }
 }
 
+pub mod totp {
+    pub mod v1 {
+        include!("../generated/service.ourchat.totp.v1.rs");
+    }
+}
+
 pub mod unregister {
     pub mod v1 {
         include!("../generated/service.ourchat.unregister.v1.rs");
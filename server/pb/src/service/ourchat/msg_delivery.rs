@@ -8,6 +8,66 @@ pub mod recall {
     }
 }
 
+pub mod edit {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.edit.v1.rs");
+    }
+}
+
+pub mod expire {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.expire.v1.rs");
+    }
+}
+
+pub mod history {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.history.v1.rs");
+    }
+}
+
+pub mod pin {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.pin.v1.rs");
+    }
+}
+
+pub mod reaction {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.reaction.v1.rs");
+    }
+}
+
+pub mod read {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.read.v1.rs");
+    }
+}
+
+pub mod scheduled {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.scheduled.v1.rs");
+    }
+}
+
+pub mod search {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.search.v1.rs");
+    }
+}
+
+pub mod thread {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.thread.v1.rs");
+    }
+}
+
+pub mod typing {
+    pub mod v1 {
+        include!("../../generated/service.ourchat.msg_delivery.typing.v1.rs");
+    }
+}
+
 pub mod announcement {
     pub mod v1 {
         use entities::announcement;
@@ -26,6 +26,7 @@ pub mod v1 {
                     size: size as u64,
                     auto_clean,
                     session_id,
+                    content_type: None,
                 })),
             }
         }
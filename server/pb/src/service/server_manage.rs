@@ -76,6 +76,7 @@ impl From<entities::metrics_history::Model> for MetricDataPoint {
                     task: Some(model_to_task_metrics(&record)),
                 }),
             }),
+            node_id: record.node_id,
         }
     }
 }
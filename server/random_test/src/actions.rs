@@ -1120,6 +1120,7 @@ impl ActionExecutor {
                 name: name.map(|s| s.to_string()),
                 description: None,
                 avatar_key: None,
+                message_ttl: None,
             };
             match user_guard.oc().set_session_info(req).await {
                 Ok(_) => ActionResult::Success {
@@ -1149,6 +1150,7 @@ impl ActionExecutor {
                 avatar_key: None,
                 user_defined_status: None,
                 ocid: None,
+                public_key: None,
             };
             match user_guard.oc().set_self_info(req).await {
                 Ok(_) => ActionResult::Success {
@@ -9,7 +9,7 @@ use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
 use size::Size;
 use utils::{merge_json, resolve_relative_path, serde_default};
 
-use crate::{ParserCfg, config::http::HttpCfg};
+use crate::{ParserCfg, config::http::HttpCfg, process::totp::TotpSecretKey};
 use base::{
     constants::{self, CONFIG_FILE_ENV_VAR, SessionID},
     database::{postgres::PostgresDbCfg, redis_cfg::RedisCfg},
@@ -76,6 +76,7 @@ pub struct MainCfg {
     pub user_files_limit: Size,
     pub friends_number_limit: u32,
     pub files_storage_path: PathBuf,
+    pub storage: StorageCfg,
     pub enable_file_cache: bool,
     pub enable_hierarchical_storage: bool,
     pub enable_file_deduplication: bool,
@@ -91,10 +92,18 @@ pub struct MainCfg {
     pub log_clean_duration: Duration,
     #[serde(with = "humantime_serde")]
     pub log_keep: Duration,
-    pub single_instance: bool,
-    pub leader_node: bool,
+    #[serde(with = "humantime_serde")]
+    pub leader_lease_duration: Duration,
     #[serde(with = "humantime_serde")]
     pub room_key_duration: Duration,
+    #[serde(with = "humantime_serde")]
+    pub scheduled_msg_check_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub expired_msg_clean_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub access_token_expire: Duration,
+    #[serde(with = "humantime_serde")]
+    pub refresh_token_expire: Duration,
     pub unregister_policy: UnregisterPolicy,
     pub password_hash: PasswordHash,
     pub db: DbArgCfg,
@@ -109,6 +118,14 @@ pub struct MainCfg {
     pub initial_admin_ocid: Option<OCID>,
     #[serde(default = "constants::default_patches_directory")]
     pub patches_directory: String,
+    /// Base64 of the 32 bytes key which encrypts the TOTP secrets in the database, derived from
+    /// the secret of the server info if unset
+    pub totp_secret_key: Option<String>,
+
+    /// The options still set in the config though they are ignored now, warned about once the
+    /// log is set up
+    #[serde(skip)]
+    pub deprecated_options: Vec<&'static str>,
 
     #[serde(skip)]
     pub cmd_args: ParserCfg,
@@ -130,10 +147,74 @@ pub struct OAuthCfg {
     pub github_client_id: String,
     #[serde(default = "base::constants::default_oauth_github_client_secret")]
     pub github_client_secret: String,
+    /// OpenID Connect providers, logged in with at `/oauth/{name}`
+    #[serde(default)]
+    pub oidc: Vec<OidcProviderCfg>,
 }
 
 serde_default!(OAuthCfg);
 
+impl OAuthCfg {
+    /// Whether users can log in with the provider, GitHub is always available once OAuth is enabled
+    pub fn has_provider(&self, name: &str) -> bool {
+        self.enable
+            && (name == GITHUB_PROVIDER || self.oidc.iter().any(|provider| provider.name == name))
+    }
+}
+
+pub const GITHUB_PROVIDER: &str = "github";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OidcProviderCfg {
+    pub name: String,
+    /// The issuer identifier, the discovery document is fetched from
+    /// `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Public clients which only use PKCE have no secret
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default = "base::constants::default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+/// Where the uploaded files are kept, the temporary files of uploads are always written under
+/// `files_storage_path`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StorageCfg {
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    #[serde(default)]
+    pub s3: Option<S3Cfg>,
+}
+
+serde_default!(StorageCfg);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    /// Under `files_storage_path`
+    #[default]
+    Local,
+    /// An S3-compatible object storage, like AWS S3 or MinIO
+    S3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct S3Cfg {
+    /// Like `https://s3.us-east-1.amazonaws.com` or `http://127.0.0.1:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "base::constants::default_s3_region")]
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Address the bucket as `{endpoint}/{bucket}` instead of `{bucket}.{endpoint host}`, which
+    /// most self-hosted services need
+    #[serde(default = "base::constants::default_s3_path_style")]
+    pub path_style: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PasswordHash {
     #[serde(default = "constants::default_m_cost")]
@@ -208,6 +289,8 @@ pub struct RawMainCfg {
     pub friends_number_limit: u32,
     #[serde(default = "constants::default_files_storage_path")]
     pub files_storage_path: PathBuf,
+    #[serde(default)]
+    pub storage: StorageCfg,
     #[serde(default = "constants::default_enable_file_cache")]
     pub enable_file_cache: bool,
     #[serde(default = "constants::default_enable_hierarchical_storage")]
@@ -240,15 +323,43 @@ pub struct RawMainCfg {
     pub log_clean_duration: Duration,
     #[serde(default = "constants::default_log_keep", with = "humantime_serde")]
     pub log_keep: Duration,
-    #[serde(default = "constants::default_single_instance")]
-    pub single_instance: bool,
-    #[serde(default = "constants::default_leader_node")]
-    pub leader_node: bool,
+    #[serde(
+        default = "constants::default_leader_lease_duration",
+        with = "humantime_serde"
+    )]
+    pub leader_lease_duration: Duration,
+    /// Deprecated and ignored since the instances elect the leader, kept so that the old configs
+    /// still load
+    #[serde(default)]
+    pub single_instance: Option<bool>,
+    /// Deprecated and ignored, see `single_instance`
+    #[serde(default)]
+    pub leader_node: Option<bool>,
     #[serde(
         default = "constants::default_room_key_duration",
         with = "humantime_serde"
     )]
     pub room_key_duration: Duration,
+    #[serde(
+        default = "constants::default_scheduled_msg_check_interval",
+        with = "humantime_serde"
+    )]
+    pub scheduled_msg_check_interval: Duration,
+    #[serde(
+        default = "constants::default_expired_msg_clean_interval",
+        with = "humantime_serde"
+    )]
+    pub expired_msg_clean_interval: Duration,
+    #[serde(
+        default = "constants::default_access_token_expire",
+        with = "humantime_serde"
+    )]
+    pub access_token_expire: Duration,
+    #[serde(
+        default = "constants::default_refresh_token_expire",
+        with = "humantime_serde"
+    )]
+    pub refresh_token_expire: Duration,
     #[serde(default)]
     pub unregister_policy: UnregisterPolicy,
     #[serde(default)]
@@ -277,6 +388,8 @@ pub struct RawMainCfg {
     pub initial_admin_ocid: Option<OCID>,
     #[serde(default = "constants::default_patches_directory")]
     pub patches_directory: String,
+    #[serde(default)]
+    pub totp_secret_key: Option<String>,
 }
 
 impl<'de> Deserialize<'de> for MainCfg {
@@ -301,6 +414,29 @@ impl<'de> Deserialize<'de> for MainCfg {
         if raw.room_key_duration.is_zero() {
             return Err(D::Error::custom("room_key_duration cannot be zero"));
         }
+        if raw.leader_lease_duration < Duration::from_secs(3) {
+            return Err(D::Error::custom(
+                "leader_lease_duration must be at least 3 seconds",
+            ));
+        }
+        if raw.scheduled_msg_check_interval.is_zero() {
+            return Err(D::Error::custom(
+                "scheduled_msg_check_interval cannot be zero",
+            ));
+        }
+        if raw.expired_msg_clean_interval.is_zero() {
+            return Err(D::Error::custom(
+                "expired_msg_clean_interval cannot be zero",
+            ));
+        }
+        if raw.access_token_expire.is_zero() {
+            return Err(D::Error::custom("access_token_expire cannot be zero"));
+        }
+        if raw.refresh_token_expire < raw.access_token_expire {
+            return Err(D::Error::custom(
+                "refresh_token_expire cannot be shorter than access_token_expire",
+            ));
+        }
         if raw.verification_expire_time.is_zero() {
             return Err(D::Error::custom("verification_expire_time cannot be zero"));
         }
@@ -309,6 +445,39 @@ impl<'de> Deserialize<'de> for MainCfg {
                 "fetch_msg_page_size must be greater than 0",
             ));
         }
+        let mut oidc_names = std::collections::HashSet::new();
+        for provider in &raw.oauth.oidc {
+            if provider.name.is_empty()
+                || provider.name == GITHUB_PROVIDER
+                || !oidc_names.insert(provider.name.as_str())
+            {
+                return Err(D::Error::custom(format!(
+                    "invalid or duplicate oidc provider name \"{}\"",
+                    provider.name
+                )));
+            }
+        }
+        if raw.storage.backend == StorageBackendKind::S3 && raw.storage.s3.is_none() {
+            return Err(D::Error::custom(
+                "storage.s3 must be set for the s3 storage backend",
+            ));
+        }
+
+        if let Some(key) = &raw.totp_secret_key
+            && TotpSecretKey::from_base64(key).is_none()
+        {
+            return Err(D::Error::custom(
+                "totp_secret_key must be the base64 of 32 bytes",
+            ));
+        }
+
+        let deprecated_options = [
+            ("single_instance", raw.single_instance.is_some()),
+            ("leader_node", raw.leader_node.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(option, set)| set.then_some(option))
+        .collect();
 
         Ok(MainCfg {
             inherit: raw.inherit,
@@ -322,6 +491,7 @@ impl<'de> Deserialize<'de> for MainCfg {
             user_files_limit: raw.user_files_limit,
             friends_number_limit: raw.friends_number_limit,
             files_storage_path: raw.files_storage_path,
+            storage: raw.storage,
             enable_file_cache: raw.enable_file_cache,
             enable_hierarchical_storage: raw.enable_hierarchical_storage,
             enable_file_deduplication: raw.enable_file_deduplication,
@@ -332,9 +502,12 @@ impl<'de> Deserialize<'de> for MainCfg {
             user_defined_status_expire_time: raw.user_defined_status_expire_time,
             log_clean_duration: raw.log_clean_duration,
             log_keep: raw.log_keep,
-            single_instance: raw.single_instance,
-            leader_node: raw.leader_node,
+            leader_lease_duration: raw.leader_lease_duration,
             room_key_duration: raw.room_key_duration,
+            scheduled_msg_check_interval: raw.scheduled_msg_check_interval,
+            expired_msg_clean_interval: raw.expired_msg_clean_interval,
+            access_token_expire: raw.access_token_expire,
+            refresh_token_expire: raw.refresh_token_expire,
             unregister_policy: raw.unregister_policy,
             password_hash: raw.password_hash,
             db: raw.db,
@@ -347,6 +520,8 @@ impl<'de> Deserialize<'de> for MainCfg {
             lock_account_duration: raw.lock_account_duration,
             initial_admin_ocid: raw.initial_admin_ocid,
             patches_directory: raw.patches_directory,
+            totp_secret_key: raw.totp_secret_key,
+            deprecated_options,
             cmd_args: ParserCfg::default(),
         })
     }
@@ -392,10 +567,6 @@ impl MainCfg {
         Ok(cfg)
     }
 
-    pub fn unique_instance(&self) -> bool {
-        self.leader_node || self.single_instance
-    }
-
     pub fn get_file_path_from_key(&self, key: &str) -> PathBuf {
         self.files_storage_path.join(key)
     }
@@ -602,6 +773,30 @@ mod tests {
         assert_eq!(cfg.friends_number_limit, 100);
     }
 
+    #[test]
+    fn test_deprecated_leader_options_are_ignored() {
+        let mut config = minimal_valid_config();
+        config["single_instance"] = json!(true);
+        config["leader_node"] = json!(false);
+        let cfg: MainCfg = serde_json::from_value(config).unwrap();
+        assert_eq!(cfg.deprecated_options, ["single_instance", "leader_node"]);
+        let cfg: MainCfg = serde_json::from_value(minimal_valid_config()).unwrap();
+        assert!(cfg.deprecated_options.is_empty());
+    }
+
+    #[test]
+    fn test_totp_secret_key_must_be_32_bytes() {
+        let mut config = minimal_valid_config();
+        config["totp_secret_key"] = json!("c2hvcnQ=");
+        let err = serde_json::from_value::<MainCfg>(config)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("totp_secret_key must be the base64 of 32 bytes"));
+        let mut config = minimal_valid_config();
+        config["totp_secret_key"] = json!("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=");
+        assert!(serde_json::from_value::<MainCfg>(config).is_ok());
+    }
+
     #[test]
     fn test_friends_number_limit_zero_fails() {
         let mut config = minimal_valid_config();
@@ -646,6 +841,97 @@ mod tests {
         assert!(err.contains("room_key_duration cannot be zero"));
     }
 
+    #[test]
+    fn test_scheduled_msg_check_interval_zero_fails() {
+        let mut config = minimal_valid_config();
+        config["scheduled_msg_check_interval"] = json!("0s");
+        let result: Result<MainCfg, _> = serde_json::from_value(config);
+        assert!(result.is_err());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("scheduled_msg_check_interval cannot be zero"));
+    }
+
+    #[test]
+    fn test_expired_msg_clean_interval_zero_fails() {
+        let mut config = minimal_valid_config();
+        config["expired_msg_clean_interval"] = json!("0s");
+        let result: Result<MainCfg, _> = serde_json::from_value(config);
+        assert!(result.is_err());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("expired_msg_clean_interval cannot be zero"));
+    }
+
+    #[test]
+    fn test_access_token_expire_zero_fails() {
+        let mut config = minimal_valid_config();
+        config["access_token_expire"] = json!("0s");
+        let result: Result<MainCfg, _> = serde_json::from_value(config);
+        assert!(result.is_err());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("access_token_expire cannot be zero"));
+    }
+
+    #[test]
+    fn test_duplicate_oidc_provider_fails() {
+        let mut config = minimal_valid_config();
+        let provider = json!({
+            "name": "example",
+            "issuer": "https://id.example.com",
+            "client_id": "ourchat"
+        });
+        config["oauth"] = json!({ "oidc": [provider.clone()] });
+        let cfg: MainCfg = serde_json::from_value(config.clone()).unwrap();
+        assert_eq!(cfg.oauth.oidc[0].scopes, ["openid", "email", "profile"]);
+        assert!(cfg.oauth.oidc[0].client_secret.is_none());
+
+        config["oauth"] = json!({ "oidc": [provider.clone(), provider] });
+        let result: Result<MainCfg, _> = serde_json::from_value(config.clone());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("duplicate oidc provider name"));
+
+        config["oauth"] = json!({ "oidc": [{
+            "name": "github",
+            "issuer": "https://id.example.com",
+            "client_id": "ourchat"
+        }] });
+        let result: Result<MainCfg, _> = serde_json::from_value(config);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_s3_storage_requires_section() {
+        let mut config = minimal_valid_config();
+        let cfg: MainCfg = serde_json::from_value(config.clone()).unwrap();
+        assert_eq!(cfg.storage.backend, StorageBackendKind::Local);
+
+        config["storage"] = json!({ "backend": "s3" });
+        let result: Result<MainCfg, _> = serde_json::from_value(config.clone());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("storage.s3 must be set"));
+
+        config["storage"]["s3"] = json!({
+            "endpoint": "http://127.0.0.1:9000",
+            "bucket": "ourchat",
+            "access_key_id": "key",
+            "secret_access_key": "secret"
+        });
+        let cfg: MainCfg = serde_json::from_value(config).unwrap();
+        let s3 = cfg.storage.s3.unwrap();
+        assert_eq!(s3.region, "us-east-1");
+        assert!(s3.path_style);
+    }
+
+    #[test]
+    fn test_refresh_token_shorter_than_access_token_fails() {
+        let mut config = minimal_valid_config();
+        config["access_token_expire"] = json!("1h");
+        config["refresh_token_expire"] = json!("10m");
+        let result: Result<MainCfg, _> = serde_json::from_value(config);
+        assert!(result.is_err());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("refresh_token_expire cannot be shorter than access_token_expire"));
+    }
+
     #[test]
     fn test_verification_expire_time_zero_fails() {
         let mut config = minimal_valid_config();
@@ -766,6 +1052,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_user_setting_password_reset_defaults() {
+        let mut config = minimal_valid_config();
+        config["user_setting"] = json!({
+            "contacts": [],
+            "support_page": "http://example.com"
+        });
+        let cfg: MainCfg = serde_json::from_value(config).unwrap();
+        let ConfigSource::Inline(user_setting) = &cfg.user_setting else {
+            panic!("Expected inline config, got path");
+        };
+        assert_eq!(
+            user_setting.password_reset_expiry,
+            base::constants::default_password_reset_expiry()
+        );
+        assert_eq!(
+            user_setting.password_reset_interval,
+            base::constants::default_password_reset_interval()
+        );
+    }
+
     #[test]
     fn test_inline_http_config() {
         let mut config = minimal_valid_config();
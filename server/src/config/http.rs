@@ -105,6 +105,14 @@ impl<'de> Deserialize<'de> for HttpCfg {
         if raw.lop_keep.is_zero() {
             return Err(D::Error::custom("lop_keep cannot be zero"));
         }
+        if raw.rate_limit.num_of_burst_requests == 0 {
+            return Err(D::Error::custom(
+                "num_of_burst_requests must be greater than 0",
+            ));
+        }
+        if raw.rate_limit.replenish_duration.is_zero() {
+            return Err(D::Error::custom("replenish_duration cannot be zero"));
+        }
 
         Ok(HttpCfg {
             inherit: raw.inherit,
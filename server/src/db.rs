@@ -1,11 +1,19 @@
 //! Database
 
+pub mod device;
+pub mod device_keys;
+pub mod file_blobs;
 pub mod file_storage;
 pub mod friend;
 pub mod helper;
+pub mod key_history;
+pub mod leader_lease;
+pub mod login_session;
 pub mod manager;
 pub mod messages;
 pub mod metrics;
+pub mod oauth;
 pub mod redis_mappings;
+pub mod server_status;
 pub mod session;
 pub mod user;
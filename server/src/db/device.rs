@@ -0,0 +1,129 @@
+//! Devices the users are logged in on, one for every login in [`super::login_session`]
+
+use base::constants::ID;
+use chrono::{DateTime, Utc};
+use entities::{login_devices, prelude::*};
+use pb::time::TimeStamp;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+/// Record the device of a new login.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn save_login_device(
+    user_id: ID,
+    sid: &str,
+    device_name: String,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<login_devices::Model, sea_orm::DbErr> {
+    let now = Utc::now();
+    login_devices::ActiveModel {
+        sid: ActiveValue::Set(sid.to_string()),
+        user_id: ActiveValue::Set(user_id.into()),
+        device_name: ActiveValue::Set(device_name),
+        user_agent: ActiveValue::Set(user_agent),
+        ip: ActiveValue::Set(ip),
+        created_time: ActiveValue::Set(now.into()),
+        last_used_time: ActiveValue::Set(now.into()),
+    }
+    .insert(db_conn)
+    .await
+}
+
+/// Update the last used time of the device of a login.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn touch_login_device(
+    sid: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    LoginDevices::update_many()
+        .col_expr(
+            login_devices::Column::LastUsedTime,
+            Expr::value(TimeStamp::from(Utc::now())),
+        )
+        .filter(login_devices::Column::Sid.eq(sid))
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+/// Get the devices of a user which have been used since `since`, the most recently used first.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_login_devices(
+    user_id: ID,
+    since: DateTime<Utc>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<login_devices::Model>, sea_orm::DbErr> {
+    LoginDevices::find()
+        .filter(login_devices::Column::UserId.eq(user_id))
+        .filter(login_devices::Column::LastUsedTime.gte(since))
+        .order_by_desc(login_devices::Column::LastUsedTime)
+        .all(db_conn)
+        .await
+}
+
+/// Delete the devices of a user which haven't been used since `before`, their logins have
+/// expired already.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn delete_stale_login_devices(
+    user_id: ID,
+    before: DateTime<Utc>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    LoginDevices::delete_many()
+        .filter(login_devices::Column::UserId.eq(user_id))
+        .filter(login_devices::Column::LastUsedTime.lt(before))
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+/// Delete a device of the user, returning false if the user has no such device.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn delete_login_device(
+    user_id: ID,
+    sid: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, sea_orm::DbErr> {
+    let res = LoginDevices::delete_many()
+        .filter(login_devices::Column::UserId.eq(user_id))
+        .filter(login_devices::Column::Sid.eq(sid))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Delete all the devices of a user.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn delete_user_login_devices(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    LoginDevices::delete_many()
+        .filter(login_devices::Column::UserId.eq(user_id))
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
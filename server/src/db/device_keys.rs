@@ -0,0 +1,213 @@
+//! End-to-end encryption keys of the devices in [`super::device`], dropped with the device
+
+use base::constants::ID;
+use chrono::{DateTime, Utc};
+use entities::{device_keys, login_devices, one_time_prekeys, prelude::*};
+use pb::time::TimeStamp;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseBackend, EntityTrait,
+    FromQueryResult, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+    Statement, sea_query::OnConflict,
+};
+
+/// A signed prekey as it is stored
+#[derive(Debug, Clone)]
+pub struct SignedPrekey {
+    pub key_id: i64,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Publish the keys of a device, replacing the ones it published before along with their
+/// one-time prekeys.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn save_device_keys(
+    user_id: ID,
+    sid: &str,
+    identity_key: Vec<u8>,
+    signed_prekey: SignedPrekey,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    DeviceKeys::delete_by_id(sid).exec(db_conn).await?;
+    device_keys::ActiveModel {
+        sid: ActiveValue::Set(sid.to_string()),
+        user_id: ActiveValue::Set(user_id.into()),
+        identity_key: ActiveValue::Set(identity_key),
+        signed_prekey_id: ActiveValue::Set(signed_prekey.key_id),
+        signed_prekey: ActiveValue::Set(signed_prekey.public_key),
+        signed_prekey_signature: ActiveValue::Set(signed_prekey.signature),
+        updated_time: ActiveValue::Set(Utc::now().into()),
+    }
+    .insert(db_conn)
+    .await?;
+    Ok(())
+}
+
+/// Rotate the signed prekey of a device, returning false if the device has published no keys.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn update_signed_prekey(
+    sid: &str,
+    signed_prekey: SignedPrekey,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, sea_orm::DbErr> {
+    let res = DeviceKeys::update_many()
+        .col_expr(
+            device_keys::Column::SignedPrekeyId,
+            Expr::value(signed_prekey.key_id),
+        )
+        .col_expr(
+            device_keys::Column::SignedPrekey,
+            Expr::value(signed_prekey.public_key),
+        )
+        .col_expr(
+            device_keys::Column::SignedPrekeySignature,
+            Expr::value(signed_prekey.signature),
+        )
+        .col_expr(
+            device_keys::Column::UpdatedTime,
+            Expr::value(TimeStamp::from(Utc::now())),
+        )
+        .filter(device_keys::Column::Sid.eq(sid))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Get the keys published by a device
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_device_keys(
+    sid: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<device_keys::Model>, sea_orm::DbErr> {
+    DeviceKeys::find_by_id(sid).one(db_conn).await
+}
+
+/// Add one-time prekeys to a device, the ones with the ids it has already are replaced. The ids
+/// must be distinct among `prekeys`.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn add_one_time_prekeys(
+    sid: &str,
+    prekeys: impl IntoIterator<Item = (i64, Vec<u8>)>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    let prekeys: Vec<_> = prekeys
+        .into_iter()
+        .map(|(key_id, public_key)| one_time_prekeys::ActiveModel {
+            sid: ActiveValue::Set(sid.to_string()),
+            key_id: ActiveValue::Set(key_id),
+            public_key: ActiveValue::Set(public_key),
+        })
+        .collect();
+    if prekeys.is_empty() {
+        return Ok(());
+    }
+    OneTimePrekeys::insert_many(prekeys)
+        .on_conflict(
+            OnConflict::columns([
+                one_time_prekeys::Column::Sid,
+                one_time_prekeys::Column::KeyId,
+            ])
+            .update_column(one_time_prekeys::Column::PublicKey)
+            .to_owned(),
+        )
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+/// Count the one-time prekeys left to a device
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn count_one_time_prekeys(
+    sid: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<u64, sea_orm::DbErr> {
+    OneTimePrekeys::find()
+        .filter(one_time_prekeys::Column::Sid.eq(sid))
+        .count(db_conn)
+        .await
+}
+
+/// Hand out a one-time prekey of a device, deleting it so that no one else gets it.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn take_one_time_prekey(
+    sid: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<one_time_prekeys::Model>, sea_orm::DbErr> {
+    one_time_prekeys::Model::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        r#"DELETE FROM one_time_prekeys WHERE (sid, key_id) = (
+SELECT sid, key_id FROM one_time_prekeys WHERE sid = $1 ORDER BY key_id LIMIT 1 FOR UPDATE SKIP LOCKED
+) RETURNING *"#,
+        [sid.into()],
+    ))
+    .one(db_conn)
+    .await
+}
+
+/// Get the keys of the devices of a user which have been used since `since`, the most recently
+/// used first.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_user_device_keys(
+    user_id: ID,
+    since: DateTime<Utc>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<device_keys::Model>, sea_orm::DbErr> {
+    DeviceKeys::find()
+        .join(
+            sea_orm::JoinType::InnerJoin,
+            device_keys::Relation::LoginDevices.def(),
+        )
+        .filter(device_keys::Column::UserId.eq(user_id))
+        .filter(login_devices::Column::LastUsedTime.gte(since))
+        .order_by_desc(login_devices::Column::LastUsedTime)
+        .all(db_conn)
+        .await
+}
+
+#[derive(Debug, FromQueryResult)]
+struct Shared {
+    shared: bool,
+}
+
+/// Whether the two users are members of a common session
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn share_session(
+    user_id: ID,
+    other_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, sea_orm::DbErr> {
+    let res = Shared::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        r#"SELECT EXISTS(SELECT 1 FROM session_relation a JOIN session_relation b
+ON a.session_id = b.session_id WHERE a.user_id = $1 AND b.user_id = $2) AS shared"#,
+        [user_id.into(), other_id.into()],
+    ))
+    .one(db_conn)
+    .await?;
+    Ok(res.is_some_and(|res| res.shared))
+}
@@ -0,0 +1,229 @@
+//! Content-addressed blobs shared by the files uploaded with the same content
+//!
+//! With `enable_file_deduplication`, the content is stored once as `blobs/{hash[..2]}/{hash}`
+//! and every files record uploaded with that hash points at it. The blob counts the records
+//! referencing it, the object and its thumbnails are deleted after the last one is gone. The
+//! changes of a blob are serialized by an advisory lock on its hash held until the transaction
+//! ends, so a new reference can't race with the deletion of the object.
+
+use std::future::Future;
+
+use entities::{file_blobs, files, prelude::*};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseBackend, DatabaseTransaction, DbErr,
+    EntityTrait, Statement, TransactionTrait,
+};
+
+use crate::{
+    process::files::media::{THUMBNAIL_SIDES, delete_file_objects, thumbnail_object},
+    storage::{StorageBackend, StorageError},
+};
+
+const BLOB_PREFIX: &str = "blobs/";
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileBlobError {
+    #[error("database error:{0:?}")]
+    Db(#[from] DbErr),
+    #[error("storage error:{0:?}")]
+    Storage(#[from] StorageError),
+}
+
+/// The object name of the content with the hex hash
+pub fn blob_object(hash: &str) -> String {
+    format!("{BLOB_PREFIX}{}/{hash}", &hash[..2])
+}
+
+/// Whether the object is a shared blob rather than the object of a single file
+pub fn is_blob_object(path: &str) -> bool {
+    path.starts_with(BLOB_PREFIX)
+}
+
+async fn lock_blob(txn: &DatabaseTransaction, hash: &str) -> Result<(), DbErr> {
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "SELECT pg_advisory_xact_lock(hashtextextended($1, 0))",
+        [hash.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Count one more reference to the blob if it exists, the lock of the hash must be held
+async fn increase_ref_count(
+    txn: &DatabaseTransaction,
+    hash: &str,
+) -> Result<Option<file_blobs::Model>, DbErr> {
+    let Some(blob) = FileBlobs::find_by_id(hash).one(txn).await? else {
+        return Ok(None);
+    };
+    let ref_count = blob.ref_count + 1;
+    let mut blob: file_blobs::ActiveModel = blob.into();
+    blob.ref_count = ActiveValue::Set(ref_count);
+    Ok(Some(blob.update(txn).await?))
+}
+
+/// Take a reference to the blob of the content, calling `store` with the object name to put
+/// the content when the blob is new.
+///
+/// Returns what `store` returned, or `None` if the content was stored already. Nothing is
+/// referenced if `store` fails.
+pub async fn acquire_blob<T, E, F, Fut>(
+    hash: &str,
+    size: i64,
+    db_conn: &impl TransactionTrait,
+    store: F,
+) -> Result<Option<T>, E>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<DbErr>,
+{
+    let txn = db_conn.begin().await?;
+    lock_blob(&txn, hash).await?;
+    let stored = match increase_ref_count(&txn, hash).await? {
+        Some(_) => None,
+        None => {
+            let path = blob_object(hash);
+            let stored = store(path.clone()).await?;
+            file_blobs::ActiveModel {
+                hash: ActiveValue::Set(hash.to_string()),
+                path: ActiveValue::Set(path),
+                size: ActiveValue::Set(size),
+                ref_count: ActiveValue::Set(1),
+                created_time: ActiveValue::NotSet,
+            }
+            .insert(&txn)
+            .await?;
+            Some(stored)
+        }
+    };
+    txn.commit().await?;
+    Ok(stored)
+}
+
+/// Take another reference to a blob which is known to be stored, returning it, or `None` if it
+/// has just gone away
+pub async fn reference_blob(
+    hash: &str,
+    db_conn: &impl TransactionTrait,
+) -> Result<Option<file_blobs::Model>, DbErr> {
+    let txn = db_conn.begin().await?;
+    lock_blob(&txn, hash).await?;
+    let blob = increase_ref_count(&txn, hash).await?;
+    txn.commit().await?;
+    Ok(blob)
+}
+
+/// The objects which nothing references any more once a transaction is committed, see
+/// [`delete_released_objects`]
+#[derive(Debug)]
+#[must_use]
+pub enum ReleasedObjects {
+    /// The objects are still used by other files
+    None,
+    /// The objects of a file stored on its own
+    File(files::Model),
+    /// A blob whose last reference is gone
+    Blob(file_blobs::Model),
+}
+
+/// Drop a reference to the blob in the transaction, the blob is deleted with the last one and
+/// its objects are returned
+async fn release_blob_ref(hash: &str, txn: &DatabaseTransaction) -> Result<ReleasedObjects, DbErr> {
+    lock_blob(txn, hash).await?;
+    let Some(blob) = FileBlobs::find_by_id(hash).one(txn).await? else {
+        tracing::warn!("releasing the blob {} which doesn't exist", hash);
+        return Ok(ReleasedObjects::None);
+    };
+    if blob.ref_count > 1 {
+        let ref_count = blob.ref_count - 1;
+        let mut blob: file_blobs::ActiveModel = blob.into();
+        blob.ref_count = ActiveValue::Set(ref_count);
+        blob.update(txn).await?;
+        return Ok(ReleasedObjects::None);
+    }
+    FileBlobs::delete_by_id(hash).exec(txn).await?;
+    Ok(ReleasedObjects::Blob(blob))
+}
+
+/// Delete the record of a file in the transaction along with its reference to the content.
+///
+/// The objects are only deleted by [`delete_released_objects`] after the transaction is
+/// committed, so a rollback never leaves a record without its content.
+pub async fn delete_file_record(
+    file_info: &files::Model,
+    txn: &DatabaseTransaction,
+) -> Result<ReleasedObjects, DbErr> {
+    Files::delete_by_id(&file_info.key).exec(txn).await?;
+    if is_blob_object(&file_info.path) {
+        release_blob_ref(&file_info.hash, txn).await
+    } else {
+        Ok(ReleasedObjects::File(file_info.clone()))
+    }
+}
+
+/// Delete the objects released by a committed transaction.
+///
+/// A blob may have been stored again meanwhile under the same object, which is kept then.
+pub async fn delete_released_objects(
+    released: ReleasedObjects,
+    db_conn: &impl TransactionTrait,
+    storage: &dyn StorageBackend,
+) -> Result<(), FileBlobError> {
+    match released {
+        ReleasedObjects::None => {}
+        ReleasedObjects::File(file_info) => delete_file_objects(&file_info, storage).await?,
+        ReleasedObjects::Blob(blob) => {
+            let txn = db_conn.begin().await?;
+            lock_blob(&txn, &blob.hash).await?;
+            // Still under the lock, no one can store the content again meanwhile
+            if FileBlobs::find_by_id(&blob.hash).one(&txn).await?.is_none() {
+                for side in THUMBNAIL_SIDES {
+                    storage.delete(&thumbnail_object(&blob.path, side)).await?;
+                }
+                storage.delete(&blob.path).await?;
+            }
+            txn.commit().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Drop a reference to the blob, deleting the object and its thumbnails with the last one
+pub async fn release_blob(
+    hash: &str,
+    db_conn: &impl TransactionTrait,
+    storage: &dyn StorageBackend,
+) -> Result<(), FileBlobError> {
+    let txn = db_conn.begin().await?;
+    let released = release_blob_ref(hash, &txn).await?;
+    txn.commit().await?;
+    delete_released_objects(released, db_conn, storage).await
+}
+
+/// Delete the record of a file and then its objects, the shared ones only when no other file
+/// uses them
+pub async fn remove_file_record(
+    file_info: &files::Model,
+    db_conn: &impl TransactionTrait,
+    storage: &dyn StorageBackend,
+) -> Result<(), FileBlobError> {
+    let txn = db_conn.begin().await?;
+    let released = delete_file_record(file_info, &txn).await?;
+    txn.commit().await?;
+    delete_released_objects(released, db_conn, storage).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_object() {
+        let object = blob_object("ab12cd");
+        assert_eq!(object, "blobs/ab/ab12cd");
+        assert!(is_blob_object(&object));
+        assert!(!is_blob_object("0a/ab/0123456789abcdefghijab12cd"));
+    }
+}
@@ -1,49 +1,75 @@
-//! Manage the file storage with simple ownership model
+//! Manage the file storage with simple ownership model, the deduplicated contents are shared
+//! through [`super::file_blobs`]
 
 use base::constants::ID;
-use entities::{files, prelude::*};
+use entities::{files, prelude::*, user};
 use parking_lot::RwLock;
-use sea_orm::{ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+};
+use size::Size;
 use std::{fs::exists, sync::Arc};
 use tokio::fs::remove_file;
 use tokio_cron_scheduler::Job;
 use tracing::{instrument, trace};
 
 use crate::config::Cfg;
+use crate::db::file_blobs::remove_file_record;
+use crate::leader::LeaderElection;
+use crate::process::files::media::{THUMBNAIL_SIDES, thumbnail_object};
+use crate::storage::{LocalStorage, StorageBackend, StorageError};
 
 #[derive(Debug)]
 pub struct FileSys {
     db_conn: DatabaseConnection,
     shared_cfg: Arc<RwLock<Cfg>>,
+    storage: Arc<dyn StorageBackend>,
 }
 
 impl FileSys {
-    pub fn new(db_conn: DatabaseConnection, shared_cfg: Arc<RwLock<Cfg>>) -> Self {
+    pub fn new(
+        db_conn: DatabaseConnection,
+        shared_cfg: Arc<RwLock<Cfg>>,
+        storage: Arc<dyn StorageBackend>,
+    ) -> Self {
         Self {
             db_conn,
             shared_cfg,
+            storage,
         }
     }
 
+    /// The backend keeping the uploaded files
+    pub fn storage(&self) -> &Arc<dyn StorageBackend> {
+        &self.storage
+    }
+
     fn init() {
         if exists("files_storage").is_err() {
             std::fs::create_dir("files_storage").expect("Could not create files_storage directory");
         }
     }
 
-    pub async fn generate_job(&self) -> anyhow::Result<Job> {
+    /// The job cleaning up the files regularly, on the leader only
+    pub async fn generate_job(&self, leader: Arc<LeaderElection>) -> anyhow::Result<Job> {
         let db_conn = self.db_conn.clone();
         Self::init();
         let read = self.shared_cfg.read();
         let shared_cfg = self.shared_cfg.clone();
+        let storage = self.storage.clone();
         trace!("Clean Cron: {}", read.main_cfg.auto_clean_duration);
         Ok(Job::new_async(
             read.main_cfg.auto_clean_duration.clone(),
             move |_uuid, _l| {
                 let db_conn = db_conn.clone();
                 let shared_cfg = shared_cfg.clone();
+                let storage = storage.clone();
+                let leader = leader.clone();
                 Box::pin(async move {
-                    auto_clean_files(shared_cfg, db_conn).await;
+                    if !leader.is_leader() {
+                        return;
+                    }
+                    auto_clean_files(shared_cfg, db_conn, storage).await;
                 })
             },
         )?)
@@ -53,31 +79,33 @@ impl FileSys {
 pub async fn clean_files(
     shared_cfg: Arc<RwLock<Cfg>>,
     db_conn: &mut DatabaseConnection,
+    storage: &dyn StorageBackend,
 ) -> Result<(), FileStorageError> {
     // Query the file first
     let del_time = chrono::Utc::now() - shared_cfg.read().main_cfg.files_save_time;
     let cond = files::Column::Date.lt(del_time.timestamp());
-    let files = Files::find().filter(cond.clone()).all(db_conn).await?;
+    let files = Files::find().filter(cond).all(db_conn).await?;
+    let mut deleted = 0;
     for i in files {
-        match remove_file(i.path).await {
-            Ok(_) => {}
+        match remove_file_record(&i, db_conn, storage).await {
+            Ok(_) => deleted += 1,
             Err(e) => {
                 tracing::error!("delete file error: {}", e);
             }
         }
     }
-    let res = files::Entity::delete_many()
-        .filter(cond)
-        .exec(db_conn)
-        .await?;
-    tracing::info!("delete {} files", res.rows_affected);
+    tracing::info!("delete {} files", deleted);
     Ok(())
 }
 
 /// auto clean files that are out-of-dated
 #[tracing::instrument]
-pub async fn auto_clean_files(shared_cfg: Arc<RwLock<Cfg>>, mut connection: DatabaseConnection) {
-    match clean_files(shared_cfg, &mut connection).await {
+pub async fn auto_clean_files(
+    shared_cfg: Arc<RwLock<Cfg>>,
+    mut connection: DatabaseConnection,
+    storage: Arc<dyn StorageBackend>,
+) {
+    match clean_files(shared_cfg, &mut connection, storage.as_ref()).await {
         Ok(_) => {}
         Err(e) => {
             tracing::error!("clean files error:{e}");
@@ -91,15 +119,20 @@ pub enum FileStorageError {
     Db(#[from] sea_orm::DbErr),
     #[error("Internal IO error")]
     InternalIO(#[from] std::io::Error),
+    #[error("storage error:{0:?}")]
+    Storage(#[from] StorageError),
+    #[error("file blob error:{0:?}")]
+    Blob(#[from] super::file_blobs::FileBlobError),
     #[error("Not found")]
     NotFound,
 }
 
-/// Delete a file immediately - no reference counting needed
-#[instrument(skip(db_conn))]
+/// Delete a file immediately, its content is deleted as well unless another file shares it
+#[instrument(skip(db_conn, storage))]
 pub async fn delete_file(
     key: impl Into<String> + std::fmt::Debug,
-    db_conn: &impl ConnectionTrait,
+    db_conn: &(impl ConnectionTrait + TransactionTrait),
+    storage: &dyn StorageBackend,
 ) -> Result<(), FileStorageError> {
     let key = key.into();
 
@@ -109,16 +142,31 @@ pub async fn delete_file(
         None => return Err(FileStorageError::NotFound),
     };
 
-    // Delete file from database
-    Files::delete_by_id(&key).exec(db_conn).await?;
-
-    // Delete file from filesystem
-    remove_file(&file.path).await?;
+    // Delete file from database, and then from the storage
+    remove_file_record(&file, db_conn, storage).await?;
 
     Ok(())
 }
 
-/// Generate hierarchical storage path for better filesystem performance
+/// The files limit of the user, the one set by the admins or the default one of the server
+pub fn user_files_limit(user: &user::Model, default_limit: Size) -> Size {
+    user.files_limit.map_or(default_limit, Size::from_bytes)
+}
+
+/// Query the files limit of the user, the default one is returned if the user doesn't exist
+pub async fn get_user_files_limit(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+    default_limit: Size,
+) -> Result<Size, sea_orm::DbErr> {
+    Ok(User::find_by_id(user_id)
+        .one(db_conn)
+        .await?
+        .map_or(default_limit, |user| user_files_limit(&user, default_limit)))
+}
+
+/// Generate hierarchical storage path for better filesystem performance, the temporary files of
+/// uploads are kept there
 pub fn generate_hierarchical_path(
     base_path: &std::path::Path,
     user_id: ID,
@@ -138,16 +186,25 @@ pub fn generate_hierarchical_path(
 #[instrument(skip(db_conn))]
 pub async fn cleanup_orphaned_files(
     db_conn: &impl ConnectionTrait,
-    files_storage_path: &std::path::Path,
+    storage: &LocalStorage,
 ) -> Result<usize, FileStorageError> {
     let db_files = Files::find().all(db_conn).await?;
-    let db_paths: std::collections::HashSet<String> =
-        db_files.iter().map(|f| f.path.clone()).collect();
+    let db_paths: std::collections::HashSet<String> = db_files
+        .iter()
+        .flat_map(|f| {
+            std::iter::once(f.path.clone()).chain(
+                THUMBNAIL_SIDES
+                    .into_iter()
+                    .map(|side| thumbnail_object(&f.path, side)),
+            )
+        })
+        .map(|path| storage.resolve(&path).to_string_lossy().to_string())
+        .collect();
 
     let mut orphaned_count = 0;
 
     // Recursively scan files_storage_path
-    let mut entries = tokio::fs::read_dir(files_storage_path).await?;
+    let mut entries = tokio::fs::read_dir(storage.root()).await?;
     while let Some(entry) = entries.next_entry().await? {
         let path = entry.path();
         if path.is_file() {
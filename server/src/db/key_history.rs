@@ -0,0 +1,70 @@
+//! The public keys the users have had, the last one being the key they use now, and the identity
+//! keys their devices have had
+
+use base::constants::ID;
+use chrono::Utc;
+use entities::{prelude::*, user_key_history};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+/// Record that the user has started using the public key
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn record_public_key(
+    user_id: ID,
+    public_key: Vec<u8>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<user_key_history::Model, sea_orm::DbErr> {
+    user_key_history::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id.into()),
+        public_key: ActiveValue::Set(public_key),
+        created_time: ActiveValue::Set(Utc::now().into()),
+        device_id: ActiveValue::Set(None),
+    }
+    .insert(db_conn)
+    .await
+}
+
+/// Record that the device of the user has started using the identity key
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn record_device_key(
+    user_id: ID,
+    sid: &str,
+    identity_key: Vec<u8>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<user_key_history::Model, sea_orm::DbErr> {
+    user_key_history::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id.into()),
+        public_key: ActiveValue::Set(identity_key),
+        created_time: ActiveValue::Set(Utc::now().into()),
+        device_id: ActiveValue::Set(Some(sid.to_string())),
+    }
+    .insert(db_conn)
+    .await
+}
+
+/// Get the record of the last key the user or one of its devices has started using
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_last_key_change(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<user_key_history::Model>, sea_orm::DbErr> {
+    UserKeyHistory::find()
+        .filter(user_key_history::Column::UserId.eq(user_id))
+        .order_by_desc(user_key_history::Column::CreatedTime)
+        .order_by_desc(user_key_history::Column::Id)
+        .one(db_conn)
+        .await
+}
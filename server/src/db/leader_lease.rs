@@ -0,0 +1,75 @@
+//! The lease of the instance running the background jobs of the cluster
+//!
+//! The times are those of the database, so the clocks of the instances don't need to agree.
+
+use std::time::Duration;
+
+use entities::{leader_lease, prelude::*};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DbErr, EntityTrait, FromQueryResult,
+    QueryFilter, Statement,
+};
+
+const LEADER_LEASE_ID: i32 = 1;
+
+/// Take the lease if it is free or has expired, or renew it if the node holds it already.
+///
+/// Returns the lease if the node holds it afterward.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn try_acquire_lease(
+    node_id: &str,
+    lease: Duration,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<leader_lease::Model>, DbErr> {
+    leader_lease::Model::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        r#"INSERT INTO leader_lease (id, node_id, acquired_time, expire_time)
+VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+ON CONFLICT (id) DO UPDATE SET
+    node_id = excluded.node_id,
+    acquired_time = CASE WHEN leader_lease.node_id = excluded.node_id
+        THEN leader_lease.acquired_time ELSE excluded.acquired_time END,
+    expire_time = excluded.expire_time
+WHERE leader_lease.node_id = excluded.node_id OR leader_lease.expire_time < now()
+RETURNING *"#,
+        [
+            LEADER_LEASE_ID.into(),
+            node_id.into(),
+            lease.as_secs_f64().into(),
+        ],
+    ))
+    .one(db_conn)
+    .await
+}
+
+/// Give the lease up so that another node takes it over without waiting for it to expire
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn release_lease(node_id: &str, db_conn: &impl ConnectionTrait) -> Result<(), DbErr> {
+    LeaderLease::delete_many()
+        .filter(leader_lease::Column::Id.eq(LEADER_LEASE_ID))
+        .filter(leader_lease::Column::NodeId.eq(node_id))
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+/// Get the lease of the current leader, none if no node holds an unexpired one
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_leader(
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<leader_lease::Model>, DbErr> {
+    LeaderLease::find_by_id(LEADER_LEASE_ID)
+        .filter(Expr::col(leader_lease::Column::ExpireTime).gt(Expr::current_timestamp()))
+        .one(db_conn)
+        .await
+}
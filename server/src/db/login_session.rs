@@ -0,0 +1,150 @@
+//! Logins stored in redis.
+//!
+//! Every login gets a session id which is put into the access tokens issued for it, together
+//! with a single-use refresh token. Revoking a login marks its session id as revoked for as long
+//! as an access token of it may still be valid, and drops the login so that its refresh token
+//! stops working.
+
+use std::time::Duration;
+
+use base::constants::ID;
+use redis::AsyncCommands;
+
+use crate::db::redis_mappings::{
+    map_login_session_to_redis, map_refresh_token_to_redis, map_revoked_session_to_redis,
+    map_used_refresh_token_to_redis, map_user_login_sessions_to_redis,
+};
+
+/// Store a new login of the user, or extend it when its refresh token is rotated.
+pub async fn save_login_session(
+    user_id: ID,
+    sid: &str,
+    refresh_token: &str,
+    refresh_expire: Duration,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<(), redis::RedisError> {
+    let expire = refresh_expire.as_secs();
+    let sessions_key = map_user_login_sessions_to_redis(user_id);
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(map_login_session_to_redis(sid), u64::from(user_id), expire)
+        .set_ex(map_refresh_token_to_redis(refresh_token), sid, expire)
+        .sadd(&sessions_key, sid)
+        .expire(&sessions_key, expire as i64)
+        .query_async(redis_conn)
+        .await?;
+    Ok(())
+}
+
+/// Consume a refresh token, returning the session id it was issued for.
+///
+/// A refresh token is used only once. The consumed token is remembered until it would have
+/// expired, so that a reuse can be told apart from an unknown token by
+/// [`used_refresh_token_session`].
+pub async fn take_refresh_token(
+    refresh_token: &str,
+    refresh_expire: Duration,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<Option<String>, redis::RedisError> {
+    let sid: Option<String> = redis_conn
+        .get_del(map_refresh_token_to_redis(refresh_token))
+        .await?;
+    if let Some(sid) = &sid {
+        let _: () = redis_conn
+            .set_ex(
+                map_used_refresh_token_to_redis(refresh_token),
+                sid,
+                refresh_expire.as_secs(),
+            )
+            .await?;
+    }
+    Ok(sid)
+}
+
+/// Get the session id of a refresh token which has been used already
+pub async fn used_refresh_token_session(
+    refresh_token: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<Option<String>, redis::RedisError> {
+    redis_conn
+        .get(map_used_refresh_token_to_redis(refresh_token))
+        .await
+}
+
+/// Get the owner of a login, `None` if the login has been revoked or has expired
+pub async fn login_session_owner(
+    sid: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<Option<ID>, redis::RedisError> {
+    let owner: Option<u64> = redis_conn.get(map_login_session_to_redis(sid)).await?;
+    Ok(owner.map(ID))
+}
+
+/// Get the session ids of the logins of the user
+pub async fn user_login_sessions(
+    user_id: ID,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<Vec<String>, redis::RedisError> {
+    redis_conn
+        .smembers(map_user_login_sessions_to_redis(user_id))
+        .await
+}
+
+/// Check whether the access tokens of a login are revoked
+pub async fn is_login_session_revoked(
+    sid: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<bool, redis::RedisError> {
+    redis_conn.exists(map_revoked_session_to_redis(sid)).await
+}
+
+/// Revoke a login of the user.
+///
+/// `access_expire` is the lifetime of the access tokens, after which the revocation mark is not
+/// needed anymore.
+pub async fn revoke_login_session(
+    user_id: ID,
+    sid: &str,
+    access_expire: Duration,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<(), redis::RedisError> {
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(
+            map_revoked_session_to_redis(sid),
+            1,
+            access_expire.as_secs(),
+        )
+        .del(map_login_session_to_redis(sid))
+        .srem(map_user_login_sessions_to_redis(user_id), sid)
+        .query_async(redis_conn)
+        .await?;
+    Ok(())
+}
+
+/// Revoke all the logins of the user
+pub async fn revoke_all_login_sessions(
+    user_id: ID,
+    access_expire: Duration,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<(), redis::RedisError> {
+    let sessions_key = map_user_login_sessions_to_redis(user_id);
+    let sids: Vec<String> = redis_conn.smembers(&sessions_key).await?;
+    if sids.is_empty() {
+        return Ok(());
+    }
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for sid in &sids {
+        pipe.set_ex(
+            map_revoked_session_to_redis(sid),
+            1,
+            access_expire.as_secs(),
+        )
+        .del(map_login_session_to_redis(sid));
+    }
+    // a login created meanwhile is kept
+    pipe.srem(&sessions_key, &sids);
+    let _: () = pipe.query_async(redis_conn).await?;
+    Ok(())
+}
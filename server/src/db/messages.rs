@@ -1,13 +1,24 @@
-use entities::{message_records, prelude::MessageRecords};
+use std::collections::HashMap;
+
+use entities::{
+    message_reactions, message_records, message_revisions, pinned_messages,
+    prelude::{
+        MessageReactions, MessageRecords, MessageRevisions, PinnedMessages, ScheduledMessages,
+    },
+    scheduled_messages, session_relation,
+};
 use migration::predefined::PredefinedPermissions;
 use pb::time::TimeStamp;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseBackend, EntityTrait, ModelTrait,
-    Paginator, PaginatorTrait, Statement,
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseBackend,
+    EntityTrait, FromQueryResult, IntoActiveModel, ModelTrait, Order, Paginator, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, Statement,
+    sea_query::{Expr, LockBehavior, LockType, OnConflict, Query},
 };
 
 use super::session::if_permission_exist;
 use base::constants::{ID, SessionID};
+use pb::service::ourchat::msg_delivery::reaction::v1::ReactionCount;
 use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
 
 #[derive(Debug, thiserror::Error)]
@@ -105,6 +116,8 @@ pub async fn del_msg(
 /// Insert a new message record into the database.
 ///
 /// The message is specified by `user_id`, `session_id`, `msg`, and `is_encrypted`.
+/// If `msg` is a reply, the thread information is also stored in its own columns, so that
+/// the thread can be queried without looking into `msg_data`.
 /// The return value is the `MsgID` of the inserted record.
 ///
 /// Returns `MsgError::DbError` if a database error occurs.
@@ -116,14 +129,530 @@ pub async fn insert_msg_record(
     db_conn: &impl ConnectionTrait,
     is_all_user: bool,
 ) -> Result<message_records::Model, MsgError> {
-    let msg = message_records::ActiveModel {
+    let msg = msg_record(sender_id, session_id, msg, is_encrypted, is_all_user)?;
+    let msg = msg.insert(db_conn).await?;
+    Ok(msg)
+}
+
+/// Insert a message record which is deleted once `ttl` has passed, or kept forever if it is
+/// `None`. The expire time is stored by the same insert, so the message never exists without it.
+pub async fn insert_expiring_msg_record(
+    sender_id: Option<ID>,
+    session_id: Option<SessionID>,
+    msg: RespondEventType,
+    is_encrypted: bool,
+    ttl: Option<chrono::TimeDelta>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<message_records::Model, MsgError> {
+    let mut msg = msg_record(sender_id, session_id, msg, is_encrypted, false)?;
+    if let Some(ttl) = ttl {
+        let time: TimeStamp = chrono::Utc::now().into();
+        msg.time = ActiveValue::Set(time);
+        msg.expire_time = ActiveValue::Set(time.checked_add_signed(ttl));
+    }
+    let msg = msg.insert(db_conn).await?;
+    Ok(msg)
+}
+
+/// Insert a message record about another message, which is deleted together with it at
+/// `expire_time`, or kept forever if it is `None`.
+pub async fn insert_msg_record_with_expire_time(
+    sender_id: Option<ID>,
+    session_id: Option<SessionID>,
+    msg: RespondEventType,
+    is_encrypted: bool,
+    expire_time: Option<TimeStamp>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<message_records::Model, MsgError> {
+    let mut msg = msg_record(sender_id, session_id, msg, is_encrypted, false)?;
+    msg.expire_time = ActiveValue::Set(expire_time);
+    let msg = msg.insert(db_conn).await?;
+    Ok(msg)
+}
+
+fn msg_record(
+    sender_id: Option<ID>,
+    session_id: Option<SessionID>,
+    msg: RespondEventType,
+    is_encrypted: bool,
+    is_all_user: bool,
+) -> Result<message_records::ActiveModel, MsgError> {
+    let (reply_to_msg_id, thread_root_id) = match &msg {
+        RespondEventType::Msg(content) => (content.reply_to_msg_id, content.thread_root_id),
+        _ => (None, None),
+    };
+    Ok(message_records::ActiveModel {
         msg_data: ActiveValue::Set(serde_json::to_value(msg)?),
         sender_id: ActiveValue::Set(sender_id.map(i64::from)),
         session_id: ActiveValue::Set(session_id.map(i64::from)),
         is_encrypted: ActiveValue::Set(is_encrypted),
         is_all_user: ActiveValue::Set(is_all_user),
+        reply_to_msg_id: ActiveValue::Set(reply_to_msg_id.map(|id| id as i64)),
+        thread_root_id: ActiveValue::Set(thread_root_id.map(|id| id as i64)),
         ..Default::default()
-    };
-    let msg = msg.insert(db_conn).await?;
+    })
+}
+
+/// Replace the content of a message record with `new_content`, keeping the previous
+/// `msg_data` in `message_revisions`.
+///
+/// The caller is responsible for checking that the editor owns the message. It should be
+/// called inside a transaction so that the revision and the new content are stored together.
+///
+/// Returns the updated record and its revision number, which starts at 1 for the first edit.
+pub async fn edit_msg(
+    msg: message_records::Model,
+    new_content: RespondEventType,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(message_records::Model, u64), MsgError> {
+    let revision = MessageRevisions::find()
+        .filter(message_revisions::Column::MsgId.eq(msg.msg_id))
+        .count(db_conn)
+        .await?
+        + 1;
+    message_revisions::ActiveModel {
+        msg_id: ActiveValue::Set(msg.msg_id),
+        msg_data: ActiveValue::Set(msg.msg_data.clone()),
+        revision: ActiveValue::Set(revision as i64),
+        ..Default::default()
+    }
+    .insert(db_conn)
+    .await?;
+    let mut msg = msg.into_active_model();
+    msg.msg_data = ActiveValue::Set(serde_json::to_value(new_content)?);
+    let msg = msg.update(db_conn).await?;
+    Ok((msg, revision))
+}
+
+/// Get a message of the specified session by its id.
+///
+/// Returns `None` if the message does not exist or belongs to another session.
+pub async fn get_session_msg(
+    msg_id: u64,
+    session_id: SessionID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<message_records::Model>, MsgError> {
+    let msg = MessageRecords::find_by_id(msg_id as i64)
+        .filter(message_records::Column::SessionId.eq(session_id))
+        .one(db_conn)
+        .await?;
     Ok(msg)
 }
+
+/// Get at most `limit` replies of the thread started by `thread_root_id`, whose id is greater
+/// than `after_msg_id`, in ascending order of their ids.
+pub async fn get_thread_replies(
+    session_id: SessionID,
+    thread_root_id: u64,
+    after_msg_id: u64,
+    limit: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<message_records::Model>, MsgError> {
+    let msgs = MessageRecords::find()
+        .filter(message_records::Column::SessionId.eq(session_id))
+        .filter(message_records::Column::ThreadRootId.eq(thread_root_id as i64))
+        .filter(message_records::Column::MsgId.gt(after_msg_id as i64))
+        .order_by_asc(message_records::Column::MsgId)
+        .limit(limit)
+        .all(db_conn)
+        .await?;
+    Ok(msgs)
+}
+
+/// Add a reaction of `user_id` to the message.
+///
+/// Returns `false` if the user has already reacted to the message with the same emoji.
+pub async fn add_reaction(
+    msg_id: u64,
+    user_id: ID,
+    emoji: String,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let reaction = message_reactions::ActiveModel {
+        msg_id: ActiveValue::Set(msg_id as i64),
+        user_id: ActiveValue::Set(user_id.into()),
+        emoji: ActiveValue::Set(emoji),
+        ..Default::default()
+    };
+    let inserted = MessageReactions::insert(reaction)
+        .on_conflict(
+            OnConflict::columns([
+                message_reactions::Column::MsgId,
+                message_reactions::Column::UserId,
+                message_reactions::Column::Emoji,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(db_conn)
+        .await?;
+    Ok(inserted != 0)
+}
+
+/// Remove a reaction of `user_id` from the message.
+///
+/// Returns `false` if there is no such reaction.
+pub async fn remove_reaction(
+    msg_id: u64,
+    user_id: ID,
+    emoji: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let res = MessageReactions::delete_many()
+        .filter(message_reactions::Column::MsgId.eq(msg_id as i64))
+        .filter(message_reactions::Column::UserId.eq(user_id))
+        .filter(message_reactions::Column::Emoji.eq(emoji))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected != 0)
+}
+
+/// Count the reactions of the messages, grouped by emoji.
+///
+/// The emojis of a message are ordered by the time they were first used. Messages without
+/// reactions are absent from the returned map.
+pub async fn get_reaction_counts(
+    msg_ids: impl IntoIterator<Item = i64>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<HashMap<i64, Vec<ReactionCount>>, MsgError> {
+    let rows: Vec<(i64, String, i64)> = MessageReactions::find()
+        .select_only()
+        .column(message_reactions::Column::MsgId)
+        .column(message_reactions::Column::Emoji)
+        .column_as(message_reactions::Column::UserId.count(), "count")
+        .filter(message_reactions::Column::MsgId.is_in(msg_ids))
+        .group_by(message_reactions::Column::MsgId)
+        .group_by(message_reactions::Column::Emoji)
+        .order_by_asc(message_reactions::Column::CreatedTime.min())
+        .into_tuple()
+        .all(db_conn)
+        .await?;
+    let mut counts: HashMap<i64, Vec<ReactionCount>> = HashMap::new();
+    for (msg_id, emoji, count) in rows {
+        counts.entry(msg_id).or_default().push(ReactionCount {
+            emoji,
+            count: count as u64,
+        });
+    }
+    Ok(counts)
+}
+
+/// Where [`get_session_history`] starts reading the history of a session.
+pub enum HistoryCursor {
+    /// Start from the latest message when reading backward, or the earliest one when reading
+    /// forward
+    Edge,
+    /// Start from a message of the session, excluding itself
+    Msg(message_records::Model),
+    /// Start from a point in time
+    Time(TimeStamp),
+}
+
+/// Get at most `limit` messages of a session next to `cursor`, ordered by `(time, msg_id)`.
+///
+/// When `before` is true, the messages older than the cursor are returned in descending order,
+/// otherwise the newer ones are returned in ascending order.
+///
+/// The caller is responsible for checking whether the user is a member of the session.
+pub async fn get_session_history(
+    session_id: SessionID,
+    cursor: HistoryCursor,
+    before: bool,
+    limit: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<message_records::Model>, MsgError> {
+    use message_records::Column;
+    let mut query = MessageRecords::find().filter(Column::SessionId.eq(session_id));
+    query = match (cursor, before) {
+        (HistoryCursor::Edge, _) => query,
+        (HistoryCursor::Msg(msg), true) => query.filter(
+            Condition::any().add(Column::Time.lt(msg.time)).add(
+                Condition::all()
+                    .add(Column::Time.eq(msg.time))
+                    .add(Column::MsgId.lt(msg.msg_id)),
+            ),
+        ),
+        (HistoryCursor::Msg(msg), false) => query.filter(
+            Condition::any().add(Column::Time.gt(msg.time)).add(
+                Condition::all()
+                    .add(Column::Time.eq(msg.time))
+                    .add(Column::MsgId.gt(msg.msg_id)),
+            ),
+        ),
+        (HistoryCursor::Time(time), true) => query.filter(Column::Time.lt(time)),
+        (HistoryCursor::Time(time), false) => query.filter(Column::Time.gt(time)),
+    };
+    let order = if before { Order::Desc } else { Order::Asc };
+    let msgs = query
+        .order_by(Column::Time, order.clone())
+        .order_by(Column::MsgId, order)
+        .limit(limit)
+        .all(db_conn)
+        .await?;
+    Ok(msgs)
+}
+
+/// Filters of [`search_msgs`], `None` means no restriction.
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    pub session_id: Option<SessionID>,
+    pub sender_id: Option<ID>,
+    pub start_time: Option<TimeStamp>,
+    pub end_time: Option<TimeStamp>,
+    pub before_msg_id: Option<u64>,
+}
+
+/// Search at most `limit` messages containing all words of `query` in descending order of their
+/// ids.
+///
+/// Only the messages of the sessions `user_id` is in are searched. Encrypted messages are never
+/// returned because their `search_vector` is always `NULL`.
+pub async fn search_msgs(
+    user_id: ID,
+    query: &str,
+    filter: SearchFilter,
+    limit: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<message_records::Model>, MsgError> {
+    use message_records::Column;
+    let joined_sessions = Query::select()
+        .column(session_relation::Column::SessionId)
+        .from(session_relation::Entity)
+        .and_where(session_relation::Column::UserId.eq(user_id))
+        .to_owned();
+    let mut select = MessageRecords::find()
+        .filter(Column::IsEncrypted.eq(false))
+        .filter(Expr::cust_with_values(
+            "search_vector @@ plainto_tsquery('simple', $1)",
+            [query],
+        ))
+        .filter(Column::SessionId.in_subquery(joined_sessions));
+    if let Some(session_id) = filter.session_id {
+        select = select.filter(Column::SessionId.eq(session_id));
+    }
+    if let Some(sender_id) = filter.sender_id {
+        select = select.filter(Column::SenderId.eq(sender_id));
+    }
+    if let Some(start_time) = filter.start_time {
+        select = select.filter(Column::Time.gte(start_time));
+    }
+    if let Some(end_time) = filter.end_time {
+        select = select.filter(Column::Time.lt(end_time));
+    }
+    if let Some(before_msg_id) = filter.before_msg_id {
+        select = select.filter(Column::MsgId.lt(before_msg_id as i64));
+    }
+    let msgs = select
+        .order_by_desc(Column::MsgId)
+        .limit(limit)
+        .all(db_conn)
+        .await?;
+    Ok(msgs)
+}
+
+/// Pin a message of its session.
+///
+/// Returns `false` if the message has already been pinned.
+pub async fn pin_msg(
+    msg: &message_records::Model,
+    pinned_by: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let Some(session_id) = msg.session_id else {
+        return Err(MsgError::NotFound);
+    };
+    let pinned = pinned_messages::ActiveModel {
+        msg_id: ActiveValue::Set(msg.msg_id),
+        session_id: ActiveValue::Set(session_id),
+        pinned_by: ActiveValue::Set(Some(pinned_by.into())),
+        ..Default::default()
+    };
+    let inserted = PinnedMessages::insert(pinned)
+        .on_conflict(
+            OnConflict::column(pinned_messages::Column::MsgId)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db_conn)
+        .await?;
+    Ok(inserted != 0)
+}
+
+/// Unpin a message of the session.
+///
+/// Returns `false` if the message is not pinned.
+pub async fn unpin_msg(
+    msg_id: u64,
+    session_id: SessionID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let res = PinnedMessages::delete_many()
+        .filter(pinned_messages::Column::MsgId.eq(msg_id as i64))
+        .filter(pinned_messages::Column::SessionId.eq(session_id))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected != 0)
+}
+
+/// Get the pinned messages of the session in descending order of the pinned time.
+pub async fn get_pinned_msgs(
+    session_id: SessionID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<(pinned_messages::Model, message_records::Model)>, MsgError> {
+    let pinned = PinnedMessages::find()
+        .filter(pinned_messages::Column::SessionId.eq(session_id))
+        .find_also_related(MessageRecords)
+        .order_by_desc(pinned_messages::Column::PinnedTime)
+        .all(db_conn)
+        .await?;
+    Ok(pinned
+        .into_iter()
+        .filter_map(|(pin, msg)| msg.map(|msg| (pin, msg)))
+        .collect())
+}
+
+/// Store a message which will be delivered at `send_at`.
+///
+/// `ttl` is the time to live in seconds requested by the sender, which is applied on delivery.
+pub async fn insert_scheduled_msg(
+    sender_id: ID,
+    session_id: SessionID,
+    msg: RespondEventType,
+    is_encrypted: bool,
+    send_at: TimeStamp,
+    ttl: Option<u64>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<scheduled_messages::Model, MsgError> {
+    let scheduled = scheduled_messages::ActiveModel {
+        msg_data: ActiveValue::Set(serde_json::to_value(msg)?),
+        sender_id: ActiveValue::Set(sender_id.into()),
+        session_id: ActiveValue::Set(session_id.into()),
+        is_encrypted: ActiveValue::Set(is_encrypted),
+        send_at: ActiveValue::Set(send_at),
+        ttl: ActiveValue::Set(ttl.map(|ttl| ttl.min(i64::MAX as u64) as i64)),
+        ..Default::default()
+    };
+    Ok(scheduled.insert(db_conn).await?)
+}
+
+/// Get the pending messages scheduled by the user in ascending order of the delivery time.
+pub async fn get_scheduled_msgs(
+    sender_id: ID,
+    session_id: Option<SessionID>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<scheduled_messages::Model>, MsgError> {
+    let mut select =
+        ScheduledMessages::find().filter(scheduled_messages::Column::SenderId.eq(sender_id));
+    if let Some(session_id) = session_id {
+        select = select.filter(scheduled_messages::Column::SessionId.eq(session_id));
+    }
+    Ok(select
+        .order_by_asc(scheduled_messages::Column::SendAt)
+        .order_by_asc(scheduled_messages::Column::Id)
+        .all(db_conn)
+        .await?)
+}
+
+/// Delete a pending message scheduled by the user.
+///
+/// Returns `false` if there is no such pending message, including the case that it has been
+/// delivered already.
+pub async fn cancel_scheduled_msg(
+    scheduled_msg_id: u64,
+    sender_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let res = ScheduledMessages::delete_many()
+        .filter(scheduled_messages::Column::Id.eq(scheduled_msg_id as i64))
+        .filter(scheduled_messages::Column::SenderId.eq(sender_id))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected != 0)
+}
+
+/// Lock at most `limit` pending messages whose delivery time has come.
+///
+/// The rows locked by another transaction are skipped, so that several instances can deliver
+/// the messages concurrently without delivering one twice. It must be called inside a
+/// transaction and the rows should be deleted before committing.
+pub async fn lock_due_scheduled_msgs(
+    limit: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<scheduled_messages::Model>, MsgError> {
+    Ok(ScheduledMessages::find()
+        .filter(Expr::col(scheduled_messages::Column::SendAt).lte(Expr::current_timestamp()))
+        .order_by_asc(scheduled_messages::Column::SendAt)
+        .order_by_asc(scheduled_messages::Column::Id)
+        .limit(limit)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .all(db_conn)
+        .await?)
+}
+
+/// Delete the pending messages by their ids.
+pub async fn delete_scheduled_msgs(
+    ids: impl IntoIterator<Item = i64>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), MsgError> {
+    ScheduledMessages::delete_many()
+        .filter(scheduled_messages::Column::Id.is_in(ids))
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+/// Work out how long a message is kept.
+///
+/// `session_ttl` is the `message_ttl` of the session and `msg_ttl` is the one requested by the
+/// sender, both in seconds. The shorter one wins when both are set, and `None` means the message
+/// is kept forever.
+pub fn msg_ttl(session_ttl: Option<i64>, msg_ttl: Option<u64>) -> Option<chrono::TimeDelta> {
+    let session_ttl = session_ttl.map(|ttl| ttl.max(0) as u64);
+    let ttl = match (session_ttl, msg_ttl) {
+        (Some(session_ttl), Some(msg_ttl)) => session_ttl.min(msg_ttl),
+        (ttl, None) | (None, ttl) => ttl?,
+    };
+    chrono::Duration::try_seconds(i64::try_from(ttl).ok()?)
+}
+
+/// Delete at most `limit` messages whose time to live is over and return them.
+///
+/// The rows being deleted by another instance are skipped, so every expired message is
+/// returned exactly once.
+pub async fn delete_expired_msgs(
+    limit: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<message_records::Model>, MsgError> {
+    Ok(
+        message_records::Model::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            r#"DELETE FROM message_records WHERE msg_id IN (
+SELECT msg_id FROM message_records WHERE expire_time <= CURRENT_TIMESTAMP
+ORDER BY expire_time LIMIT $1 FOR UPDATE SKIP LOCKED)
+RETURNING *"#,
+            [(limit as i64).into()],
+        ))
+        .all(db_conn)
+        .await?,
+    )
+}
+
+/// Check whether a file is still attached to a stored or pending message, or to the edit of one.
+pub async fn is_file_referenced(
+    key: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, MsgError> {
+    let res = db_conn
+        .query_one(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            r#"SELECT EXISTS(SELECT 1 FROM message_records WHERE msg_data->'Msg'->'involved_files' ? $1
+OR msg_data->'Edit'->'involved_files' ? $1)
+OR EXISTS(SELECT 1 FROM scheduled_messages WHERE msg_data->'Msg'->'involved_files' ? $1) AS referenced"#,
+            [key.into()],
+        ))
+        .await?;
+    Ok(match res {
+        Some(row) => row.try_get("", "referenced")?,
+        None => false,
+    })
+}
@@ -7,7 +7,7 @@ use pb::{
 };
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, Order, QueryFilter, QueryOrder,
-    Set,
+    QueryTrait, Set,
 };
 use std::collections::HashMap;
 use std::time::Duration;
@@ -22,6 +22,7 @@ use entities::metrics_history as MetricsHistoryEntity;
 /// * `start_time` - Start timestamp
 /// * `end_time` - End timestamp
 /// * `interval` - Aggregation interval (0 = no aggregation)
+/// * `node_id` - Only the snapshots of this node (None = all nodes)
 ///
 /// # Errors
 ///
@@ -41,6 +42,7 @@ pub async fn get_historical_metrics(
     start_time: Option<TimeStampUtc>,
     end_time: Option<TimeStampUtc>,
     interval: Option<Duration>,
+    node_id: Option<&str>,
 ) -> Result<Vec<MetricDataPoint>, sea_orm::DbErr> {
     use entities::metrics_history;
 
@@ -53,6 +55,9 @@ pub async fn get_historical_metrics(
     let records = metrics_history::Entity::find()
         .filter(metrics_history::Column::Timestamp.gte(start_time_fixed))
         .filter(metrics_history::Column::Timestamp.lte(end_time_fixed))
+        .apply_if(node_id, |query, node_id| {
+            query.filter(metrics_history::Column::NodeId.eq(node_id))
+        })
         .order_by(metrics_history::Column::Timestamp, Order::Asc)
         .all(db)
         .await?;
@@ -113,6 +118,7 @@ fn aggregate_by_interval(
                 nanos: 0,
             }),
             metrics: Some(aggregated),
+            node_id: None,
         });
     }
 
@@ -401,6 +407,8 @@ where
 
 /// Save metrics snapshot to database
 ///
+/// Every node saves the snapshot of its own process, tagged with `node_id`
+///
 /// # Errors
 ///
 /// Returns database errors
@@ -411,15 +419,13 @@ where
 /// Run the migration and regenerate entities first.
 pub async fn save_metrics_snapshot(
     db: &impl ConnectionTrait,
+    id: i64,
+    node_id: &str,
     metrics: &MonitoringMetrics,
     timestamp: TimeStampUtc,
 ) -> Result<(), sea_orm::DbErr> {
     use entities::metrics_history;
 
-    // Generate a unique ID using timestamp (which should be unique for each snapshot)
-    // If there could be multiple snapshots per second, we could use timestamp + random
-    let id = timestamp.timestamp();
-
     // Convert i64 timestamp to DateTime<FixedOffset> for database storage
     let timestamp_dt = timestamp.into();
 
@@ -430,6 +436,7 @@ pub async fn save_metrics_snapshot(
 
     let snapshot = metrics_history::ActiveModel {
         id: Set(id),
+        node_id: Set(Some(node_id.to_owned())),
         timestamp: Set(timestamp_dt),
         active_connections: Set(metrics.active_connections),
         total_users: Set(metrics.total_users),
@@ -0,0 +1,101 @@
+//! External identities the users log in with through the OAuth routes of the http server
+
+use base::constants::ID;
+use chrono::Utc;
+use entities::{oauth_identities, prelude::*};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+/// Find the identity `subject` of the provider.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn find_oauth_identity(
+    provider: &str,
+    subject: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<oauth_identities::Model>, sea_orm::DbErr> {
+    OauthIdentities::find_by_id((provider.to_string(), subject.to_string()))
+        .one(db_conn)
+        .await
+}
+
+/// Link the identity to the user.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database, including the identity or the provider being
+/// linked already.
+pub async fn link_oauth_identity(
+    user_id: ID,
+    provider: &str,
+    subject: &str,
+    email: Option<String>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<oauth_identities::Model, sea_orm::DbErr> {
+    oauth_identities::ActiveModel {
+        provider: ActiveValue::Set(provider.to_string()),
+        subject: ActiveValue::Set(subject.to_string()),
+        user_id: ActiveValue::Set(user_id.into()),
+        email: ActiveValue::Set(email),
+        created_time: ActiveValue::Set(Utc::now().into()),
+    }
+    .insert(db_conn)
+    .await
+}
+
+/// Update the email the provider reports for the identity.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn update_oauth_identity_email(
+    identity: oauth_identities::Model,
+    email: Option<String>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    if identity.email == email {
+        return Ok(());
+    }
+    let mut identity: oauth_identities::ActiveModel = identity.into();
+    identity.email = ActiveValue::Set(email);
+    identity.update(db_conn).await?;
+    Ok(())
+}
+
+/// Get the identities linked to the user, the earliest linked first.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_user_oauth_identities(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<oauth_identities::Model>, sea_orm::DbErr> {
+    OauthIdentities::find()
+        .filter(oauth_identities::Column::UserId.eq(user_id))
+        .order_by_asc(oauth_identities::Column::CreatedTime)
+        .all(db_conn)
+        .await
+}
+
+/// Unlink the identity of the provider from the user, returning false if none is linked.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn delete_oauth_identity(
+    user_id: ID,
+    provider: &str,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, sea_orm::DbErr> {
+    let res = OauthIdentities::delete_many()
+        .filter(oauth_identities::Column::UserId.eq(user_id))
+        .filter(oauth_identities::Column::Provider.eq(provider))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
@@ -31,6 +31,10 @@ pub fn map_ban_all_to_redis(session: SessionID) -> String {
     redis_key!("ban:{session}:all")
 }
 
+pub fn map_typing_to_redis(session: SessionID, user_id: ID) -> String {
+    redis_key!("typing:{session}:{user_id}")
+}
+
 pub fn map_server_ban_to_redis(user_id: ID) -> String {
     redis_key!("server_ban:{user_id}")
 }
@@ -38,3 +42,59 @@ pub fn map_server_ban_to_redis(user_id: ID) -> String {
 pub fn map_failed_login_to_redis(user_id: ID) -> String {
     redis_key!("failed_login:{user_id}")
 }
+
+pub fn map_login_session_to_redis(sid: &str) -> String {
+    redis_key!("login_session:{sid}")
+}
+
+pub fn map_user_login_sessions_to_redis(user_id: ID) -> String {
+    redis_key!("login_sessions:{user_id}")
+}
+
+pub fn map_revoked_session_to_redis(sid: &str) -> String {
+    redis_key!("revoked_session:{sid}")
+}
+
+pub fn map_refresh_token_to_redis(token: &str) -> String {
+    redis_key!("refresh_token:{token}")
+}
+
+pub fn map_used_refresh_token_to_redis(token: &str) -> String {
+    redis_key!("used_refresh_token:{token}")
+}
+
+pub fn map_totp_challenge_to_redis(challenge: &str) -> String {
+    redis_key!("totp_challenge:{challenge}")
+}
+
+pub fn map_used_totp_code_to_redis(user_id: ID, code: &str) -> String {
+    redis_key!("used_totp_code:{user_id}:{code}")
+}
+
+pub fn map_password_reset_token_to_redis(token: &str) -> String {
+    redis_key!("password_reset:{token}")
+}
+
+pub fn map_user_password_reset_token_to_redis(user_id: ID) -> String {
+    redis_key!("password_reset_user:{user_id}")
+}
+
+pub fn map_password_reset_limit_to_redis(user_id: ID) -> String {
+    redis_key!("password_reset_limit:{user_id}")
+}
+
+pub fn map_oauth_link_ticket_to_redis(provider: &str, ticket: &str) -> String {
+    redis_key!("oauth_link:{provider}:{ticket}")
+}
+
+pub fn map_oauth_link_code_to_redis(provider: &str, code: &str) -> String {
+    redis_key!("oauth_link_code:{provider}:{code}")
+}
+
+pub fn map_oauth_state_to_redis(state: &str) -> String {
+    redis_key!("oauth_state:{state}")
+}
+
+pub fn map_rate_limit_to_redis(key: &str) -> String {
+    redis_key!("rate_limit:{key}")
+}
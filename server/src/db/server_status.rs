@@ -0,0 +1,130 @@
+//! The status of the server, shared by all of its instances
+//!
+//! It is kept in a single row, the instances load it when starting and are told about the changes
+//! through the [`crate::rabbitmq::SERVER_STATUS_FANOUT_EXCHANGE`].
+
+use chrono::Utc;
+use entities::{prelude::*, server_status};
+use pb::time::TimeStampUtc;
+use sea_orm::{ActiveValue, ConnectionTrait, DbErr, EntityTrait, sea_query::OnConflict};
+use serde::{Deserialize, Serialize};
+
+const SERVER_STATUS_ID: i32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatusRecord {
+    pub maintaining: bool,
+    pub reason: String,
+    /// When the maintenance starts, right away if unset
+    pub start_time: Option<TimeStampUtc>,
+    /// When the maintenance ends by itself, never if unset
+    pub end_time: Option<TimeStampUtc>,
+}
+
+impl ServerStatusRecord {
+    /// Under maintenance right away until it is set back to normal
+    pub fn maintaining(reason: impl Into<String>) -> Self {
+        Self {
+            maintaining: true,
+            reason: reason.into(),
+            start_time: None,
+            end_time: None,
+        }
+    }
+
+    /// Whether the server is under maintenance at the time
+    pub fn is_maintaining_at(&self, now: TimeStampUtc) -> bool {
+        self.maintaining
+            && self.start_time.is_none_or(|start| start <= now)
+            && self.end_time.is_none_or(|end| now < end)
+    }
+
+    /// Whether the maintenance is going on or yet to come at the time
+    pub fn is_pending_at(&self, now: TimeStampUtc) -> bool {
+        self.maintaining && self.end_time.is_none_or(|end| now < end)
+    }
+}
+
+impl From<server_status::Model> for ServerStatusRecord {
+    fn from(value: server_status::Model) -> Self {
+        Self {
+            maintaining: value.maintaining,
+            reason: value.reason,
+            start_time: value.start_time.map(|time| time.to_utc()),
+            end_time: value.end_time.map(|time| time.to_utc()),
+        }
+    }
+}
+
+/// Get the status of the server, normal if it has never been set
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn get_server_status(
+    db_conn: &impl ConnectionTrait,
+) -> Result<ServerStatusRecord, DbErr> {
+    Ok(ServerStatus::find_by_id(SERVER_STATUS_ID)
+        .one(db_conn)
+        .await?
+        .map(Into::into)
+        .unwrap_or_default())
+}
+
+/// Set the status of the server
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn save_server_status(
+    status: &ServerStatusRecord,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), DbErr> {
+    let model = server_status::ActiveModel {
+        id: ActiveValue::Set(SERVER_STATUS_ID),
+        maintaining: ActiveValue::Set(status.maintaining),
+        reason: ActiveValue::Set(status.reason.clone()),
+        start_time: ActiveValue::Set(status.start_time.map(Into::into)),
+        end_time: ActiveValue::Set(status.end_time.map(Into::into)),
+        updated_time: ActiveValue::Set(Utc::now().into()),
+    };
+    ServerStatus::insert(model)
+        .on_conflict(
+            OnConflict::column(server_status::Column::Id)
+                .update_columns([
+                    server_status::Column::Maintaining,
+                    server_status::Column::Reason,
+                    server_status::Column::StartTime,
+                    server_status::Column::EndTime,
+                    server_status::Column::UpdatedTime,
+                ])
+                .to_owned(),
+        )
+        .exec(db_conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_maintenance_window() {
+        let now = Utc::now();
+        assert!(!ServerStatusRecord::default().is_maintaining_at(now));
+        assert!(ServerStatusRecord::maintaining("").is_maintaining_at(now));
+
+        let scheduled = ServerStatusRecord {
+            start_time: Some(now + Duration::hours(1)),
+            end_time: Some(now + Duration::hours(2)),
+            ..ServerStatusRecord::maintaining("upgrade")
+        };
+        assert!(!scheduled.is_maintaining_at(now));
+        assert!(scheduled.is_pending_at(now));
+        assert!(scheduled.is_maintaining_at(now + Duration::minutes(90)));
+        assert!(!scheduled.is_maintaining_at(now + Duration::hours(2)));
+        assert!(!scheduled.is_pending_at(now + Duration::hours(2)));
+    }
+}
@@ -6,7 +6,10 @@ use base::{
     types::{PermissionId, RoleId},
 };
 use entities::{role, role_permissions, session, session_relation, user_role_relation};
-use sea_orm::{ActiveValue, DatabaseTransaction, QuerySelect, prelude::*};
+use sea_orm::{
+    ActiveValue, Condition, DatabaseBackend, DatabaseTransaction, FromQueryResult, QuerySelect,
+    Statement, prelude::*,
+};
 use std::time::Duration;
 
 /// Retrieves all session relations associated with the given user ID.
@@ -32,6 +35,29 @@ pub async fn get_all_session_relations(
     Ok(ret)
 }
 
+/// Retrieves the end-to-end encrypted sessions the given user is a member of.
+///
+/// # Arguments
+///
+/// * `user_id` - The ID of the user whose sessions are to be fetched.
+/// * `db_conn` - A reference to the database connection implementing the `ConnectionTrait`.
+///
+/// # Returns
+///
+/// * `Result<Vec<session::Model>, sea_orm::DbErr>` - A vector of `session::Model` objects of the
+///   E2EE sessions of the user, or a `DbErr` if the operation fails.
+pub async fn get_e2ee_sessions(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<session::Model>, DbErr> {
+    session::Entity::find()
+        .inner_join(session_relation::Entity)
+        .filter(session_relation::Column::UserId.eq(user_id))
+        .filter(session::Column::E2eeOn.eq(true))
+        .all(db_conn)
+        .await
+}
+
 /// Retrieves all members of the specified session.
 ///
 /// # Arguments
@@ -178,6 +204,83 @@ pub async fn in_session(
     Ok(ret.is_some())
 }
 
+/// Moves the read position of a user in a session forward to `msg_id`.
+///
+/// # Arguments
+///
+/// * `user_id` - The ID of the reader.
+/// * `session_id` - The ID of the session.
+/// * `msg_id` - The ID of the latest message the user has read.
+/// * `db_conn` - A reference to the database connection implementing the `ConnectionTrait`.
+///
+/// # Returns
+///
+/// * `Result<bool, sea_orm::DbErr>` - `false` if the user is not in the session or has already
+///   read up to a later message, or a `DbErr` if the operation fails.
+pub async fn mark_read(
+    user_id: ID,
+    session_id: SessionID,
+    msg_id: u64,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, DbErr> {
+    let ret = session_relation::Entity::update_many()
+        .col_expr(
+            session_relation::Column::LastReadMsgId,
+            Expr::value(msg_id as i64),
+        )
+        .filter(session_relation::Column::SessionId.eq(session_id))
+        .filter(session_relation::Column::UserId.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(session_relation::Column::LastReadMsgId.is_null())
+                .add(session_relation::Column::LastReadMsgId.lt(msg_id as i64)),
+        )
+        .exec(db_conn)
+        .await?;
+    Ok(ret.rows_affected != 0)
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct UnreadCount {
+    pub session_id: i64,
+    pub last_read_msg_id: Option<i64>,
+    pub unread: i64,
+}
+
+/// Counts the unread messages in every session of the user.
+///
+/// Only the messages sent by other users after the read position are counted, notifications such
+/// as recalls and reactions are ignored.
+///
+/// # Arguments
+///
+/// * `user_id` - The ID of the user.
+/// * `db_conn` - A reference to the database connection implementing the `ConnectionTrait`.
+///
+/// # Returns
+///
+/// * `Result<Vec<UnreadCount>, sea_orm::DbErr>` - One item for every session of the user, or a
+///   `DbErr` if the operation fails.
+pub async fn get_unread_counts(
+    user_id: ID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Vec<UnreadCount>, DbErr> {
+    UnreadCount::find_by_statement(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        r#"SELECT session_relation.session_id, session_relation.last_read_msg_id, COUNT(message_records.msg_id) AS unread
+FROM session_relation LEFT JOIN message_records ON
+message_records.session_id = session_relation.session_id AND
+message_records.msg_id > COALESCE(session_relation.last_read_msg_id, 0) AND
+message_records.sender_id IS DISTINCT FROM session_relation.user_id AND
+message_records.msg_data ? 'Msg'
+WHERE session_relation.user_id = $1
+GROUP BY session_relation.session_id, session_relation.last_read_msg_id"#,
+        [user_id.into()],
+    ))
+    .all(db_conn)
+    .await
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SessionError {
     #[error("Session not found")]
@@ -1,5 +1,7 @@
-use entities::{friend, prelude::*, user};
-use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter};
+use entities::{friend, prelude::*, totp_recovery_codes, user};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+};
 
 use base::constants::ID;
 
@@ -52,3 +54,89 @@ pub async fn query_contact_user_info(
         .await?;
     Ok(model)
 }
+
+/// Set the TOTP secret of a user and whether TOTP is required to log in.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn set_totp(
+    id: ID,
+    secret: Option<String>,
+    enabled: bool,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    user::ActiveModel {
+        id: ActiveValue::Set(id.into()),
+        totp_secret: ActiveValue::Set(secret),
+        totp_enabled: ActiveValue::Set(enabled),
+        ..Default::default()
+    }
+    .update(db_conn)
+    .await?;
+    Ok(())
+}
+
+/// Set the password hash of a user.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn set_password(
+    id: ID,
+    passwd: String,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    user::ActiveModel {
+        id: ActiveValue::Set(id.into()),
+        passwd: ActiveValue::Set(Some(passwd)),
+        ..Default::default()
+    }
+    .update(db_conn)
+    .await?;
+    Ok(())
+}
+
+/// Replace the TOTP recovery codes of a user, an empty list only removes the old ones.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn replace_recovery_codes(
+    id: ID,
+    code_hashes: Vec<String>,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(), sea_orm::DbErr> {
+    TotpRecoveryCodes::delete_many()
+        .filter(totp_recovery_codes::Column::UserId.eq(id))
+        .exec(db_conn)
+        .await?;
+    if code_hashes.is_empty() {
+        return Ok(());
+    }
+    TotpRecoveryCodes::insert_many(code_hashes.into_iter().map(|code_hash| {
+        totp_recovery_codes::ActiveModel {
+            user_id: ActiveValue::Set(id.into()),
+            code_hash: ActiveValue::Set(code_hash),
+        }
+    }))
+    .exec(db_conn)
+    .await?;
+    Ok(())
+}
+
+/// Consume a TOTP recovery code of a user, returning whether the code exists.
+///
+/// # Errors
+///
+/// Fails if any error occurs in the database.
+pub async fn use_recovery_code(
+    id: ID,
+    code_hash: String,
+    db_conn: &impl ConnectionTrait,
+) -> Result<bool, sea_orm::DbErr> {
+    let res = TotpRecoveryCodes::delete_by_id((id.into(), code_hash))
+        .exec(db_conn)
+        .await?;
+    Ok(res.rows_affected == 1)
+}
@@ -27,8 +27,8 @@ impl MachineId for SnowflakeParams {
 pub type MySnowflake = Snowflake<ClassicLayout<SnowflakeParams>, SnowflakeParams>;
 pub type MySnowflakeGenerator = Generator<ClassicLayout<SnowflakeParams>, SnowflakeParams>;
 
-// Three generators share the same machine id and the same epoch, but they will be used in different places.
-// So don't worry about the conflicting when the three are called at the same time
+// The generators share the same machine id and the same epoch, but they will be used in different places.
+// So don't worry about the conflicting when they are called at the same time
 
 /// A Generator of Snowflake, only being created to generate user id
 pub static USER_ID_GENERATOR: LazyLock<MySnowflakeGenerator> =
@@ -37,6 +37,8 @@ pub static WEBRTC_ROOM_ID_GENERATOR: LazyLock<MySnowflakeGenerator> =
     LazyLock::new(MySnowflakeGenerator::default);
 pub static SESSION_ID_GENERATOR: LazyLock<MySnowflakeGenerator> =
     LazyLock::new(MySnowflakeGenerator::default);
+pub static METRICS_ID_GENERATOR: LazyLock<MySnowflakeGenerator> =
+    LazyLock::new(MySnowflakeGenerator::default);
 
 /// Generate ocid by random
 pub fn generate_ocid(bits: usize) -> String {
@@ -95,6 +97,10 @@ pub fn generate_session_id() -> anyhow::Result<SessionID> {
     Ok(SESSION_ID_GENERATOR.generate()?.into_i64().into())
 }
 
+pub fn generate_metrics_snapshot_id() -> anyhow::Result<i64> {
+    Ok(METRICS_ID_GENERATOR.generate()?.into_i64())
+}
+
 pub fn generate_webrtc_room_id() -> anyhow::Result<RoomId> {
     Ok(RoomId(
         // Safe for the snowflake algorithm now
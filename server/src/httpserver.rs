@@ -1,9 +1,13 @@
 mod avatar;
+mod files;
 mod oauth;
+mod password_reset;
+mod rate_limit;
 mod status;
 pub mod verify;
 
 use crate::process::error_msg;
+use crate::process::token::TokenExpire;
 use crate::{Cfg, SharedData};
 use anyhow::{Context, anyhow};
 use axum::body::Body;
@@ -32,7 +36,6 @@ use std::time::Duration;
 use tokio::{select, signal};
 use tokio_stream::StreamExt;
 use tower::ServiceBuilder;
-use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
 use tracing::{debug, info, warn};
 
 pub struct HttpServer {
@@ -87,34 +90,6 @@ impl HttpServer {
                 http::HeaderName::from_static("x-user-agent"),
             ])
             .max_age(Duration::from_secs(86400));
-        let rate_governor_config = GovernorConfigBuilder::default()
-            .burst_size(shared_data.cfg().http_cfg.rate_limit.num_of_burst_requests)
-            .per_millisecond(
-                shared_data
-                    .cfg()
-                    .http_cfg
-                    .rate_limit
-                    .replenish_duration
-                    .as_millis() as u64,
-            )
-            .finish()
-            .expect("Could not build rate governor config. Please check the config values.");
-        let rate_governor_limiter = rate_governor_config.limiter().clone();
-        // background task to clean up
-        // copy the example of tower_governor
-        if shared_data.cfg().http_cfg.rate_limit.enable {
-            tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(Duration::from_mins(1)).await;
-                    info!(
-                        "rate limiting storage size: {}",
-                        rate_governor_limiter.len()
-                    );
-                    rate_governor_limiter.retain_recent();
-                }
-            });
-        }
-
         let v1 = axum::Router::new()
             .route("/status", get(status::status))
             .route_service(
@@ -122,30 +97,23 @@ impl HttpServer {
                 tower_http::services::ServeFile::new(shared_data.cfg().http_cfg.logo_path.clone()),
             )
             .route("/avatar", get(avatar::avatar))
+            .route("/files/{key}", get(files::download))
             .merge(verify::config().with_state(db_pool.clone()))
             .layer(cors.clone());
 
         // OAuth routes - only setup if enabled
         let oauth_routes = if shared_data.cfg().main_cfg.oauth.enable {
-            let oauth_config = oauth::OAuthConfig {
-                github_client_id: shared_data.cfg().main_cfg.oauth.github_client_id.clone(),
-                github_client_secret: shared_data
-                    .cfg()
-                    .main_cfg
-                    .oauth
-                    .github_client_secret
-                    .clone(),
-                github_redirect_uri: format!(
-                    "{}/oauth/github/callback",
-                    shared_data.cfg().http_cfg.base_url()
-                ),
-            };
-
-            let oauth_state = Arc::new(oauth::OAuthState {
-                db_pool: db_pool.clone(),
-                oauth_config,
-                oauth_states: dashmap::DashMap::new(),
-            });
+            let providers = oauth::build_providers(
+                &shared_data.cfg().main_cfg.oauth,
+                &shared_data.cfg().http_cfg.base_url().to_string(),
+            );
+            let oauth_state = Arc::new(oauth::OAuthState::new(
+                db_pool.clone(),
+                providers,
+                TokenExpire::from_cfg(&shared_data.cfg().main_cfg),
+                shared_data.cfg().main_cfg.friends_number_limit,
+                rabbitmq.clone(),
+            ));
 
             Some(oauth::config().with_state(oauth_state))
         } else {
@@ -188,7 +156,18 @@ impl HttpServer {
             );
         if shared_data.cfg().http_cfg.rate_limit.enable {
             info!("Http rate limit enabled");
-            router = router.layer(GovernorLayer::new(rate_governor_config));
+            let rate_limiter = {
+                let cfg = &shared_data.cfg().http_cfg.rate_limit;
+                rate_limit::RateLimiter::new(
+                    db_pool.clone(),
+                    cfg.num_of_burst_requests,
+                    cfg.replenish_duration,
+                )
+            };
+            router = router.layer(middleware::from_fn_with_state(
+                Arc::new(rate_limiter),
+                rate_limit::rate_limit_middleware,
+            ));
         } else {
             warn!("Http rate limit disabled");
         }
@@ -361,7 +340,7 @@ impl HttpServer {
                 .await?;
             // Wait for the channel to be set
             let mut try_cnt = 0;
-            let mut consumer = loop {
+            let consumer = loop {
                 match mq_channel
                     .basic_consume(
                         base::rabbitmq::http_server::VERIFY_QUEUE,
@@ -384,6 +363,15 @@ impl HttpServer {
                 tokio::time::sleep(Duration::from_secs(3)).await;
                 try_cnt += 1;
             };
+            let password_reset_consumer = mq_channel
+                .basic_consume(
+                    base::rabbitmq::http_server::PASSWORD_RESET_QUEUE,
+                    "http_server_password_reset",
+                    deadpool_lapin::lapin::options::BasicConsumeOptions::default(),
+                    deadpool_lapin::lapin::types::FieldTable::default(),
+                )
+                .await?;
+            let mut consumer = consumer.merge(password_reset_consumer);
             debug!("Starting to consume verification");
             while let Some(data) = consumer.next().await {
                 let delivery = match data {
@@ -393,6 +381,31 @@ impl HttpServer {
                         continue;
                     }
                 };
+                if delivery.routing_key.as_str()
+                    == base::rabbitmq::http_server::PASSWORD_RESET_QUEUE
+                {
+                    // The token is stored already, a failed email can only be requested again
+                    match serde_json::from_slice::<base::rabbitmq::http_server::PasswordResetRecord>(
+                        &delivery.data[..],
+                    ) {
+                        Ok(record) => {
+                            if let Err(e) = password_reset::send_password_reset_email(
+                                &email_client,
+                                record,
+                                &shared_data,
+                            )
+                            .await
+                            {
+                                tracing::error!("send password reset email failed:{}", e);
+                            }
+                        }
+                        Err(e) => tracing::error!("invalid password reset record:{}", e),
+                    }
+                    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                        tracing::error!("ack password reset failed:{}", e);
+                    }
+                    continue;
+                }
                 let verify_record = serde_json::from_slice::<
                     base::rabbitmq::http_server::VerifyRecord,
                 >(&delivery.data[..])?;
@@ -583,9 +596,10 @@ async fn maintaining_middleware(
     let path = request.uri().path().to_string();
 
     // Allow SetServerStatus to bypass maintaining check (for exiting maintenance mode)
-    // Also allow /health or /status endpoints
+    // Also allow /health or /status endpoints, and GetServerInfo telling about the maintenance
     let allowed_paths = [
         "/service.server_manage.v1.ServerManageService/SetServerStatus",
+        "/service.basic.v1.BasicService/GetServerInfo",
         "/v1/status",
     ];
     let is_allowed = allowed_paths.iter().any(|p| path.contains(p));
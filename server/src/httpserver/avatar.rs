@@ -6,14 +6,24 @@ use axum::{
     response::IntoResponse,
 };
 use base::{constants::ID, database::DbPool};
-use http::StatusCode;
+use http::{StatusCode, header};
 use serde::{Deserialize, Serialize};
 
-use crate::{SharedData, db::user::get_account_info_db};
+use entities::prelude::Files;
+use sea_orm::EntityTrait;
+
+use crate::{
+    SharedData,
+    db::user::get_account_info_db,
+    process::files::download::{DEFAULT_CONTENT_TYPE, download_target},
+    storage::object_name,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AvatarParams {
     user_id: ID,
+    /// Serve the thumbnail large enough for this many pixels, if the avatar has one
+    size: Option<u32>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -56,17 +66,29 @@ pub async fn avatar(
     };
     match user.avatar {
         Some(avatar_key) => {
-            // Use hierarchical storage path for better filesystem performance
-            let base_path = shared_data.cfg().main_cfg.files_storage_path.clone();
-            let path = crate::db::file_storage::generate_hierarchical_path(
-                &base_path,
-                params.user_id,
-                &avatar_key,
-            );
-            let bytes = tokio::fs::read(&path)
+            let storage = shared_data.file_sys.storage();
+            // The avatars uploaded before the storage backends may be recorded by absolute paths
+            let (object, content_type) = match Files::find_by_id(&avatar_key)
+                .one(&pool.db_pool)
+                .await
+                .context("db error")?
+            {
+                Some(file) => {
+                    let target = download_target(&file, params.size, storage.as_ref())
+                        .await
+                        .context("read avatar file failed")?;
+                    (target.object, target.content_type)
+                }
+                None => (
+                    object_name(params.user_id, &avatar_key),
+                    DEFAULT_CONTENT_TYPE.to_string(),
+                ),
+            };
+            let bytes = storage
+                .read(&object)
                 .await
-                .with_context(|| format!("read avatar file failed: {}", path.display()))?;
-            Ok(bytes)
+                .with_context(|| format!("read avatar file failed: {}", object))?;
+            Ok(([(header::CONTENT_TYPE, content_type)], bytes).into_response())
         }
         None => {
             // use default avatar
@@ -74,7 +96,7 @@ pub async fn avatar(
             let bytes = tokio::fs::read(path)
                 .await
                 .context("read default avatar failed")?;
-            Ok(bytes)
+            Ok(bytes.into_response())
         }
     }
 }
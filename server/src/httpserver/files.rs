@@ -0,0 +1,273 @@
+//! Downloading files over plain http, for the browsers and the web panel to stream media without
+//! grpc
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use base::{
+    constants::{ID, JWT_HEADER},
+    database::DbPool,
+};
+use http::{HeaderMap, HeaderValue, StatusCode, header};
+use migration::predefined::AccountStatus;
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    SharedData,
+    db::user::get_account_info_db,
+    process::{
+        error_msg::{ACCOUNT_DELETED, PERMISSION_DENIED, SERVER_ERROR, not_found},
+        files::download::{DownloadError, download_target, find_downloadable_file},
+    },
+    server::RpcServer,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FileParams {
+    /// For the clients which can't set the `Authorization` header, like `<video src>`
+    access_token: Option<String>,
+    /// Send the thumbnail large enough for this many pixels instead, if the file has one
+    thumbnail: Option<u32>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileError {
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+    #[error("permission denied")]
+    PermissionDenied,
+    #[error("range not satisfiable")]
+    RangeNotSatisfiable { size: u64 },
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<DownloadError> for FileError {
+    fn from(value: DownloadError) -> Self {
+        match value {
+            DownloadError::PermissionDenied => FileError::PermissionDenied,
+            e => FileError::Internal(e.into()),
+        }
+    }
+}
+
+impl IntoResponse for FileError {
+    fn into_response(self) -> Response {
+        match self {
+            FileError::Unauthenticated(msg) => (StatusCode::UNAUTHORIZED, msg).into_response(),
+            FileError::PermissionDenied => {
+                (StatusCode::FORBIDDEN, PERMISSION_DENIED).into_response()
+            }
+            FileError::RangeNotSatisfiable { size } => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+            )
+                .into_response(),
+            FileError::Internal(e) => {
+                tracing::error!("Internal server error: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, SERVER_ERROR).into_response()
+            }
+        }
+    }
+}
+
+/// The part of the file asked for by the `Range` header
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// No usable range, send the whole file
+    Full,
+    /// From the first byte to the last byte, both included
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range against a file of `size` bytes.
+///
+/// Malformed ranges and multiple ranges are ignored, the whole file is sent for them as RFC 9110
+/// allows.
+fn parse_range(range: &str, size: u64) -> ByteRange {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // the last `end` bytes
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(size.saturating_sub(suffix), size - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return ByteRange::Full,
+        }
+    };
+    if start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end.min(size - 1))
+}
+
+/// Whether the `If-None-Match` header lists the entity tag, compared weakly
+fn etag_matches(header: &HeaderValue, etag: &str) -> bool {
+    let Ok(header) = header.to_str() else {
+        return false;
+    };
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Authenticate the request the same way as the grpc services
+async fn authenticate(
+    db: &DbPool,
+    mut headers: HeaderMap,
+    access_token: Option<String>,
+) -> Result<ID, FileError> {
+    if let Some(token) = access_token
+        && !headers.contains_key(JWT_HEADER)
+    {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|_| FileError::Unauthenticated(PERMISSION_DENIED.to_string()))?;
+        headers.insert(JWT_HEADER, value);
+    }
+    let id = RpcServer::check_auth(db, &mut headers)
+        .await
+        .map_err(|status| FileError::Unauthenticated(status.message().to_string()))?;
+    let account = get_account_info_db(id, &db.db_pool)
+        .await
+        .map_err(anyhow::Error::from)?
+        .ok_or_else(|| FileError::Unauthenticated(not_found::USER.to_string()))?;
+    if account.account_status == AccountStatus::Deleted as i32 {
+        return Err(FileError::Unauthenticated(ACCOUNT_DELETED.to_string()));
+    }
+    Ok(id)
+}
+
+/// `GET /v1/files/{key}`, supporting `Range`, `If-Range` and `If-None-Match`
+pub async fn download(
+    State((pool, shared_data)): State<(DbPool, Arc<SharedData>)>,
+    Path(key): Path<String>,
+    Query(params): Query<FileParams>,
+    headers: HeaderMap,
+) -> Result<Response, FileError> {
+    let id = authenticate(&pool, headers.clone(), params.access_token).await?;
+    let storage = shared_data.file_sys.storage();
+    let file_info = find_downloadable_file(id, &key, &pool).await?;
+    let target = download_target(&file_info, params.thumbnail, storage.as_ref())
+        .await
+        .map_err(anyhow::Error::from)?;
+    let size = target.size;
+    let etag = match target.thumbnail {
+        Some(side) => format!("\"{}-{}\"", file_info.hash, side),
+        None => format!("\"{}\"", file_info.hash),
+    };
+    let etag_value = HeaderValue::from_str(&etag).map_err(anyhow::Error::from)?;
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| etag_matches(value, &etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response());
+    }
+    // A range of another version of the file is useless to the client
+    let range_applies = headers
+        .get(header::IF_RANGE)
+        .is_none_or(|value| value.as_bytes() == etag.as_bytes());
+    let range = match headers.get(header::RANGE) {
+        Some(range) if range_applies => range
+            .to_str()
+            .map_or(ByteRange::Full, |range| parse_range(range, size)),
+        _ => ByteRange::Full,
+    };
+    let (status, start, length) = match range {
+        ByteRange::Full => (StatusCode::OK, 0, size),
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        ByteRange::Unsatisfiable => return Err(FileError::RangeNotSatisfiable { size }),
+    };
+
+    let reader = storage
+        .read_range(&target.object, start, length)
+        .await
+        .map_err(anyhow::Error::from)?;
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, target.content_type)
+        .header(header::CONTENT_LENGTH, length)
+        .header(header::ETAG, etag_value)
+        .header(header::CACHE_CONTROL, "private");
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, start + length - 1, size),
+        );
+    }
+    Ok(response
+        .body(Body::from_stream(ReaderStream::new(reader)))
+        .map_err(anyhow::Error::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(
+            parse_range("bytes=900-5000", 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            ByteRange::Partial(900, 999)
+        );
+        assert_eq!(parse_range("bytes=-5000", 1000), ByteRange::Partial(0, 999));
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        // ignored
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=9-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=a-b", 1000), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let etag = "\"abc\"";
+        assert!(etag_matches(&HeaderValue::from_static("\"abc\""), etag));
+        assert!(etag_matches(&HeaderValue::from_static("W/\"abc\""), etag));
+        assert!(etag_matches(
+            &HeaderValue::from_static("\"x\", \"abc\""),
+            etag
+        ));
+        assert!(etag_matches(&HeaderValue::from_static("*"), etag));
+        assert!(!etag_matches(&HeaderValue::from_static("\"abcd\""), etag));
+    }
+}
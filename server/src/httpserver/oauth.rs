@@ -1,16 +1,57 @@
-use crate::helper::{USER_ID_GENERATOR, generate_ocid, generate_random_string};
-use crate::process::generate_access_token;
+mod provider;
+
+use crate::db::helper::is_conflict;
+use crate::db::oauth::{find_oauth_identity, link_oauth_identity, update_oauth_identity_email};
+use crate::db::redis_mappings::{map_oauth_link_ticket_to_redis, map_oauth_state_to_redis};
+use crate::db::user::get_account_info_db;
+use crate::helper::{USER_ID_GENERATOR, generate_ocid};
+use crate::process::device::DeviceInfo;
+use crate::process::error_msg::{
+    ACCOUNT_DELETED, OAUTH_EMAIL_UNVERIFIED, OAUTH_EMAIL_USED, OAUTH_LINK_TICKET_INVALID,
+    OAUTH_STATE_INVALID, SERVER_ERROR, not_found,
+};
+use crate::process::oauth::{PendingLink, create_link_code};
+use crate::process::token::{NewLogin, TokenExpire};
+use crate::process::totp::create_totp_challenge;
 use axum::{
-    extract::{Query, State},
+    Json,
+    extract::{ConnectInfo, Path, Query, State},
     response::{IntoResponse, Redirect},
     routing::get,
 };
+use base::constants::{ID, OCID_LEN};
 use base::database::DbPool;
 use chrono::Utc;
-use http::StatusCode;
+use entities::{prelude::*, user};
+use http::{HeaderMap, StatusCode, header};
+use migration::predefined::AccountStatus;
+use redis::AsyncCommands;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::{DatabaseConnection, TransactionTrait};
 use serde::{Deserialize, Serialize};
 use snowdon::ClassicLayoutSnowflakeExtension;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+
+use provider::{AuthorizationRequest, ExternalIdentity, OAuthProvider};
+pub use provider::{Providers, build_providers};
+
+/// How long the user has for logging in at the provider
+const AUTHORIZATION_EXPIRY: Duration = Duration::from_mins(10);
+
+type OAuthResult<T> = Result<T, (StatusCode, &'static str)>;
+
+fn server_error(e: impl std::fmt::Debug) -> (StatusCode, &'static str) {
+    tracing::error!("OAuth error: {:?}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, SERVER_ERROR)
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthStartParams {
+    /// Got from LinkProvider, links the provider to the account instead of logging in
+    link_ticket: Option<String>,
+}
 
 #[derive(Debug, Deserialize)]
 struct OAuthCallbackParams {
@@ -18,204 +59,287 @@ struct OAuthCallbackParams {
     state: String,
 }
 
+/// What the callback tells the client, sent with `Cache-Control: no-store` since it may carry the
+/// tokens of the account
 #[derive(Debug, Serialize)]
-struct GitHubTokenRequest {
-    client_id: String,
-    client_secret: String,
-    code: String,
-    redirect_uri: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct GitHubTokenResponse {
-    access_token: String,
-    token_type: String,
-    scope: String,
+#[serde(tag = "status", rename_all = "snake_case")]
+enum OAuthCallbackResponse {
+    /// The account which asked for linking finishes it with ConfirmLinkProvider
+    LinkPending { provider: String, link_code: String },
+    /// The account turned TOTP on, the login is finished with AuthTotp
+    TotpRequired { totp_challenge: String },
+    LoggedIn {
+        access_token: String,
+        refresh_token: String,
+        token_type: &'static str,
+    },
 }
 
-#[derive(Debug, Deserialize)]
-struct GitHubUserInfo {
-    id: u64,
-    login: String,
-    email: Option<String>,
-    name: Option<String>,
-    avatar_url: Option<String>,
+impl IntoResponse for OAuthCallbackResponse {
+    fn into_response(self) -> axum::response::Response {
+        ([(header::CACHE_CONTROL, "no-store")], Json(self)).into_response()
+    }
 }
 
-pub struct OAuthConfig {
-    pub github_client_id: String,
-    pub github_client_secret: String,
-    pub github_redirect_uri: String,
+/// An authorization waiting for the provider to redirect back, kept in redis so that the
+/// provider may redirect to any instance
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingAuthorization {
+    provider: String,
+    request: AuthorizationRequest,
+    link_user: Option<ID>,
 }
 
 pub struct OAuthState {
     pub db_pool: DbPool,
-    pub oauth_config: OAuthConfig,
-    pub oauth_states: dashmap::DashMap<String, chrono::DateTime<Utc>>,
+    pub providers: Providers,
+    pub token_expire: TokenExpire,
+    pub friends_number_limit: u32,
+    pub rabbitmq: deadpool_lapin::Pool,
 }
 
-async fn github_oauth_start(
-    State(state): State<Arc<OAuthState>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let state_param = generate_random_string(16);
+impl OAuthState {
+    pub fn new(
+        db_pool: DbPool,
+        providers: Providers,
+        token_expire: TokenExpire,
+        friends_number_limit: u32,
+        rabbitmq: deadpool_lapin::Pool,
+    ) -> Self {
+        Self {
+            db_pool,
+            providers,
+            token_expire,
+            friends_number_limit,
+            rabbitmq,
+        }
+    }
 
-    // Store the state with timestamp for CSRF protection
-    state.oauth_states.insert(state_param.clone(), Utc::now());
+    fn provider(&self, name: &str) -> OAuthResult<&Arc<dyn OAuthProvider>> {
+        self.providers
+            .get(name)
+            .ok_or((StatusCode::NOT_FOUND, not_found::OAUTH_PROVIDER))
+    }
+}
 
-    let auth_url = format!(
-        "https://github.com/login/oauth/authorize?client_id={}&redirect_uri={}&scope=user:email&state={}",
-        state.oauth_config.github_client_id, state.oauth_config.github_redirect_uri, state_param
-    );
+async fn oauth_start(
+    State(state): State<Arc<OAuthState>>,
+    Path(provider_name): Path<String>,
+    Query(params): Query<OAuthStartParams>,
+) -> OAuthResult<impl IntoResponse> {
+    let provider = state.provider(&provider_name)?;
+    let link_user = match params.link_ticket {
+        Some(ticket) => {
+            let user_id: Option<u64> = state
+                .db_pool
+                .redis()
+                .get_del(map_oauth_link_ticket_to_redis(&provider_name, &ticket))
+                .await
+                .map_err(server_error)?;
+            let user_id = user_id.ok_or((StatusCode::BAD_REQUEST, OAUTH_LINK_TICKET_INVALID))?;
+            Some(ID(user_id))
+        }
+        None => None,
+    };
 
+    let request = AuthorizationRequest::new();
+    let auth_url = provider
+        .authorize_url(&request)
+        .await
+        .map_err(server_error)?;
+    // The authorizations the users have given up expire by themselves
+    let key = map_oauth_state_to_redis(&request.state);
+    let pending = PendingAuthorization {
+        provider: provider_name,
+        request,
+        link_user,
+    };
+    let pending = serde_json::to_string(&pending).map_err(server_error)?;
+    let _: () = state
+        .db_pool
+        .redis()
+        .set_ex(key, pending, AUTHORIZATION_EXPIRY.as_secs())
+        .await
+        .map_err(server_error)?;
     Ok(Redirect::to(&auth_url))
 }
 
-async fn github_oauth_callback(
+async fn oauth_callback(
     State(state): State<Arc<OAuthState>>,
+    Path(provider_name): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(params): Query<OAuthCallbackParams>,
-) -> Result<impl IntoResponse, StatusCode> {
-    // Validate state parameter for CSRF protection
-    if let Some((_, timestamp)) = state.oauth_states.remove(&params.state) {
-        // Check if state is not too old (e.g., 10 minutes)
-        if Utc::now() - timestamp > chrono::Duration::minutes(10) {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    } else {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    // Exchange code for access token
-    let token_response = exchange_code_for_token(&state.oauth_config, &params.code)
+) -> OAuthResult<OAuthCallbackResponse> {
+    let provider = state.provider(&provider_name)?;
+    // Validate state parameter for CSRF protection, it is used only once
+    let pending: Option<String> = state
+        .db_pool
+        .redis()
+        .get_del(map_oauth_state_to_redis(&params.state))
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(server_error)?;
+    let pending = pending
+        .map(|pending| serde_json::from_str::<PendingAuthorization>(&pending))
+        .transpose()
+        .map_err(server_error)?
+        .filter(|pending| pending.provider == provider_name)
+        .ok_or((StatusCode::BAD_REQUEST, OAUTH_STATE_INVALID))?;
 
-    // Log token type and scope for debugging
-    tracing::debug!(
-        "GitHub OAuth token received - type: {}, scope: {}",
-        token_response.token_type,
-        token_response.scope
+    let identity = provider
+        .fetch_identity(&params.code, &pending.request)
+        .await
+        .map_err(server_error)?;
+    tracing::info!(
+        "{} OAuth identity {} authorized",
+        provider.name(),
+        identity.subject
     );
 
-    // Get user info from GitHub
-    let user_info = get_github_user_info(&token_response.access_token)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let db_conn = &state.db_pool.db_pool;
+    // Whoever opens the link url logs in at the provider, so the identity is linked only after the
+    // account which got the ticket confirms it
+    if let Some(user_id) = pending.link_user {
+        let pending_link = PendingLink {
+            user_id,
+            subject: identity.subject,
+            email: identity.email,
+        };
+        let link_code = create_link_code(&provider_name, &pending_link, &mut state.db_pool.redis())
+            .await
+            .map_err(server_error)?;
+        return Ok(OAuthCallbackResponse::LinkPending {
+            provider: provider_name,
+            link_code,
+        });
+    }
 
-    // Create or update user in database
-    let user_id = create_or_update_user_from_github(&state.db_pool, user_info)
+    let user_id = match find_oauth_identity(&provider_name, &identity.subject, db_conn)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Generate JWT token
-    let token =
-        generate_access_token(user_id.into()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Return success with JWT token
-    Ok(format!("GitHub OAuth successful. Token: Bearer {}", token))
-}
-
-async fn exchange_code_for_token(
-    config: &OAuthConfig,
-    code: &str,
-) -> anyhow::Result<GitHubTokenResponse> {
-    let client = reqwest::Client::new();
-    let token_request = GitHubTokenRequest {
-        client_id: config.github_client_id.clone(),
-        client_secret: config.github_client_secret.clone(),
-        code: code.to_string(),
-        redirect_uri: config.github_redirect_uri.clone(),
+        .map_err(server_error)?
+    {
+        Some(linked) => {
+            let user_id = ID::from(linked.user_id);
+            update_oauth_identity_email(linked, identity.email, db_conn)
+                .await
+                .map_err(server_error)?;
+            user_id
+        }
+        None => {
+            create_user_from_identity(
+                &provider_name,
+                identity,
+                state.friends_number_limit,
+                db_conn,
+            )
+            .await?
+        }
     };
 
-    let response = client
-        .post("https://github.com/login/oauth/access_token")
-        .header("Accept", "application/json")
-        .json(&token_request)
-        .send()
-        .await?;
+    let user = get_account_info_db(user_id, db_conn)
+        .await
+        .map_err(server_error)?
+        .ok_or((StatusCode::NOT_FOUND, not_found::USER))?;
+    if user.account_status == AccountStatus::Deleted as i32 {
+        return Err((StatusCode::FORBIDDEN, ACCOUNT_DELETED));
+    }
+    // The users who turned TOTP on finish logging in with AuthTotp
+    if user.totp_enabled {
+        let challenge = create_totp_challenge(user_id, &mut state.db_pool.redis())
+            .await
+            .map_err(server_error)?;
+        return Ok(OAuthCallbackResponse::TotpRequired {
+            totp_challenge: challenge,
+        });
+    }
 
-    let token_response: GitHubTokenResponse = response.json().await?;
-    Ok(token_response)
-}
+    // Generate JWT token and refresh token
+    let login = NewLogin {
+        expire: state.token_expire,
+        device: DeviceInfo::from_parts(&headers, Some(addr)),
+        rabbitmq: &state.rabbitmq,
+    };
+    let tokens = login
+        .start(user_id, &state.db_pool)
+        .await
+        .map_err(server_error)?;
 
-async fn get_github_user_info(access_token: &str) -> anyhow::Result<GitHubUserInfo> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://api.github.com/user")
-        .header("User-Agent", "OurChat")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?;
-
-    let user_info: GitHubUserInfo = response.json().await?;
-    Ok(user_info)
+    Ok(OAuthCallbackResponse::LoggedIn {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        token_type: "Bearer",
+    })
 }
 
-async fn create_or_update_user_from_github(
-    db_pool: &DbPool,
-    github_user: GitHubUserInfo,
-) -> anyhow::Result<i64> {
-    use entities::user::Entity as UserEntity;
-    use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
-
-    let github_id = github_user.id.to_string();
-
-    // Check if user already exists with this GitHub ID
-    let existing_user = UserEntity::find()
-        .filter(entities::user::Column::GithubId.eq(&github_id))
-        .one(&db_pool.db_pool)
-        .await?;
-
-    let user_id = if let Some(user) = existing_user {
-        // Update existing user
-        let user_id = user.id;
-        let mut user_active: entities::user::ActiveModel = user.into();
-        user_active.name = Set(github_user
-            .name
-            .unwrap_or_else(|| github_user.login.clone()));
-        user_active.email = Set(github_user.email.unwrap_or_default());
-        user_active.avatar = Set(github_user.avatar_url);
-        user_active.oauth_provider = Set(Some("github".to_string()));
-        user_active.update_time = Set(Utc::now().into());
-
-        UserEntity::update(user_active)
-            .exec(&db_pool.db_pool)
-            .await?;
-        user_id
-    } else {
-        // Create new user
-        let user_id = USER_ID_GENERATOR.generate()?.into_i64();
-        let new_user = entities::user::ActiveModel {
-            id: Set(user_id),
-            ocid: Set(generate_ocid(10)),
-            passwd: Set(None), // OAuth users don't have passwords
-            name: Set(github_user
-                .name
-                .unwrap_or_else(|| github_user.login.clone())),
-            email: Set(github_user.email.unwrap_or_default()),
-            time: Set(Utc::now().into()),
-            resource_used: Set(0),
-            friend_limit: Set(5000),
-            friends_num: Set(0),
-            avatar: Set(github_user.avatar_url),
-            public_update_time: Set(Utc::now().into()),
-            update_time: Set(Utc::now().into()),
-            account_status: Set(1), // Active
-            deleted_at: Set(None),
-            public_key: Set(vec![]), // TODO: Generate public key for OAuth users
-            github_id: Set(Some(github_id)),
-            oauth_provider: Set(Some("github".to_string())),
-            email_verified: Set(true), // OAuth users from trusted providers are automatically verified
-        };
+/// Sign up with the identity.
+///
+/// An account already using the email isn't logged in, as the provider may let anyone claim
+/// the email, its owner can link the provider from the account instead.
+async fn create_user_from_identity(
+    provider: &str,
+    identity: ExternalIdentity,
+    friends_number_limit: u32,
+    db_conn: &DatabaseConnection,
+) -> OAuthResult<ID> {
+    let email = identity
+        .email
+        .filter(|_| identity.email_verified)
+        .ok_or((StatusCode::FORBIDDEN, OAUTH_EMAIL_UNVERIFIED))?;
+    let email_used = User::find()
+        .filter(user::Column::Email.eq(&email))
+        .one(db_conn)
+        .await
+        .map_err(server_error)?
+        .is_some();
+    if email_used {
+        return Err((StatusCode::CONFLICT, OAUTH_EMAIL_USED));
+    }
 
-        UserEntity::insert(new_user).exec(&db_pool.db_pool).await?;
-        user_id
+    let user_id = USER_ID_GENERATOR
+        .generate()
+        .map_err(server_error)?
+        .into_i64();
+    let new_user = user::ActiveModel {
+        id: ActiveValue::Set(user_id),
+        ocid: ActiveValue::Set(generate_ocid(OCID_LEN)),
+        passwd: ActiveValue::Set(None), // OAuth users don't have passwords
+        name: ActiveValue::Set(identity.name.unwrap_or_else(|| email.clone())),
+        email: ActiveValue::Set(email.clone()),
+        time: ActiveValue::Set(Utc::now().into()),
+        resource_used: ActiveValue::Set(0),
+        friends_num: ActiveValue::Set(0),
+        friend_limit: ActiveValue::Set(friends_number_limit.try_into().map_err(server_error)?),
+        avatar: ActiveValue::Set(identity.avatar),
+        public_key: ActiveValue::Set(vec![]), // TODO: Generate public key for OAuth users
+        email_verified: ActiveValue::Set(true),
+        ..Default::default()
     };
-
-    Ok(user_id)
+    let transaction = db_conn.begin().await.map_err(server_error)?;
+    let created = async {
+        new_user.insert(&transaction).await?;
+        link_oauth_identity(
+            ID::from(user_id),
+            provider,
+            &identity.subject,
+            Some(email),
+            &transaction,
+        )
+        .await
+    }
+    .await;
+    match created {
+        Ok(_) => {}
+        // Signed up at the same time by another request
+        Err(e) if is_conflict(&e) => return Err((StatusCode::CONFLICT, OAUTH_EMAIL_USED)),
+        Err(e) => return Err(server_error(e)),
+    }
+    transaction.commit().await.map_err(server_error)?;
+    tracing::info!("user {} signed up with {}", user_id, provider);
+    Ok(ID::from(user_id))
 }
 
 pub fn config() -> axum::Router<Arc<OAuthState>> {
     axum::Router::new()
-        .route("/oauth/github", get(github_oauth_start))
-        .route("/oauth/github/callback", get(github_oauth_callback))
+        .route("/oauth/{provider}", get(oauth_start))
+        .route("/oauth/{provider}/callback", get(oauth_callback))
 }
@@ -0,0 +1,438 @@
+//! The identity providers users can log in with: GitHub and any OpenID Connect issuer
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail, ensure};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{GITHUB_PROVIDER, OAuthCfg, OidcProviderCfg};
+use crate::helper::generate_random_string;
+
+const STATE_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+/// RFC 7636 allows 43 to 128 characters
+const CODE_VERIFIER_LEN: usize = 64;
+/// How long the signing keys of an OIDC provider are trusted before being fetched again. A key
+/// unknown to the cache is fetched at once, since the provider may have rotated its keys.
+const JWKS_TTL: Duration = Duration::from_hours(1);
+
+/// The account of the user at the provider
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    /// Stable id of the account at the provider
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// The secrets of one authorization, generated when redirecting the user to the provider and
+/// checked when the provider redirects back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationRequest {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl AuthorizationRequest {
+    pub fn new() -> Self {
+        Self {
+            state: generate_random_string(STATE_LEN),
+            nonce: generate_random_string(NONCE_LEN),
+            code_verifier: generate_random_string(CODE_VERIFIER_LEN),
+        }
+    }
+
+    /// The PKCE challenge of the S256 method
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+}
+
+#[async_trait::async_trait]
+pub trait OAuthProvider: Send + Sync {
+    /// The name in the login url `/oauth/{name}`
+    fn name(&self) -> &str;
+
+    /// Where to send the user to log in at the provider
+    async fn authorize_url(&self, request: &AuthorizationRequest) -> anyhow::Result<String>;
+
+    /// Exchange the code the provider redirected back with for the account of the user
+    async fn fetch_identity(
+        &self,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<ExternalIdentity>;
+}
+
+pub type Providers = HashMap<String, Arc<dyn OAuthProvider>>;
+
+/// Build the configured providers, their callbacks are at `{base_url}/oauth/{name}/callback`
+pub fn build_providers(cfg: &OAuthCfg, base_url: &str) -> Providers {
+    let base_url = base_url.trim_end_matches('/');
+    let redirect_uri = |name: &str| format!("{base_url}/oauth/{name}/callback");
+    let http = reqwest::Client::new();
+    let mut providers: Providers = HashMap::new();
+    providers.insert(
+        GITHUB_PROVIDER.to_string(),
+        Arc::new(GitHubProvider {
+            client_id: cfg.github_client_id.clone(),
+            client_secret: cfg.github_client_secret.clone(),
+            redirect_uri: redirect_uri(GITHUB_PROVIDER),
+            http: http.clone(),
+        }),
+    );
+    for oidc in &cfg.oidc {
+        providers.insert(
+            oidc.name.clone(),
+            Arc::new(OidcProvider {
+                redirect_uri: redirect_uri(&oidc.name),
+                cfg: oidc.clone(),
+                http: http.clone(),
+                discovery: tokio::sync::OnceCell::new(),
+                jwks: parking_lot::Mutex::new(None),
+            }),
+        );
+    }
+    providers
+}
+
+#[derive(Debug, Serialize)]
+struct GitHubTokenRequest<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    code: &'a str,
+    redirect_uri: &'a str,
+    code_verifier: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubTokenResponse {
+    access_token: String,
+    token_type: String,
+    scope: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubUserInfo {
+    id: u64,
+    login: String,
+    email: Option<String>,
+    name: Option<String>,
+    avatar_url: Option<String>,
+}
+
+struct GitHubProvider {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    http: reqwest::Client,
+}
+
+#[async_trait::async_trait]
+impl OAuthProvider for GitHubProvider {
+    fn name(&self) -> &str {
+        GITHUB_PROVIDER
+    }
+
+    async fn authorize_url(&self, request: &AuthorizationRequest) -> anyhow::Result<String> {
+        Ok(format!(
+            "https://github.com/login/oauth/authorize?client_id={}&redirect_uri={}&scope=user:email&state={}&code_challenge={}&code_challenge_method=S256",
+            self.client_id,
+            self.redirect_uri,
+            request.state,
+            request.code_challenge()
+        ))
+    }
+
+    async fn fetch_identity(
+        &self,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<ExternalIdentity> {
+        let token_response: GitHubTokenResponse = self
+            .http
+            .post("https://github.com/login/oauth/access_token")
+            .header("Accept", "application/json")
+            .json(&GitHubTokenRequest {
+                client_id: &self.client_id,
+                client_secret: &self.client_secret,
+                code,
+                redirect_uri: &self.redirect_uri,
+                code_verifier: &request.code_verifier,
+            })
+            .send()
+            .await?
+            .json()
+            .await?;
+        tracing::debug!(
+            "GitHub OAuth token received - type: {}, scope: {}",
+            token_response.token_type,
+            token_response.scope
+        );
+
+        let user_info: GitHubUserInfo = self
+            .http
+            .get("https://api.github.com/user")
+            .header("User-Agent", "OurChat")
+            .header(
+                "Authorization",
+                format!("Bearer {}", token_response.access_token),
+            )
+            .send()
+            .await?
+            .json()
+            .await?;
+        Ok(ExternalIdentity {
+            subject: user_info.id.to_string(),
+            // GitHub only shows the verified emails
+            email_verified: user_info.email.is_some(),
+            email: user_info.email,
+            name: Some(user_info.name.unwrap_or(user_info.login)),
+            avatar: user_info.avatar_url,
+        })
+    }
+}
+
+/// The part of the discovery document used for logging in
+#[derive(Debug, Deserialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OidcTokenRequest<'a> {
+    grant_type: &'a str,
+    code: &'a str,
+    redirect_uri: &'a str,
+    client_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<&'a str>,
+    code_verifier: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct OidcTokenResponse {
+    id_token: String,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// The claims of the ID token and the userinfo endpoint OurChat cares about
+#[derive(Debug, Deserialize)]
+struct OidcClaims {
+    sub: String,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    picture: Option<String>,
+}
+
+struct OidcProvider {
+    cfg: OidcProviderCfg,
+    redirect_uri: String,
+    http: reqwest::Client,
+    discovery: tokio::sync::OnceCell<DiscoveryDocument>,
+    /// The signing keys and when they were fetched
+    jwks: parking_lot::Mutex<Option<(JwkSet, Instant)>>,
+}
+
+impl OidcProvider {
+    async fn discovery(&self) -> anyhow::Result<&DiscoveryDocument> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let issuer = self.cfg.issuer.trim_end_matches('/');
+                let document: DiscoveryDocument = self
+                    .http
+                    .get(format!("{issuer}/.well-known/openid-configuration"))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+                    .context("invalid discovery document")?;
+                ensure!(
+                    document.issuer.trim_end_matches('/') == issuer,
+                    "discovery document of {} is issued by {}",
+                    issuer,
+                    document.issuer
+                );
+                Ok(document)
+            })
+            .await
+    }
+
+    /// The key signing the ID token, from the cached keys if they are fresh and know it
+    async fn signing_key(
+        &self,
+        discovery: &DiscoveryDocument,
+        kid: Option<&str>,
+    ) -> anyhow::Result<Jwk> {
+        let find = |jwks: &JwkSet| {
+            match kid {
+                Some(kid) => jwks.find(kid),
+                None => jwks.keys.first(),
+            }
+            .cloned()
+        };
+        if let Some((jwks, fetched)) = &*self.jwks.lock()
+            && fetched.elapsed() < JWKS_TTL
+            && let Some(jwk) = find(jwks)
+        {
+            return Ok(jwk);
+        }
+        let jwks: JwkSet = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("invalid jwks")?;
+        let jwk = find(&jwks);
+        *self.jwks.lock() = Some((jwks, Instant::now()));
+        jwk.context("no key in the jwks matches the ID token")
+    }
+
+    /// Check the signature, issuer, audience, expiry and nonce of the ID token
+    async fn validate_id_token(
+        &self,
+        discovery: &DiscoveryDocument,
+        id_token: &str,
+        nonce: &str,
+    ) -> anyhow::Result<OidcClaims> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        let jwk = self.signing_key(discovery, header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk)?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.cfg.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<OidcClaims>(id_token, &key, &validation)?.claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            bail!("nonce of the ID token doesn't match");
+        }
+        Ok(claims)
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthProvider for OidcProvider {
+    fn name(&self) -> &str {
+        &self.cfg.name
+    }
+
+    async fn authorize_url(&self, request: &AuthorizationRequest) -> anyhow::Result<String> {
+        let discovery = self.discovery().await?;
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.cfg.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.cfg.scopes.join(" "))
+            .append_pair("state", &request.state)
+            .append_pair("nonce", &request.nonce)
+            .append_pair("code_challenge", &request.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    async fn fetch_identity(
+        &self,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> anyhow::Result<ExternalIdentity> {
+        let discovery = self.discovery().await?;
+        let token_response: OidcTokenResponse = self
+            .http
+            .post(&discovery.token_endpoint)
+            .header("Accept", "application/json")
+            .form(&OidcTokenRequest {
+                grant_type: "authorization_code",
+                code,
+                redirect_uri: &self.redirect_uri,
+                client_id: &self.cfg.client_id,
+                client_secret: self.cfg.client_secret.as_deref(),
+                code_verifier: &request.code_verifier,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut claims = self
+            .validate_id_token(discovery, &token_response.id_token, &request.nonce)
+            .await?;
+
+        // Some providers only put the profile into the userinfo
+        if claims.email.is_none()
+            && let (Some(endpoint), Some(access_token)) =
+                (&discovery.userinfo_endpoint, &token_response.access_token)
+        {
+            let userinfo: OidcClaims = self
+                .http
+                .get(endpoint)
+                .bearer_auth(access_token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            ensure!(
+                userinfo.sub == claims.sub,
+                "userinfo is about another subject"
+            );
+            claims.email = userinfo.email;
+            claims.email_verified = userinfo.email_verified;
+            claims.name = claims.name.or(userinfo.name);
+            claims.picture = claims.picture.or(userinfo.picture);
+        }
+        Ok(ExternalIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified.unwrap_or(false),
+            name: claims.name,
+            avatar: claims.picture,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_challenge_rfc7636() {
+        // The example of RFC 7636 appendix B
+        let request = AuthorizationRequest {
+            state: String::new(),
+            nonce: String::new(),
+            code_verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string(),
+        };
+        assert_eq!(
+            request.code_challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
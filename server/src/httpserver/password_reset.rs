@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::SharedData;
+use crate::httpserver::EmailClientType;
+use anyhow::Context;
+use base::constants;
+use base::rabbitmq::http_server::PasswordResetRecord;
+
+/// Send the password reset token to the user, who enters it into the client to reset the
+/// password
+pub async fn send_password_reset_email(
+    email_client: &Option<EmailClientType>,
+    data: PasswordResetRecord,
+    shared_data: &Arc<SharedData>,
+) -> anyhow::Result<()> {
+    let Some(email_client) = email_client else {
+        tracing::warn!("email is not configured, cannot send the password reset email");
+        return Ok(());
+    };
+    let user_mailbox = format!("User <{}>", data.email);
+    let user_mailbox = user_mailbox
+        .parse()
+        .with_context(|| format!("email {user_mailbox} parse failed"))?;
+    let expiry = shared_data.cfg().user_setting.password_reset_expiry;
+    let text_body = format!(
+        "Your password reset token is \"{}\", it expires in {}. If you did not request a password reset, please ignore this email.",
+        data.token,
+        humantime_serde::re::humantime::format_duration(expiry)
+    );
+    email_client
+        .send(
+            user_mailbox,
+            format!("{} Password Reset", constants::APP_NAME),
+            text_body,
+            None,
+        )
+        .await
+}
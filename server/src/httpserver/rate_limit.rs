@@ -0,0 +1,134 @@
+//! Rate limiting shared by all the instances.
+//!
+//! Every user, or every IP address for the requests without a valid token, has a bucket in
+//! redis checked with the generic cell rate algorithm, so the limit holds whichever instance the
+//! requests reach. gRPC requests are refused with a gRPC status, the others with `429`.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use base::constants::JWT_HEADER;
+use base::database::DbPool;
+use http::{StatusCode, header};
+use tracing::debug;
+
+use crate::db::redis_mappings::map_rate_limit_to_redis;
+use crate::process::{self, error_msg};
+
+/// Takes a cell from the bucket, returning how many milliseconds to wait if it is empty and 0
+/// otherwise. The time of redis is used so that the clocks of the instances don't matter.
+static GCRA_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local interval = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then
+    tat = now
+end
+local new_tat = tat + interval
+local allow_at = new_tat - burst * interval
+if now < allow_at then
+    return allow_at - now
+end
+redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+return 0
+"#,
+    )
+});
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    db_pool: DbPool,
+    burst: u32,
+    replenish_duration: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(db_pool: DbPool, burst: u32, replenish_duration: Duration) -> Self {
+        Self {
+            db_pool,
+            burst,
+            replenish_duration,
+        }
+    }
+
+    /// Take a request from the bucket of the key, returning how long to wait if there is none
+    /// left
+    pub async fn check(&self, key: &str) -> Result<Option<Duration>, redis::RedisError> {
+        let wait: u64 = GCRA_SCRIPT
+            .key(map_rate_limit_to_redis(key))
+            .arg(self.replenish_duration.as_millis() as u64)
+            .arg(self.burst)
+            .invoke_async(&mut self.db_pool.redis())
+            .await?;
+        Ok((wait > 0).then(|| Duration::from_millis(wait)))
+    }
+}
+
+/// The bucket of the request, the user if it carries a valid token and the address otherwise
+fn limit_key(request: &Request, addr: SocketAddr) -> String {
+    request
+        .headers()
+        .get(JWT_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .and_then(|token| process::check_token(token).ok())
+        .map_or_else(
+            || format!("ip:{}", addr.ip()),
+            |jwt| format!("user:{}", jwt.id),
+        )
+}
+
+fn is_grpc(request: &Request) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/grpc"))
+}
+
+fn too_many_requests(grpc: bool, wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil() as u64;
+    let response = if grpc {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .header(
+                "grpc-status",
+                (tonic::Code::ResourceExhausted as i32).to_string(),
+            )
+            .header("grpc-message", error_msg::TOO_MANY_REQUESTS)
+    } else {
+        Response::builder().status(StatusCode::TOO_MANY_REQUESTS)
+    };
+    response
+        .header(header::RETRY_AFTER, retry_after.to_string())
+        .body(Body::empty())
+        .expect("Failed to build rate limit response")
+}
+
+pub async fn rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = limit_key(&request, addr);
+    match limiter.check(&key).await {
+        Ok(None) => {}
+        Ok(Some(wait)) => {
+            debug!("Refusing request of {} for {:?}", key, wait);
+            return too_many_requests(is_grpc(&request), wait);
+        }
+        // Not worth refusing every request while redis is unavailable
+        Err(e) => tracing::error!("failed to check the rate limit of {}:{}", key, e),
+    }
+    next.run(request).await
+}
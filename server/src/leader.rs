@@ -0,0 +1,92 @@
+//! Electing the instance which runs the background jobs of the cluster.
+//!
+//! The instances race for a lease in the database. The one holding it renews it regularly, and
+//! the others take it over once it has expired, so the jobs go on when the leader dies.
+
+use std::time::{Duration, Instant};
+
+use base::shutdown::ShutdownRev;
+use parking_lot::Mutex;
+use sea_orm::DatabaseConnection;
+use tokio::select;
+
+use crate::SERVER_INFO;
+use crate::db::leader_lease::{release_lease, try_acquire_lease};
+
+#[derive(Debug)]
+pub struct LeaderElection {
+    node_id: String,
+    lease: Duration,
+    /// Until when this node surely holds the lease, none if it doesn't
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl LeaderElection {
+    pub fn new(lease: Duration) -> Self {
+        Self {
+            node_id: format!(
+                "{}-{}",
+                SERVER_INFO.machine_id,
+                uuid::Uuid::new_v4().simple()
+            ),
+            lease,
+            deadline: Mutex::new(None),
+        }
+    }
+
+    /// The id of this node, unique among the instances
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Whether this node should run the background jobs of the cluster
+    pub fn is_leader(&self) -> bool {
+        self.deadline
+            .lock()
+            .is_some_and(|deadline| Instant::now() < deadline)
+    }
+
+    fn set_leader(&self, deadline: Option<Instant>) {
+        let was_leader = self.is_leader();
+        *self.deadline.lock() = deadline;
+        match (was_leader, deadline.is_some()) {
+            (false, true) => tracing::info!("node {} becomes the leader", self.node_id),
+            (true, false) => tracing::warn!("node {} is no longer the leader", self.node_id),
+            _ => {}
+        }
+    }
+
+    /// Take or renew the lease once
+    async fn campaign(&self, db_conn: &DatabaseConnection) {
+        // The lease is counted from before the request, the database can only have granted less
+        let start = Instant::now();
+        match try_acquire_lease(&self.node_id, self.lease, db_conn).await {
+            Ok(Some(_)) => self.set_leader(Some(start + self.lease)),
+            Ok(None) => self.set_leader(None),
+            Err(e) => {
+                tracing::error!("failed to renew the leader lease:{e}");
+                // Nothing tells whether the lease is still held, so stop acting as the leader
+                self.set_leader(None);
+            }
+        }
+    }
+
+    /// Keep campaigning for the lease until shutting down, then give it up if held
+    pub async fn run(
+        &self,
+        db_conn: DatabaseConnection,
+        mut shutdown_rev: ShutdownRev,
+    ) -> anyhow::Result<()> {
+        // Renewed well before it expires, so a slow round doesn't lose it
+        let mut interval = tokio::time::interval(self.lease / 3);
+        loop {
+            select! {
+                _ = interval.tick() => self.campaign(&db_conn).await,
+                _ = shutdown_rev.wait_shutting_down() => break,
+            }
+        }
+        self.set_leader(None);
+        release_lease(&self.node_id, &db_conn).await?;
+        Ok(())
+    }
+}
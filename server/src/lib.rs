@@ -6,11 +6,13 @@ pub mod config;
 pub mod db;
 pub mod helper;
 pub mod httpserver;
+pub mod leader;
 pub mod matrix;
 pub mod metrics;
 pub mod process;
 pub mod rabbitmq;
 mod server;
+pub mod storage;
 pub mod webrtc;
 
 use crate::config::{Cfg, MainCfg};
@@ -20,7 +22,7 @@ use base::database::DbPool;
 use base::log;
 use base::shutdown::ShutdownSdr;
 use base::wrapper::JobSchedulerWrapper;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dashmap::DashMap;
 use parking_lot::{Mutex, Once, RwLock};
 use rand::RngExt;
@@ -45,6 +47,38 @@ pub struct ArgsParser {
     pub shared_cfg: ParserCfg,
     #[arg(long, help = "server info file path", default_value = SERVER_INFO_PATH)]
     pub server_info: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Maintenance commands run instead of the server
+#[derive(Debug, Subcommand, Clone)]
+pub enum Command {
+    /// Move the uploaded files to another storage backend, run it while the server is stopped
+    MigrateStorage {
+        #[arg(long)]
+        from: config::StorageBackendKind,
+        #[arg(long)]
+        to: config::StorageBackendKind,
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "keep the files in the source backend"
+        )]
+        keep_source: bool,
+    },
+}
+
+impl Command {
+    pub async fn run(self, cfg: &Cfg) -> anyhow::Result<()> {
+        match self {
+            Command::MigrateStorage {
+                from,
+                to,
+                keep_source,
+            } => storage::migrate::run_migrate_storage(cfg, from, to, keep_source).await,
+        }
+    }
 }
 
 impl Default for ArgsParser {
@@ -54,6 +88,7 @@ impl Default for ArgsParser {
             ip: None,
             shared_cfg: ParserCfg::default(),
             server_info: PathBuf::from(SERVER_INFO_PATH),
+            command: None,
         }
     }
 }
@@ -70,7 +105,7 @@ pub struct ParserCfg {
     pub clear: bool,
     #[arg(
         long,
-        help = "enable when server is maintaining",
+        help = "put the server, every instance of it, under maintenance",
         default_value_t = false
     )]
     pub maintaining: bool,
@@ -299,19 +334,26 @@ pub struct SharedData {
     pub verify_record: DashMap<String, Arc<tokio::sync::Notify>>,
     pub file_sys: db::file_storage::FileSys,
     pub upload_local_state: DashMap<String, process::LocalUploadState>,
-    maintaining: Mutex<bool>,
+    server_status: Mutex<db::server_status::ServerStatusRecord>,
     sched: tokio::sync::Mutex<JobSchedulerWrapper>,
     pub metrics: Option<Arc<metrics::recorder::OurChatRecorder>>,
+    pub leader: Arc<leader::LeaderElection>,
 }
 
 impl SharedData {
-    pub fn set_maintaining(&self, maintaining: bool) {
-        *self.maintaining.lock() = maintaining;
-        info!("set maintaining:{}", maintaining);
+    pub fn set_server_status(&self, status: db::server_status::ServerStatusRecord) {
+        info!("set server status:{:?}", status);
+        *self.server_status.lock() = status;
+    }
+
+    pub fn server_status(&self) -> db::server_status::ServerStatusRecord {
+        self.server_status.lock().clone()
     }
 
     pub fn get_maintaining(&self) -> bool {
-        *self.maintaining.lock()
+        self.server_status
+            .lock()
+            .is_maintaining_at(chrono::Utc::now())
     }
 
     pub fn cfg(&self) -> parking_lot::RwLockReadGuard<'_, Cfg> {
@@ -388,6 +430,12 @@ impl Application {
             );
         }
         info!("Machine ID: {}", SERVER_INFO.machine_id);
+        for option in &main_cfg.deprecated_options {
+            tracing::warn!(
+                "the config option `{option}` is deprecated and ignored, the instances elect the \
+                 leader running the background jobs by themselves"
+            );
+        }
         let maintaining = main_cfg.cmd_args.maintaining;
 
         if let Some(new_ip) = parser.ip {
@@ -405,18 +453,30 @@ impl Application {
         // connect to db
         let db_pool = DbPool::build(&cfg.db_cfg, &cfg.redis_cfg, true).await?;
         db_pool.init().await?;
+        if maintaining {
+            db::server_status::save_server_status(
+                &db::server_status::ServerStatusRecord::maintaining(""),
+                &db_pool.db_pool,
+            )
+            .await?;
+        }
+        let server_status = db::server_status::get_server_status(&db_pool.db_pool).await?;
         // Bootstrap initial admin if configured
         db::manager::bootstrap_initial_admin(&cfg.main_cfg.initial_admin_ocid, &db_pool.db_pool)
             .await?;
         // connect to rabbitmq
         let rmq_pool = cfg.rabbitmq_cfg.build().await?;
-        if cfg.main_cfg.unique_instance() {
-            rabbitmq::init(&rmq_pool).await?;
-        }
+        rabbitmq::init(&rmq_pool).await?;
+        // The jobs of the whole cluster only run on the leader
+        let leader = Arc::new(leader::LeaderElection::new(
+            cfg.main_cfg.leader_lease_duration,
+        ));
+        info!("Node ID: {}", leader.node_id());
 
         let sched = tokio::sync::Mutex::new(JobSchedulerWrapper::new(
             tokio_cron_scheduler::JobScheduler::new().await?,
         ));
+        // Every node cleans the logs it has written itself
         log::add_clean_to_scheduler(
             constants::OURCHAT_LOG_PREFIX,
             cfg.main_cfg.log_keep,
@@ -431,13 +491,34 @@ impl Application {
         process::webrtc::clean_rooms(
             cfg.main_cfg.voip.empty_room_keep_duration,
             db_pool.clone(),
+            leader.clone(),
+            sched.lock().await,
+        )
+        .await?;
+        process::deliver_scheduled_msgs(
+            cfg.main_cfg.scheduled_msg_check_interval,
+            cfg.main_cfg.room_key_duration,
+            cfg.main_cfg.refresh_token_expire,
+            db_pool.clone(),
+            rmq_pool.clone(),
+            sched.lock().await,
+        )
+        .await?;
+        let storage = storage::build_storage(&cfg.main_cfg, cfg.main_cfg.storage.backend)?;
+        info!("Files are stored in the {:?} backend", storage.kind());
+        process::clean_expired_msgs(
+            cfg.main_cfg.expired_msg_clean_interval,
+            db_pool.clone(),
+            rmq_pool.clone(),
+            storage.clone(),
             sched.lock().await,
         )
         .await?;
 
         let cfg = Arc::new(RwLock::new(cfg));
         // Create FileSys with the temporary SharedData
-        let file_sys = db::file_storage::FileSys::new(db_pool.db_pool.clone(), cfg.clone());
+        let file_sys =
+            db::file_storage::FileSys::new(db_pool.db_pool.clone(), cfg.clone(), storage);
 
         // Create metrics collector
         let enable = cfg.read().main_cfg.enable_metrics;
@@ -458,9 +539,10 @@ impl Application {
             verify_record: DashMap::new(),
             file_sys,
             upload_local_state: DashMap::new(),
-            maintaining: Mutex::new(maintaining),
+            server_status: Mutex::new(server_status),
             sched,
             metrics,
+            leader,
         });
 
         Ok(Self {
@@ -526,7 +608,34 @@ impl Application {
                 .await
         });
 
-        let job = self.shared.file_sys.generate_job().await?;
+        let status_rev = self.abort_sender.new_receiver(
+            "server status",
+            "listen to rabbitmq to get the changes of the server status",
+        );
+        tokio::spawn(process::listen_server_status(
+            self.rabbitmq.clone(),
+            self.pool.db_pool.clone(),
+            self.shared.clone(),
+            status_rev,
+        ));
+
+        let leader_rev = self.abort_sender.new_receiver(
+            "leader election",
+            "keep the lease of the leader running the background jobs",
+        );
+        let leader = self.shared.leader.clone();
+        let db_conn = self.pool.db_pool.clone();
+        tokio::spawn(async move {
+            if let Err(e) = leader.run(db_conn, leader_rev).await {
+                tracing::error!("leader election error:{e}");
+            }
+        });
+
+        let job = self
+            .shared
+            .file_sys
+            .generate_job(self.shared.leader.clone())
+            .await?;
         // Start the database file system
         self.shared.sched.lock().await.add(job).await?;
 
@@ -536,6 +645,7 @@ impl Application {
             let metrics_job = generate_metrics_snapshot_job(
                 metrics.clone(),
                 self.pool.clone(),
+                self.shared.leader.clone(),
                 metrics_snapshot_interval,
             )?;
             self.shared.sched.lock().await.add(metrics_job).await?;
@@ -573,10 +683,12 @@ fn init() {
 
 /// Generate a job for periodic metrics snapshots
 ///
-/// Creates a scheduled job that saves metrics to the database at the configured interval
+/// Creates a scheduled job that saves metrics to the database at the configured interval. The
+/// metrics belong to this process, so every node saves its own snapshot tagged with its node id
 fn generate_metrics_snapshot_job(
     metrics: Arc<metrics::recorder::OurChatRecorder>,
     db_pool: base::database::DbPool,
+    leader: Arc<leader::LeaderElection>,
     interval: Duration,
 ) -> anyhow::Result<tokio_cron_scheduler::Job> {
     use tokio_cron_scheduler::Job;
@@ -584,8 +696,9 @@ fn generate_metrics_snapshot_job(
     Ok(Job::new_repeated_async(interval, move |_uuid, _l| {
         let metrics = metrics.clone();
         let db_pool = db_pool.clone();
+        let leader = leader.clone();
         Box::pin(async move {
-            if let Err(e) = save_metrics_snapshot_worker(metrics, db_pool).await {
+            if let Err(e) = save_metrics_snapshot_worker(metrics, db_pool, leader.node_id()).await {
                 tracing::error!("Failed to save metrics snapshot: {}", e);
             }
         })
@@ -595,6 +708,7 @@ fn generate_metrics_snapshot_job(
 async fn save_metrics_snapshot_worker(
     metrics: Arc<metrics::recorder::OurChatRecorder>,
     db_pool: base::database::DbPool,
+    node_id: &str,
 ) -> anyhow::Result<()> {
     // Update system metrics before saving
     metrics.update_system_metrics();
@@ -602,6 +716,13 @@ async fn save_metrics_snapshot_worker(
     let metrics_data = metrics
         .get_monitoring_metrics(&db_pool.db_pool, &db_pool.pg_pool, true, true)
         .await;
-    db::metrics::save_metrics_snapshot(&db_pool.db_pool, &metrics_data, chrono::Utc::now()).await?;
+    db::metrics::save_metrics_snapshot(
+        &db_pool.db_pool,
+        helper::generate_metrics_snapshot_id()?,
+        node_id,
+        &metrics_data,
+        chrono::Utc::now(),
+    )
+    .await?;
     Ok(())
 }
@@ -10,6 +10,9 @@ async fn main() -> anyhow::Result<()> {
     let dotenv_err = dotenvy::dotenv();
     *RUN_AS_STANDALONE.lock() = true;
     let config = server::get_configuration(ARG_PARSER.shared_cfg.config.clone())?;
+    if let Some(command) = ARG_PARSER.command.clone() {
+        return command.run(&config).await;
+    }
     let mut application = server::Application::build(ARG_PARSER.clone(), config).await?;
     if let Err(e) = dotenv_err {
         tracing::warn!(".env file is not loaded: {:?}", e)
@@ -2,7 +2,7 @@
 //!
 //! For grpc development, a template of unary calling is provided as follows:
 //! ```ignore
-//! use crate::{process::error_msg::SERVER_ERROR, server::RpcServer};
+//! use crate::{process::RpcError, server::RpcServer};
 //! use base::constants::ID;
 //! use pb::service::ourchat::session::set_role::v1::{SetRoleRequest, SetRoleResponse};
 //! use tonic::{Request, Response, Status};
@@ -14,31 +14,15 @@
 //! ) -> Result<Response<SetRoleResponse>, Status> {
 //!     match set_role_impl(server, id, request).await {
 //!         Ok(res) => Ok(Response::new(res)),
-//!         Err(e) => match e {
-//!             SetRoleErr::Db(_) | SetRoleErr::Internal(_) => {
-//!                 tracing::error!("{}", e);
-//!                 Err(Status::internal(SERVER_ERROR))
-//!             }
-//!             SetRoleErr::Status(status) => Err(status),
-//!         },
+//!         Err(e) => Err(e.into()),
 //!     }
 //! }
 //!
-//! #[derive(thiserror::Error, Debug)]
-//! enum SetRoleErr {
-//!     #[error("database error:{0:?}")]
-//!     Db(#[from] sea_orm::DbErr),
-//!     #[error("status error:{0:?}")]
-//!     Status(#[from] Status),
-//!     #[error("internal error:{0:?}")]
-//!     Internal(#[from] anyhow::Error),
-//! }
-//!
 //! async fn set_role_impl(
 //!     server: &RpcServer,
 //!     id: ID,
 //!     request: Request<SetRoleRequest>,
-//! ) -> Result<SetRoleResponse, SetRoleErr> {
+//! ) -> Result<SetRoleResponse, RpcError> {
 //!     todo!()
 //! }
 //! ```
@@ -46,15 +30,22 @@
 pub mod auth;
 pub mod basic;
 mod delete_file;
+pub mod device;
+pub mod device_keys;
 pub mod error_msg;
-mod files;
+pub mod files;
 mod friends;
 pub mod get_account_info;
+pub mod key_verification;
 mod message;
+pub mod oauth;
+pub mod password;
 pub mod register;
 mod server_manage;
 mod session;
 mod set_self_info;
+pub mod token;
+pub mod totp;
 pub mod unregister;
 pub mod verify;
 pub mod voip;
@@ -79,6 +70,8 @@ use tonic::Request;
 
 pub use delete_file::delete_file;
 pub use files::download::download;
+pub use files::file_info::get_file_info;
+pub use files::storage_usage::{get_storage_usage, list_user_files};
 pub use files::{
     upload::{LocalUploadState, upload},
     upload_chunked::{cancel_upload, complete_upload, start_upload, upload_chunk},
@@ -87,7 +80,21 @@ pub use friends::{
     accept_friend_invitation::accept_friend_invitation, add_friend::add_friend,
     delete_friend::delete_friend, set_friend_info::set_friend_info,
 };
-pub use message::{fetch_user_msg::fetch_user_msg, recall::recall_msg, send_msg::send_msg};
+pub use message::{
+    edit::edit_msg,
+    expire::clean_expired_msgs,
+    fetch_thread::fetch_thread,
+    fetch_user_msg::fetch_user_msg,
+    get_session_history::get_session_history,
+    pin::{list_pinned_msgs, pin_msg, unpin_msg},
+    reaction::{add_reaction, remove_reaction},
+    read::{get_unread_counts, mark_read},
+    recall::recall_msg,
+    scheduled::{cancel_scheduled_msg, deliver_scheduled_msgs, list_scheduled_msgs},
+    search::search_messages,
+    send_msg::send_msg,
+    typing::set_typing,
+};
 pub use server_manage::{
     announcement::{
         add_announcement::add_announcement,
@@ -98,13 +105,14 @@ pub use server_manage::{
     config::set_config::set_config,
     delete_account::delete_account,
     metrics::{get_historical_metrics, get_monitoring_metrics},
-    set_server_status::set_server_status,
+    set_server_status::{listen_server_status, set_server_status},
     user_manage::assign_server_role::assign_server_role,
     user_manage::ban_user::server_ban_user,
     user_manage::list_server_role_permissions::list_server_role_permissions,
     user_manage::list_server_roles::list_server_roles,
     user_manage::list_user_server_roles::list_user_server_roles,
     user_manage::remove_server_role::remove_server_role,
+    user_manage::set_user_files_limit::set_user_files_limit,
     user_manage::unban_user::server_unban_user,
 };
 pub use session::{
@@ -141,7 +149,7 @@ use crate::SERVER_INFO;
 use crate::db::messages::MsgError;
 use crate::db::redis_mappings::redis_key;
 use crate::db::session::get_members;
-use crate::process::error_msg::SERVER_ERROR;
+use crate::process::error_msg::{PERMISSION_DENIED, SERVER_ERROR, not_found};
 use crate::rabbitmq::USER_MSG_BROADCAST_EXCHANGE;
 use crate::rabbitmq::USER_MSG_DIRECT_EXCHANGE;
 use crate::rabbitmq::generate_route_key;
@@ -161,17 +169,25 @@ pub mod db {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JWTdata {
     pub id: ID,
+    /// Id of the login the token is issued for, used to revoke the token
+    pub sid: String,
+    iat: i64,
     exp: i64,
 }
 
-const EXPIRE_TIME: Duration = Duration::from_days(5);
-
-pub fn generate_access_token(id: ID) -> Result<String, jsonwebtoken::errors::Error> {
+pub fn generate_access_token(
+    id: ID,
+    sid: &str,
+    expire: Duration,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = chrono::Utc::now().timestamp();
     jsonwebtoken::encode(
         &jsonwebtoken::Header::default(),
         &JWTdata {
             id,
-            exp: chrono::Utc::now().timestamp() + EXPIRE_TIME.as_secs() as i64,
+            sid: sid.to_string(),
+            iat: now,
+            exp: now + expire.as_secs() as i64,
         },
         &EncodingKey::from_secret(SERVER_INFO.secret.as_bytes()),
     )
@@ -230,6 +246,14 @@ pub fn get_id_from_req<T>(req: &Request<T>) -> Option<ID> {
         .map(ID)
 }
 
+/// Get the session id of the login which issued the access token of the request
+pub fn get_sid_from_req<T>(req: &Request<T>) -> Option<String> {
+    req.metadata()
+        .get("sid")
+        .and_then(|sid| sid.to_str().ok())
+        .map(str::to_string)
+}
+
 pub fn get_id_from_req_or_err<T: Debug>(req: &Request<T>) -> Result<ID, tonic::Status> {
     get_id_from_req(req).ok_or_else(|| {
         tracing::error!("Cannot extract id from request {0:?}", req);
@@ -251,6 +275,8 @@ pub async fn check_user_exist(
 pub enum Dest {
     User(ID),
     Session(SessionID),
+    /// All members of the session except the user
+    SessionExcept(SessionID, ID),
     All,
 }
 
@@ -288,6 +314,23 @@ async fn transmit_msg(
                     .await?;
             }
         }
+        Dest::SessionExcept(id, except) => {
+            for i in get_members(id, db_connection).await? {
+                let dest_id: ID = i.user_id.into();
+                if dest_id == except {
+                    continue;
+                }
+                rabbitmq_connection
+                    .basic_publish(
+                        USER_MSG_DIRECT_EXCHANGE,
+                        &generate_route_key(dest_id),
+                        BasicPublishOptions::default(),
+                        buf.as_ref(),
+                        Default::default(),
+                    )
+                    .await?;
+            }
+        }
         Dest::All => {
             rabbitmq_connection
                 .basic_publish(
@@ -327,6 +370,51 @@ impl From<MsgError> for MsgInsTransmitErr {
     }
 }
 
+/// The error shared by the rpc handlers, a `Status` is returned to the client as it is, while
+/// the others are logged and reported as a server error
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("database error:{0:?}")]
+    Db(#[from] sea_orm::DbErr),
+    #[error("redis error:{0:?}")]
+    Redis(#[from] redis::RedisError),
+    #[error("status:{0:?}")]
+    Status(#[from] tonic::Status),
+    #[error("internal error:{0:?}")]
+    Internal(#[from] anyhow::Error),
+    #[error("message error:{0:?}")]
+    MessageError(#[from] MsgInsTransmitErr),
+}
+
+impl From<MsgError> for RpcError {
+    fn from(value: MsgError) -> Self {
+        match value {
+            MsgError::DbError(db_err) => Self::Db(db_err),
+            MsgError::PermissionDenied => {
+                Self::Status(tonic::Status::permission_denied(PERMISSION_DENIED))
+            }
+            MsgError::NotFound => Self::Status(tonic::Status::not_found(not_found::MSG)),
+            MsgError::UnknownError(error) => Self::Internal(error),
+            MsgError::SerdeError(error) => Self::Internal(error.into()),
+        }
+    }
+}
+
+impl From<RpcError> for tonic::Status {
+    fn from(value: RpcError) -> Self {
+        match value {
+            RpcError::Status(status) => status,
+            RpcError::Db(_)
+            | RpcError::Redis(_)
+            | RpcError::Internal(_)
+            | RpcError::MessageError(_) => {
+                tracing::error!("{}", value);
+                tonic::Status::internal(SERVER_ERROR)
+            }
+        }
+    }
+}
+
 /// Insert a new message record into the database and transmit it to RabbitMQ(Corresponding user).
 ///
 /// `sender_id` and `session_id` specify the sender and session of the message,
@@ -354,19 +442,47 @@ pub async fn message_insert_and_transmit(
     db_conn: &impl ConnectionTrait,
     rmq_chan: &mut deadpool_lapin::lapin::Channel,
 ) -> Result<message_records::Model, MsgInsTransmitErr> {
-    let msg_model = crate::db::messages::insert_msg_record(
+    expiring_message_insert_and_transmit(
+        sender_id,
+        session_id,
+        msg,
+        dest,
+        is_encrypted,
+        None,
+        db_conn,
+        rmq_chan,
+    )
+    .await
+}
+
+/// Same as `message_insert_and_transmit`, but the message record is deleted at `expire_time`.
+///
+/// It is used for the notifications about a self-destructing message, which must not outlive it.
+#[allow(clippy::too_many_arguments)]
+pub async fn expiring_message_insert_and_transmit(
+    sender_id: Option<ID>,
+    session_id: Option<SessionID>,
+    msg: RespondEventType,
+    dest: Dest,
+    is_encrypted: bool,
+    expire_time: Option<pb::time::TimeStamp>,
+    db_conn: &impl ConnectionTrait,
+    rmq_chan: &mut deadpool_lapin::lapin::Channel,
+) -> Result<message_records::Model, MsgInsTransmitErr> {
+    let msg_model = crate::db::messages::insert_msg_record_with_expire_time(
         sender_id,
         session_id,
         msg.clone(),
         is_encrypted,
+        expire_time,
         db_conn,
-        false,
     )
     .await?;
     let fetch_response = FetchMsgsResponse {
         msg_id: msg_model.msg_id as u64,
         time: Some(msg_model.time.into()),
         respond_event_type: Some(msg),
+        reactions: vec![],
     };
     transmit_msg(fetch_response, dest, rmq_chan, db_conn).await?;
     Ok(msg_model)
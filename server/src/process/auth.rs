@@ -1,7 +1,15 @@
-use super::{error_msg::not_found, generate_access_token};
+use super::device::DeviceInfo;
+use super::error_msg::not_found;
+use super::token::{NewLogin, TokenExpire};
+use super::totp::{
+    SecondFactor, TotpSecretKey, check_second_factor, create_totp_challenge, finish_totp_challenge,
+    totp_challenge_owner,
+};
 use crate::db::redis_mappings::map_failed_login_to_redis;
+use crate::db::user::get_account_info_db;
 use crate::process::error_msg::{
-    ACCOUNT_LOCKED, EMAIL_NOT_VERIFIED, MISSING_AUTH_TYPE, WRONG_PASSWORD,
+    ACCOUNT_LOCKED, EMAIL_NOT_VERIFIED, MISSING_AUTH_TYPE, MISSING_TOTP_CODE,
+    TOTP_CHALLENGE_INVALID, WRONG_PASSWORD, WRONG_TOTP_CODE,
 };
 use crate::{
     db::helper::is_conflict, helper, process::error_msg::SERVER_ERROR, server::AuthServiceProvider,
@@ -12,6 +20,7 @@ use base::constants::ID;
 use base::database::DbPool;
 use entities::{prelude::*, user};
 use pb::service::auth::authorize::v1::{AuthRequest, AuthResponse, auth_request::Account};
+use pb::service::auth::totp::v1::{AuthTotpRequest, auth_totp_request};
 use sea_orm::{ColumnTrait, DbErr, EntityTrait, QueryFilter};
 use tonic::{Response, Status};
 
@@ -27,6 +36,12 @@ enum AuthError {
     MissingAuthType,
     #[error("email not verified")]
     EmailNotVerified,
+    #[error("wrong totp code")]
+    WrongTotpCode,
+    #[error("missing totp code")]
+    MissingTotpCode,
+    #[error("totp challenge invalid")]
+    TotpChallengeInvalid,
     #[error("db error:{0:?}")]
     DbError(#[from] DbErr),
     #[error("Unknown Error:{0:?}")]
@@ -43,23 +58,30 @@ async fn check_account_locked(
     redis_conn: &mut impl redis::AsyncCommands,
     max_attempts: u32,
 ) -> Result<(), AuthError> {
-    let key = map_failed_login_to_redis(user_id);
-    let attempts: Option<u32> = redis_conn.get(&key).await?;
-
-    if let Some(attempts) = attempts
-        && attempts >= max_attempts
-    {
+    if is_account_locked(user_id, redis_conn, max_attempts).await? {
         return Err(AuthError::AccountLocked);
     }
     Ok(())
 }
 
+/// Whether the user account is locked due to too many failed attempts of logging in or of
+/// entering the password otherwise
+pub(crate) async fn is_account_locked(
+    user_id: ID,
+    redis_conn: &mut impl redis::AsyncCommands,
+    max_attempts: u32,
+) -> Result<bool, redis::RedisError> {
+    let key = map_failed_login_to_redis(user_id);
+    let attempts: Option<u32> = redis_conn.get(&key).await?;
+    Ok(attempts.is_some_and(|attempts| attempts >= max_attempts))
+}
+
 /// Increment the failed login counter for a user.
-async fn increment_failed_login(
+pub(crate) async fn increment_failed_login(
     user_id: ID,
     redis_conn: &mut impl redis::AsyncCommands,
     lock_duration_seconds: u64,
-) -> Result<(), AuthError> {
+) -> Result<(), redis::RedisError> {
     let key = map_failed_login_to_redis(user_id);
     let _: isize = redis_conn.incr(&key, 1).await?;
 
@@ -75,10 +97,10 @@ async fn increment_failed_login(
 }
 
 /// Clear failed login attempts for a user after successful login.
-async fn clear_failed_login(
+pub(crate) async fn clear_failed_login(
     user_id: ID,
     redis_conn: &mut impl redis::AsyncCommands,
-) -> Result<(), AuthError> {
+) -> Result<(), redis::RedisError> {
     let key = map_failed_login_to_redis(user_id);
     let _: () = redis_conn.del(&key).await?;
 
@@ -98,6 +120,7 @@ async fn auth_db(
     require_email_verification: bool,
     max_failed_attempts: u32,
     lock_duration_seconds: u64,
+    login: NewLogin<'_>,
 ) -> Result<AuthResponse, AuthError> {
     // Judge login type
     let login_type = match request.account {
@@ -142,16 +165,30 @@ async fn auth_db(
                 .context("computing and verifying password")?
                 .is_ok()
                 {
+                    if user.totp_enabled {
+                        // The failed attempts are kept until the TOTP code is verified as well,
+                        // otherwise the code could be guessed without limit
+                        let challenge =
+                            create_totp_challenge(user.id.into(), &mut redis_conn).await?;
+                        return Ok(AuthResponse {
+                            id: user.id as u64,
+                            token: String::new(),
+                            ocid: user.ocid.clone(),
+                            refresh_token: String::new(),
+                            totp_challenge: Some(challenge),
+                        });
+                    }
                     // Clear failed login attempts on successful login
                     clear_failed_login(user.id.into(), &mut redis_conn).await?;
 
-                    let token = generate_access_token(user.id.into())
-                        .with_context(|| format!("Couldn't generate jwt for {}", user.id))?;
+                    let tokens = login.start(user.id.into(), db_connection).await?;
 
                     Ok(AuthResponse {
                         id: user.id as u64,
-                        token,
+                        token: tokens.access_token,
                         ocid: user.ocid.clone(),
+                        refresh_token: tokens.refresh_token,
+                        totp_challenge: None,
                     })
                 } else {
                     // Increment failed login counter
@@ -172,46 +209,144 @@ async fn auth_db(
     }
 }
 
+/// Finish logging in with the TOTP code, or a recovery code, of the user.
+///
+/// A wrong code counts as a failed login attempt, so the code can't be guessed before the
+/// account is locked.
+async fn auth_totp_db(
+    request: AuthTotpRequest,
+    db_connection: &DbPool,
+    totp_key: &TotpSecretKey,
+    max_failed_attempts: u32,
+    lock_duration_seconds: u64,
+    login: NewLogin<'_>,
+) -> Result<AuthResponse, AuthError> {
+    let mut redis_conn = db_connection.redis();
+    let user_id = totp_challenge_owner(&request.challenge, &mut redis_conn)
+        .await?
+        .ok_or(AuthError::TotpChallengeInvalid)?;
+    check_account_locked(user_id, &mut redis_conn, max_failed_attempts).await?;
+    let user = get_account_info_db(user_id, &db_connection.db_pool)
+        .await?
+        .ok_or(AuthError::UserNotFound)?;
+    // TOTP may have been disabled since the challenge was created
+    let (Some(secret), true) = (user.totp_secret, user.totp_enabled) else {
+        return Err(AuthError::TotpChallengeInvalid);
+    };
+    let factor = match &request.code {
+        Some(auth_totp_request::Code::TotpCode(code)) => SecondFactor::Totp(code),
+        Some(auth_totp_request::Code::RecoveryCode(code)) => SecondFactor::Recovery(code),
+        None => return Err(AuthError::MissingTotpCode),
+    };
+    if !check_second_factor(user_id, totp_key, &secret, factor, db_connection).await? {
+        increment_failed_login(user_id, &mut redis_conn, lock_duration_seconds).await?;
+        tracing::info!("Failed totp attempt for user id {}", user_id);
+        return Err(AuthError::WrongTotpCode);
+    }
+    if !finish_totp_challenge(&request.challenge, &mut redis_conn).await? {
+        return Err(AuthError::TotpChallengeInvalid);
+    }
+    clear_failed_login(user_id, &mut redis_conn).await?;
+
+    let tokens = login.start(user_id, db_connection).await?;
+    Ok(AuthResponse {
+        id: user.id as u64,
+        token: tokens.access_token,
+        ocid: user.ocid,
+        refresh_token: tokens.refresh_token,
+        totp_challenge: None,
+    })
+}
+
+fn auth_error_to_status(e: AuthError) -> Status {
+    match e {
+        AuthError::WrongPassword => Status::unauthenticated(WRONG_PASSWORD),
+        AuthError::AccountLocked => Status::unauthenticated(ACCOUNT_LOCKED),
+        AuthError::EmailNotVerified => Status::unauthenticated(EMAIL_NOT_VERIFIED),
+        AuthError::MissingAuthType => Status::invalid_argument(MISSING_AUTH_TYPE),
+        AuthError::UserNotFound => Status::not_found(not_found::USER),
+        AuthError::WrongTotpCode => Status::unauthenticated(WRONG_TOTP_CODE),
+        AuthError::MissingTotpCode => Status::invalid_argument(MISSING_TOTP_CODE),
+        AuthError::TotpChallengeInvalid => Status::unauthenticated(TOTP_CHALLENGE_INVALID),
+        _ => {
+            tracing::error!("{}", e);
+            Status::internal(SERVER_ERROR)
+        }
+    }
+}
+
 /// Login Request
 pub async fn auth(
     server: &AuthServiceProvider,
     request: tonic::Request<AuthRequest>,
 ) -> Result<Response<AuthResponse>, Status> {
     // Copy config values and drop the lock guard before await
-    let (require_email_verification, max_failed_attempts, lock_duration_seconds) = {
+    let (require_email_verification, max_failed_attempts, lock_duration_seconds, token_expire) = {
         let cfg = server.shared_data.cfg();
         (
             cfg.main_cfg.require_email_verification,
             cfg.main_cfg.lock_account_after_failed_logins,
             cfg.main_cfg.lock_account_duration.as_secs(),
+            TokenExpire::from_cfg(&cfg.main_cfg),
         )
     };
 
+    let login = NewLogin {
+        expire: token_expire,
+        device: DeviceInfo::from_request(&request),
+        rabbitmq: &server.rabbitmq,
+    };
     match auth_db(
         request.into_inner(),
         &server.db,
         require_email_verification,
         max_failed_attempts,
         lock_duration_seconds,
+        login,
     )
     .await
     {
         Ok(ok_resp) => Ok(Response::new(ok_resp)),
-        Err(e) => Err(match e {
-            AuthError::WrongPassword => Status::unauthenticated(WRONG_PASSWORD),
-            AuthError::AccountLocked => Status::unauthenticated(ACCOUNT_LOCKED),
-            AuthError::EmailNotVerified => Status::unauthenticated(EMAIL_NOT_VERIFIED),
-            AuthError::MissingAuthType => Status::invalid_argument(MISSING_AUTH_TYPE),
-            AuthError::UserNotFound => Status::not_found(not_found::USER),
-            _ => {
-                tracing::error!("{}", e);
-                Status::internal(SERVER_ERROR)
-            }
-        }),
+        Err(e) => Err(auth_error_to_status(e)),
+    }
+}
+
+/// The second step of logging in for the users who turned TOTP on
+pub async fn auth_totp(
+    server: &AuthServiceProvider,
+    request: tonic::Request<AuthTotpRequest>,
+) -> Result<Response<AuthResponse>, Status> {
+    let (totp_key, max_failed_attempts, lock_duration_seconds, token_expire) = {
+        let cfg = server.shared_data.cfg();
+        (
+            TotpSecretKey::from_cfg(&cfg.main_cfg),
+            cfg.main_cfg.lock_account_after_failed_logins,
+            cfg.main_cfg.lock_account_duration.as_secs(),
+            TokenExpire::from_cfg(&cfg.main_cfg),
+        )
+    };
+
+    let login = NewLogin {
+        expire: token_expire,
+        device: DeviceInfo::from_request(&request),
+        rabbitmq: &server.rabbitmq,
+    };
+    match auth_totp_db(
+        request.into_inner(),
+        &server.db,
+        &totp_key,
+        max_failed_attempts,
+        lock_duration_seconds,
+        login,
+    )
+    .await
+    {
+        Ok(ok_resp) => Ok(Response::new(ok_resp)),
+        Err(e) => Err(auth_error_to_status(e)),
     }
 }
 
-fn verify_password_hash(password: &str, password_hash: &str) -> anyhow::Result<()> {
+pub(crate) fn verify_password_hash(password: &str, password_hash: &str) -> anyhow::Result<()> {
     let expected = PasswordHash::new(password_hash).context("Not PHC string")?;
     argon2::Argon2::default()
         .verify_password(password.as_bytes(), &expected)
@@ -2,15 +2,15 @@ use super::{
     Files,
     error_msg::{PERMISSION_DENIED, SERVER_ERROR},
 };
+use crate::db::file_blobs::{FileBlobError, delete_file_record, delete_released_objects};
 use crate::server::RpcServer;
+use crate::storage::{StorageBackend, StorageError};
 use base::constants::ID;
 use base::database::DbPool;
-use entities::user;
+use entities::{files, user};
 use pb::service::ourchat::delete::v1::{DeleteFileRequest, DeleteFileResponse};
-use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+use sea_orm::{ActiveModelTrait, ConnectionTrait, EntityTrait, Set, TransactionTrait};
 use size::Size;
-use std::path::PathBuf;
-use tokio::fs;
 use tonic::{Request, Response, Status};
 
 #[derive(Debug, thiserror::Error)]
@@ -23,12 +23,17 @@ pub enum DeleteFileError {
     PermissionDenied,
     #[error("Internal IO error:{0:?}")]
     InternalIOError(#[from] std::io::Error),
+    #[error("storage error:{0:?}")]
+    StorageError(#[from] StorageError),
+    #[error("file blob error:{0:?}")]
+    FileBlobError(#[from] FileBlobError),
 }
 
 async fn delete_file_impl(
     id: ID,
     req: DeleteFileRequest,
     db_conn: &DbPool,
+    storage: &dyn StorageBackend,
 ) -> Result<DeleteFileResponse, DeleteFileError> {
     // First check if the file exists and get file info
     let file_info = match Files::find_by_id(&req.key).one(&db_conn.db_pool).await? {
@@ -48,32 +53,49 @@ async fn delete_file_impl(
         return Err(DeleteFileError::PermissionDenied);
     }
 
+    remove_file(file_info, &db_conn.db_pool, storage).await?;
+    Ok(DeleteFileResponse {})
+}
+
+/// Delete a stored file and its record, and give the space back to its owner.
+///
+/// A deduplicated content is only deleted when no other file keeps it.
+pub async fn remove_file(
+    file_info: files::Model,
+    db_conn: &(impl ConnectionTrait + TransactionTrait),
+    storage: &dyn StorageBackend,
+) -> Result<(), DeleteFileError> {
     // Get file size before deleting
-    let path = PathBuf::from(&file_info.path);
-    let file_size = match fs::metadata(&path).await {
-        Ok(metadata) => Size::from_bytes(metadata.len()),
-        Err(_) => Size::from_bytes(0),
+    let file_size = match file_info.size {
+        Some(size) => Size::from_bytes(size),
+        None => match storage.size(&file_info.path).await {
+            Ok(size) => Size::from_bytes(size),
+            Err(_) => Size::from_bytes(0),
+        },
     };
 
-    // Delete the file from filesystem
-    if path.exists() {
-        fs::remove_file(&path).await?;
-    }
+    // The record, the reference to the content and the usage change together
+    let txn = db_conn.begin().await?;
+    let released = delete_file_record(&file_info, &txn).await?;
 
     // Update user's resource usage
-    if let Some(user) = user::Entity::find_by_id(id).one(&db_conn.db_pool).await? {
+    if let Some(user) = user::Entity::find_by_id(file_info.user_id)
+        .one(&txn)
+        .await?
+    {
         let current_usage = user.resource_used as u64;
         let file_size_bytes = file_size.bytes() as u64;
         let new_usage = current_usage.saturating_sub(file_size_bytes);
         let mut user_active: user::ActiveModel = user.into();
         user_active.resource_used = Set(new_usage as i64);
-        user_active.update(&db_conn.db_pool).await?;
+        user_active.update(&txn).await?;
     }
+    txn.commit().await?;
 
-    // Delete the file record from database
-    Files::delete_by_id(&req.key).exec(&db_conn.db_pool).await?;
+    // Delete the file from the storage
+    delete_released_objects(released, db_conn, storage).await?;
 
-    Ok(DeleteFileResponse {})
+    Ok(())
 }
 
 pub async fn delete_file(
@@ -82,7 +104,8 @@ pub async fn delete_file(
     request: Request<DeleteFileRequest>,
 ) -> Result<Response<DeleteFileResponse>, Status> {
     let req = request.into_inner();
-    match delete_file_impl(id, req, &server.db).await {
+    let storage = server.shared_data.file_sys.storage();
+    match delete_file_impl(id, req, &server.db, storage.as_ref()).await {
         Ok(response) => Ok(Response::new(response)),
         Err(e) => match e {
             DeleteFileError::PermissionDenied => Err(Status::permission_denied(PERMISSION_DENIED)),
@@ -0,0 +1,198 @@
+//! Devices the user is logged in on
+
+use std::net::SocketAddr;
+
+use crate::db::device::{
+    delete_login_device, delete_stale_login_devices, get_login_devices, save_login_device,
+};
+use crate::db::login_session::revoke_login_session;
+use crate::process::error_msg::not_found;
+use crate::process::{Dest, RpcError, get_sid_from_req, message_insert_and_transmit};
+use crate::server::RpcServer;
+use anyhow::Context;
+use axum::extract::ConnectInfo;
+use base::constants::{DEVICE_NAME_HEADER, ID};
+use base::database::DbPool;
+use chrono::Utc;
+use pb::service::ourchat::device::v1::{
+    Device, ListDevicesRequest, ListDevicesResponse, NewLoginNotification, RevokeDeviceRequest,
+    RevokeDeviceResponse,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::TransactionTrait;
+use tonic::{Request, Response, Status};
+
+const DEVICE_NAME_MAX_LEN: usize = 64;
+const USER_AGENT_MAX_LEN: usize = 256;
+const UNKNOWN_DEVICE_NAME: &str = "Unknown Device";
+
+/// The device a login comes from
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub device_name: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Read the device from the headers of the login request and the address it comes from
+    pub fn from_parts(headers: &http::HeaderMap, addr: Option<SocketAddr>) -> Self {
+        let header = |name: &str, max_len: usize| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().chars().take(max_len).collect::<String>())
+                .filter(|value| !value.is_empty())
+        };
+        Self {
+            device_name: header(DEVICE_NAME_HEADER, DEVICE_NAME_MAX_LEN)
+                .unwrap_or_else(|| UNKNOWN_DEVICE_NAME.to_string()),
+            user_agent: header(http::header::USER_AGENT.as_str(), USER_AGENT_MAX_LEN),
+            ip: addr.map(|addr| addr.ip().to_string()),
+        }
+    }
+
+    pub fn from_request<T>(request: &Request<T>) -> Self {
+        let addr = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+        Self::from_parts(request.metadata().as_ref(), addr)
+    }
+}
+
+/// Record the device of a new login of the user
+pub async fn record_login_device(
+    user_id: ID,
+    sid: &str,
+    device: &DeviceInfo,
+    db: &DbPool,
+) -> Result<(), sea_orm::DbErr> {
+    save_login_device(
+        user_id,
+        sid,
+        device.device_name.clone(),
+        device.user_agent.clone(),
+        device.ip.clone(),
+        &db.db_pool,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn send_new_login_notification(
+    user_id: ID,
+    sid: &str,
+    device: &DeviceInfo,
+    db: &DbPool,
+    rabbitmq: &deadpool_lapin::Pool,
+) -> anyhow::Result<()> {
+    let msg = RespondEventType::NewLogin(NewLoginNotification {
+        device_id: sid.to_string(),
+        device_name: device.device_name.clone(),
+        user_agent: device.user_agent.clone(),
+        ip: device.ip.clone(),
+    });
+    let rmq_conn = rabbitmq
+        .get()
+        .await
+        .context("cannot get rabbitmq connection")?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create channel")?;
+    message_insert_and_transmit(
+        None,
+        None,
+        msg,
+        Dest::User(user_id),
+        false,
+        &db.db_pool,
+        &mut channel,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Tell the user that the account has been logged in on a new device.
+///
+/// The login has succeeded already, so a failure is only logged.
+pub async fn notify_new_login(
+    user_id: ID,
+    sid: &str,
+    device: &DeviceInfo,
+    db: &DbPool,
+    rabbitmq: &deadpool_lapin::Pool,
+) {
+    if let Err(e) = send_new_login_notification(user_id, sid, device, db, rabbitmq).await {
+        tracing::error!(
+            "cannot send new login notification to user {}: {:?}",
+            user_id,
+            e
+        );
+    }
+}
+
+async fn list_devices_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListDevicesRequest>,
+) -> Result<ListDevicesResponse, RpcError> {
+    let current_sid = get_sid_from_req(&request);
+    let refresh_expire = server.shared_data.cfg().main_cfg.refresh_token_expire;
+    // a login whose refresh token hasn't been used for so long has expired
+    let since = Utc::now() - refresh_expire;
+    delete_stale_login_devices(id, since, &server.db.db_pool).await?;
+    let devices = get_login_devices(id, since, &server.db.db_pool)
+        .await?
+        .into_iter()
+        .map(|device| Device {
+            current: current_sid.as_deref() == Some(device.sid.as_str()),
+            device_id: device.sid,
+            device_name: device.device_name,
+            user_agent: device.user_agent,
+            ip: device.ip,
+            created_time: Some(device.created_time.into()),
+            last_used_time: Some(device.last_used_time.into()),
+        })
+        .collect();
+    Ok(ListDevicesResponse { devices })
+}
+
+async fn revoke_device_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<RevokeDeviceRequest>,
+) -> Result<RevokeDeviceResponse, RpcError> {
+    let req = request.into_inner();
+    let access_expire = server.shared_data.cfg().main_cfg.access_token_expire;
+    let transaction = server.db.db_pool.begin().await?;
+    if !delete_login_device(id, &req.device_id, &transaction).await? {
+        Err(Status::not_found(not_found::DEVICE))?;
+    }
+    revoke_login_session(id, &req.device_id, access_expire, &mut server.db.redis()).await?;
+    transaction.commit().await?;
+    Ok(RevokeDeviceResponse {})
+}
+
+pub async fn list_devices(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListDevicesRequest>,
+) -> Result<Response<ListDevicesResponse>, Status> {
+    match list_devices_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn revoke_device(
+    server: &RpcServer,
+    id: ID,
+    request: Request<RevokeDeviceRequest>,
+) -> Result<Response<RevokeDeviceResponse>, Status> {
+    match revoke_device_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
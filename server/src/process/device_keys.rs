@@ -0,0 +1,273 @@
+//! End-to-end encryption keys of the devices.
+//!
+//! Every device publishes an identity key, a signed prekey and a batch of one-time prekeys.
+//! Whoever encrypts a room key for a device fetches its bundle, taking one of the one-time
+//! prekeys, and the device is told to replenish them when few are left.
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+use base::constants::ID;
+use chrono::Utc;
+use entities::device_keys;
+use pb::service::ourchat::device::v1::{
+    FetchKeyBundlesRequest, FetchKeyBundlesResponse, KeyBundle, OneTimePrekey,
+    PrekeysLowNotification, ReplenishPrekeysRequest, ReplenishPrekeysResponse, SignedPrekey,
+    UploadKeyBundleRequest, UploadKeyBundleResponse,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::{EntityTrait, TransactionTrait};
+use tonic::{Request, Response, Status};
+
+use crate::db::device_keys::{
+    SignedPrekey as StoredSignedPrekey, add_one_time_prekeys, count_one_time_prekeys,
+    get_device_keys, get_user_device_keys, save_device_keys, share_session, take_one_time_prekey,
+    update_signed_prekey,
+};
+use crate::db::key_history::record_device_key;
+use crate::process::error_msg::{PERMISSION_DENIED, TOO_MANY_PREKEYS, invalid, not_found};
+use crate::process::key_verification::notify_public_key_changed;
+use crate::process::{
+    Dest, RpcError, check_user_exist, get_sid_from_req, message_insert_and_transmit,
+};
+use crate::server::RpcServer;
+
+/// The keys are raw curve points or signatures, anything larger is not a key
+const MAX_KEY_LEN: usize = 1024;
+/// One-time prekeys a device keeps on the server at most
+pub const MAX_ONE_TIME_PREKEYS: u64 = 200;
+/// The device is asked for more one-time prekeys when it has fewer than this
+pub const PREKEYS_LOW_THRESHOLD: u64 = 20;
+
+fn check_key(key: &[u8]) -> Result<(), Status> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(Status::invalid_argument(invalid::KEY_BUNDLE));
+    }
+    Ok(())
+}
+
+fn signed_prekey(prekey: Option<SignedPrekey>) -> Result<StoredSignedPrekey, Status> {
+    let Some(prekey) = prekey else {
+        return Err(Status::invalid_argument(invalid::KEY_BUNDLE));
+    };
+    check_key(&prekey.public_key)?;
+    check_key(&prekey.signature)?;
+    Ok(StoredSignedPrekey {
+        key_id: prekey.key_id as i64,
+        public_key: prekey.public_key.into(),
+        signature: prekey.signature.into(),
+    })
+}
+
+/// The ids of the one-time prekeys must be unique in the request as well
+fn one_time_prekeys(prekeys: Vec<OneTimePrekey>) -> Result<Vec<(i64, Vec<u8>)>, Status> {
+    let mut key_ids = HashSet::with_capacity(prekeys.len());
+    prekeys
+        .into_iter()
+        .map(|prekey| {
+            check_key(&prekey.public_key)?;
+            if !key_ids.insert(prekey.key_id) {
+                return Err(Status::invalid_argument(invalid::KEY_BUNDLE));
+            }
+            Ok((prekey.key_id as i64, prekey.public_key.into()))
+        })
+        .collect()
+}
+
+/// The device making the request, it must be one the user is logged in on
+async fn request_device<T>(
+    server: &RpcServer,
+    id: ID,
+    request: &Request<T>,
+) -> Result<String, RpcError> {
+    let Some(sid) = get_sid_from_req(request) else {
+        Err(anyhow::anyhow!("no sid in the request of user {}", id))?
+    };
+    let device = entities::prelude::LoginDevices::find_by_id(&sid)
+        .one(&server.db.db_pool)
+        .await?;
+    if device.is_none_or(|device| device.user_id != i64::from(id)) {
+        Err(Status::not_found(not_found::DEVICE))?
+    }
+    Ok(sid)
+}
+
+async fn upload_key_bundle_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UploadKeyBundleRequest>,
+) -> Result<UploadKeyBundleResponse, RpcError> {
+    let sid = request_device(server, id, &request).await?;
+    let req = request.into_inner();
+    check_key(&req.identity_key)?;
+    let signed_prekey = signed_prekey(req.signed_prekey)?;
+    if req.one_time_prekeys.len() as u64 > MAX_ONE_TIME_PREKEYS {
+        Err(Status::invalid_argument(TOO_MANY_PREKEYS))?
+    }
+    let prekeys = one_time_prekeys(req.one_time_prekeys)?;
+
+    let identity_key: Vec<u8> = req.identity_key.into();
+    let transaction = server.db.db_pool.begin().await?;
+    // A device uploading its bundle again may well keep its identity key
+    let new_key = match get_device_keys(&sid, &transaction).await? {
+        Some(device) if device.identity_key == identity_key => None,
+        _ => Some(record_device_key(id, &sid, identity_key.clone(), &transaction).await?),
+    };
+    save_device_keys(id, &sid, identity_key, signed_prekey, &transaction).await?;
+    add_one_time_prekeys(&sid, prekeys, &transaction).await?;
+    let count = count_one_time_prekeys(&sid, &transaction).await?;
+    transaction.commit().await?;
+    // The keys are saved already, the members learn about them when fetching the bundles anyway
+    if let Some(key) = new_key
+        && let Err(e) = notify_public_key_changed(server, id, &key).await
+    {
+        tracing::error!("failed to notify the identity key change of {}: {}", sid, e);
+    }
+    Ok(UploadKeyBundleResponse {
+        one_time_prekeys_count: count,
+    })
+}
+
+async fn replenish_prekeys_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ReplenishPrekeysRequest>,
+) -> Result<ReplenishPrekeysResponse, RpcError> {
+    let sid = request_device(server, id, &request).await?;
+    let req = request.into_inner();
+    let signed_prekey = req
+        .signed_prekey
+        .map(|prekey| signed_prekey(Some(prekey)))
+        .transpose()?;
+    let prekeys = one_time_prekeys(req.one_time_prekeys)?;
+
+    let transaction = server.db.db_pool.begin().await?;
+    if get_device_keys(&sid, &transaction).await?.is_none() {
+        Err(Status::failed_precondition(not_found::DEVICE_KEYS))?
+    }
+    if let Some(signed_prekey) = signed_prekey {
+        update_signed_prekey(&sid, signed_prekey, &transaction).await?;
+    }
+    let count = count_one_time_prekeys(&sid, &transaction).await?;
+    if count + prekeys.len() as u64 > MAX_ONE_TIME_PREKEYS {
+        Err(Status::invalid_argument(TOO_MANY_PREKEYS))?
+    }
+    let added = prekeys.len() as u64;
+    add_one_time_prekeys(&sid, prekeys, &transaction).await?;
+    let count = if added == 0 {
+        count
+    } else {
+        count_one_time_prekeys(&sid, &transaction).await?
+    };
+    transaction.commit().await?;
+    Ok(ReplenishPrekeysResponse {
+        one_time_prekeys_count: count,
+    })
+}
+
+/// Hand out the bundle of a device with one of its one-time prekeys, asking the device for more
+/// if it is running low
+async fn key_bundle(
+    server: &RpcServer,
+    owner: ID,
+    device: device_keys::Model,
+    channel: &mut deadpool_lapin::lapin::Channel,
+) -> Result<KeyBundle, RpcError> {
+    let prekey = take_one_time_prekey(&device.sid, &server.db.db_pool).await?;
+    if prekey.is_some() {
+        let left = count_one_time_prekeys(&device.sid, &server.db.db_pool).await?;
+        // Told once when crossing the threshold, and again when they have run out
+        if left + 1 == PREKEYS_LOW_THRESHOLD || left == 0 {
+            let msg = RespondEventType::PrekeysLow(PrekeysLowNotification {
+                device_id: device.sid.clone(),
+                one_time_prekeys_count: left,
+            });
+            message_insert_and_transmit(
+                None,
+                None,
+                msg,
+                Dest::User(owner),
+                false,
+                &server.db.db_pool,
+                channel,
+            )
+            .await?;
+        }
+    }
+    Ok(KeyBundle {
+        device_id: device.sid,
+        identity_key: device.identity_key.into(),
+        signed_prekey: Some(SignedPrekey {
+            key_id: device.signed_prekey_id as u64,
+            public_key: device.signed_prekey.into(),
+            signature: device.signed_prekey_signature.into(),
+        }),
+        one_time_prekey: prekey.map(|prekey| OneTimePrekey {
+            key_id: prekey.key_id as u64,
+            public_key: prekey.public_key.into(),
+        }),
+    })
+}
+
+async fn fetch_key_bundles_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<FetchKeyBundlesRequest>,
+) -> Result<FetchKeyBundlesResponse, RpcError> {
+    let req = request.into_inner();
+    let user_id = ID(req.user_id);
+    if !check_user_exist(user_id, &server.db.db_pool).await? {
+        Err(Status::not_found(not_found::USER))?
+    }
+    // The one-time prekeys are limited, only the ones who may talk to the user take them
+    if user_id != id && !share_session(id, user_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(PERMISSION_DENIED))?
+    }
+    let refresh_expire = server.shared_data.cfg().main_cfg.refresh_token_expire;
+    let since = Utc::now() - refresh_expire;
+    let devices = get_user_device_keys(user_id, since, &server.db.db_pool).await?;
+
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    let mut bundles = Vec::with_capacity(devices.len());
+    for device in devices {
+        bundles.push(key_bundle(server, user_id, device, &mut channel).await?);
+    }
+    Ok(FetchKeyBundlesResponse { bundles })
+}
+
+pub async fn upload_key_bundle(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UploadKeyBundleRequest>,
+) -> Result<Response<UploadKeyBundleResponse>, Status> {
+    match upload_key_bundle_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn replenish_prekeys(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ReplenishPrekeysRequest>,
+) -> Result<Response<ReplenishPrekeysResponse>, Status> {
+    match replenish_prekeys_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn fetch_key_bundles(
+    server: &RpcServer,
+    id: ID,
+    request: Request<FetchKeyBundlesRequest>,
+) -> Result<Response<FetchKeyBundlesResponse>, Status> {
+    match fetch_key_bundles_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -12,6 +12,14 @@ pub mod not_found {
     pub const FILE: &str = "File Not Found";
     pub const WEBRTC_ROOM: &str = "WebRTC Room Not Found";
     pub const UPLOAD_SESSION: &str = "Upload Session Not Found";
+    pub const REACTION: &str = "Reaction Not Found";
+    pub const PINNED_MSG: &str = "Pinned Message Not Found";
+    pub const SCHEDULED_MSG: &str = "Scheduled Message Not Found";
+    pub const DEVICE: &str = "Device Not Found";
+    pub const DEVICE_KEYS: &str = "Device Keys Not Found";
+    pub const PUBLIC_KEY: &str = "Public Key Not Found";
+    pub const OAUTH_PROVIDER: &str = "OAuth Provider Not Found";
+    pub const OAUTH_IDENTITY: &str = "OAuth Identity Not Linked";
 }
 
 pub mod exist {
@@ -20,6 +28,8 @@ pub mod exist {
     pub const USER_IN_SESSION: &str = "User Already In Session";
     pub const MSG: &str = "Message Already Exists";
     pub const FRIEND: &str = "Friend Already Exists";
+    pub const REACTION: &str = "Reaction Already Exists";
+    pub const PINNED_MSG: &str = "Pinned Message Already Exists";
 }
 
 pub mod invalid {
@@ -28,6 +38,11 @@ pub mod invalid {
     pub const STATUS_TOO_LONG: &str = "Status Too Long";
     pub const OCID_TOO_LONG: &str = "Ocid Too Long";
     pub const PUBLIC_KEY: &str = "Public Key Is Invalid";
+    pub const EMOJI: &str = "Emoji Is Invalid";
+    pub const SEARCH_QUERY: &str = "Search Query Is Invalid";
+    pub const CONTENT_TYPE: &str = "Content Type Is Invalid";
+    pub const KEY_BUNDLE: &str = "Key Bundle Is Invalid";
+    pub const MAINTENANCE_TIME: &str = "Maintenance Time Is Invalid";
 }
 
 pub mod metrics {
@@ -47,6 +62,21 @@ pub const MUTE: &str = "User Muted";
 pub const BAN: &str = "User Banned";
 pub const ACCOUNT_DELETED: &str = "Account Deleted";
 pub const E2EE_NOT_ON: &str = "E2EE Not On";
+pub const TOO_MANY_PREKEYS: &str = "Too Many Prekeys";
+pub const TOO_MANY_REQUESTS: &str = "Too Many Requests";
+
+// edit msg
+
+pub const ONLY_MSG_CAN_BE_EDITED: &str = "Only Msg Can Be Edited";
+pub const ENCRYPTION_STATE_MISMATCH: &str = "Encryption State Mismatch";
+
+// pin msg
+
+pub const ONLY_MSG_CAN_BE_PINNED: &str = "Only Msg Can Be Pinned";
+
+// thread
+
+pub const THREAD_ROOT_MISMATCH: &str = "Thread Root Mismatch";
 
 // fetch msg
 
@@ -62,10 +92,15 @@ pub const METADATA_ERROR: &str = "Metadata Error";
 pub const INCORRECT_ORDER: &str = "Incorrect Order Of Uploading";
 pub const UPLOAD_SESSION_NOT_IN_THIS_INSTANCE: &str = "Upload Session Not In This Instance";
 
+// download
+
+pub const DOWNLOAD_OUT_OF_RANGE: &str = "Download Out Of Range";
+
 // Set Session Info
 pub const CANNOT_SET_NAME: &str = "Cannot Set Name";
 pub const CANNOT_SET_DESCRIPTION: &str = "Cannot Set Description";
 pub const CANNOT_SET_AVATAR: &str = "Cannot Set Avatar";
+pub const CANNOT_SET_MESSAGE_TTL: &str = "Cannot Set Message TTL";
 
 // Auth
 pub const MISSING_AUTH_TYPE: &str = "Missing AuthType";
@@ -73,6 +108,27 @@ pub const WRONG_PASSWORD: &str = "Wrong Password";
 pub const ACCOUNT_LOCKED: &str = "Account Locked";
 pub const EMAIL_NOT_VERIFIED: &str = "Email Not Verified";
 
+// TOTP
+pub const TOTP_ALREADY_ENABLED: &str = "TOTP Already Enabled";
+pub const TOTP_NOT_ENABLED: &str = "TOTP Not Enabled";
+pub const TOTP_NOT_ENROLLED: &str = "TOTP Not Enrolled";
+pub const WRONG_TOTP_CODE: &str = "Wrong TOTP Code";
+pub const TOTP_CHALLENGE_INVALID: &str = "TOTP Challenge Invalid";
+pub const MISSING_TOTP_CODE: &str = "Missing TOTP Code";
+
+// Password
+pub const PASSWORD_RESET_TOKEN_INVALID: &str = "Password Reset Token Invalid";
+
+// OAuth
+pub const LAST_LOGIN_METHOD: &str = "Cannot Remove The Last Login Method";
+pub const OAUTH_STATE_INVALID: &str = "OAuth State Invalid";
+pub const OAUTH_LINK_TICKET_INVALID: &str = "OAuth Link Ticket Invalid";
+pub const OAUTH_LINK_CODE_INVALID: &str = "OAuth Link Code Invalid";
+pub const OAUTH_IDENTITY_LINKED: &str = "OAuth Identity Linked To Another Account";
+pub const OAUTH_PROVIDER_LINKED: &str = "OAuth Provider Already Linked";
+pub const OAUTH_EMAIL_USED: &str = "Email Used By Another Account";
+pub const OAUTH_EMAIL_UNVERIFIED: &str = "OAuth Email Unverified";
+
 // Role
 pub const ROLE_NAME_EMPTY: &str = "Role Name Empty";
 
@@ -85,6 +141,8 @@ pub mod token {
     pub const MISSING: &str = "Token Missing";
     pub const UNSUPPORTED_AUTHORIZATION_HEADER: &str = "Only Support Bearer";
     pub const INCORRECT_FORMAT: &str = "Correct format is Authorization: Bearer <jwt>";
+    pub const REVOKED: &str = "Token Revoked";
+    pub const REFRESH_TOKEN_INVALID: &str = "Refresh Token Invalid";
 }
 
 pub mod webrtc {
@@ -1,4 +1,7 @@
 pub mod download;
+pub mod file_info;
+pub mod media;
+pub mod storage_usage;
 pub mod upload;
 pub mod upload_chunked;
 pub mod upload_foundation;
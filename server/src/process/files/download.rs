@@ -1,20 +1,32 @@
-use std::path::PathBuf;
+use std::sync::Arc;
 
-use super::super::{Files, error_msg::PERMISSION_DENIED};
+use super::super::{
+    Files,
+    error_msg::{DOWNLOAD_OUT_OF_RANGE, PERMISSION_DENIED},
+};
 use crate::{
-    process::error_msg::SERVER_ERROR,
+    process::{
+        error_msg::SERVER_ERROR,
+        files::media::{pick_thumbnail, thumbnail_object},
+    },
     server::{DownloadStream, RpcServer},
+    storage::{StorageBackend, StorageError},
 };
 use base::constants::ID;
 use base::database::DbPool;
 use bytes::BytesMut;
-use entities::session_relation;
-use pb::service::ourchat::download::v1::{DownloadRequest, DownloadResponse};
+use entities::{files, session_relation};
+use pb::service::ourchat::download::v1::{
+    DownloadHeader, DownloadRequest, DownloadResponse, download_response,
+};
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use tokio::{io::AsyncReadExt, sync::mpsc};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
+/// Sent for the files uploaded without a MIME type
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
 #[derive(Debug, thiserror::Error)]
 pub enum DownloadError {
     #[error("unknown error:{0:?}")]
@@ -23,8 +35,12 @@ pub enum DownloadError {
     DbError(#[from] sea_orm::DbErr),
     #[error("permission denied")]
     PermissionDenied,
+    #[error("out of range")]
+    OutOfRange,
     #[error("Internal IO error:{0:?}")]
     InternalIOError(#[from] std::io::Error),
+    #[error("storage error:{0:?}")]
+    StorageError(#[from] StorageError),
 }
 
 /// Check if a user is in the specified session
@@ -42,14 +58,16 @@ async fn is_user_in_session(
     Ok(result.is_some())
 }
 
-async fn download_impl(
+/// Find the file for the user to download.
+///
+/// A file that doesn't exist is reported as [`DownloadError::PermissionDenied`] too, so that the
+/// keys of other users' files can't be probed.
+pub async fn find_downloadable_file(
     id: ID,
-    req: DownloadRequest,
-    tx: &mpsc::Sender<Result<DownloadResponse, Status>>,
+    key: &str,
     db_conn: &DbPool,
-) -> Result<(), DownloadError> {
-    // First check if the file exists and get file info
-    let file_info = match Files::find_by_id(&req.key).one(&db_conn.db_pool).await? {
+) -> Result<files::Model, DownloadError> {
+    let file_info = match Files::find_by_id(key).one(&db_conn.db_pool).await? {
         Some(f) => f,
         None => return Err(DownloadError::PermissionDenied),
     };
@@ -68,22 +86,137 @@ async fn download_impl(
     if !has_permission {
         return Err(DownloadError::PermissionDenied);
     }
+    Ok(file_info)
+}
 
-    // Use the stored path from database (which should be hierarchical)
-    let path = PathBuf::from(&file_info.path);
-    let file = tokio::fs::File::open(&path).await?;
-    let mut buf_reader = tokio::io::BufReader::new(file);
-    let mut buf = BytesMut::with_capacity(1024);
-    let mut file_data = Vec::new();
+/// Bytes num of the whole file, the files uploaded before the size was recorded are measured
+/// in the storage
+pub async fn file_size(
+    file_info: &files::Model,
+    storage: &dyn StorageBackend,
+) -> Result<u64, StorageError> {
+    match file_info.size {
+        Some(size) => Ok(size as u64),
+        None => storage.size(&file_info.path).await,
+    }
+}
+
+pub fn content_type(file_info: &files::Model) -> &str {
+    file_info
+        .content_type
+        .as_deref()
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+}
+
+/// What a download sends, the file itself or one of its thumbnails
+#[derive(Debug)]
+pub struct DownloadTarget {
+    pub object: String,
+    pub size: u64,
+    pub content_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The max side of the thumbnail sent instead of the file
+    pub thumbnail: Option<u32>,
+}
+
+/// Choose what to send for the download of the file, which asks for a thumbnail large enough for
+/// `thumbnail` pixels optionally
+pub async fn download_target(
+    file_info: &files::Model,
+    thumbnail: Option<u32>,
+    storage: &dyn StorageBackend,
+) -> Result<DownloadTarget, StorageError> {
+    if let Some(thumbnail) = thumbnail.and_then(|side| pick_thumbnail(file_info, side)) {
+        let object = thumbnail_object(&file_info.path, thumbnail.max_side);
+        return Ok(DownloadTarget {
+            size: storage.size(&object).await?,
+            object,
+            content_type: thumbnail.content_type,
+            width: Some(thumbnail.width),
+            height: Some(thumbnail.height),
+            thumbnail: Some(thumbnail.max_side),
+        });
+    }
+    Ok(DownloadTarget {
+        object: file_info.path.clone(),
+        size: file_size(file_info, storage).await?,
+        content_type: content_type(file_info).to_string(),
+        width: file_info.width.map(|width| width as u32),
+        height: file_info.height.map(|height| height as u32),
+        thumbnail: None,
+    })
+}
+
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+async fn download_impl(
+    id: ID,
+    req: DownloadRequest,
+    tx: &mpsc::Sender<Result<DownloadResponse, Status>>,
+    db_conn: &DbPool,
+    storage: &dyn StorageBackend,
+) -> Result<(), DownloadError> {
+    let file_info = find_downloadable_file(id, &req.key, db_conn).await?;
+    let target = download_target(&file_info, req.thumbnail, storage).await?;
+    let size = target.size;
+    if req.offset > size {
+        return Err(DownloadError::OutOfRange);
+    }
+    let length = req
+        .length
+        .map_or(size - req.offset, |length| length.min(size - req.offset));
+
+    // The thumbnails are not what the hash was computed from
+    let hash = match target.thumbnail {
+        Some(_) => vec![],
+        None => decode_hex(&file_info.hash).unwrap_or_default(),
+    };
+    let header = DownloadHeader {
+        size,
+        hash: hash.into(),
+        content_type: target.content_type,
+        width: target.width,
+        height: target.height,
+    };
+    if tx
+        .send(Ok(DownloadResponse {
+            data: Some(download_response::Data::Header(header)),
+        }))
+        .await
+        .is_err()
+    {
+        // the client has gone
+        return Ok(());
+    }
 
+    let mut reader = storage
+        .read_range(&target.object, req.offset, length)
+        .await?;
+    let mut buf = BytesMut::with_capacity(1024);
     loop {
-        let n = buf_reader.read_buf(&mut buf).await?;
+        let n = reader.read_buf(&mut buf).await?;
         if n == 0 {
             break;
         }
         let chunk = buf.split().freeze();
-        file_data.extend_from_slice(&chunk);
-        tx.send(Ok(DownloadResponse { data: chunk })).await.ok();
+        if tx
+            .send(Ok(DownloadResponse {
+                data: Some(download_response::Data::Content(chunk)),
+            }))
+            .await
+            .is_err()
+        {
+            break;
+        }
     }
     Ok(())
 }
@@ -96,9 +229,10 @@ pub async fn download(
     let req = request.into_inner();
     let (tx, rx) = mpsc::channel(16);
     let db_conn = server.db.clone();
+    let storage: Arc<dyn StorageBackend> = server.shared_data.file_sys.storage().clone();
 
     tokio::spawn(async move {
-        match download_impl(id, req, &tx, &db_conn).await {
+        match download_impl(id, req, &tx, &db_conn, storage.as_ref()).await {
             Ok(_) => {}
             Err(e) => match e {
                 DownloadError::PermissionDenied => {
@@ -106,6 +240,11 @@ pub async fn download(
                         .await
                         .ok();
                 }
+                DownloadError::OutOfRange => {
+                    tx.send(Err(Status::out_of_range(DOWNLOAD_OUT_OF_RANGE)))
+                        .await
+                        .ok();
+                }
                 _ => {
                     tracing::error!("{}", e);
                     tx.send(Err(Status::internal(SERVER_ERROR))).await.ok();
@@ -116,3 +255,16 @@ pub async fn download(
     let output_stream = ReceiverStream::new(rx);
     Ok(Response::new(Box::pin(output_stream) as DownloadStream))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::decode_hex;
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("00ff1a"), Some(vec![0x00, 0xff, 0x1a]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
@@ -0,0 +1,53 @@
+use base::constants::ID;
+use pb::{
+    google::protobuf::Timestamp,
+    service::ourchat::file_info::v1::{GetFileInfoRequest, GetFileInfoResponse},
+};
+use tonic::{Request, Response, Status};
+
+use super::{
+    download::{DownloadError, content_type, decode_hex, file_size, find_downloadable_file},
+    media::thumbnails,
+};
+use crate::{
+    process::error_msg::{PERMISSION_DENIED, SERVER_ERROR},
+    server::RpcServer,
+};
+
+async fn get_file_info_impl(
+    server: &RpcServer,
+    id: ID,
+    req: GetFileInfoRequest,
+) -> Result<GetFileInfoResponse, DownloadError> {
+    let file_info = find_downloadable_file(id, &req.key, &server.db).await?;
+    let size = file_size(&file_info, server.shared_data.file_sys.storage().as_ref()).await?;
+    Ok(GetFileInfoResponse {
+        size,
+        hash: decode_hex(&file_info.hash).unwrap_or_default().into(),
+        content_type: content_type(&file_info).to_string(),
+        width: file_info.width.map(|width| width as u32),
+        height: file_info.height.map(|height| height as u32),
+        thumbnails: thumbnails(&file_info),
+        upload_time: Some(Timestamp {
+            seconds: file_info.date,
+            nanos: 0,
+        }),
+        session_id: file_info.session_id.map(|session_id| session_id as u64),
+        key: file_info.key,
+    })
+}
+
+pub async fn get_file_info(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetFileInfoRequest>,
+) -> Result<Response<GetFileInfoResponse>, Status> {
+    match get_file_info_impl(server, id, request.into_inner()).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(DownloadError::PermissionDenied) => Err(Status::permission_denied(PERMISSION_DENIED)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            Err(Status::internal(SERVER_ERROR))
+        }
+    }
+}
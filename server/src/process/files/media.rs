@@ -0,0 +1,277 @@
+//! Metadata and thumbnails of the uploaded images.
+//!
+//! The thumbnails are stored next to the image as `{object}.{max_side}`, so that the chat
+//! previews and the avatars don't need the full-size image. They don't count to the files limit
+//! of the user. The deduplicated files share the thumbnails of their blob.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use entities::files;
+use image::{
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, codecs::jpeg::JpegEncoder,
+};
+use pb::service::ourchat::file_info::v1::Thumbnail;
+use sea_orm::{ActiveModelTrait, ActiveValue, ConnectionTrait};
+
+use crate::storage::{StorageBackend, StorageError};
+
+/// The longer sides of the thumbnails generated, only the ones smaller than the image are
+pub const THUMBNAIL_SIDES: [u32; 2] = [128, 512];
+/// The images larger than this are not decoded
+const MAX_IMAGE_SIDE: u32 = 16384;
+const MAX_DECODE_ALLOC: u64 = 512 * 1024 * 1024;
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+/// What is found out about an uploaded image
+#[derive(Debug)]
+pub struct MediaInfo {
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// MIME type of the thumbnails
+    pub thumbnail_type: String,
+    /// The encoded thumbnails ordered by the max side
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+/// Decode the file if it is an image, generating its thumbnails.
+///
+/// The files which are not images, or can't be decoded, have no media info. It never fails the
+/// upload.
+pub async fn extract_media(path: &Path) -> Option<MediaInfo> {
+    let path = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || extract_media_blocking(&path)).await {
+        Ok(Ok(media)) => media,
+        Ok(Err(e)) => {
+            tracing::info!("cannot decode the uploaded image: {}", e);
+            None
+        }
+        Err(e) => {
+            tracing::error!("image decoding task failed: {}", e);
+            None
+        }
+    }
+}
+
+fn extract_media_blocking(path: &Path) -> anyhow::Result<Option<MediaInfo>> {
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = match reader.format() {
+        Some(
+            format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP),
+        ) => format,
+        _ => return Ok(None),
+    };
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_SIDE);
+    limits.max_image_height = Some(MAX_IMAGE_SIDE);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    // The photos of the phones are often stored rotated, with the orientation in the exif data
+    image.apply_orientation(orientation);
+
+    let (width, height) = (image.width(), image.height());
+    let has_alpha = image.color().has_alpha();
+    let thumbnail_type = if has_alpha {
+        ImageFormat::Png
+    } else {
+        ImageFormat::Jpeg
+    };
+    let mut thumbnails = Vec::new();
+    for side in available_sides(width, height) {
+        let (thumbnail_width, thumbnail_height) = thumbnail_dimensions(width, height, side);
+        let thumbnail = image.thumbnail_exact(thumbnail_width, thumbnail_height);
+        let mut data = Vec::new();
+        if has_alpha {
+            thumbnail.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+        } else {
+            JpegEncoder::new_with_quality(&mut data, THUMBNAIL_JPEG_QUALITY)
+                .encode_image(&thumbnail.to_rgb8())?;
+        }
+        thumbnails.push((side, data));
+    }
+    Ok(Some(MediaInfo {
+        content_type: format.to_mime_type().to_string(),
+        width,
+        height,
+        thumbnail_type: thumbnail_type.to_mime_type().to_string(),
+        thumbnails,
+    }))
+}
+
+/// The thumbnail sides smaller than the image, a small image is its own thumbnail
+fn available_sides(width: u32, height: u32) -> impl Iterator<Item = u32> {
+    let longer = width.max(height);
+    THUMBNAIL_SIDES
+        .into_iter()
+        .filter(move |side| *side < longer)
+}
+
+/// The size of the thumbnail fitting in a `side` square, keeping the aspect ratio
+pub fn thumbnail_dimensions(width: u32, height: u32, side: u32) -> (u32, u32) {
+    let scale = |short: u32, long: u32| {
+        ((short as u64 * side as u64 + long as u64 / 2) / long as u64).max(1) as u32
+    };
+    if width >= height {
+        (side, scale(height, width))
+    } else {
+        (scale(width, height), side)
+    }
+}
+
+/// The object name of a thumbnail of the file stored as `object`
+pub fn thumbnail_object(object: &str, side: u32) -> String {
+    format!("{object}.{side}")
+}
+
+/// The thumbnails stored for the file, ordered by the max side
+pub fn thumbnails(file_info: &files::Model) -> Vec<Thumbnail> {
+    let (Some(content_type), Some(width), Some(height)) =
+        (&file_info.thumbnail_type, file_info.width, file_info.height)
+    else {
+        return vec![];
+    };
+    let (width, height) = (width as u32, height as u32);
+    available_sides(width, height)
+        .map(|side| {
+            let (width, height) = thumbnail_dimensions(width, height, side);
+            Thumbnail {
+                max_side: side,
+                width,
+                height,
+                content_type: content_type.clone(),
+            }
+        })
+        .collect()
+}
+
+/// The smallest thumbnail at least `side` large, or the largest one
+pub fn pick_thumbnail(file_info: &files::Model, side: u32) -> Option<Thumbnail> {
+    let mut thumbnails = thumbnails(file_info);
+    match thumbnails.iter().position(|t| t.max_side >= side) {
+        Some(idx) => Some(thumbnails.swap_remove(idx)),
+        None => thumbnails.pop(),
+    }
+}
+
+/// Store the thumbnails of the file, staging them as `{staging}.{max_side}`.
+///
+/// The thumbnails are dropped from the record if any of them can't be stored, the file is still
+/// usable without them.
+pub async fn store_thumbnails(
+    file_info: files::Model,
+    media: MediaInfo,
+    staging: &Path,
+    db_conn: &impl ConnectionTrait,
+    storage: &dyn StorageBackend,
+) -> Result<(), sea_orm::DbErr> {
+    if file_info.thumbnail_type.is_none()
+        || put_thumbnails(&file_info.path, media.thumbnails, staging, storage).await
+    {
+        return Ok(());
+    }
+    let mut file: files::ActiveModel = file_info.into();
+    file.thumbnail_type = ActiveValue::Set(None);
+    file.update(db_conn).await?;
+    Ok(())
+}
+
+/// Put the thumbnails of the file stored as `object`, staging them as `{staging}.{max_side}`.
+///
+/// Returns whether all of them are stored, the ones stored are deleted otherwise.
+pub async fn put_thumbnails(
+    object: &str,
+    thumbnails: Vec<(u32, Vec<u8>)>,
+    staging: &Path,
+    storage: &dyn StorageBackend,
+) -> bool {
+    for (side, data) in thumbnails {
+        let staging_path = PathBuf::from(format!("{}.{side}", staging.display()));
+        let result = async {
+            tokio::fs::write(&staging_path, data).await?;
+            storage
+                .put_file(&thumbnail_object(object, side), &staging_path)
+                .await
+        }
+        .await;
+        match tokio::fs::remove_file(&staging_path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("cannot remove staged thumbnail: {}", e),
+        }
+        if let Err(e) = result {
+            tracing::error!("cannot store the thumbnail of {}: {}", object, e);
+            for side in THUMBNAIL_SIDES {
+                if let Err(e) = storage.delete(&thumbnail_object(object, side)).await {
+                    tracing::error!("cannot delete the thumbnails of {}: {}", object, e);
+                }
+            }
+            return false;
+        }
+    }
+    true
+}
+
+pub async fn delete_thumbnails(
+    file_info: &files::Model,
+    storage: &dyn StorageBackend,
+) -> Result<(), StorageError> {
+    if file_info.thumbnail_type.is_none() {
+        return Ok(());
+    }
+    for side in THUMBNAIL_SIDES {
+        storage
+            .delete(&thumbnail_object(&file_info.path, side))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Delete the file and its thumbnails from the storage
+pub async fn delete_file_objects(
+    file_info: &files::Model,
+    storage: &dyn StorageBackend,
+) -> Result<(), StorageError> {
+    delete_thumbnails(file_info, storage).await?;
+    storage.delete(&file_info.path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumbnail_dimensions() {
+        assert_eq!(thumbnail_dimensions(4000, 3000, 512), (512, 384));
+        assert_eq!(thumbnail_dimensions(3000, 4000, 128), (96, 128));
+        assert_eq!(thumbnail_dimensions(1000, 1000, 128), (128, 128));
+        assert_eq!(thumbnail_dimensions(10000, 1, 128), (128, 1));
+        assert_eq!(available_sides(300, 200).collect::<Vec<_>>(), vec![128]);
+        assert_eq!(available_sides(100, 120).count(), 0);
+    }
+
+    #[test]
+    fn test_extract_media() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image");
+        DynamicImage::new_rgb8(1024, 256)
+            .save_with_format(&path, ImageFormat::Png)
+            .unwrap();
+        let media = extract_media_blocking(&path).unwrap().unwrap();
+        assert_eq!(media.content_type, "image/png");
+        assert_eq!((media.width, media.height), (1024, 256));
+        assert_eq!(media.thumbnail_type, "image/jpeg");
+        let sides: Vec<_> = media.thumbnails.iter().map(|(side, _)| *side).collect();
+        assert_eq!(sides, THUMBNAIL_SIDES);
+        let thumbnail = image::load_from_memory(&media.thumbnails[0].1).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 32));
+
+        std::fs::write(&path, b"not an image").unwrap();
+        assert!(extract_media_blocking(&path).unwrap().is_none());
+    }
+}
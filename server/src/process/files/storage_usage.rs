@@ -0,0 +1,144 @@
+//! The files limit and the files of the user, for the users to clean up their files themselves
+
+use base::constants::ID;
+use entities::{files, prelude::*};
+use pb::{
+    google::protobuf::Timestamp,
+    service::ourchat::storage::v1::{
+        FileEntry, FileOrder, GetStorageUsageRequest, GetStorageUsageResponse,
+        ListUserFilesRequest, ListUserFilesResponse,
+    },
+};
+use sea_orm::{
+    ColumnTrait, EntityTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+    sea_query::NullOrdering,
+};
+use tonic::{Request, Response, Status};
+
+use super::download::content_type;
+use crate::{
+    db::file_storage::user_files_limit,
+    process::error_msg::{SERVER_ERROR, not_found},
+    server::RpcServer,
+};
+
+/// The most files returned at once
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Debug, thiserror::Error)]
+enum StorageUsageError {
+    #[error("database error:{0:?}")]
+    DbError(#[from] sea_orm::DbErr),
+    #[error("user not found")]
+    UserNotFound,
+}
+
+impl From<StorageUsageError> for Status {
+    fn from(value: StorageUsageError) -> Self {
+        match value {
+            StorageUsageError::UserNotFound => Status::not_found(not_found::USER),
+            StorageUsageError::DbError(_) => Status::internal(SERVER_ERROR),
+        }
+    }
+}
+
+fn page_size(requested: u32) -> u64 {
+    match requested as u64 {
+        0 => MAX_PAGE_SIZE,
+        size => size.min(MAX_PAGE_SIZE),
+    }
+}
+
+fn file_entry(file: files::Model) -> FileEntry {
+    FileEntry {
+        size: file.size.unwrap_or_default() as u64,
+        content_type: content_type(&file).to_string(),
+        upload_time: Some(Timestamp {
+            seconds: file.date,
+            nanos: 0,
+        }),
+        session_id: file.session_id.map(|session_id| session_id as u64),
+        auto_clean: file.auto_clean,
+        key: file.key,
+    }
+}
+
+/// The files of the user, the files uploaded before the size was recorded are sorted as the
+/// smallest ones
+fn user_files(id: ID, order: FileOrder) -> sea_orm::Select<Files> {
+    let query = Files::find().filter(files::Column::UserId.eq(id));
+    match order {
+        FileOrder::NewestUnspecified => query.order_by_desc(files::Column::Date),
+        FileOrder::Oldest => query.order_by_asc(files::Column::Date),
+        FileOrder::Largest => {
+            query.order_by_with_nulls(files::Column::Size, Order::Desc, NullOrdering::Last)
+        }
+    }
+    .order_by_asc(files::Column::Key)
+}
+
+async fn get_storage_usage_impl(
+    server: &RpcServer,
+    id: ID,
+    req: GetStorageUsageRequest,
+) -> Result<GetStorageUsageResponse, StorageUsageError> {
+    let user = User::find_by_id(id)
+        .one(&server.db.db_pool)
+        .await?
+        .ok_or(StorageUsageError::UserNotFound)?;
+    let default_limit = server.shared_data.cfg().main_cfg.user_files_limit;
+    let largest_files = user_files(id, FileOrder::Largest)
+        .limit(page_size(req.largest_files_num))
+        .all(&server.db.db_pool)
+        .await?;
+    Ok(GetStorageUsageResponse {
+        used: user.resource_used.max(0) as u64,
+        limit: user_files_limit(&user, default_limit).bytes() as u64,
+        custom_limit: user.files_limit.is_some(),
+        largest_files: largest_files.into_iter().map(file_entry).collect(),
+    })
+}
+
+pub async fn get_storage_usage(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetStorageUsageRequest>,
+) -> Result<Response<GetStorageUsageResponse>, Status> {
+    match get_storage_usage_impl(server, id, request.into_inner()).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_user_files_impl(
+    server: &RpcServer,
+    id: ID,
+    req: ListUserFilesRequest,
+) -> Result<ListUserFilesResponse, StorageUsageError> {
+    let page_size = page_size(req.page_size);
+    let paginator = user_files(id, req.order()).paginate(&server.db.db_pool, page_size);
+    let total_count = paginator.num_items().await?;
+    let files = paginator.fetch_page(req.page as u64).await?;
+    Ok(ListUserFilesResponse {
+        has_more: (req.page as u64 + 1) * page_size < total_count,
+        files: files.into_iter().map(file_entry).collect(),
+        total_count,
+    })
+}
+
+pub async fn list_user_files(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListUserFilesRequest>,
+) -> Result<Response<ListUserFilesResponse>, Status> {
+    match list_user_files_impl(server, id, request.into_inner()).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            Err(e.into())
+        }
+    }
+}
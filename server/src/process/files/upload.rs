@@ -1,19 +1,32 @@
 use crate::{
-    db::file_storage::generate_hierarchical_path,
+    db::{
+        file_blobs::{acquire_blob, blob_object, reference_blob, release_blob, remove_file_record},
+        file_storage::{generate_hierarchical_path, get_user_files_limit, user_files_limit},
+    },
     helper::create_file_with_dirs_if_not_exist,
-    process::files::upload_foundation::{UploadError, generate_key_name},
+    process::{
+        delete_file::remove_file,
+        files::{
+            media::{MediaInfo, extract_media, put_thumbnails, store_thumbnails},
+            upload_foundation::{UploadError, check_content_type, generate_key_name},
+        },
+    },
     server::RpcServer,
+    storage::{StorageBackend, object_name},
 };
 use base::constants::{ID, SessionID};
 use entities::{files, prelude::*, user};
 use pb::service::ourchat::upload::v1::{UploadRequest, UploadResponse, upload_request};
 use sea_orm::{
     ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
-    PaginatorTrait, QueryFilter, QueryOrder,
+    PaginatorTrait, QueryFilter, QueryOrder, TransactionTrait,
 };
 use sha3::{Digest, Sha3_256};
 use size::Size;
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::{fs, io::AsyncWriteExt, sync::Notify};
 use tokio_stream::StreamExt;
 use tonic::{Response, Status};
@@ -24,10 +37,19 @@ pub struct AddFileRecordConfig {
     pub id: ID,
     pub size: Size,
     pub key: String,
+    /// Hex hash the file was uploaded with
+    pub hash: String,
+    pub content_type: Option<String>,
+    /// Width and height in pixels, for the images
+    pub dimensions: Option<(u32, u32)>,
+    /// MIME type of the thumbnails, if any were generated
+    pub thumbnail_type: Option<String>,
     pub auto_clean: bool,
-    pub files_storage_path: PathBuf,
+    /// The default files limit, replaced by the one set for the user
     pub limit_size: Size,
     pub session_id: Option<SessionID>,
+    /// The object keeping the content, a new one of the file itself if not set
+    pub object: Option<String>,
 }
 
 /// Local state for open file handles (only on the server instance that created the upload)
@@ -44,7 +66,7 @@ impl LocalUploadState {
     }
 }
 
-/// Add a new file record to the database without creating the file on disk
+/// Add a new file record to the database without storing the file
 ///
 /// # Warning
 /// This function will delete files if the limit has been reached
@@ -53,13 +75,12 @@ impl LocalUploadState {
 ///
 /// * `config` - Configuration containing file upload parameters
 /// * `db_connection` - Database connection handle
+/// * `storage` - Backend keeping the files, for deleting the old files
 pub async fn add_file_record(
     config: AddFileRecordConfig,
     db_connection: &DatabaseConnection,
-) -> Result<(), UploadError> {
-    if config.size > config.limit_size {
-        return Err(UploadError::FileSizeOverflow);
-    }
+    storage: &dyn StorageBackend,
+) -> Result<files::Model, UploadError> {
     let user_info = match User::find_by_id(config.id).one(db_connection).await? {
         Some(user) => user,
         None => Err(anyhow::anyhow!(
@@ -67,13 +88,17 @@ pub async fn add_file_record(
             config.id
         ))?,
     };
+    let limit_size = user_files_limit(&user_info, config.limit_size);
+    if config.size > limit_size {
+        return Err(UploadError::FileSizeOverflow);
+    }
     // first check if the limit has been reached
     let res_used = Size::from_bytes(user_info.resource_used);
     let will_used = res_used + config.size;
-    tracing::debug!("will used: {}, bytes_num: {}", will_used, config.limit_size);
-    if will_used > config.limit_size {
+    tracing::debug!("will used: {}, bytes_num: {}", will_used, limit_size);
+    if will_used > limit_size {
         // reach the limit,delete some files to preserve the limit
-        clean_files(will_used - config.limit_size, db_connection, config.id).await?;
+        clean_files(will_used - limit_size, db_connection, config.id, storage).await?;
     }
     let updated_res_lim = res_used + config.size;
     let mut user_info: user::ActiveModel = user_info.into();
@@ -82,39 +107,213 @@ pub async fn add_file_record(
 
     let timestamp = chrono::Utc::now().timestamp();
 
-    // Use hierarchical storage path for better filesystem performance
-    let hierarchical_path =
-        generate_hierarchical_path(&config.files_storage_path, config.id, &config.key);
-    let path = match hierarchical_path.to_str() {
-        Some(path) => path,
-        None => {
-            return Err(UploadError::InvalidPathError);
-        }
-    };
     let file = files::ActiveModel {
+        path: sea_orm::Set(
+            config
+                .object
+                .unwrap_or_else(|| object_name(config.id, &config.key)),
+        ),
         key: sea_orm::Set(config.key),
-        path: sea_orm::Set(path.to_string()),
         date: sea_orm::Set(timestamp),
         auto_clean: sea_orm::Set(config.auto_clean),
         user_id: sea_orm::Set(config.id.into()),
         session_id: sea_orm::Set(config.session_id.map(|s| s.0 as i64)),
+        size: sea_orm::Set(Some(config.size.bytes())),
+        hash: sea_orm::Set(config.hash),
+        content_type: sea_orm::Set(config.content_type),
+        width: sea_orm::Set(config.dimensions.map(|(width, _)| width as i32)),
+        height: sea_orm::Set(config.dimensions.map(|(_, height)| height as i32)),
+        thumbnail_type: sea_orm::Set(config.thumbnail_type),
     };
-    file.insert(db_connection).await?;
+    Ok(file.insert(db_connection).await?)
+}
+
+/// Record the verified file at `staging` and hand it to the storage, along with the thumbnails
+/// if it is an image.
+///
+/// With `deduplicate`, the content is kept in the blob of its hash, which is only stored if no
+/// other file has the same content.
+pub async fn store_uploaded_file(
+    mut config: AddFileRecordConfig,
+    staging: &Path,
+    deduplicate: bool,
+    db_connection: &DatabaseConnection,
+    storage: &dyn StorageBackend,
+) -> Result<(), UploadError> {
+    if deduplicate {
+        return store_deduplicated_file(config, staging, db_connection, storage).await;
+    }
+    let media = extract_media(staging).await;
+    if let Some(media) = &media {
+        apply_media(&mut config, media);
+    }
+    let file_info = add_file_record(config, db_connection, storage).await?;
+    if let Err(e) = storage.put_file(&file_info.path, staging).await {
+        remove_file(file_info, db_connection, storage).await?;
+        return Err(e.into());
+    }
+    if let Some(media) = media {
+        store_thumbnails(file_info, media, staging, db_connection, storage).await?;
+    }
     Ok(())
 }
 
+fn apply_media(config: &mut AddFileRecordConfig, media: &MediaInfo) {
+    // Detected from the content, more reliable than the one told by the client
+    config.content_type = Some(media.content_type.clone());
+    config.dimensions = Some((media.width, media.height));
+    config.thumbnail_type = (!media.thumbnails.is_empty()).then(|| media.thumbnail_type.clone());
+}
+
+/// Copy what was found out about the content when it was first uploaded
+fn apply_known_media(config: &mut AddFileRecordConfig, known: &files::Model) {
+    if let (Some(width), Some(height)) = (known.width, known.height) {
+        config.content_type = known.content_type.clone();
+        config.dimensions = Some((width as u32, height as u32));
+        config.thumbnail_type = known.thumbnail_type.clone();
+    }
+}
+
+async fn store_deduplicated_file(
+    mut config: AddFileRecordConfig,
+    staging: &Path,
+    db_connection: &DatabaseConnection,
+    storage: &dyn StorageBackend,
+) -> Result<(), UploadError> {
+    let object = blob_object(&config.hash);
+    let known = Files::find()
+        .filter(files::Column::Path.eq(&object))
+        .one(db_connection)
+        .await?;
+    let media = match &known {
+        Some(known) => {
+            apply_known_media(&mut config, known);
+            None
+        }
+        None => extract_media(staging).await,
+    };
+    if let Some(media) = &media {
+        apply_media(&mut config, media);
+    }
+    let stored = acquire_blob(
+        &config.hash,
+        config.size.bytes(),
+        db_connection,
+        |object| async move {
+            storage.put_file(&object, staging).await?;
+            Ok::<_, UploadError>(match media {
+                Some(media) => put_thumbnails(&object, media.thumbnails, staging, storage).await,
+                None => true,
+            })
+        },
+    )
+    .await?;
+    match stored {
+        Some(false) => config.thumbnail_type = None,
+        Some(true) => {}
+        // The content is kept by the blob already
+        None => match fs::remove_file(staging).await {
+            Ok(()) => {}
+            Err(e) => tracing::warn!("cannot remove the staged upload: {}", e),
+        },
+    }
+    add_blob_file_record(config, object, db_connection, storage).await?;
+    Ok(())
+}
+
+/// Add the record of a file kept in the blob taken a reference to, which is dropped again if the
+/// record can't be added
+async fn add_blob_file_record(
+    mut config: AddFileRecordConfig,
+    object: String,
+    db_connection: &DatabaseConnection,
+    storage: &dyn StorageBackend,
+) -> Result<files::Model, UploadError> {
+    let hash = config.hash.clone();
+    config.object = Some(object);
+    match add_file_record(config, db_connection, storage).await {
+        Ok(file_info) => Ok(file_info),
+        Err(e) => {
+            if let Err(e) = release_blob(&hash, db_connection, storage).await {
+                tracing::error!("cannot release the blob {}: {}", hash, e);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// A deduplicated file of the content which the user can download already, so that uploading it
+/// again doesn't need its content.
+///
+/// Knowing the hash alone doesn't prove having the content, so the files the user has no access
+/// to are never reused.
+async fn downloadable_file_with_hash(
+    id: ID,
+    hash_hex: &str,
+    size: u64,
+    db_connection: &DatabaseConnection,
+) -> Result<Option<files::Model>, UploadError> {
+    let candidates = Files::find()
+        .filter(files::Column::Hash.eq(hash_hex))
+        .filter(files::Column::Path.eq(blob_object(hash_hex)))
+        .filter(files::Column::Size.eq(size as i64))
+        .all(db_connection)
+        .await?;
+    for file in candidates {
+        if file.user_id == i64::from(id) {
+            return Ok(Some(file));
+        }
+        if let Some(session_id) = file.session_id
+            && SessionRelation::find_by_id((session_id, i64::from(id)))
+                .one(db_connection)
+                .await?
+                .is_some()
+        {
+            return Ok(Some(file));
+        }
+    }
+    Ok(None)
+}
+
+/// Add a file of content the server has already without uploading it again, returning its key,
+/// or `None` if the content has to be uploaded
+pub async fn add_known_file(
+    mut config: AddFileRecordConfig,
+    db_connection: &DatabaseConnection,
+    storage: &dyn StorageBackend,
+) -> Result<Option<String>, UploadError> {
+    let Some(known) = downloadable_file_with_hash(
+        config.id,
+        &config.hash,
+        config.size.bytes() as u64,
+        db_connection,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+    if reference_blob(&config.hash, db_connection).await?.is_none() {
+        return Ok(None);
+    }
+    apply_known_media(&mut config, &known);
+    let file_info = add_blob_file_record(config, known.path, db_connection, storage).await?;
+    Ok(Some(file_info.key))
+}
+
 /// Clean up files to free up storage space
 /// # Arguments
 /// * `need_to_delete` - Amount of space that needs to be freed
 /// * `db_connection` - Database connection handle
 /// * `user_id` - ID of the user whose files need to be cleaned
+/// * `storage` - Backend keeping the files
 ///
 /// # Details
 /// Files are deleted in order of creation date (oldest first) until sufficient space is freed
 pub async fn clean_files(
     need_to_delete: Size,
-    db_connection: &impl ConnectionTrait,
+    db_connection: &(impl ConnectionTrait + TransactionTrait),
     user_id: ID,
+    storage: &dyn StorageBackend,
 ) -> Result<(), UploadError> {
     tracing::debug!("Begin to clean files");
     let mut files_pages = Files::find()
@@ -124,11 +323,13 @@ pub async fn clean_files(
     let mut deleted_size = Size::from_bytes(0);
     'reserve_space: while let Some(files) = files_pages.fetch_and_next().await? {
         for file in files {
-            let delta_size = Size::from_bytes(fs::metadata(&file.path).await?.len());
+            let delta_size = Size::from_bytes(match file.size {
+                Some(size) => size as u64,
+                None => storage.size(&file.path).await?,
+            });
             deleted_size += delta_size;
-            fs::remove_file(&file.path).await?;
+            remove_file_record(&file, db_connection, storage).await?;
             tracing::debug!("Deleted file: {}", &file.key);
-            Files::delete_by_id(&file.key).exec(db_connection).await?;
             if deleted_size + delta_size > need_to_delete {
                 break 'reserve_space;
             }
@@ -167,14 +368,18 @@ async fn upload_impl(
         Some(data) => data,
     };
 
-    let key = generate_key_name(format!("{:x}", metadata.hash));
+    let hash_hex = format!("{:x}", metadata.hash);
+    let key = generate_key_name(&hash_hex);
     let key_clone = key.clone();
     let files_storage_path = server.shared_data.cfg().main_cfg.files_storage_path.clone();
     let limit_size = server.shared_data.cfg().main_cfg.user_files_limit;
-    if metadata.size > limit_size.bytes() as u64 {
+    let deduplicate = server.shared_data.cfg().main_cfg.enable_file_deduplication;
+    let user_limit = get_user_files_limit(id, &server.db.db_pool, limit_size).await?;
+    if metadata.size > user_limit.bytes() as u64 {
         return Err(UploadError::FileSizeOverflow);
     }
     let session_id = metadata.session_id.map(SessionID);
+    let content_type = check_content_type(metadata.content_type.clone())?;
 
     // Create temporary file path for streaming using hierarchical structure
     let temp_key = format!("{}.tmp", key);
@@ -216,26 +421,33 @@ async fn upload_impl(
             return Err(UploadError::FileHashError);
         }
 
-        // All verifications passed, now create the database record and move file
-        let final_path = generate_hierarchical_path(&files_storage_path, id, &key);
+        temp_file.flush().await?;
+        temp_file.sync_all().await?;
+        drop(temp_file);
 
-        // Create database record - this will handle storage limits and cleanup
+        // All verifications passed, now create the database record and store the file
         let config = AddFileRecordConfig {
             id,
             size: Size::from_bytes(metadata.size),
             key: key.clone(),
+            hash: hash_hex,
+            content_type,
+            dimensions: None,
+            thumbnail_type: None,
             auto_clean: metadata.auto_clean,
-            files_storage_path: files_storage_path.clone(),
             limit_size,
             session_id,
+            object: None,
         };
-        add_file_record(config, &server.db.db_pool).await?;
-        temp_file.flush().await?;
-        temp_file.sync_all().await?;
-
-        // Move temporary file to final location
-        fs::rename(&temp_path, &final_path).await?;
-        Ok(())
+        let storage = server.shared_data.file_sys.storage();
+        store_uploaded_file(
+            config,
+            &temp_path,
+            deduplicate,
+            &server.db.db_pool,
+            storage.as_ref(),
+        )
+        .await
     };
     match logic.await {
         Ok(_) => {}
@@ -18,16 +18,16 @@ use tonic::{Response, Status};
 use tracing::info;
 
 use crate::{
-    db::file_storage::generate_hierarchical_path,
+    db::file_storage::{generate_hierarchical_path, get_user_files_limit},
     helper::{create_file_with_dirs_if_not_exist, generate_random_string},
     process::{
         LocalUploadState,
         error_msg::{self, SERVER_ERROR},
         files::{
-            upload::{AddFileRecordConfig, add_file_record},
+            upload::{AddFileRecordConfig, add_known_file, store_uploaded_file},
             upload_foundation::{
-                UPLOAD_TIMEOUT_SECONDS, UploadError, UploadSessionMetadata, generate_key_name,
-                recommended_chunk_size, upload_session_key,
+                UPLOAD_TIMEOUT_SECONDS, UploadError, UploadSessionMetadata, check_content_type,
+                generate_key_name, recommended_chunk_size, upload_session_key,
             },
         },
     },
@@ -83,10 +83,41 @@ pub async fn start_upload_impl(
     let mut redis = server.db.redis();
 
     // Check user quota and size limits
-    let limit_size = server.shared_data.cfg().main_cfg.user_files_limit;
+    let default_limit = server.shared_data.cfg().main_cfg.user_files_limit;
+    let limit_size = get_user_files_limit(id, &server.db.db_pool, default_limit).await?;
     if req.size > limit_size.bytes() as u64 {
         return Err(UploadError::FileSizeOverflow);
     }
+    let content_type = check_content_type(req.content_type)?;
+    let session_id = req.session_id.map(SessionID);
+
+    // The content may be known already, then the file is added without uploading it
+    let deduplicate = server.shared_data.cfg().main_cfg.enable_file_deduplication;
+    if deduplicate && req.hash.len() == <Sha3_256 as Digest>::output_size() {
+        let hash_hex = format!("{:x}", req.hash);
+        let config = AddFileRecordConfig {
+            id,
+            size: Size::from_bytes(req.size),
+            key: generate_key_name(&hash_hex),
+            hash: hash_hex,
+            content_type: content_type.clone(),
+            dimensions: None,
+            thumbnail_type: None,
+            auto_clean: req.auto_clean,
+            limit_size: default_limit,
+            session_id,
+            object: None,
+        };
+        let storage = server.shared_data.file_sys.storage();
+        if let Some(key) = add_known_file(config, &server.db.db_pool, storage.as_ref()).await? {
+            return Ok(StartUploadResponse {
+                upload_id: String::new(),
+                chunk_size: 0,
+                timeout_seconds: 0,
+                key: Some(key),
+            });
+        }
+    }
 
     // Generate unique upload ID
     let id_itself = generate_random_string(32);
@@ -104,17 +135,19 @@ pub async fn start_upload_impl(
     // Create temp dir
     create_dir_all(&temp_path).await?;
 
-    let session_id = req.session_id.map(SessionID);
     // Create metadata
-    let metadata = UploadSessionMetadata::new(
-        upload_id.clone(),
-        id,
-        req.hash,
-        req.size as usize,
-        req.auto_clean,
-        session_id,
-        temp_path.clone(),
-    );
+    let metadata = UploadSessionMetadata {
+        content_type,
+        ..UploadSessionMetadata::new(
+            upload_id.clone(),
+            id,
+            req.hash,
+            req.size as usize,
+            req.auto_clean,
+            session_id,
+            temp_path.clone(),
+        )
+    };
 
     // Save to Redis with TTL
     save_session_to_redis(&mut redis, &metadata).await?;
@@ -154,6 +187,7 @@ pub async fn start_upload_impl(
         upload_id,
         chunk_size: recommended_chunk_size().bytes() as u32,
         timeout_seconds: UPLOAD_TIMEOUT_SECONDS.as_secs() as u32,
+        key: None,
     })
 }
 
@@ -289,16 +323,29 @@ pub async fn complete_upload_impl(
         ));
     }
 
-    // Generate final key
+    // The local state was taken out of the map, so its cleanup task won't remove the directory
+    let result = assemble_and_store(server, id, &local_state, metadata).await;
+    if let Err(e) = fs::remove_dir_all(&local_state.temp_dir).await {
+        tracing::error!("Failed to clean up upload {}: {:?}", req.upload_id, e);
+    }
+    local_state.cleanup();
+    let key = result?;
+
+    Ok(CompleteUploadResponse { key })
+}
+
+/// Join the chunks, verify the hash and hand the file to the storage, returning the key
+async fn assemble_and_store(
+    server: &RpcServer,
+    id: ID,
+    local_state: &LocalUploadState,
+    metadata: UploadSessionMetadata,
+) -> Result<String, UploadError> {
     let hash_hex = format!("{:x}", metadata.expected_hash);
     let key = generate_key_name(&hash_hex);
-    let final_path = generate_hierarchical_path(
-        &server.shared_data.cfg().main_cfg.files_storage_path,
-        id,
-        &key,
-    );
+    let complete_path = local_state.temp_dir.join("complete");
     // write file
-    let mut file = create_file_with_dirs_if_not_exist(&final_path).await?;
+    let mut file = create_file_with_dirs_if_not_exist(&complete_path).await?;
     let mut tmp_files: Vec<_> = ReadDirStream::new(fs::read_dir(&local_state.temp_dir).await?)
         .filter_map(|item| {
             item.ok()
@@ -322,11 +369,10 @@ pub async fn complete_upload_impl(
             hasher.update(&buf[..n]);
         }
     }
+    file.flush().await?;
+    drop(file);
     let actual_hash = hasher.finalize();
     if actual_hash.as_slice() != metadata.expected_hash {
-        // Clean up file and local state
-        let _ = fs::remove_file(&final_path).await;
-        local_state.cleanup();
         return Err(UploadError::FileHashError);
     }
 
@@ -335,14 +381,26 @@ pub async fn complete_upload_impl(
         id,
         size: Size::from_bytes(metadata.expected_size),
         key: key.clone(),
+        hash: hash_hex,
+        content_type: metadata.content_type,
+        dimensions: None,
+        thumbnail_type: None,
         auto_clean: metadata.auto_clean,
-        files_storage_path: server.shared_data.cfg().main_cfg.files_storage_path.clone(),
         limit_size: server.shared_data.cfg().main_cfg.user_files_limit,
         session_id: metadata.session_id,
+        object: None,
     };
-    add_file_record(config, &server.db.db_pool).await?;
-
-    Ok(CompleteUploadResponse { key })
+    let deduplicate = server.shared_data.cfg().main_cfg.enable_file_deduplication;
+    let storage = server.shared_data.file_sys.storage();
+    store_uploaded_file(
+        config,
+        &complete_path,
+        deduplicate,
+        &server.db.db_pool,
+        storage.as_ref(),
+    )
+    .await?;
+    Ok(key)
 }
 
 pub async fn complete_upload(
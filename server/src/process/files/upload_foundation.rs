@@ -10,7 +10,7 @@ use crate::{
     helper::generate_random_string,
     process::error_msg::{
         FILE_HASH_ERROR, FILE_SIZE_ERROR, INCORRECT_ORDER, METADATA_ERROR, SERVER_ERROR,
-        STORAGE_FULL,
+        STORAGE_FULL, invalid,
     },
 };
 use base::constants::{ID, SessionID};
@@ -48,6 +48,12 @@ pub enum UploadError {
     RedisError(#[from] redis::RedisError),
     #[error("json error:{0:?}")]
     JsonError(#[from] serde_json::Error),
+    #[error("storage error:{0:?}")]
+    StorageError(#[from] crate::storage::StorageError),
+    #[error("delete file error:{0:?}")]
+    DeleteFileError(#[from] crate::process::delete_file::DeleteFileError),
+    #[error("file blob error:{0:?}")]
+    FileBlobError(#[from] crate::db::file_blobs::FileBlobError),
 }
 
 impl From<UploadError> for Status {
@@ -60,7 +66,10 @@ impl From<UploadError> for Status {
             | UploadError::InternalIOError(_)
             | UploadError::InvalidPathError
             | UploadError::RedisError(_)
-            | UploadError::JsonError(_) => Status::internal(SERVER_ERROR),
+            | UploadError::JsonError(_)
+            | UploadError::StorageError(_)
+            | UploadError::DeleteFileError(_)
+            | UploadError::FileBlobError(_) => Status::internal(SERVER_ERROR),
             UploadError::StatusError(e) => e,
             UploadError::WrongStructure => Status::invalid_argument(INCORRECT_ORDER),
             UploadError::FileSizeError(..) => Status::invalid_argument(FILE_SIZE_ERROR),
@@ -79,6 +88,8 @@ pub struct UploadSessionMetadata {
     pub expected_size: usize,
     pub auto_clean: bool,
     pub session_id: Option<SessionID>, // Stored as i64, u64 values should be converted
+    #[serde(default)]
+    pub content_type: Option<String>,
     pub temp_path: String,
     pub bytes_received: usize,
     pub created_at: TimeStampUtc,
@@ -104,6 +115,7 @@ impl UploadSessionMetadata {
             expected_size,
             auto_clean,
             session_id,
+            content_type: None,
             temp_path: temp_path.to_string_lossy().to_string(),
             bytes_received: 0,
             created_at: now,
@@ -138,3 +150,20 @@ pub fn generate_key_name(hash: impl AsRef<str>) -> String {
     let prefix: String = generate_random_string(PREFIX_LEN);
     format!("{prefix}{}", hash.as_ref())
 }
+
+const CONTENT_TYPE_MAX_LEN: usize = 255;
+
+/// Check the MIME type given by the client, which is sent back as the `Content-Type` header
+/// when downloading over http. A blank one means no type.
+pub fn check_content_type(content_type: Option<String>) -> Result<Option<String>, UploadError> {
+    let Some(content_type) = content_type.filter(|content_type| !content_type.is_empty()) else {
+        return Ok(None);
+    };
+    let valid = content_type.len() <= CONTENT_TYPE_MAX_LEN
+        && content_type.contains('/')
+        && http::HeaderValue::from_str(&content_type).is_ok();
+    if !valid {
+        Err(Status::invalid_argument(invalid::CONTENT_TYPE))?
+    }
+    Ok(Some(content_type))
+}
@@ -146,6 +146,7 @@ async fn accept_friend_invitation_impl(
         msg_id: msg_model.msg_id as u64,
         time: Some(msg_model.time.into()),
         respond_event_type: Some(respond_msg),
+        reactions: vec![],
     };
     transmit_msg(
         fetch_response,
@@ -124,6 +124,7 @@ async fn add_friend_impl(
         msg_id: msg_model.msg_id as u64,
         time: Some(msg_model.time.into()),
         respond_event_type: Some(respond_msg),
+        reactions: vec![],
     };
     let rmq_conn = server.get_rabbitmq_manager().await?;
     let mut conn = rmq_conn
@@ -0,0 +1,220 @@
+//! Verifying the public keys of the users.
+//!
+//! Every public key a user starts using is recorded, and the members of the end-to-end encrypted
+//! sessions of the user are told about it. The safety number of two users is derived from both
+//! of their keys, comparing it out of band shows whether one of them has been handed a key the
+//! other doesn't have. The room keys are sent to the identity keys of the devices as well, so
+//! these keys are part of the safety number too.
+
+use anyhow::Context;
+use base::constants::ID;
+use chrono::Utc;
+use entities::user_key_history;
+use pb::service::ourchat::device::v1::{
+    GetKeyFingerprintRequest, GetKeyFingerprintResponse, PublicKeyChangedNotification,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sha2::{Digest, Sha512};
+use tonic::{Request, Response, Status};
+
+use crate::db::device_keys::get_user_device_keys;
+use crate::db::key_history::get_last_key_change;
+use crate::db::session::get_e2ee_sessions;
+use crate::db::user::get_account_info_db;
+use crate::helper::spawn_blocking_with_tracing;
+use crate::process::RpcError;
+use crate::process::error_msg::not_found;
+use crate::process::{Dest, MsgInsTransmitErr, message_insert_and_transmit};
+use crate::server::RpcServer;
+
+const FINGERPRINT_VERSION: u16 = 1;
+/// The hash is iterated to make finding another key with the same fingerprint expensive
+const FINGERPRINT_ITERATIONS: usize = 5200;
+/// Bytes of the fingerprint, every 5 of them become 5 digits of the safety number
+const FINGERPRINT_LEN: usize = 30;
+
+/// The fingerprint of the keys of a user, the public key of the account and the identity keys of
+/// its devices in any order
+pub fn fingerprint(user_id: ID, public_key: &[u8], device_keys: &[Vec<u8>]) -> Vec<u8> {
+    let mut device_keys: Vec<&[u8]> = device_keys.iter().map(Vec::as_slice).collect();
+    device_keys.sort_unstable();
+    device_keys.dedup();
+    // Every key is prefixed with its length, so the keys can't be split up in another way
+    let mut keys = Vec::new();
+    for key in std::iter::once(public_key).chain(device_keys) {
+        keys.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        keys.extend_from_slice(key);
+    }
+    let mut hash = Sha512::new()
+        .chain_update(FINGERPRINT_VERSION.to_be_bytes())
+        .chain_update(&keys)
+        .chain_update(user_id.0.to_be_bytes())
+        .finalize();
+    for _ in 1..FINGERPRINT_ITERATIONS {
+        hash = Sha512::new()
+            .chain_update(hash)
+            .chain_update(&keys)
+            .finalize();
+    }
+    hash[..FINGERPRINT_LEN].to_vec()
+}
+
+fn displayable_fingerprint(fingerprint: &[u8]) -> String {
+    fingerprint
+        .chunks(5)
+        .map(|chunk| {
+            let value = chunk
+                .iter()
+                .fold(0u64, |value, byte| (value << 8) | u64::from(*byte));
+            format!("{:05}", value % 100_000)
+        })
+        .collect()
+}
+
+/// The safety number of two users from their fingerprints, the same whichever of them asks
+pub fn safety_number(a: (ID, &[u8]), b: (ID, &[u8])) -> String {
+    let (first, second) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+    displayable_fingerprint(first.1) + &displayable_fingerprint(second.1)
+}
+
+/// Tell the members of the end-to-end encrypted sessions of the user about the new public key, or
+/// the new identity key of one of its devices
+pub async fn notify_public_key_changed(
+    server: &RpcServer,
+    id: ID,
+    key: &user_key_history::Model,
+) -> Result<(), MsgInsTransmitErr> {
+    let sessions = get_e2ee_sessions(id, &server.db.db_pool).await?;
+    if sessions.is_empty() {
+        return Ok(());
+    }
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    for session in sessions {
+        let msg = RespondEventType::PublicKeyChanged(PublicKeyChangedNotification {
+            session_id: session.session_id as u64,
+            user_id: id.into(),
+            public_key: key.public_key.clone().into(),
+            changed_time: Some(key.created_time.into()),
+            device_id: key.device_id.clone(),
+        });
+        message_insert_and_transmit(
+            Some(id),
+            Some(session.session_id.into()),
+            msg,
+            Dest::SessionExcept(session.session_id.into(), id),
+            false,
+            &server.db.db_pool,
+            &mut channel,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// The identity keys of the devices the room keys are sent to
+async fn active_device_keys(
+    server: &RpcServer,
+    user_id: ID,
+) -> Result<Vec<Vec<u8>>, sea_orm::DbErr> {
+    let since = Utc::now() - server.shared_data.cfg().main_cfg.refresh_token_expire;
+    Ok(get_user_device_keys(user_id, since, &server.db.db_pool)
+        .await?
+        .into_iter()
+        .map(|device| device.identity_key)
+        .collect())
+}
+
+async fn get_key_fingerprint_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetKeyFingerprintRequest>,
+) -> Result<GetKeyFingerprintResponse, RpcError> {
+    let peer_id = ID(request.into_inner().user_id);
+    let Some(peer) = get_account_info_db(peer_id, &server.db.db_pool).await? else {
+        Err(Status::not_found(not_found::USER))?
+    };
+    let user = get_account_info_db(id, &server.db.db_pool)
+        .await?
+        .context("cannot find the user")?;
+    let user_device_keys = active_device_keys(server, id).await?;
+    let peer_device_keys = active_device_keys(server, peer_id).await?;
+    if (user.public_key.is_empty() && user_device_keys.is_empty())
+        || (peer.public_key.is_empty() && peer_device_keys.is_empty())
+    {
+        Err(Status::failed_precondition(not_found::PUBLIC_KEY))?
+    }
+    let peer_key = get_last_key_change(peer_id, &server.db.db_pool).await?;
+
+    let (fingerprint, peer_fingerprint) = spawn_blocking_with_tracing(move || {
+        (
+            fingerprint(id, &user.public_key, &user_device_keys),
+            fingerprint(peer_id, &peer.public_key, &peer_device_keys),
+        )
+    })
+    .await
+    .context("compute fingerprint async task error")?;
+    Ok(GetKeyFingerprintResponse {
+        safety_number: safety_number((id, &fingerprint), (peer_id, &peer_fingerprint)),
+        fingerprint: fingerprint.into(),
+        peer_fingerprint: peer_fingerprint.into(),
+        peer_key_changed_time: peer_key.map(|key| key.created_time.into()),
+    })
+}
+
+pub async fn get_key_fingerprint(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetKeyFingerprintRequest>,
+) -> Result<Response<GetKeyFingerprintResponse>, Status> {
+    match get_key_fingerprint_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safety_number() {
+        let (a, b) = (ID(1), ID(2));
+        let key_a = fingerprint(a, b"key of a", &[]);
+        let key_b = fingerprint(b, b"key of b", &[]);
+        assert_eq!(key_a.len(), FINGERPRINT_LEN);
+        assert_eq!(key_a, fingerprint(a, b"key of a", &[]));
+        // the same key of another user has another fingerprint
+        assert_ne!(key_a, fingerprint(b, b"key of a", &[]));
+
+        let number = safety_number((a, &key_a), (b, &key_b));
+        assert_eq!(number.len(), 60);
+        assert!(number.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(number, safety_number((b, &key_b), (a, &key_a)));
+
+        let new_key_b = fingerprint(b, b"new key of b", &[]);
+        let new_number = safety_number((a, &key_a), (b, &new_key_b));
+        assert_eq!(number[..30], new_number[..30]);
+        assert_ne!(number[30..], new_number[30..]);
+    }
+
+    #[test]
+    fn test_fingerprint_of_device_keys() {
+        let id = ID(1);
+        let devices = vec![b"device 1".to_vec(), b"device 2".to_vec()];
+        let key = fingerprint(id, b"key", &devices);
+        assert_ne!(key, fingerprint(id, b"key", &[]));
+        // a new device changes the fingerprint, the order of the devices doesn't
+        assert_ne!(key, fingerprint(id, b"key", &devices[..1]));
+        let reversed: Vec<_> = devices.iter().rev().cloned().collect();
+        assert_eq!(key, fingerprint(id, b"key", &reversed));
+        // the keys can't be moved from one to another
+        assert_ne!(
+            fingerprint(id, b"ke", &[b"y".to_vec()]),
+            fingerprint(id, b"k", &[b"ey".to_vec()])
+        );
+    }
+}
@@ -1,3 +1,13 @@
+pub mod edit;
+pub mod expire;
+pub mod fetch_thread;
 pub mod fetch_user_msg;
+pub mod get_session_history;
+pub mod pin;
+pub mod reaction;
+pub mod read;
 pub mod recall;
+pub mod scheduled;
+pub mod search;
 pub mod send_msg;
+pub mod typing;
@@ -0,0 +1,102 @@
+use crate::db::session::{in_session, user_muted_status};
+use crate::db::{self};
+use crate::process::error_msg::{
+    self, ENCRYPTION_STATE_MISMATCH, ONLY_MSG_CAN_BE_EDITED, PERMISSION_DENIED, not_found,
+};
+use crate::process::{Dest, RpcError, expiring_message_insert_and_transmit};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::{ID, SessionID};
+use entities::prelude::MessageRecords;
+use pb::service::ourchat::msg_delivery::edit::v1::{
+    EditMsgRequest, EditMsgResponse, EditNotification,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::{EntityTrait, QuerySelect, TransactionTrait};
+use tonic::{Request, Response, Status};
+
+pub async fn edit_msg(
+    server: &RpcServer,
+    id: ID,
+    request: Request<EditMsgRequest>,
+) -> Result<Response<EditMsgResponse>, Status> {
+    match edit_msg_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn edit_msg_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<EditMsgRequest>,
+) -> Result<EditMsgResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let mut redis_connection = server.db.redis();
+    if user_muted_status(id, session_id, &mut redis_connection)
+        .await?
+        .is_some()
+    {
+        Err(Status::permission_denied(error_msg::MUTE))?
+    }
+
+    let transaction = server.db.db_pool.begin().await?;
+    let msg = MessageRecords::find_by_id(req.msg_id as i64)
+        .lock_exclusive()
+        .one(&transaction)
+        .await?
+        .filter(|msg| msg.session_id == Some(session_id.into()))
+        .ok_or_else(|| Status::not_found(not_found::MSG))?;
+    if msg.sender_id != Some(id.into()) {
+        Err(Status::permission_denied(PERMISSION_DENIED))?;
+    }
+    let RespondEventType::Msg(mut content) =
+        serde_json::from_value(msg.msg_data.clone()).context("incorrect msg in database")?
+    else {
+        Err(Status::invalid_argument(ONLY_MSG_CAN_BE_EDITED))?
+    };
+    // The server never looks into encrypted content, so switching the encryption state of a
+    // message by editing it is not allowed
+    if content.is_encrypted != req.is_encrypted {
+        Err(Status::invalid_argument(ENCRYPTION_STATE_MISMATCH))?;
+    }
+    // The edit is deleted together with the message, or the new content outlives it
+    let expire_time = msg.expire_time;
+    content.markdown_text = req.markdown_text;
+    content.involved_files = req.involved_files;
+    let (_, revision) =
+        db::messages::edit_msg(msg, RespondEventType::Msg(content.clone()), &transaction).await?;
+    transaction.commit().await?;
+
+    let respond_msg = RespondEventType::Edit(EditNotification {
+        msg_id: req.msg_id,
+        session_id: req.session_id,
+        markdown_text: content.markdown_text,
+        involved_files: content.involved_files,
+        is_encrypted: content.is_encrypted,
+        revision,
+    });
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    let notification = expiring_message_insert_and_transmit(
+        Some(id),
+        Some(session_id),
+        respond_msg,
+        Dest::Session(session_id),
+        content.is_encrypted,
+        expire_time,
+        &server.db.db_pool,
+        &mut channel,
+    )
+    .await?;
+    Ok(EditMsgResponse {
+        msg_id: notification.msg_id as u64,
+    })
+}
@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::messages::{delete_expired_msgs, insert_msg_record, is_file_referenced};
+use crate::process::delete_file::remove_file;
+use crate::process::{Dest, transmit_msg};
+use crate::storage::StorageBackend;
+use anyhow::Context;
+use base::constants::SessionID;
+use base::database::DbPool;
+use base::wrapper::JobSchedulerWrapper;
+use entities::prelude::Files;
+use pb::service::ourchat::msg_delivery::expire::v1::ExpireNotification;
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::EntityTrait;
+use tokio::sync::MutexGuard;
+use tokio_cron_scheduler::Job;
+
+/// How many expired messages are deleted in one query
+const CLEAN_BATCH_SIZE: u64 = 500;
+
+/// Add a job deleting the messages whose time to live is over.
+///
+/// The members of the session are notified by an `ExpireNotification`, which is stored as well
+/// so that the offline members can purge their local copies later. The edits, reactions and pins
+/// of a message expire with it. The files attached to the deleted messages and edits are removed
+/// once no message refers to them anymore.
+pub async fn clean_expired_msgs(
+    interval: Duration,
+    db_pool: DbPool,
+    rmq_pool: deadpool_lapin::Pool,
+    storage: Arc<dyn StorageBackend>,
+    job_scheduler: MutexGuard<'_, JobSchedulerWrapper>,
+) -> anyhow::Result<()> {
+    let job = Job::new_repeated_async(interval, move |_uuid, _l| {
+        let db_pool = db_pool.clone();
+        let rmq_pool = rmq_pool.clone();
+        let storage = storage.clone();
+        Box::pin(async move {
+            if let Err(e) = clean_expired_msgs_worker(&db_pool, &rmq_pool, storage.as_ref()).await {
+                tracing::error!("Failed to clean expired messages: {:?}", e);
+            }
+        })
+    })?;
+    job_scheduler.add(job).await?;
+    Ok(())
+}
+
+async fn clean_expired_msgs_worker(
+    db_pool: &DbPool,
+    rmq_pool: &deadpool_lapin::Pool,
+    storage: &dyn StorageBackend,
+) -> anyhow::Result<()> {
+    loop {
+        let expired = delete_expired_msgs(CLEAN_BATCH_SIZE, &db_pool.db_pool).await?;
+        if expired.is_empty() {
+            return Ok(());
+        }
+        tracing::info!("delete {} expired messages", expired.len());
+        let mut expired_by_session: BTreeMap<SessionID, Vec<u64>> = BTreeMap::new();
+        let mut involved_files = BTreeSet::new();
+        for msg in expired {
+            let Some(session_id) = msg.session_id else {
+                continue;
+            };
+            expired_by_session
+                .entry(session_id.into())
+                .or_default()
+                .push(msg.msg_id as u64);
+            match serde_json::from_value(msg.msg_data) {
+                Ok(RespondEventType::Msg(content)) => involved_files.extend(content.involved_files),
+                Ok(RespondEventType::Edit(edit)) => involved_files.extend(edit.involved_files),
+                _ => {}
+            }
+        }
+
+        let mut channel = rmq_pool
+            .get()
+            .await
+            .context("cannot get rabbitmq connection")?
+            .create_channel()
+            .await
+            .context("cannot create rabbitmq channel")?;
+        for (session_id, msg_ids) in expired_by_session {
+            let respond_msg = RespondEventType::Expire(ExpireNotification {
+                session_id: session_id.into(),
+                msg_ids,
+            });
+            let msg = insert_msg_record(
+                None,
+                Some(session_id),
+                respond_msg.clone(),
+                false,
+                &db_pool.db_pool,
+                false,
+            )
+            .await?;
+            transmit_msg(
+                FetchMsgsResponse {
+                    msg_id: msg.msg_id as u64,
+                    time: Some(msg.time.into()),
+                    respond_event_type: Some(respond_msg),
+                    reactions: vec![],
+                },
+                Dest::Session(session_id),
+                &mut channel,
+                &db_pool.db_pool,
+            )
+            .await?;
+        }
+
+        for key in involved_files {
+            if is_file_referenced(&key, &db_pool.db_pool).await? {
+                continue;
+            }
+            let Some(file_info) = Files::find_by_id(&key).one(&db_pool.db_pool).await? else {
+                continue;
+            };
+            if let Err(e) = remove_file(file_info, &db_pool.db_pool, storage).await {
+                tracing::error!(
+                    "failed to remove the file {} of expired messages: {}",
+                    key,
+                    e
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::db::messages::{get_reaction_counts, get_session_msg, get_thread_replies};
+use crate::db::session::in_session;
+use crate::process::RpcError;
+use crate::process::error_msg::not_found;
+use crate::server::RpcServer;
+use base::constants::{ID, SessionID};
+use pb::service::ourchat::msg_delivery::thread::v1::{FetchThreadRequest, FetchThreadResponse};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use tonic::{Request, Response, Status};
+
+pub async fn fetch_thread(
+    server: &RpcServer,
+    id: ID,
+    request: Request<FetchThreadRequest>,
+) -> Result<Response<FetchThreadResponse>, Status> {
+    match fetch_thread_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn fetch_thread_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<FetchThreadRequest>,
+) -> Result<FetchThreadResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    if get_session_msg(req.thread_root_id, session_id, &server.db.db_pool)
+        .await?
+        .is_none()
+    {
+        Err(Status::not_found(not_found::MSG))?;
+    }
+    let max_page_size = server.shared_data.cfg().main_cfg.db.fetch_msg_page_size;
+    let page_size = match req.page_size {
+        0 => max_page_size,
+        size => size.min(max_page_size),
+    };
+    // fetch one more record to know whether there is a next page
+    let mut msgs = get_thread_replies(
+        session_id,
+        req.thread_root_id,
+        req.after_msg_id,
+        page_size + 1,
+        &server.db.db_pool,
+    )
+    .await?;
+    let has_more = msgs.len() as u64 > page_size;
+    msgs.truncate(page_size as usize);
+    let mut reactions =
+        get_reaction_counts(msgs.iter().map(|msg| msg.msg_id), &server.db.db_pool).await?;
+    let mut replies = Vec::with_capacity(msgs.len());
+    for msg_model in msgs {
+        let msg: RespondEventType = match serde_json::from_value(msg_model.msg_data) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("incorrect msg in database:{e}");
+                continue;
+            }
+        };
+        replies.push(FetchMsgsResponse {
+            respond_event_type: Some(msg),
+            msg_id: msg_model.msg_id as u64,
+            time: Some(msg_model.time.into()),
+            reactions: reactions.remove(&msg_model.msg_id).unwrap_or_default(),
+        });
+    }
+    Ok(FetchThreadResponse { replies, has_more })
+}
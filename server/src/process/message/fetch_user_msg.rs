@@ -2,7 +2,10 @@ use std::time::Duration;
 
 use crate::{
     db,
-    process::error_msg::{SERVER_ERROR, TIME_FORMAT_ERROR, TIME_MISSING},
+    process::{
+        error_msg::{SERVER_ERROR, TIME_FORMAT_ERROR, TIME_MISSING},
+        get_sid_from_req,
+    },
     rabbitmq::{create_user_message_broadcast_exchange, create_user_message_direct_exchange},
     server::{FetchMsgsStream, RpcServer},
 };
@@ -54,6 +57,7 @@ async fn fetch_user_msg_impl(
     id: ID,
     request: tonic::Request<FetchMsgsRequest>,
 ) -> Result<Response<FetchMsgsStream>, FetchMsgError> {
+    let device = get_sid_from_req(&request);
     let request = request.into_inner();
     let announcement_only = request.announcement_only;
     let time: TimeStampUtc = match match request.time {
@@ -96,6 +100,11 @@ async fn fetch_user_msg_impl(
                 Ok(mut pag) => {
                     let db_logic = async {
                         while let Some(msgs) = pag.fetch_and_next().await? {
+                            let mut reactions = db::messages::get_reaction_counts(
+                                msgs.iter().map(|msg| msg.msg_id),
+                                &db_conn.db_pool,
+                            )
+                            .await?;
                             for msg_model in msgs {
                                 let msg: RespondEventType =
                                     match serde_json::from_value(msg_model.msg_data) {
@@ -105,10 +114,16 @@ async fn fetch_user_msg_impl(
                                             continue;
                                         }
                                     };
+                                if for_other_device(&msg, device.as_deref()) {
+                                    continue;
+                                }
                                 tx.send(Ok(FetchMsgsResponse {
                                     respond_event_type: Some(msg),
                                     msg_id: msg_model.msg_id as u64,
                                     time: Some(msg_model.time.into()),
+                                    reactions: reactions
+                                        .remove(&msg_model.msg_id)
+                                        .unwrap_or_default(),
                                 }))
                                 .await?;
                             }
@@ -136,7 +151,7 @@ async fn fetch_user_msg_impl(
                 .create_channel()
                 .await
                 .context("cannot create channel")?;
-            let queue_name = crate::rabbitmq::generate_client_name(id);
+            let queue_name = crate::rabbitmq::generate_client_name(id, device.as_deref());
             tracing::info!("queue name: {}", queue_name);
             channel
                 .queue_declare(
@@ -194,6 +209,13 @@ async fn fetch_user_msg_impl(
                             continue;
                         }
                     };
+                    if msg
+                        .respond_event_type
+                        .as_ref()
+                        .is_some_and(|msg| for_other_device(msg, device.as_deref()))
+                    {
+                        continue;
+                    }
                     tx.send(Ok(msg)).await?;
                 }
                 anyhow::Ok(())
@@ -228,3 +250,13 @@ async fn fetch_user_msg_impl(
     let output_stream = ReceiverStream::new(rx);
     Ok(Response::new(Box::pin(output_stream) as FetchMsgsStream))
 }
+
+/// Whether the event is meant for another device of the user only
+fn for_other_device(event: &RespondEventType, device: Option<&str>) -> bool {
+    let target = match event {
+        RespondEventType::ReceiveRoomKey(notification) => notification.device_id.as_deref(),
+        RespondEventType::PrekeysLow(notification) => Some(notification.device_id.as_str()),
+        _ => None,
+    };
+    target.is_some_and(|target| device != Some(target))
+}
@@ -0,0 +1,99 @@
+use crate::db;
+use crate::db::messages::HistoryCursor;
+use crate::db::session::in_session;
+use crate::process::RpcError;
+use crate::process::error_msg::{REQUEST_INVALID_VALUE, TIME_FORMAT_ERROR, not_found};
+use crate::server::RpcServer;
+use base::constants::{ID, SessionID};
+use pb::service::ourchat::msg_delivery::history::v1::{
+    GetSessionHistoryRequest, GetSessionHistoryResponse, HistoryDirection,
+    get_session_history_request::Cursor,
+};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::time::TimeStampUtc;
+use tonic::{Request, Response, Status};
+
+pub async fn get_session_history(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetSessionHistoryRequest>,
+) -> Result<Response<GetSessionHistoryResponse>, Status> {
+    match get_session_history_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn get_session_history_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetSessionHistoryRequest>,
+) -> Result<GetSessionHistoryResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let before = match HistoryDirection::try_from(req.direction) {
+        Ok(HistoryDirection::Unspecified | HistoryDirection::Before) => true,
+        Ok(HistoryDirection::After) => false,
+        Err(_) => Err(Status::invalid_argument(REQUEST_INVALID_VALUE))?,
+    };
+    let cursor = match req.cursor {
+        None => HistoryCursor::Edge,
+        Some(Cursor::MsgId(msg_id)) => HistoryCursor::Msg(
+            db::messages::get_session_msg(msg_id, session_id, &server.db.db_pool)
+                .await?
+                .ok_or_else(|| Status::not_found(not_found::MSG))?,
+        ),
+        Some(Cursor::Time(time)) => {
+            let time: TimeStampUtc = time
+                .try_into()
+                .map_err(|_| Status::invalid_argument(TIME_FORMAT_ERROR))?;
+            HistoryCursor::Time(time.into())
+        }
+    };
+    let max_page_size = server.shared_data.cfg().main_cfg.db.fetch_msg_page_size;
+    let limit = match req.limit {
+        0 => max_page_size,
+        limit => limit.min(max_page_size),
+    };
+    // fetch one more record to know whether there is a next page
+    let mut msgs = db::messages::get_session_history(
+        session_id,
+        cursor,
+        before,
+        limit + 1,
+        &server.db.db_pool,
+    )
+    .await?;
+    let has_more = msgs.len() as u64 > limit;
+    msgs.truncate(limit as usize);
+    if before {
+        msgs.reverse();
+    }
+    let mut reactions =
+        db::messages::get_reaction_counts(msgs.iter().map(|msg| msg.msg_id), &server.db.db_pool)
+            .await?;
+    let mut ret = Vec::with_capacity(msgs.len());
+    for msg_model in msgs {
+        let msg: RespondEventType = match serde_json::from_value(msg_model.msg_data) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("incorrect msg in database:{e}");
+                continue;
+            }
+        };
+        ret.push(FetchMsgsResponse {
+            respond_event_type: Some(msg),
+            msg_id: msg_model.msg_id as u64,
+            time: Some(msg_model.time.into()),
+            reactions: reactions.remove(&msg_model.msg_id).unwrap_or_default(),
+        });
+    }
+    Ok(GetSessionHistoryResponse {
+        msgs: ret,
+        has_more,
+    })
+}
@@ -0,0 +1,185 @@
+use crate::db::session::{if_permission_exist, in_session};
+use crate::db::{self};
+use crate::process::error_msg::{ONLY_MSG_CAN_BE_PINNED, PERMISSION_DENIED, exist, not_found};
+use crate::process::{Dest, RpcError, expiring_message_insert_and_transmit};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::{ID, SessionID};
+use migration::predefined::PredefinedPermissions;
+use pb::service::ourchat::msg_delivery::pin::v1::{
+    ListPinnedMsgsRequest, ListPinnedMsgsResponse, PinMsgRequest, PinMsgResponse, PinNotification,
+    PinnedMsg, UnpinMsgRequest, UnpinMsgResponse,
+};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::time::TimeStamp;
+use tonic::{Request, Response, Status};
+
+pub async fn pin_msg(
+    server: &RpcServer,
+    id: ID,
+    request: Request<PinMsgRequest>,
+) -> Result<Response<PinMsgResponse>, Status> {
+    match pin_msg_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn unpin_msg(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UnpinMsgRequest>,
+) -> Result<Response<UnpinMsgResponse>, Status> {
+    match unpin_msg_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn list_pinned_msgs(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListPinnedMsgsRequest>,
+) -> Result<Response<ListPinnedMsgsResponse>, Status> {
+    match list_pinned_msgs_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn check_pin_permission(
+    server: &RpcServer,
+    id: ID,
+    session_id: SessionID,
+) -> Result<(), RpcError> {
+    if !if_permission_exist(
+        id,
+        session_id,
+        PredefinedPermissions::PinMsg.into(),
+        &server.db.db_pool,
+    )
+    .await?
+    {
+        Err(Status::permission_denied(PERMISSION_DENIED))?;
+    }
+    Ok(())
+}
+
+async fn notify_pin(
+    server: &RpcServer,
+    id: ID,
+    session_id: SessionID,
+    notification: PinNotification,
+    expire_time: Option<TimeStamp>,
+) -> Result<(), RpcError> {
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    expiring_message_insert_and_transmit(
+        Some(id),
+        Some(session_id),
+        RespondEventType::Pin(notification),
+        Dest::Session(session_id),
+        false,
+        expire_time,
+        &server.db.db_pool,
+        &mut channel,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn pin_msg_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<PinMsgRequest>,
+) -> Result<PinMsgResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    check_pin_permission(server, id, session_id).await?;
+    let msg = db::messages::get_session_msg(req.msg_id, session_id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::MSG))?;
+    let content: RespondEventType =
+        serde_json::from_value(msg.msg_data.clone()).context("incorrect msg in database")?;
+    if !matches!(content, RespondEventType::Msg(_)) {
+        Err(Status::invalid_argument(ONLY_MSG_CAN_BE_PINNED))?;
+    }
+    if !db::messages::pin_msg(&msg, id, &server.db.db_pool).await? {
+        Err(Status::already_exists(exist::PINNED_MSG))?;
+    }
+    let notification = PinNotification {
+        session_id: req.session_id,
+        msg_id: req.msg_id,
+        user_id: id.into(),
+        pinned: true,
+    };
+    notify_pin(server, id, session_id, notification, msg.expire_time).await?;
+    Ok(PinMsgResponse {})
+}
+
+async fn unpin_msg_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UnpinMsgRequest>,
+) -> Result<UnpinMsgResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    check_pin_permission(server, id, session_id).await?;
+    let expire_time = db::messages::get_session_msg(req.msg_id, session_id, &server.db.db_pool)
+        .await?
+        .and_then(|msg| msg.expire_time);
+    if !db::messages::unpin_msg(req.msg_id, session_id, &server.db.db_pool).await? {
+        Err(Status::not_found(not_found::PINNED_MSG))?;
+    }
+    let notification = PinNotification {
+        session_id: req.session_id,
+        msg_id: req.msg_id,
+        user_id: id.into(),
+        pinned: false,
+    };
+    notify_pin(server, id, session_id, notification, expire_time).await?;
+    Ok(UnpinMsgResponse {})
+}
+
+async fn list_pinned_msgs_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListPinnedMsgsRequest>,
+) -> Result<ListPinnedMsgsResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let pinned = db::messages::get_pinned_msgs(session_id, &server.db.db_pool).await?;
+    let mut reactions = db::messages::get_reaction_counts(
+        pinned.iter().map(|(_, msg)| msg.msg_id),
+        &server.db.db_pool,
+    )
+    .await?;
+    let mut pinned_msgs = Vec::with_capacity(pinned.len());
+    for (pin, msg_model) in pinned {
+        let msg: RespondEventType = match serde_json::from_value(msg_model.msg_data) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("incorrect msg in database:{e}");
+                continue;
+            }
+        };
+        pinned_msgs.push(PinnedMsg {
+            msg: Some(FetchMsgsResponse {
+                respond_event_type: Some(msg),
+                msg_id: msg_model.msg_id as u64,
+                time: Some(msg_model.time.into()),
+                reactions: reactions.remove(&msg_model.msg_id).unwrap_or_default(),
+            }),
+            pinned_by: pin.pinned_by.unwrap_or_default() as u64,
+            pinned_time: Some(pin.pinned_time.into()),
+        });
+    }
+    Ok(ListPinnedMsgsResponse { pinned_msgs })
+}
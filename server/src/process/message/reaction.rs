@@ -0,0 +1,142 @@
+use crate::db::session::{in_session, user_muted_status};
+use crate::db::{self};
+use crate::process::error_msg::{self, exist, invalid, not_found};
+use crate::process::{Dest, RpcError, expiring_message_insert_and_transmit};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::{ID, SessionID};
+use migration::constants::EMOJI_MAX_LEN;
+use pb::service::ourchat::msg_delivery::reaction::v1::{
+    AddReactionRequest, AddReactionResponse, ReactionNotification, RemoveReactionRequest,
+    RemoveReactionResponse,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::time::TimeStamp;
+use tonic::{Request, Response, Status};
+
+pub async fn add_reaction(
+    server: &RpcServer,
+    id: ID,
+    request: Request<AddReactionRequest>,
+) -> Result<Response<AddReactionResponse>, Status> {
+    match add_reaction_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn remove_reaction(
+    server: &RpcServer,
+    id: ID,
+    request: Request<RemoveReactionRequest>,
+) -> Result<Response<RemoveReactionResponse>, Status> {
+    match remove_reaction_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn check_emoji(emoji: &str) -> Result<(), RpcError> {
+    if emoji.is_empty()
+        || emoji.chars().count() > EMOJI_MAX_LEN
+        || emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        Err(Status::invalid_argument(invalid::EMOJI))?
+    }
+    Ok(())
+}
+
+/// Check whether the user can react to the message and the message is in the session, returning
+/// the expire time of the message
+async fn check_reactable(
+    server: &RpcServer,
+    id: ID,
+    session_id: SessionID,
+    msg_id: u64,
+) -> Result<Option<TimeStamp>, RpcError> {
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let mut redis_connection = server.db.redis();
+    if user_muted_status(id, session_id, &mut redis_connection)
+        .await?
+        .is_some()
+    {
+        Err(Status::permission_denied(error_msg::MUTE))?
+    }
+    let msg = db::messages::get_session_msg(msg_id, session_id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::MSG))?;
+    Ok(msg.expire_time)
+}
+
+async fn notify_reaction(
+    server: &RpcServer,
+    id: ID,
+    session_id: SessionID,
+    notification: ReactionNotification,
+    expire_time: Option<TimeStamp>,
+) -> Result<(), RpcError> {
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    expiring_message_insert_and_transmit(
+        Some(id),
+        Some(session_id),
+        RespondEventType::Reaction(notification),
+        Dest::Session(session_id),
+        false,
+        expire_time,
+        &server.db.db_pool,
+        &mut channel,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn add_reaction_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<AddReactionRequest>,
+) -> Result<AddReactionResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    check_emoji(&req.emoji)?;
+    let expire_time = check_reactable(server, id, session_id, req.msg_id).await?;
+    if !db::messages::add_reaction(req.msg_id, id, req.emoji.clone(), &server.db.db_pool).await? {
+        Err(Status::already_exists(exist::REACTION))?;
+    }
+    let notification = ReactionNotification {
+        session_id: req.session_id,
+        msg_id: req.msg_id,
+        user_id: id.into(),
+        emoji: req.emoji,
+        added: true,
+    };
+    notify_reaction(server, id, session_id, notification, expire_time).await?;
+    Ok(AddReactionResponse {})
+}
+
+async fn remove_reaction_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<RemoveReactionRequest>,
+) -> Result<RemoveReactionResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    let expire_time = check_reactable(server, id, session_id, req.msg_id).await?;
+    if !db::messages::remove_reaction(req.msg_id, id, &req.emoji, &server.db.db_pool).await? {
+        Err(Status::not_found(not_found::REACTION))?;
+    }
+    let notification = ReactionNotification {
+        session_id: req.session_id,
+        msg_id: req.msg_id,
+        user_id: id.into(),
+        emoji: req.emoji,
+        added: false,
+    };
+    notify_reaction(server, id, session_id, notification, expire_time).await?;
+    Ok(RemoveReactionResponse {})
+}
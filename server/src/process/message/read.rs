@@ -0,0 +1,95 @@
+use crate::db::session::in_session;
+use crate::db::{self};
+use crate::process::error_msg::not_found;
+use crate::process::{Dest, RpcError, message_insert_and_transmit};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::{ID, SessionID};
+use pb::service::ourchat::msg_delivery::read::v1::{
+    GetUnreadCountsRequest, GetUnreadCountsResponse, MarkReadRequest, MarkReadResponse,
+    ReadReceiptNotification, UnreadCount,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use tonic::{Request, Response, Status};
+
+pub async fn mark_read(
+    server: &RpcServer,
+    id: ID,
+    request: Request<MarkReadRequest>,
+) -> Result<Response<MarkReadResponse>, Status> {
+    match mark_read_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn get_unread_counts(
+    server: &RpcServer,
+    id: ID,
+    request: Request<GetUnreadCountsRequest>,
+) -> Result<Response<GetUnreadCountsResponse>, Status> {
+    match get_unread_counts_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn mark_read_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<MarkReadRequest>,
+) -> Result<MarkReadResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    if db::messages::get_session_msg(req.msg_id, session_id, &server.db.db_pool)
+        .await?
+        .is_none()
+    {
+        Err(Status::not_found(not_found::MSG))?;
+    }
+    // Marking an older message as read changes nothing, so others needn't be notified
+    if !db::session::mark_read(id, session_id, req.msg_id, &server.db.db_pool).await? {
+        return Ok(MarkReadResponse {});
+    }
+    let respond_msg = RespondEventType::ReadReceipt(ReadReceiptNotification {
+        session_id: req.session_id,
+        user_id: id.into(),
+        msg_id: req.msg_id,
+    });
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    message_insert_and_transmit(
+        Some(id),
+        Some(session_id),
+        respond_msg,
+        Dest::Session(session_id),
+        false,
+        &server.db.db_pool,
+        &mut channel,
+    )
+    .await?;
+    Ok(MarkReadResponse {})
+}
+
+async fn get_unread_counts_impl(
+    server: &RpcServer,
+    id: ID,
+    _request: Request<GetUnreadCountsRequest>,
+) -> Result<GetUnreadCountsResponse, RpcError> {
+    let counts = db::session::get_unread_counts(id, &server.db.db_pool)
+        .await?
+        .into_iter()
+        .map(|count| UnreadCount {
+            session_id: count.session_id as u64,
+            unread: count.unread as u64,
+            last_read_msg_id: count.last_read_msg_id.unwrap_or_default() as u64,
+        })
+        .collect();
+    Ok(GetUnreadCountsResponse { counts })
+}
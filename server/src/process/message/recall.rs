@@ -92,6 +92,7 @@ async fn recall_msg_internal(
             msg_id: msg.msg_id as u64,
             respond_event_type: Some(respond_msg),
             time: Some(msg.time.into()),
+            reactions: vec![],
         },
         Dest::Session(req.session_id.into()),
         &mut channel,
@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use crate::db::messages::{
+    cancel_scheduled_msg as cancel_scheduled_msg_db, delete_scheduled_msgs, get_scheduled_msgs,
+    insert_expiring_msg_record, lock_due_scheduled_msgs, msg_ttl,
+};
+use crate::db::session::{get_session_by_id, in_session, user_banned_status, user_muted_status};
+use crate::process::error_msg::not_found;
+use crate::process::session::session_room_key::rotate_room_key_if_due;
+use crate::process::{Dest, RpcError, transmit_msg};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::ID;
+use base::database::DbPool;
+use base::wrapper::JobSchedulerWrapper;
+use metrics::counter;
+use pb::service::ourchat::msg_delivery::scheduled::v1::{
+    CancelScheduledMsgRequest, CancelScheduledMsgResponse, ListScheduledMsgsRequest,
+    ListScheduledMsgsResponse, ScheduledMsg,
+};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::TransactionTrait;
+use tokio::sync::MutexGuard;
+use tokio_cron_scheduler::Job;
+use tonic::{Request, Response, Status};
+
+/// How many due messages are delivered in one transaction
+const DELIVER_BATCH_SIZE: u64 = 100;
+
+pub async fn list_scheduled_msgs(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListScheduledMsgsRequest>,
+) -> Result<Response<ListScheduledMsgsResponse>, Status> {
+    match list_scheduled_msgs_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn cancel_scheduled_msg(
+    server: &RpcServer,
+    id: ID,
+    request: Request<CancelScheduledMsgRequest>,
+) -> Result<Response<CancelScheduledMsgResponse>, Status> {
+    match cancel_scheduled_msg_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn list_scheduled_msgs_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListScheduledMsgsRequest>,
+) -> Result<ListScheduledMsgsResponse, RpcError> {
+    let req = request.into_inner();
+    let scheduled =
+        get_scheduled_msgs(id, req.session_id.map(Into::into), &server.db.db_pool).await?;
+    let mut scheduled_msgs = Vec::with_capacity(scheduled.len());
+    for model in scheduled {
+        let RespondEventType::Msg(msg) =
+            serde_json::from_value(model.msg_data).context("incorrect msg in database")?
+        else {
+            tracing::warn!("scheduled message {} is not a msg", model.id);
+            continue;
+        };
+        scheduled_msgs.push(ScheduledMsg {
+            scheduled_msg_id: model.id as u64,
+            msg: Some(msg),
+            send_at: Some(model.send_at.into()),
+            created_time: Some(model.created_time.into()),
+        });
+    }
+    Ok(ListScheduledMsgsResponse { scheduled_msgs })
+}
+
+async fn cancel_scheduled_msg_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<CancelScheduledMsgRequest>,
+) -> Result<CancelScheduledMsgResponse, RpcError> {
+    let req = request.into_inner();
+    // Only the sender can see its pending messages, so others get "not found" as well
+    if !cancel_scheduled_msg_db(req.scheduled_msg_id, id, &server.db.db_pool).await? {
+        Err(Status::not_found(not_found::SCHEDULED_MSG))?;
+    }
+    Ok(CancelScheduledMsgResponse {})
+}
+
+/// Add a job delivering the scheduled messages whose time has come.
+///
+/// Every instance runs the job. A pending message is moved to `message_records` in the same
+/// transaction which locks it, so a message is delivered by exactly one instance, and the
+/// messages which are due while the server is down are delivered after it is restarted.
+///
+/// The room key of an E2EE session is rotated after the delivery like after sending a message
+/// directly, `room_key_duration` and `refresh_expire` are the ones of the main config.
+pub async fn deliver_scheduled_msgs(
+    interval: Duration,
+    room_key_duration: Duration,
+    refresh_expire: Duration,
+    db_pool: DbPool,
+    rmq_pool: deadpool_lapin::Pool,
+    job_scheduler: MutexGuard<'_, JobSchedulerWrapper>,
+) -> anyhow::Result<()> {
+    let job = Job::new_repeated_async(interval, move |_uuid, _l| {
+        let db_pool = db_pool.clone();
+        let rmq_pool = rmq_pool.clone();
+        Box::pin(async move {
+            if let Err(e) =
+                deliver_due_msgs(room_key_duration, refresh_expire, &db_pool, &rmq_pool).await
+            {
+                tracing::error!("Failed to deliver scheduled messages: {:?}", e);
+            }
+        })
+    })?;
+    job_scheduler.add(job).await?;
+    Ok(())
+}
+
+async fn deliver_due_msgs(
+    room_key_duration: Duration,
+    refresh_expire: Duration,
+    db_pool: &DbPool,
+    rmq_pool: &deadpool_lapin::Pool,
+) -> anyhow::Result<()> {
+    let mut redis_conn = db_pool.redis();
+    loop {
+        let transaction = db_pool.db_pool.begin().await?;
+        let due = lock_due_scheduled_msgs(DELIVER_BATCH_SIZE, &transaction).await?;
+        if due.is_empty() {
+            return Ok(());
+        }
+        let mut delivered = Vec::with_capacity(due.len());
+        for scheduled in &due {
+            let session_id = scheduled.session_id.into();
+            let sender_id: ID = scheduled.sender_id.into();
+            // the sender may have left the session since the message was scheduled
+            if !in_session(sender_id, session_id, &transaction).await? {
+                tracing::info!(
+                    "drop scheduled message {} because the sender left the session",
+                    scheduled.id
+                );
+                continue;
+            }
+            // and the session may have changed as well
+            if user_banned_status(sender_id, session_id, &mut redis_conn)
+                .await?
+                .is_some()
+                || user_muted_status(sender_id, session_id, &mut redis_conn)
+                    .await?
+                    .is_some()
+            {
+                tracing::info!(
+                    "drop scheduled message {} because the sender is muted or banned",
+                    scheduled.id
+                );
+                continue;
+            }
+            let Some(session) = get_session_by_id(session_id, &transaction).await? else {
+                continue;
+            };
+            if scheduled.is_encrypted && !session.e2ee_on {
+                tracing::info!(
+                    "drop scheduled message {} because the session has turned E2EE off",
+                    scheduled.id
+                );
+                continue;
+            }
+            let msg: RespondEventType = match serde_json::from_value(scheduled.msg_data.clone()) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::warn!("incorrect scheduled msg in database:{e}");
+                    continue;
+                }
+            };
+            // keep the batch going if a single message cannot be inserted, e.g. the message it
+            // replies to has been deleted
+            let savepoint = transaction.begin().await?;
+            let msg_ttl = msg_ttl(session.message_ttl, scheduled.ttl.map(|ttl| ttl as u64));
+            match insert_expiring_msg_record(
+                Some(sender_id),
+                Some(session_id),
+                msg.clone(),
+                scheduled.is_encrypted,
+                msg_ttl,
+                &savepoint,
+            )
+            .await
+            {
+                Ok(model) => {
+                    savepoint.commit().await?;
+                    delivered.push((sender_id, session_id, model, msg));
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    tracing::warn!(
+                        "failed to deliver scheduled message {}: {}",
+                        scheduled.id,
+                        e
+                    );
+                }
+            }
+        }
+        delete_scheduled_msgs(due.iter().map(|scheduled| scheduled.id), &transaction).await?;
+        transaction.commit().await?;
+
+        if delivered.is_empty() {
+            continue;
+        }
+        // The messages are stored now, members who miss the live notification still fetch them
+        let mut channel = rmq_pool
+            .get()
+            .await
+            .context("cannot get rabbitmq connection")?
+            .create_channel()
+            .await
+            .context("cannot create rabbitmq channel")?;
+        for (sender_id, session_id, model, msg) in delivered {
+            counter!("messages_sent_total").increment(1);
+            let response = FetchMsgsResponse {
+                msg_id: model.msg_id as u64,
+                time: Some(model.time.into()),
+                respond_event_type: Some(msg),
+                reactions: vec![],
+            };
+            if let Err(e) = transmit_msg(
+                response,
+                Dest::Session(session_id),
+                &mut channel,
+                &db_pool.db_pool,
+            )
+            .await
+            {
+                tracing::error!("failed to transmit scheduled message: {:?}", e);
+            }
+            // Fetched again since the key may have been rotated for a previous message
+            let Some(session) = get_session_by_id(session_id, &db_pool.db_pool).await? else {
+                continue;
+            };
+            // The device which scheduled the message is unknown, so all of them get the key
+            if let Err(e) = rotate_room_key_if_due(
+                session,
+                sender_id,
+                None,
+                room_key_duration,
+                refresh_expire,
+                &db_pool.db_pool,
+                &mut channel,
+            )
+            .await
+            {
+                tracing::error!("failed to rotate the room key: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::db::messages::SearchFilter;
+use crate::db::{self, session::in_session};
+use crate::process::RpcError;
+use crate::process::error_msg::{TIME_FORMAT_ERROR, invalid, not_found};
+use crate::server::RpcServer;
+use base::constants::{ID, SessionID};
+use pb::google::protobuf::Timestamp;
+use pb::service::ourchat::msg_delivery::search::v1::{
+    SearchMessagesRequest, SearchMessagesResponse,
+};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::time::{TimeStamp, TimeStampUtc};
+use tonic::{Request, Response, Status};
+
+pub async fn search_messages(
+    server: &RpcServer,
+    id: ID,
+    request: Request<SearchMessagesRequest>,
+) -> Result<Response<SearchMessagesResponse>, Status> {
+    match search_messages_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn convert_time(time: Option<Timestamp>) -> Result<Option<TimeStamp>, RpcError> {
+    let Some(time) = time else {
+        return Ok(None);
+    };
+    let time: TimeStampUtc = time
+        .try_into()
+        .map_err(|_| Status::invalid_argument(TIME_FORMAT_ERROR))?;
+    Ok(Some(time.into()))
+}
+
+async fn search_messages_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<SearchMessagesRequest>,
+) -> Result<SearchMessagesResponse, RpcError> {
+    let req = request.into_inner();
+    let query = req.query.trim();
+    if query.is_empty() {
+        Err(Status::invalid_argument(invalid::SEARCH_QUERY))?;
+    }
+    let session_id: Option<SessionID> = req.session_id.map(Into::into);
+    if let Some(session_id) = session_id
+        && !in_session(id, session_id, &server.db.db_pool).await?
+    {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let filter = SearchFilter {
+        session_id,
+        sender_id: req.sender_id.map(Into::into),
+        start_time: convert_time(req.start_time)?,
+        end_time: convert_time(req.end_time)?,
+        before_msg_id: (req.before_msg_id != 0).then_some(req.before_msg_id),
+    };
+    let max_page_size = server.shared_data.cfg().main_cfg.db.fetch_msg_page_size;
+    let page_size = match req.page_size {
+        0 => max_page_size,
+        size => size.min(max_page_size),
+    };
+    // fetch one more record to know whether there is a next page
+    let mut msgs =
+        db::messages::search_msgs(id, query, filter, page_size + 1, &server.db.db_pool).await?;
+    let has_more = msgs.len() as u64 > page_size;
+    msgs.truncate(page_size as usize);
+    let mut reactions =
+        db::messages::get_reaction_counts(msgs.iter().map(|msg| msg.msg_id), &server.db.db_pool)
+            .await?;
+    let mut ret = Vec::with_capacity(msgs.len());
+    for msg_model in msgs {
+        let msg: RespondEventType = match serde_json::from_value(msg_model.msg_data) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("incorrect msg in database:{e}");
+                continue;
+            }
+        };
+        ret.push(FetchMsgsResponse {
+            respond_event_type: Some(msg),
+            msg_id: msg_model.msg_id as u64,
+            time: Some(msg_model.time.into()),
+            reactions: reactions.remove(&msg_model.msg_id).unwrap_or_default(),
+        });
+    }
+    Ok(SearchMessagesResponse {
+        msgs: ret,
+        has_more,
+    })
+}
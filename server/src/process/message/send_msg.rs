@@ -1,22 +1,24 @@
-use crate::db::session::{get_members, get_session_by_id, user_muted_status};
-use crate::db::user::get_account_info_db;
-use crate::process::{Dest, MsgInsTransmitErr, error_msg, message_insert_and_transmit};
+use crate::db::messages::{
+    get_session_msg, insert_expiring_msg_record, insert_scheduled_msg, msg_ttl,
+};
+use crate::db::session::{get_session_by_id, user_muted_status};
+use crate::process::session::session_room_key::rotate_room_key_if_due;
+use crate::process::{Dest, MsgInsTransmitErr, error_msg, get_sid_from_req, transmit_msg};
 use crate::{
     db::{messages::MsgError, session::in_session},
     process::error_msg::{PERMISSION_DENIED, SERVER_ERROR, not_found},
     server::RpcServer,
 };
 use anyhow::{Context, anyhow};
-use base::constants::ID;
+use base::constants::{ID, SessionID};
 use chrono::Utc;
 use metrics::counter;
 use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
-use pb::service::ourchat::msg_delivery::v1::{Msg, SendMsgRequest, SendMsgResponse};
-use pb::service::ourchat::session::session_room_key::v1::{
-    SendRoomKeyNotification, UpdateRoomKeyNotification,
+use pb::service::ourchat::msg_delivery::v1::{
+    FetchMsgsResponse, Msg, SendMsgRequest, SendMsgResponse,
 };
+use pb::time::TimeStampUtc;
 use sea_orm::entity::prelude::*;
-use sea_orm::{ActiveValue, IntoActiveModel};
 use tonic::{Request, Response, Status};
 
 pub async fn send_msg(
@@ -72,6 +74,7 @@ async fn send_msg_impl(
     id: ID,
     request: Request<SendMsgRequest>,
 ) -> Result<SendMsgResponse, SendMsgErr> {
+    let sender_device = get_sid_from_req(&request);
     let req = request.into_inner();
 
     let db_conn = server.db.clone();
@@ -94,78 +97,100 @@ async fn send_msg_impl(
     if !session.e2ee_on && req.is_encrypted {
         Err(Status::permission_denied(error_msg::E2EE_NOT_ON))?
     }
+    let thread_root_id = check_thread(
+        req.reply_to_msg_id,
+        req.thread_root_id,
+        session_id,
+        &server.db.db_pool,
+    )
+    .await?;
+    let send_at = match req.send_at {
+        Some(send_at) => {
+            let send_at: TimeStampUtc = send_at
+                .try_into()
+                .map_err(|_| Status::invalid_argument(error_msg::TIME_FORMAT_ERROR))?;
+            // a time which has passed means sending the message right now
+            (send_at > Utc::now()).then_some(send_at)
+        }
+        None => None,
+    };
+    if req.ttl == Some(0) {
+        Err(Status::invalid_argument(error_msg::REQUEST_INVALID_VALUE))?
+    }
     let respond_msg = RespondEventType::Msg(Msg {
         markdown_text: req.markdown_text,
         involved_files: req.involved_files,
         session_id: req.session_id,
         is_encrypted: req.is_encrypted,
         sender_id: id.into(),
+        reply_to_msg_id: req.reply_to_msg_id,
+        thread_root_id,
     });
 
-    let sender_id: u64 = id.into();
+    if let Some(send_at) = send_at {
+        let scheduled = insert_scheduled_msg(
+            id,
+            session_id,
+            respond_msg,
+            req.is_encrypted,
+            send_at.into(),
+            req.ttl,
+            &db_conn.db_pool,
+        )
+        .await?;
+        return Ok(SendMsgResponse {
+            msg_id: 0,
+            time: Some(scheduled.send_at.into()),
+            scheduled_msg_id: Some(scheduled.id as u64),
+        });
+    }
+
     let rmq_conn = server.get_rabbitmq_manager().await?;
     let mut conn = rmq_conn
         .create_channel()
         .await
         .context("cannot create rabbitmq channel")?;
 
-    let msg_id = message_insert_and_transmit(
+    let ttl = msg_ttl(session.message_ttl, req.ttl);
+    let msg_id = insert_expiring_msg_record(
         Some(id),
-        Some(req.session_id.into()),
-        respond_msg,
-        Dest::Session(session_id),
+        Some(session_id),
+        respond_msg.clone(),
         req.is_encrypted,
+        ttl,
         &db_conn.db_pool,
+    )
+    .await?;
+    let response = FetchMsgsResponse {
+        msg_id: msg_id.msg_id as u64,
+        time: Some(msg_id.time.into()),
+        respond_event_type: Some(respond_msg),
+        reactions: vec![],
+    };
+    transmit_msg(
+        response,
+        Dest::Session(session_id),
+        &mut conn,
+        &db_conn.db_pool,
+    )
+    .await?;
+    let (room_key_duration, refresh_expire) = {
+        let cfg = server.shared_data.cfg();
+        (
+            cfg.main_cfg.room_key_duration,
+            cfg.main_cfg.refresh_token_expire,
+        )
+    };
+    rotate_room_key_if_due(
+        session,
+        id,
+        sender_device.as_deref(),
+        room_key_duration,
+        refresh_expire,
+        &server.db.db_pool,
         &mut conn,
     )
     .await?;
-    if session.e2ee_on {
-        let last_time = session.room_key_time.with_timezone(&Utc);
-        let expire_time: chrono::TimeDelta =
-            chrono::Duration::from_std(server.shared_data.cfg().main_cfg.room_key_duration)
-                .context("Failed to convert room_key_duration to chrono duration")?;
-        if Utc::now() - last_time > expire_time || session.leaving_to_process {
-            let msg = RespondEventType::UpdateRoomKey(UpdateRoomKeyNotification {
-                session_id: session_id.into(),
-            });
-            message_insert_and_transmit(
-                None,
-                Some(session_id),
-                msg,
-                Dest::User(id),
-                false,
-                &server.db.db_pool,
-                &mut conn,
-            )
-            .await?;
-            for members in get_members(session_id, &server.db.db_pool).await? {
-                if members.user_id != sender_id as i64 {
-                    let user = get_account_info_db(members.user_id.into(), &server.db.db_pool)
-                        .await?
-                        .ok_or(anyhow!("cannot find user"))?;
-                    let msg = RespondEventType::SendRoomKey(SendRoomKeyNotification {
-                        session_id: session_id.into(),
-                        sender: members.user_id as u64,
-                        public_key: user.public_key.into(),
-                    });
-                    message_insert_and_transmit(
-                        ID::from(members.user_id).into(),
-                        Some(session_id),
-                        msg,
-                        Dest::User(id),
-                        false,
-                        &server.db.db_pool,
-                        &mut conn,
-                    )
-                    .await?;
-                }
-            }
-            let mut session = session.into_active_model();
-            session.room_key_time = ActiveValue::Set(Utc::now().into());
-            session.leaving_to_process = ActiveValue::Set(false);
-            session.update(&server.db.db_pool).await?;
-        }
-    }
 
     // Record message sent metric
     counter!("messages_sent_total").increment(1);
@@ -173,5 +198,45 @@ async fn send_msg_impl(
     Ok(SendMsgResponse {
         msg_id: msg_id.msg_id as u64,
         time: Some(msg_id.time.into()),
+        scheduled_msg_id: None,
     })
 }
+
+/// Check that the replied message and the thread root live in the session, and deduce the
+/// thread root from the replied message if it is not specified.
+async fn check_thread(
+    reply_to_msg_id: Option<u64>,
+    thread_root_id: Option<u64>,
+    session_id: SessionID,
+    db_conn: &impl ConnectionTrait,
+) -> Result<Option<u64>, SendMsgErr> {
+    let thread_root_id = match reply_to_msg_id {
+        Some(reply_to_msg_id) => {
+            let Some(target) = get_session_msg(reply_to_msg_id, session_id, db_conn).await? else {
+                Err(Status::not_found(not_found::MSG))?
+            };
+            // replying to a reply continues the thread of it
+            let root = target
+                .thread_root_id
+                .map(|root| root as u64)
+                .unwrap_or(reply_to_msg_id);
+            if thread_root_id.is_some_and(|expected| expected != root) {
+                Err(Status::invalid_argument(error_msg::THREAD_ROOT_MISMATCH))?
+            }
+            Some(root)
+        }
+        None => match thread_root_id {
+            Some(root) => {
+                let Some(target) = get_session_msg(root, session_id, db_conn).await? else {
+                    Err(Status::not_found(not_found::MSG))?
+                };
+                if target.thread_root_id.is_some() {
+                    Err(Status::invalid_argument(error_msg::THREAD_ROOT_MISMATCH))?
+                }
+                Some(root)
+            }
+            None => None,
+        },
+    };
+    Ok(thread_root_id)
+}
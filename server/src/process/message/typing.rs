@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use crate::db::redis_mappings::map_typing_to_redis;
+use crate::db::session::{in_session, user_banned_status, user_muted_status};
+use crate::process::error_msg::{self, not_found};
+use crate::process::{Dest, RpcError, transmit_msg};
+use crate::server::RpcServer;
+use anyhow::Context;
+use base::constants::{ID, SessionID};
+use pb::service::ourchat::msg_delivery::typing::v1::{
+    SetTypingRequest, SetTypingResponse, TypingNotification,
+};
+use pb::service::ourchat::msg_delivery::v1::FetchMsgsResponse;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use redis::AsyncCommands;
+use tonic::{Request, Response, Status};
+
+/// How long a typing state lasts if the client doesn't refresh or clear it
+const TYPING_TTL: Duration = Duration::from_secs(10);
+
+pub async fn set_typing(
+    server: &RpcServer,
+    id: ID,
+    request: Request<SetTypingRequest>,
+) -> Result<Response<SetTypingResponse>, Status> {
+    match set_typing_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn set_typing_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<SetTypingRequest>,
+) -> Result<SetTypingResponse, RpcError> {
+    let req = request.into_inner();
+    let session_id: SessionID = req.session_id.into();
+    if !in_session(id, session_id, &server.db.db_pool).await? {
+        Err(Status::permission_denied(not_found::USER_IN_SESSION))?;
+    }
+    let mut redis_connection = server.db.redis();
+    if user_banned_status(id, session_id, &mut redis_connection)
+        .await?
+        .is_some()
+    {
+        Err(Status::permission_denied(error_msg::BAN))?
+    }
+    if user_muted_status(id, session_id, &mut redis_connection)
+        .await?
+        .is_some()
+    {
+        Err(Status::permission_denied(error_msg::MUTE))?
+    }
+
+    let key = map_typing_to_redis(session_id, id);
+    let now = chrono::Utc::now();
+    let expire_time = if req.typing {
+        let _: () = redis_connection
+            .set_ex(&key, "1", TYPING_TTL.as_secs())
+            .await?;
+        Some((now + TYPING_TTL).into())
+    } else {
+        let deleted: u64 = redis_connection.del(&key).await?;
+        // the typing state has expired or been cleared, others have already hidden it
+        if deleted == 0 {
+            return Ok(SetTypingResponse {});
+        }
+        None
+    };
+    let notification = FetchMsgsResponse {
+        respond_event_type: Some(RespondEventType::Typing(TypingNotification {
+            session_id: req.session_id,
+            user_id: id.into(),
+            typing: req.typing,
+            expire_time,
+        })),
+        msg_id: 0,
+        time: Some(now.into()),
+        reactions: vec![],
+    };
+    let rmq_conn = server.get_rabbitmq_manager().await?;
+    let mut channel = rmq_conn
+        .create_channel()
+        .await
+        .context("cannot create rabbitmq channel")?;
+    // typing notifications are not persisted, so they are sent directly
+    transmit_msg(
+        notification,
+        Dest::SessionExcept(session_id, id),
+        &mut channel,
+        &server.db.db_pool,
+    )
+    .await?;
+    Ok(SetTypingResponse {})
+}
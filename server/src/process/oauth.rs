@@ -0,0 +1,240 @@
+//! Linking external accounts to the user, the OAuth login itself is on the http server
+
+use std::time::Duration;
+
+use crate::db::helper::is_conflict;
+use crate::db::oauth::{
+    delete_oauth_identity, find_oauth_identity, get_user_oauth_identities, link_oauth_identity,
+};
+use crate::db::redis_mappings::{map_oauth_link_code_to_redis, map_oauth_link_ticket_to_redis};
+use crate::helper::generate_random_string;
+use crate::process::error_msg::{
+    LAST_LOGIN_METHOD, OAUTH_IDENTITY_LINKED, OAUTH_LINK_CODE_INVALID, OAUTH_PROVIDER_LINKED,
+    SERVER_ERROR, not_found,
+};
+use crate::server::RpcServer;
+use base::constants::ID;
+use entities::prelude::*;
+use pb::service::ourchat::oauth::v1::{
+    ConfirmLinkProviderRequest, ConfirmLinkProviderResponse, LinkProviderRequest,
+    LinkProviderResponse, LinkedProvider, ListLinkedProvidersRequest, ListLinkedProvidersResponse,
+    UnlinkProviderRequest, UnlinkProviderResponse,
+};
+use redis::AsyncCommands;
+use sea_orm::{EntityTrait, QuerySelect, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Response, Status};
+
+const LINK_TICKET_LEN: usize = 32;
+/// How long the client has for opening the link url
+const LINK_TICKET_EXPIRY: Duration = Duration::from_secs(5 * 60);
+const LINK_CODE_LEN: usize = 32;
+/// How long the client has for confirming the link after the provider redirected back
+const LINK_CODE_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// An identity authorized at the provider for linking, waiting for the account which got the link
+/// ticket to confirm it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingLink {
+    pub user_id: ID,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+/// Keep `pending` until it is confirmed, returning the link code for ConfirmLinkProvider
+pub async fn create_link_code(
+    provider: &str,
+    pending: &PendingLink,
+    redis_conn: &mut impl AsyncCommands,
+) -> anyhow::Result<String> {
+    let code = generate_random_string(LINK_CODE_LEN);
+    let _: () = redis_conn
+        .set_ex(
+            map_oauth_link_code_to_redis(provider, &code),
+            serde_json::to_string(pending)?,
+            LINK_CODE_EXPIRY.as_secs(),
+        )
+        .await?;
+    Ok(code)
+}
+
+#[derive(Debug, thiserror::Error)]
+enum OAuthError {
+    #[error("database error:{0:?}")]
+    Db(#[from] sea_orm::DbErr),
+    #[error("redis error:{0:?}")]
+    Redis(#[from] redis::RedisError),
+    #[error("serde error:{0:?}")]
+    Serde(#[from] serde_json::Error),
+    #[error("status:{0:?}")]
+    Status(#[from] Status),
+}
+
+impl From<OAuthError> for Status {
+    fn from(value: OAuthError) -> Self {
+        match value {
+            OAuthError::Db(_) | OAuthError::Redis(_) | OAuthError::Serde(_) => {
+                tracing::error!("{}", value);
+                Status::internal(SERVER_ERROR)
+            }
+            OAuthError::Status(status) => status,
+        }
+    }
+}
+
+async fn link_provider_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<LinkProviderRequest>,
+) -> Result<LinkProviderResponse, OAuthError> {
+    let req = request.into_inner();
+    if !server
+        .shared_data
+        .cfg()
+        .main_cfg
+        .oauth
+        .has_provider(&req.provider)
+    {
+        Err(Status::not_found(not_found::OAUTH_PROVIDER))?;
+    }
+    let ticket = generate_random_string(LINK_TICKET_LEN);
+    let _: () = server
+        .db
+        .redis()
+        .set_ex(
+            map_oauth_link_ticket_to_redis(&req.provider, &ticket),
+            u64::from(id),
+            LINK_TICKET_EXPIRY.as_secs(),
+        )
+        .await?;
+    Ok(LinkProviderResponse {
+        link_ticket: ticket,
+        expires_in: Some(LINK_TICKET_EXPIRY.into()),
+    })
+}
+
+async fn confirm_link_provider_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ConfirmLinkProviderRequest>,
+) -> Result<ConfirmLinkProviderResponse, OAuthError> {
+    let req = request.into_inner();
+    // The code works only once, whoever tries it
+    let pending: Option<String> = server
+        .db
+        .redis()
+        .get_del(map_oauth_link_code_to_redis(&req.provider, &req.link_code))
+        .await?;
+    let pending = pending
+        .map(|pending| serde_json::from_str::<PendingLink>(&pending))
+        .transpose()?
+        .filter(|pending| pending.user_id == id)
+        .ok_or_else(|| Status::invalid_argument(OAUTH_LINK_CODE_INVALID))?;
+
+    let db_conn = &server.db.db_pool;
+    if let Some(linked) = find_oauth_identity(&req.provider, &pending.subject, db_conn).await? {
+        if ID::from(linked.user_id) != id {
+            Err(Status::already_exists(OAUTH_IDENTITY_LINKED))?;
+        }
+        return Ok(ConfirmLinkProviderResponse {});
+    }
+    match link_oauth_identity(id, &req.provider, &pending.subject, pending.email, db_conn).await {
+        Ok(_) => {
+            tracing::info!("{} account linked to user {}", req.provider, id);
+            Ok(ConfirmLinkProviderResponse {})
+        }
+        // Another identity of the provider is linked already
+        Err(e) if is_conflict(&e) => Err(Status::already_exists(OAUTH_PROVIDER_LINKED).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn unlink_provider_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UnlinkProviderRequest>,
+) -> Result<UnlinkProviderResponse, OAuthError> {
+    let req = request.into_inner();
+    let transaction = server.db.db_pool.begin().await?;
+    // lock the user so that unlinking two providers at once can't remove both
+    let user = User::find_by_id(id)
+        .lock_exclusive()
+        .one(&transaction)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::USER))?;
+    let identities = get_user_oauth_identities(id, &transaction).await?;
+    if !identities
+        .iter()
+        .any(|identity| identity.provider == req.provider)
+    {
+        Err(Status::not_found(not_found::OAUTH_IDENTITY))?;
+    }
+    if user.passwd.is_none() && identities.len() == 1 {
+        Err(Status::failed_precondition(LAST_LOGIN_METHOD))?;
+    }
+    delete_oauth_identity(id, &req.provider, &transaction).await?;
+    transaction.commit().await?;
+    tracing::info!("{} account unlinked from user {}", req.provider, id);
+    Ok(UnlinkProviderResponse {})
+}
+
+async fn list_linked_providers_impl(
+    server: &RpcServer,
+    id: ID,
+    _request: Request<ListLinkedProvidersRequest>,
+) -> Result<ListLinkedProvidersResponse, OAuthError> {
+    let providers = get_user_oauth_identities(id, &server.db.db_pool)
+        .await?
+        .into_iter()
+        .map(|identity| LinkedProvider {
+            provider: identity.provider,
+            email: identity.email,
+            linked_time: Some(identity.created_time.into()),
+        })
+        .collect();
+    Ok(ListLinkedProvidersResponse { providers })
+}
+
+pub async fn link_provider(
+    server: &RpcServer,
+    id: ID,
+    request: Request<LinkProviderRequest>,
+) -> Result<Response<LinkProviderResponse>, Status> {
+    match link_provider_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn confirm_link_provider(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ConfirmLinkProviderRequest>,
+) -> Result<Response<ConfirmLinkProviderResponse>, Status> {
+    match confirm_link_provider_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn unlink_provider(
+    server: &RpcServer,
+    id: ID,
+    request: Request<UnlinkProviderRequest>,
+) -> Result<Response<UnlinkProviderResponse>, Status> {
+    match unlink_provider_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn list_linked_providers(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ListLinkedProvidersRequest>,
+) -> Result<Response<ListLinkedProvidersResponse>, Status> {
+    match list_linked_providers_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,307 @@
+//! Changing and resetting the password
+
+use std::time::Duration;
+
+use crate::db::redis_mappings::{
+    map_password_reset_limit_to_redis, map_password_reset_token_to_redis,
+    map_user_password_reset_token_to_redis,
+};
+use crate::db::user::{get_account_info_db, set_password};
+use crate::helper::{self, generate_random_string};
+use crate::process::auth::{
+    clear_failed_login, increment_failed_login, is_account_locked, verify_password_hash,
+};
+use crate::process::error_msg::{
+    ACCOUNT_LOCKED, NOT_STRONG_PASSWORD, PASSWORD_RESET_TOKEN_INVALID, SERVER_ERROR,
+    WRONG_PASSWORD, not_found,
+};
+use crate::process::get_sid_from_req;
+use crate::process::register::{compute_password_hash, password_hash_params};
+use crate::process::token::{revoke_all_tokens, revoke_other_tokens};
+use crate::server::{AuthServiceProvider, RpcServer};
+use anyhow::Context;
+use base::constants::ID;
+use base::rabbitmq::http_server::{PASSWORD_RESET_QUEUE, PasswordResetRecord};
+use deadpool_lapin::lapin::options::BasicPublishOptions;
+use entities::{prelude::*, user};
+use migration::predefined::AccountStatus;
+use pb::service::auth::password_reset::v1::{
+    RequestPasswordResetRequest, RequestPasswordResetResponse, ResetPasswordRequest,
+    ResetPasswordResponse,
+};
+use pb::service::ourchat::change_password::v1::{ChangePasswordRequest, ChangePasswordResponse};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use tonic::{Request, Response, Status};
+
+const PASSWORD_RESET_TOKEN_LEN: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+enum PasswordError {
+    #[error("database error:{0:?}")]
+    Db(#[from] sea_orm::DbErr),
+    #[error("redis error:{0:?}")]
+    Redis(#[from] redis::RedisError),
+    #[error("rabbitmq error:{0:?}")]
+    Rabbitmq(#[from] deadpool_lapin::lapin::Error),
+    #[error("status:{0:?}")]
+    Status(#[from] Status),
+    #[error("internal error:{0:?}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<PasswordError> for Status {
+    fn from(value: PasswordError) -> Self {
+        match value {
+            PasswordError::Db(_)
+            | PasswordError::Redis(_)
+            | PasswordError::Rabbitmq(_)
+            | PasswordError::Internal(_) => {
+                tracing::error!("{}", value);
+                Status::internal(SERVER_ERROR)
+            }
+            PasswordError::Status(status) => status,
+        }
+    }
+}
+
+/// Check the strength of a new password, the name and email of the user make it weaker
+fn check_password_strength(
+    password: &str,
+    user: &user::Model,
+    limit: zxcvbn::Score,
+) -> Result<(), Status> {
+    if zxcvbn::zxcvbn(password, &[&user.name, &user.email]).score() < limit {
+        return Err(Status::invalid_argument(NOT_STRONG_PASSWORD));
+    }
+    Ok(())
+}
+
+/// Hash the new password and store it
+async fn update_password(
+    user_id: ID,
+    password: String,
+    params: argon2::Params,
+    db_conn: &sea_orm::DatabaseConnection,
+) -> Result<(), PasswordError> {
+    let passwd =
+        helper::spawn_blocking_with_tracing(move || compute_password_hash(&password, params))
+            .await
+            .context("compute hash async task error")??;
+    set_password(user_id, passwd, db_conn).await?;
+    Ok(())
+}
+
+async fn change_password_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ChangePasswordRequest>,
+) -> Result<ChangePasswordResponse, PasswordError> {
+    let sid = get_sid_from_req(&request);
+    let req = request.into_inner();
+    let (params, strength_limit, access_expire, max_failed_attempts, lock_duration) = {
+        let cfg = server.shared_data.cfg();
+        (
+            password_hash_params(&cfg.main_cfg)?,
+            cfg.user_setting.password_strength_limit,
+            cfg.main_cfg.access_token_expire,
+            cfg.main_cfg.lock_account_after_failed_logins,
+            cfg.main_cfg.lock_account_duration,
+        )
+    };
+    let user = get_account_info_db(id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::USER))?;
+    let Some(passwd) = user.passwd.clone() else {
+        Err(Status::unauthenticated(WRONG_PASSWORD))?
+    };
+    // The old password is limited like logging in, or it could be guessed without limit with a
+    // stolen access token
+    let mut redis_conn = server.db.redis();
+    if is_account_locked(id, &mut redis_conn, max_failed_attempts).await? {
+        Err(Status::unauthenticated(ACCOUNT_LOCKED))?;
+    }
+    let old_password = req.old_password;
+    if helper::spawn_blocking_with_tracing(move || verify_password_hash(&old_password, &passwd))
+        .await
+        .context("computing and verifying password")?
+        .is_err()
+    {
+        increment_failed_login(id, &mut redis_conn, lock_duration.as_secs()).await?;
+        tracing::info!("Failed password change attempt for user id {}", id);
+        Err(Status::unauthenticated(WRONG_PASSWORD))?;
+    }
+    clear_failed_login(id, &mut redis_conn).await?;
+    check_password_strength(&req.new_password, &user, strength_limit)?;
+
+    update_password(id, req.new_password, params, &server.db.db_pool).await?;
+    // the device changing the password stays logged in
+    match sid {
+        Some(sid) => revoke_other_tokens(id, &sid, access_expire, &server.db).await?,
+        None => revoke_all_tokens(id, access_expire, &server.db).await?,
+    }
+    Ok(ChangePasswordResponse {})
+}
+
+/// Allow one password reset email of the user in the interval
+async fn acquire_password_reset_slot(
+    user_id: ID,
+    interval: Duration,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<bool, redis::RedisError> {
+    let acquired: Option<String> = redis_conn
+        .set_options(
+            map_password_reset_limit_to_redis(user_id),
+            1,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(interval.as_secs().max(1))),
+        )
+        .await?;
+    Ok(acquired.is_some())
+}
+
+async fn request_password_reset_impl(
+    server: &AuthServiceProvider,
+    request: Request<RequestPasswordResetRequest>,
+) -> Result<RequestPasswordResetResponse, PasswordError> {
+    let req = request.into_inner();
+    let (expiry, interval) = {
+        let cfg = server.shared_data.cfg();
+        (
+            cfg.user_setting.password_reset_expiry,
+            cfg.user_setting.password_reset_interval,
+        )
+    };
+    let user = User::find()
+        .filter(user::Column::Email.eq(req.email.trim()))
+        .one(&server.db.db_pool)
+        .await?;
+    // Tell nothing about whether the email is used by an account
+    let Some(user) = user.filter(|user| {
+        user.passwd.is_some() && user.account_status != AccountStatus::Deleted as i32
+    }) else {
+        tracing::info!("password reset requested for an unknown email");
+        return Ok(RequestPasswordResetResponse {});
+    };
+    let user_id = ID::from(user.id);
+    let mut redis_conn = server.db.redis();
+    // Answered like the others as well, telling the limit apart would reveal the account
+    if !acquire_password_reset_slot(user_id, interval, &mut redis_conn).await? {
+        tracing::info!("password reset of user {} requested too often", user_id);
+        return Ok(RequestPasswordResetResponse {});
+    }
+
+    let token = generate_random_string(PASSWORD_RESET_TOKEN_LEN);
+    let _: () = redis_conn
+        .set_ex(
+            map_password_reset_token_to_redis(&token),
+            u64::from(user_id),
+            expiry.as_secs(),
+        )
+        .await?;
+    // Only the latest email works, the token of the previous one is revoked
+    let old_token: Option<String> = redis_conn
+        .set_options(
+            map_user_password_reset_token_to_redis(user_id),
+            &token,
+            SetOptions::default()
+                .get(true)
+                .with_expiration(SetExpiry::EX(expiry.as_secs())),
+        )
+        .await?;
+    if let Some(old_token) = old_token {
+        let _: () = redis_conn
+            .del(map_password_reset_token_to_redis(&old_token))
+            .await?;
+    }
+    let record = serde_json::to_string(&PasswordResetRecord {
+        token,
+        email: user.email,
+    })
+    .context("Cannot get json")?;
+    let connection = server.get_rabbitmq_manager().await?;
+    let channel = connection.create_channel().await?;
+    channel
+        .basic_publish(
+            "",
+            PASSWORD_RESET_QUEUE,
+            BasicPublishOptions::default(),
+            record.as_bytes(),
+            Default::default(),
+        )
+        .await?;
+    tracing::info!("password reset requested for user {}", user_id);
+    Ok(RequestPasswordResetResponse {})
+}
+
+async fn reset_password_impl(
+    server: &AuthServiceProvider,
+    request: Request<ResetPasswordRequest>,
+) -> Result<ResetPasswordResponse, PasswordError> {
+    let req = request.into_inner();
+    let (params, strength_limit, access_expire) = {
+        let cfg = server.shared_data.cfg();
+        (
+            password_hash_params(&cfg.main_cfg)?,
+            cfg.user_setting.password_strength_limit,
+            cfg.main_cfg.access_token_expire,
+        )
+    };
+    let token_key = map_password_reset_token_to_redis(&req.token);
+    let mut redis_conn = server.db.redis();
+    let invalid = || Status::unauthenticated(PASSWORD_RESET_TOKEN_INVALID);
+    // A weak password doesn't use the token up, so only peek at it here
+    let user_id: Option<u64> = redis_conn.get(&token_key).await?;
+    let user_id = ID(user_id.ok_or_else(invalid)?);
+    let user = get_account_info_db(user_id, &server.db.db_pool)
+        .await?
+        .ok_or_else(invalid)?;
+    check_password_strength(&req.new_password, &user, strength_limit)?;
+    let owner: Option<u64> = redis_conn.get_del(&token_key).await?;
+    if owner != Some(u64::from(user_id)) {
+        Err(invalid())?;
+    }
+
+    let _: () = redis_conn
+        .del(map_user_password_reset_token_to_redis(user_id))
+        .await?;
+
+    update_password(user_id, req.new_password, params, &server.db.db_pool).await?;
+    revoke_all_tokens(user_id, access_expire, &server.db).await?;
+    // the account may have been locked by the attempts with the forgotten password
+    clear_failed_login(user_id, &mut redis_conn).await?;
+    tracing::info!("password of user {} is reset", user_id);
+    Ok(ResetPasswordResponse {})
+}
+
+pub async fn change_password(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ChangePasswordRequest>,
+) -> Result<Response<ChangePasswordResponse>, Status> {
+    match change_password_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn request_password_reset(
+    server: &AuthServiceProvider,
+    request: Request<RequestPasswordResetRequest>,
+) -> Result<Response<RequestPasswordResetResponse>, Status> {
+    match request_password_reset_impl(server, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn reset_password(
+    server: &AuthServiceProvider,
+    request: Request<ResetPasswordRequest>,
+) -> Result<Response<ResetPasswordResponse>, Status> {
+    match reset_password_impl(server, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,5 +1,8 @@
+use super::device::DeviceInfo;
 use super::error_msg::{NOT_STRONG_PASSWORD, invalid};
-use super::generate_access_token;
+use super::token::{TokenExpire, issue_tokens};
+use crate::config::MainCfg;
+use crate::db::key_history::record_public_key;
 use crate::db::session::join_in_session_or_create;
 use crate::process::error_msg::{SERVER_ERROR, exist};
 use crate::{db, helper, server::AuthServiceProvider};
@@ -39,6 +42,8 @@ async fn add_new_user(
     params: Params,
     require_email_verification: bool,
     friends_number_limit: u32,
+    token_expire: TokenExpire,
+    device: DeviceInfo,
 ) -> Result<RegisterResponse, RegisterError> {
     // Generate snowflake id
     let id = ID(helper::USER_ID_GENERATOR
@@ -63,18 +68,21 @@ async fn add_new_user(
         resource_used: ActiveValue::Set(0),
         friends_num: ActiveValue::Set(0),
         friend_limit: ActiveValue::Set(friends_number_limit.try_into()?),
-        public_key: ActiveValue::Set(request.public_key.into()),
+        public_key: ActiveValue::Set(request.public_key.to_vec()),
         email_verified: ActiveValue::Set(!require_email_verification),
         ..Default::default()
     };
     match user.insert(&db_connection.db_pool).await {
         Ok(res) => {
             // Happy Path
+            record_public_key(id, request.public_key.into(), &db_connection.db_pool).await?;
+            // the first device of the account, nothing to notify the user about
+            let tokens = issue_tokens(id, token_expire, &device, db_connection).await?;
             let response = RegisterResponse {
                 id: res.id as u64,
-                token: generate_access_token(id)
-                    .with_context(|| format!("Couldn't generate jwt for {}", id))?,
+                token: tokens.access_token,
                 ocid: res.ocid.clone(),
+                refresh_token: tokens.refresh_token,
             };
             Ok(response)
         }
@@ -115,7 +123,18 @@ enum RegisterError {
 /// # Panics
 ///
 /// Panics if the password is too long or if the salt generation fails.
-fn compute_password_hash(password: &str, params: Params) -> anyhow::Result<String> {
+/// The argon2 parameters in the config
+pub(crate) fn password_hash_params(cfg: &MainCfg) -> anyhow::Result<Params> {
+    Params::new(
+        cfg.password_hash.m_cost,
+        cfg.password_hash.t_cost,
+        cfg.password_hash.p_cost,
+        cfg.password_hash.output_len,
+    )
+    .context("Invalid Argon2 parameters - check password_hash configuration")
+}
+
+pub(crate) fn compute_password_hash(password: &str, params: Params) -> anyhow::Result<String> {
     Ok(
         argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
             .hash_password(password.as_bytes())?
@@ -158,6 +177,11 @@ fn validate_public_key(public_key: &[u8]) -> Result<(), RegisterError> {
     Ok(())
 }
 
+/// Whether the public key is one a user may register or change to
+pub(crate) fn is_valid_public_key(public_key: &[u8]) -> bool {
+    validate_public_key(public_key).is_ok()
+}
+
 /// Internal implementation of the register process
 ///
 /// Checks the strength of the password, validity of the email and username
@@ -172,6 +196,7 @@ async fn register_impl(
     server: &AuthServiceProvider,
     request: Request<RegisterRequest>,
 ) -> Result<RegisterResponse, RegisterError> {
+    let device = DeviceInfo::from_request(&request);
     let req = request.into_inner();
 
     // Check strong password
@@ -198,21 +223,18 @@ async fn register_impl(
     // Validate public key format and size
     validate_public_key(&req.public_key)?;
 
-    let params = Params::new(
-        server.shared_data.cfg().main_cfg.password_hash.m_cost,
-        server.shared_data.cfg().main_cfg.password_hash.t_cost,
-        server.shared_data.cfg().main_cfg.password_hash.p_cost,
-        server.shared_data.cfg().main_cfg.password_hash.output_len,
-    )
-    .context("Invalid Argon2 parameters - check password_hash configuration")?;
+    let params = password_hash_params(&server.shared_data.cfg().main_cfg)?;
     let require_email_verification = server.shared_data.cfg().main_cfg.require_email_verification;
     let friends_number_limit = server.shared_data.cfg().main_cfg.friends_number_limit;
+    let token_expire = TokenExpire::from_cfg(&server.shared_data.cfg().main_cfg);
     let response = add_new_user(
         req,
         &server.db,
         params,
         require_email_verification,
         friends_number_limit,
+        token_expire,
+        device,
     )
     .await?;
     // join default session if configured
@@ -63,6 +63,7 @@ async fn publish_announcement_internal(
             msg_id: announcement.id,
             time: announcement.created_at,
             respond_event_type: Some(RespondEventType::AnnouncementResponse(announcement.clone())),
+            reactions: vec![],
         },
         Dest::All,
         &mut channel,
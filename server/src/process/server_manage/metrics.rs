@@ -1,13 +1,14 @@
 use pb::{
     service::server_manage::monitoring::v1::{
         GetHistoricalMetricsRequest, GetHistoricalMetricsResponse, GetMonitoringMetricsRequest,
-        GetMonitoringMetricsResponse,
+        GetMonitoringMetricsResponse, LeaderInfo,
     },
     time::TimeStampUtc,
 };
 use tonic::{Request, Response, Status};
 
 use crate::{
+    db::leader_lease::get_leader,
     process::error_msg::{
         SERVER_ERROR,
         metrics::{INVALID_END_TIME, INVALID_INTERVAL, INVALID_START_TIME, METRICS_DISABLED},
@@ -39,9 +40,22 @@ pub async fn get_monitoring_metrics(
                 include_tokio_metrics,
             )
             .await;
+        let leader = match get_leader(&server.db.db_pool).await {
+            Ok(leader) => leader.map(|lease| LeaderInfo {
+                node_id: lease.node_id,
+                acquired_time: Some(lease.acquired_time.into()),
+                expire_time: Some(lease.expire_time.into()),
+            }),
+            Err(e) => {
+                tracing::error!("failed to get the leader:{e}");
+                return Err(Status::internal(SERVER_ERROR));
+            }
+        };
 
         Ok(Response::new(GetMonitoringMetricsResponse {
             metrics: Some(metrics_data),
+            leader,
+            node_id: server.shared_data.leader.node_id().to_string(),
         }))
     } else {
         Err(Status::unimplemented(
@@ -111,6 +125,7 @@ async fn get_historical_metrics_impl(
         start_time,
         end_time,
         interval,
+        req.node_id.as_deref(),
     )
     .await?;
 
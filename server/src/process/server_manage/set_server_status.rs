@@ -1,11 +1,45 @@
-use crate::process::error_msg::{self, SERVER_ERROR};
-use crate::server::ServerManageServiceProvider;
+//! Setting the status of the server.
+//!
+//! The status is stored in the database and every instance is told about the change through a
+//! fanout exchange, so a maintenance set on one instance applies to all of them. The instances
+//! also reload it periodically in case they have missed a change.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use base::shutdown::ShutdownRev;
+use chrono::Utc;
+use deadpool_lapin::lapin::options::{
+    BasicConsumeOptions, BasicPublishOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use deadpool_lapin::lapin::types::FieldTable;
+use pb::service::ourchat::msg_delivery::announcement::v1::MaintenanceNotification;
+use pb::service::ourchat::msg_delivery::v1::{
+    FetchMsgsResponse, fetch_msgs_response::RespondEventType,
+};
 use pb::service::server_manage::set_server_status::{
     self,
     v1::{SetServerStatusRequest, SetServerStatusResponse},
 };
+use pb::time::TimeStampUtc;
+use sea_orm::DatabaseConnection;
+use tokio::select;
+use tokio_stream::StreamExt;
 use tonic::{Request, Response, Status};
 
+use crate::SharedData;
+use crate::db::server_status::{ServerStatusRecord, get_server_status, save_server_status};
+use crate::process::error_msg::{self, SERVER_ERROR, invalid};
+use crate::process::{Dest, transmit_msg};
+use crate::rabbitmq::{SERVER_STATUS_FANOUT_EXCHANGE, create_server_status_fanout_exchange};
+use crate::server::ServerManageServiceProvider;
+
+/// How often the instances reload the status from the database
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+/// How long to wait before listening to the changes again after failing
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
 pub async fn set_server_status(
     server: &ServerManageServiceProvider,
     request: Request<SetServerStatusRequest>,
@@ -32,24 +66,182 @@ enum SetServerStatusErr {
     Internal(#[from] anyhow::Error),
 }
 
+fn request_time(
+    time: Option<pb::google::protobuf::Timestamp>,
+) -> Result<Option<TimeStampUtc>, Status> {
+    time.map(TryInto::<TimeStampUtc>::try_into)
+        .transpose()
+        .map_err(|_| Status::invalid_argument(invalid::MAINTENANCE_TIME))
+}
+
 async fn set_server_status_impl(
     server: &ServerManageServiceProvider,
     request: Request<SetServerStatusRequest>,
 ) -> Result<SetServerStatusResponse, SetServerStatusErr> {
-    let status = request.into_inner().server_status;
-    match set_server_status::v1::ServerStatus::try_from(status) {
-        Ok(status) => match status {
-            set_server_status::v1::ServerStatus::Normal => {
-                server.shared_data.set_maintaining(false);
-            }
-            set_server_status::v1::ServerStatus::Maintaining => {
-                server.shared_data.set_maintaining(true);
+    let req = request.into_inner();
+    let status = match set_server_status::v1::ServerStatus::try_from(req.server_status) {
+        Ok(set_server_status::v1::ServerStatus::Normal) => ServerStatusRecord {
+            reason: req.reason,
+            ..Default::default()
+        },
+        Ok(set_server_status::v1::ServerStatus::Maintaining) => {
+            let now = Utc::now();
+            // A start in the past means right away
+            let start_time = request_time(req.start_time)?.filter(|start| *start > now);
+            let end_time = request_time(req.end_time)?;
+            if end_time.is_some_and(|end| end <= start_time.unwrap_or(now)) {
+                Err(Status::invalid_argument(invalid::MAINTENANCE_TIME))?
             }
-            set_server_status::v1::ServerStatus::Unspecified => {
-                // ignore
+            ServerStatusRecord {
+                maintaining: true,
+                reason: req.reason,
+                start_time,
+                end_time,
             }
-        },
+        }
+        Ok(set_server_status::v1::ServerStatus::Unspecified) => {
+            return Ok(SetServerStatusResponse {});
+        }
         Err(_) => Err(Status::invalid_argument(error_msg::REQUEST_INVALID_VALUE))?,
-    }
+    };
+    save_server_status(&status, &server.db.db_pool).await?;
+    server.shared_data.set_server_status(status.clone());
+
+    let connection = server.get_rabbitmq_manager().await?;
+    let mut channel = connection
+        .create_channel()
+        .await
+        .context("cannot create channel")?;
+    create_server_status_fanout_exchange(&channel).await?;
+    channel
+        .basic_publish(
+            SERVER_STATUS_FANOUT_EXCHANGE,
+            "",
+            BasicPublishOptions::default(),
+            &serde_json::to_vec(&status).context("cannot serialize server status")?,
+            Default::default(),
+        )
+        .await
+        .context("cannot publish server status")?;
+
+    // Tell the connected clients beforehand, those connecting later find it in the server info
+    let notification = MaintenanceNotification {
+        maintaining: status.maintaining,
+        reason: status.reason,
+        start_time: Some(status.start_time.unwrap_or_else(Utc::now).into()),
+        end_time: status.end_time.map(Into::into),
+    };
+    transmit_msg(
+        FetchMsgsResponse {
+            msg_id: 0,
+            time: Some(Utc::now().into()),
+            respond_event_type: Some(RespondEventType::Maintenance(notification)),
+            reactions: vec![],
+        },
+        Dest::All,
+        &mut channel,
+        &server.db.db_pool,
+    )
+    .await
+    .context("cannot notify the clients of the maintenance")?;
     Ok(SetServerStatusResponse {})
 }
+
+/// Take the status from the database if it differs from the one of this instance
+async fn reload_server_status(db_conn: &DatabaseConnection, shared_data: &SharedData) {
+    match get_server_status(db_conn).await {
+        Ok(status) => {
+            if status != shared_data.server_status() {
+                shared_data.set_server_status(status);
+            }
+        }
+        Err(e) => tracing::error!("failed to reload the server status:{e}"),
+    }
+}
+
+/// Apply the changes published on the fanout exchange until the consumer fails
+async fn consume_server_status(
+    rabbitmq: &deadpool_lapin::Pool,
+    db_conn: &DatabaseConnection,
+    shared_data: &SharedData,
+) -> anyhow::Result<()> {
+    let connection = rabbitmq.get().await?;
+    let channel = connection.create_channel().await?;
+    create_server_status_fanout_exchange(&channel).await?;
+    // A queue of this instance only, gone with it
+    let queue = channel
+        .queue_declare(
+            "",
+            QueueDeclareOptions {
+                exclusive: true,
+                auto_delete: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .context("failed to create queue")?;
+    channel
+        .queue_bind(
+            queue.name().as_str(),
+            SERVER_STATUS_FANOUT_EXCHANGE,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .context("failed to bind queue")?;
+    let mut consumer = channel
+        .basic_consume(
+            queue.name().as_str(),
+            "server_status",
+            BasicConsumeOptions {
+                no_ack: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .context("failed to consume")?;
+    // The changes published while the queue didn't exist are only in the database
+    reload_server_status(db_conn, shared_data).await;
+    while let Some(delivery) = consumer.next().await {
+        match serde_json::from_slice::<ServerStatusRecord>(&delivery?.data) {
+            Ok(status) => shared_data.set_server_status(status),
+            Err(e) => tracing::warn!("incorrect server status in rabbitmq:{e}"),
+        }
+    }
+    anyhow::bail!("the server status consumer is closed")
+}
+
+/// Keep the server status of this instance up to date with the changes made on any instance.
+///
+/// The consumer is set up again whenever it fails, and the status is reloaded from the database
+/// regardless of it.
+pub async fn listen_server_status(
+    rabbitmq: deadpool_lapin::Pool,
+    db_conn: DatabaseConnection,
+    shared_data: Arc<SharedData>,
+    mut shutdown_rev: ShutdownRev,
+) {
+    let listen = async {
+        loop {
+            if let Err(e) = consume_server_status(&rabbitmq, &db_conn, &shared_data).await {
+                tracing::error!("server status listener error:{e}");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    };
+    let reload = async {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            reload_server_status(&db_conn, &shared_data).await;
+        }
+    };
+    select! {
+        _ = listen => {}
+        _ = reload => {}
+        _ = shutdown_rev.wait_shutting_down() => {}
+    }
+}
@@ -4,4 +4,5 @@ pub mod list_server_role_permissions;
 pub mod list_server_roles;
 pub mod list_user_server_roles;
 pub mod remove_server_role;
+pub mod set_user_files_limit;
 pub mod unban_user;
@@ -1,4 +1,5 @@
 use crate::db::redis_mappings;
+use crate::process::token::revoke_all_tokens;
 use crate::{process::error_msg::SERVER_ERROR, server::ServerManageServiceProvider};
 use base::constants::ID;
 use migration::predefined::PredefinedServerManagementPermission;
@@ -41,6 +42,7 @@ async fn ban_user_impl(
     let req = request.into_inner();
     let user_id: ID = req.user_id.into();
 
+    let access_expire = server.shared_data.cfg().main_cfg.access_token_expire;
     let mut conn = server.db.redis();
 
     let key = redis_mappings::map_server_ban_to_redis(user_id);
@@ -73,6 +75,9 @@ async fn ban_user_impl(
         }
     }
 
+    // A banned user is logged out everywhere
+    revoke_all_tokens(user_id, access_expire, &server.db).await?;
+
     Ok(BanUserResponse {})
 }
 
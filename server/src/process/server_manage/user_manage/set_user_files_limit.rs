@@ -0,0 +1,80 @@
+use crate::{
+    process::error_msg::{PERMISSION_DENIED, SERVER_ERROR, not_found},
+    server::ServerManageServiceProvider,
+};
+use base::constants::ID;
+use entities::{prelude::User, user};
+use migration::predefined::PredefinedServerManagementPermission;
+use pb::service::server_manage::user_manage::v1::{
+    SetUserFilesLimitRequest, SetUserFilesLimitResponse,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait};
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+#[derive(Debug, thiserror::Error)]
+enum SetUserFilesLimitError {
+    #[error("database error:{0:?}")]
+    DbError(#[from] sea_orm::DbErr),
+    #[error("permission denied")]
+    PermissionDenied,
+    #[error("user not found")]
+    UserNotFound,
+}
+
+async fn set_user_files_limit_impl(
+    server: &ServerManageServiceProvider,
+    request: Request<SetUserFilesLimitRequest>,
+) -> Result<SetUserFilesLimitResponse, SetUserFilesLimitError> {
+    let requester_id = crate::process::get_id_from_req(&request)
+        .ok_or(SetUserFilesLimitError::PermissionDenied)?;
+
+    if !crate::db::manager::manage_permission_existed(
+        requester_id,
+        PredefinedServerManagementPermission::ManageStorageQuota as i64,
+        &server.db.db_pool,
+    )
+    .await?
+    {
+        return Err(SetUserFilesLimitError::PermissionDenied);
+    }
+
+    let req = request.into_inner();
+    let user_id: ID = req.user_id.into();
+    let user = User::find_by_id(user_id)
+        .one(&server.db.db_pool)
+        .await?
+        .ok_or(SetUserFilesLimitError::UserNotFound)?;
+    // The bytes num is stored as i64, a larger limit is no limit anyway
+    let files_limit = req
+        .files_limit
+        .map(|limit| limit.min(i64::MAX as u64) as i64);
+    let mut user: user::ActiveModel = user.into();
+    user.files_limit = ActiveValue::Set(files_limit);
+    user.update(&server.db.db_pool).await?;
+
+    info!(
+        "set the files limit of user {} to {:?}",
+        user_id, files_limit
+    );
+    Ok(SetUserFilesLimitResponse {})
+}
+
+pub async fn set_user_files_limit(
+    server: &ServerManageServiceProvider,
+    request: Request<SetUserFilesLimitRequest>,
+) -> Result<Response<SetUserFilesLimitResponse>, Status> {
+    match set_user_files_limit_impl(server, request).await {
+        Ok(response) => Ok(Response::new(response)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            match e {
+                SetUserFilesLimitError::PermissionDenied => {
+                    Err(Status::permission_denied(PERMISSION_DENIED))
+                }
+                SetUserFilesLimitError::UserNotFound => Err(Status::not_found(not_found::USER)),
+                _ => Err(Status::internal(SERVER_ERROR)),
+            }
+        }
+    }
+}
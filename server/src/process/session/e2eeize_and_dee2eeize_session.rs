@@ -9,7 +9,7 @@ use pb::service::ourchat::{
             Dee2eeizeSessionRequest, Dee2eeizeSessionResponse, E2eeizeSessionRequest,
             E2eeizeSessionResponse,
         },
-        session_room_key::v1::{SendRoomKeyNotification, UpdateRoomKeyNotification},
+        session_room_key::v1::UpdateRoomKeyNotification,
     },
 };
 use sea_orm::ActiveModelTrait;
@@ -17,14 +17,12 @@ use sea_orm::{ActiveValue, IntoActiveModel};
 use tonic::{Request, Response, Status};
 
 use crate::{
-    db::{
-        session::{get_members, get_session_by_id, if_permission_exist, in_session},
-        user::get_account_info_db,
-    },
+    db::session::{get_session_by_id, if_permission_exist, in_session},
     process::{
         Dest, MsgInsTransmitErr,
         error_msg::{PERMISSION_DENIED, SERVER_ERROR, not_found},
-        message_insert_and_transmit,
+        get_sid_from_req, message_insert_and_transmit,
+        session::session_room_key::request_room_key_distribution,
     },
     server::RpcServer,
 };
@@ -68,6 +66,7 @@ async fn e2eeize_session_impl(
     id: ID,
     request: Request<E2eeizeSessionRequest>,
 ) -> Result<E2eeizeSessionResponse, E2eeizeSessionError> {
+    let sender_device = get_sid_from_req(&request);
     let session_id = request.into_inner().session_id;
     let session = get_session_by_id(session_id.into(), &server.db.db_pool)
         .await?
@@ -106,29 +105,16 @@ async fn e2eeize_session_impl(
         &mut conn,
     )
     .await?;
-    let sender_id: u64 = id.into();
-    for members in get_members(session_id.into(), &server.db.db_pool).await? {
-        if members.user_id != sender_id as i64 {
-            let user = get_account_info_db(members.user_id.into(), &server.db.db_pool)
-                .await?
-                .ok_or(anyhow!("cannot find user"))?;
-            let msg = RespondEventType::SendRoomKey(SendRoomKeyNotification {
-                session_id,
-                sender: members.user_id as u64,
-                public_key: user.public_key.into(),
-            });
-            message_insert_and_transmit(
-                ID::from(members.user_id).into(),
-                Some(session_id.into()),
-                msg,
-                Dest::User(id),
-                false,
-                &server.db.db_pool,
-                &mut conn,
-            )
-            .await?;
-        }
-    }
+    let refresh_expire = server.shared_data.cfg().main_cfg.refresh_token_expire;
+    request_room_key_distribution(
+        id,
+        sender_device.as_deref(),
+        session_id.into(),
+        refresh_expire,
+        &server.db.db_pool,
+        &mut conn,
+    )
+    .await?;
     let mut session = session.into_active_model();
     session.room_key_time = ActiveValue::Set(Utc::now().into());
     session.leaving_to_process = ActiveValue::Set(false);
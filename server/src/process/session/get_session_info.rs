@@ -124,6 +124,9 @@ async fn get_session_info_impl(
             QueryValues::Description => {
                 res.description = session_data.description.clone();
             }
+            QueryValues::MessageTtl => {
+                res.message_ttl = session_data.message_ttl.map(|ttl| ttl as u64);
+            }
         }
     }
     Ok(res)
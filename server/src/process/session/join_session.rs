@@ -123,6 +123,7 @@ async fn join_session_impl(
         msg_id: msg_model.msg_id as u64,
         time: Some(msg_model.time.into()),
         respond_event_type: Some(respond_msg),
+        reactions: vec![],
     };
     let peoples_should_be_sent = get_all_session_relations(id, &server.db.db_pool).await?;
     let rmq_conn = server.get_rabbitmq_manager().await?;
@@ -183,6 +183,7 @@ pub async fn send_verification_request(
         msg_id: msg_model.msg_id as u64,
         time: Some(expire_at_google),
         respond_event_type: Some(respond_msg),
+        reactions: vec![],
     };
     let rabbitmq_connection = server.get_rabbitmq_manager().await?;
     let mut channel = rabbitmq_connection
@@ -1,22 +1,32 @@
+use std::time::Duration;
+
 use anyhow::Context;
-use base::constants::ID;
+use base::constants::{ID, SessionID};
+use chrono::Utc;
+use entities::session;
 use pb::service::ourchat::{
     msg_delivery::v1::fetch_msgs_response::RespondEventType,
     session::{
         new_session::v1::{FailedMember, FailedReason},
         session_room_key::v1::{
-            ReceiveRoomKeyNotification, SendRoomKeyRequest, SendRoomKeyResponse,
+            ReceiveRoomKeyNotification, SendRoomKeyNotification, SendRoomKeyRequest,
+            SendRoomKeyResponse, UpdateRoomKeyNotification,
         },
     },
 };
+use sea_orm::{ActiveModelTrait, ActiveValue, ConnectionTrait, IntoActiveModel};
 use tonic::{Request, Response, Status};
 
 use crate::{
-    db::session::get_session_by_id,
+    db::{
+        device_keys::{get_device_keys, get_user_device_keys},
+        session::{get_members, get_session_by_id},
+        user::get_account_info_db,
+    },
     process::{
         Dest, MsgInsTransmitErr, check_user_exist,
         error_msg::{SERVER_ERROR, not_found},
-        message_insert_and_transmit,
+        get_sid_from_req, message_insert_and_transmit,
     },
     server::RpcServer,
 };
@@ -57,6 +67,7 @@ async fn send_room_key_impl(
     id: ID,
     request: Request<SendRoomKeyRequest>,
 ) -> Result<SendRoomKeyResponse, SendRoomKeyError> {
+    let sender_device_id = get_sid_from_req(&request);
     let req = request.into_inner();
     if get_session_by_id(req.session_id.into(), &server.db.db_pool)
         .await?
@@ -73,6 +84,16 @@ async fn send_room_key_impl(
             reason: FailedReason::MemberNotFound.into(),
         });
     }
+    // The key encrypted for a device is useless to the others of the user
+    if let Some(device_id) = &req.device_id
+        && get_device_keys(device_id, &server.db.db_pool)
+            .await?
+            .is_none_or(|device| device.user_id != req.user_id as i64)
+    {
+        return Err(SendRoomKeyError::Status(Status::not_found(
+            not_found::DEVICE,
+        )));
+    }
 
     let rmq_conn = server.get_rabbitmq_manager().await?;
     let mut conn = rmq_conn
@@ -85,6 +106,8 @@ async fn send_room_key_impl(
         session_id,
         user_id: id.into(),
         room_key,
+        device_id: req.device_id,
+        sender_device_id,
     });
     message_insert_and_transmit(
         id.into(),
@@ -98,3 +121,104 @@ async fn send_room_key_impl(
     .await?;
     Ok(SendRoomKeyResponse { failed_member })
 }
+
+/// Ask the user to send the new room key of the session to every device of the members, the
+/// members without device keys get it encrypted with their public key. The device of the user
+/// making the new key needs none.
+///
+/// The devices which haven't logged in for `refresh_expire` are skipped.
+pub async fn request_room_key_distribution(
+    id: ID,
+    sender_device: Option<&str>,
+    session_id: SessionID,
+    refresh_expire: Duration,
+    db_conn: &impl ConnectionTrait,
+    conn: &mut deadpool_lapin::lapin::Channel,
+) -> Result<(), MsgInsTransmitErr> {
+    let since = Utc::now() - refresh_expire;
+    for member in get_members(session_id, db_conn).await? {
+        let member_id = ID::from(member.user_id);
+        let devices: Vec<_> = get_user_device_keys(member_id, since, db_conn)
+            .await?
+            .into_iter()
+            .filter(|device| sender_device != Some(device.sid.as_str()))
+            .collect();
+        let recipients = if !devices.is_empty() {
+            devices
+                .into_iter()
+                .map(|device| (device.identity_key, Some(device.sid)))
+                .collect()
+        } else if member_id != id {
+            let user = get_account_info_db(member_id, db_conn)
+                .await?
+                .ok_or(anyhow::anyhow!("cannot find user"))?;
+            vec![(user.public_key, None)]
+        } else {
+            vec![]
+        };
+        for (public_key, device_id) in recipients {
+            let msg = RespondEventType::SendRoomKey(SendRoomKeyNotification {
+                session_id: session_id.into(),
+                sender: member.user_id as u64,
+                public_key: public_key.into(),
+                device_id,
+            });
+            message_insert_and_transmit(
+                member_id.into(),
+                Some(session_id),
+                msg,
+                Dest::User(id),
+                false,
+                db_conn,
+                conn,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Make the user who just sent a message to an E2EE session rotate its room key, if the key is
+/// older than `room_key_duration` or a member has left since it was made.
+///
+/// Does nothing for the sessions without E2EE.
+pub async fn rotate_room_key_if_due(
+    session: session::Model,
+    id: ID,
+    sender_device: Option<&str>,
+    room_key_duration: Duration,
+    refresh_expire: Duration,
+    db_conn: &impl ConnectionTrait,
+    conn: &mut deadpool_lapin::lapin::Channel,
+) -> Result<(), MsgInsTransmitErr> {
+    if !session.e2ee_on {
+        return Ok(());
+    }
+    let session_id = SessionID::from(session.session_id);
+    let last_time = session.room_key_time.with_timezone(&Utc);
+    let expire_time = chrono::Duration::from_std(room_key_duration)
+        .context("Failed to convert room_key_duration to chrono duration")?;
+    if Utc::now() - last_time <= expire_time && !session.leaving_to_process {
+        return Ok(());
+    }
+    let msg = RespondEventType::UpdateRoomKey(UpdateRoomKeyNotification {
+        session_id: session_id.into(),
+    });
+    message_insert_and_transmit(
+        None,
+        Some(session_id),
+        msg,
+        Dest::User(id),
+        false,
+        db_conn,
+        conn,
+    )
+    .await?;
+    request_room_key_distribution(id, sender_device, session_id, refresh_expire, db_conn, conn)
+        .await?;
+    let mut session = session.into_active_model();
+    session.room_key_time = ActiveValue::Set(Utc::now().into());
+    session.leaving_to_process = ActiveValue::Set(false);
+    session.update(db_conn).await?;
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use crate::process::error_msg::{CANNOT_SET_AVATAR, CANNOT_SET_DESCRIPTION, CANNOT_SET_NAME};
+use crate::process::error_msg::{
+    self, CANNOT_SET_AVATAR, CANNOT_SET_DESCRIPTION, CANNOT_SET_MESSAGE_TTL, CANNOT_SET_NAME,
+};
 use crate::{
     db,
     process::error_msg::{CONFLICT, SERVER_ERROR},
@@ -119,6 +121,23 @@ async fn set_session_info_impl(
             modified = true;
         }
     }
+    if let Some(message_ttl) = request.message_ttl {
+        if !permissions_map.contains(&(PredefinedPermissions::SetMessageTtl as i64)) {
+            return Err(SetSessionErr::Status(Status::permission_denied(
+                CANNOT_SET_MESSAGE_TTL,
+            )));
+        }
+        // 0 means keeping the messages forever, it only affects the messages sent afterwards
+        let message_ttl = match message_ttl {
+            0 => None,
+            ttl => Some(
+                i64::try_from(ttl)
+                    .map_err(|_| Status::invalid_argument(error_msg::REQUEST_INVALID_VALUE))?,
+            ),
+        };
+        model.message_ttl = ActiveValue::Set(message_ttl);
+        modified = true;
+    }
     if modified {
         model.updated_time = ActiveValue::Set(chrono::Utc::now().into());
         if let Err(e) = model.update(&server.db.db_pool).await {
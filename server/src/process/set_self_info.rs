@@ -3,10 +3,12 @@ use super::{
         CONFLICT,
         invalid::{self, OCID_TOO_LONG, STATUS_TOO_LONG},
     },
+    key_verification::notify_public_key_changed,
     mapped_to_user_defined_status,
+    register::is_valid_public_key,
 };
 use crate::{
-    db::{self, file_storage},
+    db::{self, file_storage, key_history::record_public_key},
     process::error_msg::SERVER_ERROR,
     server::RpcServer,
 };
@@ -65,6 +67,12 @@ async fn update_account(
         Err(Status::invalid_argument(invalid::USERNAME))?
     }
 
+    if let Some(public_key) = &request_data.public_key
+        && !is_valid_public_key(public_key)
+    {
+        Err(Status::invalid_argument(invalid::PUBLIC_KEY))?
+    }
+
     // Check status length
     if let Some(status) = &request_data.user_defined_status
         && status.len() > STATUS_LENGTH_MAX
@@ -113,7 +121,13 @@ async fn update_account(
         // Simple ownership model: delete old avatar file if it exists
         if let Some(old_avatar_key) = original_user.avatar {
             // Delete the old avatar file - no reference counting needed
-            if let Err(e) = file_storage::delete_file(&old_avatar_key, &txn).await {
+            if let Err(e) = file_storage::delete_file(
+                &old_avatar_key,
+                &txn,
+                server.shared_data.file_sys.storage().as_ref(),
+            )
+            .await
+            {
                 tracing::warn!("Failed to delete old avatar file {}: {}", old_avatar_key, e);
                 // Continue anyway - the file will be cleaned up by auto-cleanup later
             }
@@ -129,6 +143,14 @@ async fn update_account(
         user.ocid = ActiveValue::Set(new_ocid);
         public_updated = true;
     }
+    let mut new_key = None;
+    if let Some(public_key) = request_data.public_key
+        && public_key != original_user.public_key
+    {
+        user.public_key = ActiveValue::Set(public_key.to_vec());
+        new_key = Some(record_public_key(id, public_key.into(), &txn).await?);
+        public_updated = true;
+    }
     // update the modified time
     let timestamp = chrono::Utc::now();
     user.update_time = ActiveValue::Set(timestamp.into());
@@ -145,5 +167,10 @@ async fn update_account(
         }
     }
     txn.commit().await?;
+    if let Some(key) = new_key
+        && let Err(e) = notify_public_key_changed(server, id, &key).await
+    {
+        tracing::error!("Failed to notify the change of the public key: {}", e);
+    }
     Ok(())
 }
@@ -0,0 +1,272 @@
+//! Access tokens, refresh tokens and logout
+
+use std::time::Duration;
+
+use crate::config::MainCfg;
+use crate::db::device::{delete_login_device, delete_user_login_devices, touch_login_device};
+use crate::db::login_session::{
+    login_session_owner, revoke_all_login_sessions, revoke_login_session, save_login_session,
+    take_refresh_token, used_refresh_token_session, user_login_sessions,
+};
+use crate::helper::generate_random_string;
+use crate::process::device::{DeviceInfo, notify_new_login, record_login_device};
+use crate::process::error_msg::{SERVER_ERROR, token};
+use crate::process::generate_access_token;
+use crate::server::AuthServiceProvider;
+use anyhow::Context;
+use base::constants::ID;
+use base::database::DbPool;
+use pb::service::auth::token::v1::{
+    LogoutAllDevicesRequest, LogoutAllDevicesResponse, LogoutRequest, LogoutResponse,
+    RefreshTokenRequest, RefreshTokenResponse,
+};
+use sea_orm::ConnectionTrait;
+use tonic::{Request, Response, Status};
+
+const REFRESH_TOKEN_LEN: usize = 32;
+
+/// Lifetimes of the tokens issued on login
+#[derive(Debug, Clone, Copy)]
+pub struct TokenExpire {
+    pub access: Duration,
+    pub refresh: Duration,
+}
+
+impl TokenExpire {
+    pub fn from_cfg(cfg: &MainCfg) -> Self {
+        Self {
+            access: cfg.access_token_expire,
+            refresh: cfg.refresh_token_expire,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenPair {
+    /// Session id of the login, also the id of its device
+    pub sid: String,
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// Start a new login of the user on the device and issue its tokens
+pub async fn issue_tokens(
+    id: ID,
+    expire: TokenExpire,
+    device: &DeviceInfo,
+    db: &DbPool,
+) -> anyhow::Result<TokenPair> {
+    let sid = uuid::Uuid::new_v4().to_string();
+    let refresh_token = generate_random_string(REFRESH_TOKEN_LEN);
+    record_login_device(id, &sid, device, db)
+        .await
+        .context("cannot record login device")?;
+    save_login_session(id, &sid, &refresh_token, expire.refresh, &mut db.redis())
+        .await
+        .context("cannot save login session")?;
+    let access_token = generate_access_token(id, &sid, expire.access)
+        .with_context(|| format!("Couldn't generate jwt for {}", id))?;
+    Ok(TokenPair {
+        sid,
+        access_token,
+        refresh_token,
+    })
+}
+
+/// A login which is about to start
+pub struct NewLogin<'a> {
+    pub expire: TokenExpire,
+    pub device: DeviceInfo,
+    pub rabbitmq: &'a deadpool_lapin::Pool,
+}
+
+impl NewLogin<'_> {
+    /// Issue the tokens of the login and tell the user about the new device
+    pub async fn start(&self, id: ID, db: &DbPool) -> anyhow::Result<TokenPair> {
+        let tokens = issue_tokens(id, self.expire, &self.device, db).await?;
+        notify_new_login(id, &tokens.sid, &self.device, db, self.rabbitmq).await;
+        Ok(tokens)
+    }
+}
+
+/// Revoke all the tokens of the user, called when the user shouldn't be logged in anymore
+pub async fn revoke_all_tokens(id: ID, access_expire: Duration, db: &DbPool) -> anyhow::Result<()> {
+    revoke_all_login_sessions(id, access_expire, &mut db.redis())
+        .await
+        .context("cannot revoke login sessions")?;
+    delete_user_login_devices(id, &db.db_pool)
+        .await
+        .context("cannot delete login devices")?;
+    tracing::info!("revoked all tokens of user {}", id);
+    Ok(())
+}
+
+/// Revoke all the logins of the user except `keep_sid`, the login making the request
+pub async fn revoke_other_tokens(
+    id: ID,
+    keep_sid: &str,
+    access_expire: Duration,
+    db: &DbPool,
+) -> anyhow::Result<()> {
+    let mut redis_conn = db.redis();
+    let sids = user_login_sessions(id, &mut redis_conn)
+        .await
+        .context("cannot get login sessions")?;
+    for sid in sids.iter().filter(|sid| sid.as_str() != keep_sid) {
+        revoke_login_session(id, sid, access_expire, &mut redis_conn)
+            .await
+            .context("cannot revoke login session")?;
+        delete_login_device(id, sid, &db.db_pool)
+            .await
+            .context("cannot delete login device")?;
+    }
+    tracing::info!("revoked the other tokens of user {}", id);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+enum TokenError {
+    #[error("refresh token invalid")]
+    Invalid,
+    #[error("database error:{0:?}")]
+    Db(#[from] sea_orm::DbErr),
+    #[error("redis error:{0:?}")]
+    Redis(#[from] redis::RedisError),
+    #[error("internal error:{0:?}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<TokenError> for Status {
+    fn from(value: TokenError) -> Self {
+        match value {
+            TokenError::Invalid => Status::unauthenticated(token::REFRESH_TOKEN_INVALID),
+            TokenError::Db(_) | TokenError::Redis(_) | TokenError::Internal(_) => {
+                tracing::error!("{}", value);
+                Status::internal(SERVER_ERROR)
+            }
+        }
+    }
+}
+
+/// Consume the refresh token and find the login it belongs to.
+///
+/// A refresh token which has been used already means that it is leaked, so the login it belongs
+/// to is revoked, logging out both the attacker and the user.
+async fn consume_refresh_token(
+    refresh_token: &str,
+    expire: TokenExpire,
+    redis_conn: &mut impl redis::AsyncCommands,
+    db_conn: &impl ConnectionTrait,
+) -> Result<(ID, String), TokenError> {
+    let Some(sid) = take_refresh_token(refresh_token, expire.refresh, redis_conn).await? else {
+        if let Some(sid) = used_refresh_token_session(refresh_token, redis_conn).await?
+            && let Some(owner) = login_session_owner(&sid, redis_conn).await?
+        {
+            tracing::warn!(
+                "refresh token of login {} of user {} is reused, revoke the login",
+                sid,
+                owner
+            );
+            revoke_login_session(owner, &sid, expire.access, redis_conn).await?;
+            delete_login_device(owner, &sid, db_conn).await?;
+        }
+        return Err(TokenError::Invalid);
+    };
+    // the login may have been revoked before the token is used
+    let owner = login_session_owner(&sid, redis_conn)
+        .await?
+        .ok_or(TokenError::Invalid)?;
+    Ok((owner, sid))
+}
+
+async fn refresh_token_impl(
+    server: &AuthServiceProvider,
+    request: Request<RefreshTokenRequest>,
+) -> Result<RefreshTokenResponse, TokenError> {
+    let req = request.into_inner();
+    let expire = TokenExpire::from_cfg(&server.shared_data.cfg().main_cfg);
+    let mut redis_conn = server.db.redis();
+    let (owner, sid) = consume_refresh_token(
+        &req.refresh_token,
+        expire,
+        &mut redis_conn,
+        &server.db.db_pool,
+    )
+    .await?;
+    let refresh_token = generate_random_string(REFRESH_TOKEN_LEN);
+    save_login_session(owner, &sid, &refresh_token, expire.refresh, &mut redis_conn).await?;
+    touch_login_device(&sid, &server.db.db_pool).await?;
+    let token = generate_access_token(owner, &sid, expire.access)
+        .with_context(|| format!("Couldn't generate jwt for {}", owner))?;
+    Ok(RefreshTokenResponse {
+        token,
+        refresh_token,
+    })
+}
+
+async fn logout_impl(
+    server: &AuthServiceProvider,
+    request: Request<LogoutRequest>,
+) -> Result<LogoutResponse, TokenError> {
+    let req = request.into_inner();
+    let expire = TokenExpire::from_cfg(&server.shared_data.cfg().main_cfg);
+    let mut redis_conn = server.db.redis();
+    let (owner, sid) = consume_refresh_token(
+        &req.refresh_token,
+        expire,
+        &mut redis_conn,
+        &server.db.db_pool,
+    )
+    .await?;
+    revoke_login_session(owner, &sid, expire.access, &mut redis_conn).await?;
+    delete_login_device(owner, &sid, &server.db.db_pool).await?;
+    Ok(LogoutResponse {})
+}
+
+async fn logout_all_devices_impl(
+    server: &AuthServiceProvider,
+    request: Request<LogoutAllDevicesRequest>,
+) -> Result<LogoutAllDevicesResponse, TokenError> {
+    let req = request.into_inner();
+    let expire = TokenExpire::from_cfg(&server.shared_data.cfg().main_cfg);
+    let mut redis_conn = server.db.redis();
+    let (owner, _) = consume_refresh_token(
+        &req.refresh_token,
+        expire,
+        &mut redis_conn,
+        &server.db.db_pool,
+    )
+    .await?;
+    revoke_all_tokens(owner, expire.access, &server.db).await?;
+    Ok(LogoutAllDevicesResponse {})
+}
+
+pub async fn refresh_token(
+    server: &AuthServiceProvider,
+    request: Request<RefreshTokenRequest>,
+) -> Result<Response<RefreshTokenResponse>, Status> {
+    match refresh_token_impl(server, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn logout(
+    server: &AuthServiceProvider,
+    request: Request<LogoutRequest>,
+) -> Result<Response<LogoutResponse>, Status> {
+    match logout_impl(server, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn logout_all_devices(
+    server: &AuthServiceProvider,
+    request: Request<LogoutAllDevicesRequest>,
+) -> Result<Response<LogoutAllDevicesResponse>, Status> {
+    match logout_all_devices_impl(server, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,383 @@
+//! TOTP two-factor authentication
+
+use std::time::Duration;
+
+use crate::SERVER_INFO;
+use crate::config::MainCfg;
+use crate::db::redis_mappings::{map_totp_challenge_to_redis, map_used_totp_code_to_redis};
+use crate::db::user::{get_account_info_db, replace_recovery_codes, set_totp, use_recovery_code};
+use crate::helper::generate_random_string;
+use crate::process::RpcError;
+use crate::process::error_msg::{
+    MISSING_TOTP_CODE, TOTP_ALREADY_ENABLED, TOTP_NOT_ENABLED, TOTP_NOT_ENROLLED, WRONG_TOTP_CODE,
+    not_found,
+};
+use crate::server::RpcServer;
+use anyhow::{Context, anyhow};
+use base::constants::ID;
+use base::database::DbPool;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use pb::service::ourchat::totp::v1::{
+    ConfirmTotpRequest, ConfirmTotpResponse, DisableTotpRequest, DisableTotpResponse,
+    EnableTotpRequest, EnableTotpResponse, disable_totp_request,
+};
+use rand::RngExt;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use sea_orm::TransactionTrait;
+use sha3::{Digest, Sha3_256};
+use tonic::{Request, Response, Status};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+/// Codes of the adjacent steps are accepted as well, allowing some clock drift
+const TOTP_SKEW: u8 = 1;
+/// 160 bits, recommended by RFC 4226
+const TOTP_SECRET_LEN: usize = 20;
+const RECOVERY_CODES_NUM: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+const TOTP_CHALLENGE_LEN: usize = 32;
+/// How long the user has to finish logging in with a TOTP code
+pub const TOTP_CHALLENGE_EXPIRE: Duration = Duration::from_mins(5);
+/// Marks the format of the encrypted secrets in the database
+const ENCRYPTED_SECRET_PREFIX: &str = "v1:";
+
+/// The key encrypting the TOTP secrets in the database with AES-256-GCM, so a dump of the
+/// database alone is not enough to pass the second factor
+pub struct TotpSecretKey([u8; 32]);
+
+impl TotpSecretKey {
+    pub fn from_base64(key: &str) -> Option<Self> {
+        let key = STANDARD.decode(key.trim()).ok()?;
+        Some(Self(key.try_into().ok()?))
+    }
+
+    /// Use the configured key, or derive one from the secret of the server info
+    pub fn from_cfg(cfg: &MainCfg) -> Self {
+        cfg.totp_secret_key
+            .as_deref()
+            .and_then(Self::from_base64)
+            .unwrap_or_else(|| {
+                let mut hasher = Sha3_256::new();
+                hasher.update(b"ourchat totp secret key:");
+                hasher.update(SERVER_INFO.secret.as_bytes());
+                Self(hasher.finalize().into())
+            })
+    }
+
+    fn aead_key(&self) -> anyhow::Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.0).map_err(|_| anyhow!("bad totp key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+
+    /// Encrypt the secret of the user, the user id is authenticated so the stored secret can't
+    /// be moved to another account
+    pub fn encrypt(&self, user_id: ID, secret: &str) -> anyhow::Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rng().fill(&mut nonce[..]);
+        let mut in_out = secret.as_bytes().to_vec();
+        self.aead_key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(u64::from(user_id).to_be_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("cannot encrypt totp secret"))?;
+        let mut data = nonce.to_vec();
+        data.extend(in_out);
+        Ok(format!(
+            "{ENCRYPTED_SECRET_PREFIX}{}",
+            STANDARD.encode(data)
+        ))
+    }
+
+    pub fn decrypt(&self, user_id: ID, stored: &str) -> anyhow::Result<String> {
+        let data = stored
+            .strip_prefix(ENCRYPTED_SECRET_PREFIX)
+            .context("unknown format of totp secret in database")?;
+        let mut data = STANDARD
+            .decode(data)
+            .context("incorrect totp secret in database")?;
+        if data.len() < NONCE_LEN {
+            anyhow::bail!("incorrect totp secret in database");
+        }
+        let mut in_out = data.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&data)
+            .map_err(|_| anyhow!("incorrect totp secret in database"))?;
+        let secret = self
+            .aead_key()?
+            .open_in_place(
+                nonce,
+                Aad::from(u64::from(user_id).to_be_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("cannot decrypt totp secret"))?;
+        String::from_utf8(secret.to_vec()).context("incorrect totp secret in database")
+    }
+}
+
+/// The second factor provided by the user
+#[derive(Debug, Clone, Copy)]
+pub enum SecondFactor<'a> {
+    Totp(&'a str),
+    Recovery(&'a str),
+}
+
+fn build_totp(secret: &str, account_name: String) -> anyhow::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .context("incorrect totp secret in database")?;
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        TOTP_SKEW,
+        TOTP_STEP,
+        secret,
+        Some(SERVER_INFO.server_name.replace(':', "")),
+        account_name,
+    )
+    .context("cannot build totp")
+}
+
+fn generate_totp_secret() -> String {
+    let mut secret = vec![0u8; TOTP_SECRET_LEN];
+    rand::rng().fill(&mut secret[..]);
+    Secret::Raw(secret).to_encoded().to_string()
+}
+
+/// Recovery codes are random enough, so a fast hash is sufficient for storing them
+fn hash_recovery_code(code: &str) -> String {
+    let mut hasher = Sha3_256::new();
+    hasher.update(code.trim().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Check a TOTP code against the encrypted secret stored for the user, a code which has been
+/// accepted once is rejected afterwards
+async fn verify_totp_code(
+    user_id: ID,
+    key: &TotpSecretKey,
+    stored_secret: &str,
+    code: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> anyhow::Result<bool> {
+    let totp = build_totp(&key.decrypt(user_id, stored_secret)?, String::new())?;
+    if !totp
+        .check_current(code.trim())
+        .context("system time error")?
+    {
+        return Ok(false);
+    }
+    // a code stays valid for the skewed steps, remember it until then
+    let window = TOTP_STEP * (2 * TOTP_SKEW as u64 + 1);
+    let first_use: Option<String> = redis_conn
+        .set_options(
+            map_used_totp_code_to_redis(user_id, code.trim()),
+            1,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(window)),
+        )
+        .await?;
+    Ok(first_use.is_some())
+}
+
+/// Check the second factor of a user whose TOTP is enabled, a recovery code is consumed
+pub async fn check_second_factor(
+    user_id: ID,
+    key: &TotpSecretKey,
+    stored_secret: &str,
+    factor: SecondFactor<'_>,
+    db: &DbPool,
+) -> anyhow::Result<bool> {
+    match factor {
+        SecondFactor::Totp(code) => {
+            verify_totp_code(user_id, key, stored_secret, code, &mut db.redis()).await
+        }
+        SecondFactor::Recovery(code) => {
+            Ok(use_recovery_code(user_id, hash_recovery_code(code), &db.db_pool).await?)
+        }
+    }
+}
+
+/// Create the challenge which is finished by `AuthTotp`
+pub async fn create_totp_challenge(
+    user_id: ID,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<String, redis::RedisError> {
+    let challenge = generate_random_string(TOTP_CHALLENGE_LEN);
+    let _: () = redis_conn
+        .set_ex(
+            map_totp_challenge_to_redis(&challenge),
+            u64::from(user_id),
+            TOTP_CHALLENGE_EXPIRE.as_secs(),
+        )
+        .await?;
+    Ok(challenge)
+}
+
+/// Get the user who is logging in with the challenge
+pub async fn totp_challenge_owner(
+    challenge: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<Option<ID>, redis::RedisError> {
+    let owner: Option<u64> = redis_conn
+        .get(map_totp_challenge_to_redis(challenge))
+        .await?;
+    Ok(owner.map(ID))
+}
+
+/// Remove a passed challenge, returning false if it has been used by another request
+pub async fn finish_totp_challenge(
+    challenge: &str,
+    redis_conn: &mut impl AsyncCommands,
+) -> Result<bool, redis::RedisError> {
+    let removed: u64 = redis_conn
+        .del(map_totp_challenge_to_redis(challenge))
+        .await?;
+    Ok(removed == 1)
+}
+
+async fn enable_totp_impl(
+    server: &RpcServer,
+    id: ID,
+    _request: Request<EnableTotpRequest>,
+) -> Result<EnableTotpResponse, RpcError> {
+    let user = get_account_info_db(id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::USER))?;
+    if user.totp_enabled {
+        Err(Status::already_exists(TOTP_ALREADY_ENABLED))?;
+    }
+    // enrolling again replaces the unconfirmed secret
+    let secret = generate_totp_secret();
+    let otpauth_url = build_totp(&secret, user.email)?.get_url();
+    let stored_secret =
+        TotpSecretKey::from_cfg(&server.shared_data.cfg().main_cfg).encrypt(id, &secret)?;
+    set_totp(id, Some(stored_secret), false, &server.db.db_pool).await?;
+    Ok(EnableTotpResponse {
+        secret,
+        otpauth_url,
+    })
+}
+
+async fn confirm_totp_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ConfirmTotpRequest>,
+) -> Result<ConfirmTotpResponse, RpcError> {
+    let req = request.into_inner();
+    let user = get_account_info_db(id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::USER))?;
+    if user.totp_enabled {
+        Err(Status::already_exists(TOTP_ALREADY_ENABLED))?;
+    }
+    let Some(secret) = user.totp_secret else {
+        Err(Status::failed_precondition(TOTP_NOT_ENROLLED))?
+    };
+    let key = TotpSecretKey::from_cfg(&server.shared_data.cfg().main_cfg);
+    if !verify_totp_code(id, &key, &secret, &req.code, &mut server.db.redis()).await? {
+        Err(Status::invalid_argument(WRONG_TOTP_CODE))?;
+    }
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES_NUM)
+        .map(|_| generate_random_string(RECOVERY_CODE_LEN))
+        .collect();
+    let transaction = server.db.db_pool.begin().await?;
+    set_totp(id, Some(secret), true, &transaction).await?;
+    replace_recovery_codes(
+        id,
+        recovery_codes
+            .iter()
+            .map(|code| hash_recovery_code(code))
+            .collect(),
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(ConfirmTotpResponse { recovery_codes })
+}
+
+async fn disable_totp_impl(
+    server: &RpcServer,
+    id: ID,
+    request: Request<DisableTotpRequest>,
+) -> Result<DisableTotpResponse, RpcError> {
+    let req = request.into_inner();
+    let user = get_account_info_db(id, &server.db.db_pool)
+        .await?
+        .ok_or_else(|| Status::not_found(not_found::USER))?;
+    let (Some(secret), true) = (user.totp_secret, user.totp_enabled) else {
+        Err(Status::failed_precondition(TOTP_NOT_ENABLED))?
+    };
+    let factor = match &req.code {
+        Some(disable_totp_request::Code::TotpCode(code)) => SecondFactor::Totp(code),
+        Some(disable_totp_request::Code::RecoveryCode(code)) => SecondFactor::Recovery(code),
+        None => Err(Status::invalid_argument(MISSING_TOTP_CODE))?,
+    };
+    let key = TotpSecretKey::from_cfg(&server.shared_data.cfg().main_cfg);
+    if !check_second_factor(id, &key, &secret, factor, &server.db).await? {
+        Err(Status::invalid_argument(WRONG_TOTP_CODE))?;
+    }
+
+    let transaction = server.db.db_pool.begin().await?;
+    set_totp(id, None, false, &transaction).await?;
+    replace_recovery_codes(id, vec![], &transaction).await?;
+    transaction.commit().await?;
+    Ok(DisableTotpResponse {})
+}
+
+pub async fn enable_totp(
+    server: &RpcServer,
+    id: ID,
+    request: Request<EnableTotpRequest>,
+) -> Result<Response<EnableTotpResponse>, Status> {
+    match enable_totp_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn confirm_totp(
+    server: &RpcServer,
+    id: ID,
+    request: Request<ConfirmTotpRequest>,
+) -> Result<Response<ConfirmTotpResponse>, Status> {
+    match confirm_totp_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn disable_totp(
+    server: &RpcServer,
+    id: ID,
+    request: Request<DisableTotpRequest>,
+) -> Result<Response<DisableTotpResponse>, Status> {
+    match disable_totp_impl(server, id, request).await {
+        Ok(res) => Ok(Response::new(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp_secret_encryption() {
+        let key = TotpSecretKey([7; 32]);
+        let secret = generate_totp_secret();
+        let stored = key.encrypt(ID(1), &secret).unwrap();
+        assert!(!stored.contains(&secret));
+        assert_ne!(stored, key.encrypt(ID(1), &secret).unwrap());
+        assert_eq!(key.decrypt(ID(1), &stored).unwrap(), secret);
+        // bound to the user and the key
+        assert!(key.decrypt(ID(2), &stored).is_err());
+        assert!(TotpSecretKey([8; 32]).decrypt(ID(1), &stored).is_err());
+        assert!(key.decrypt(ID(1), &secret).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::process::token::revoke_all_tokens;
 use crate::{process::error_msg::SERVER_ERROR, server::RpcServer};
 use base::constants::ID;
 use entities::user;
@@ -48,7 +49,13 @@ async fn unregister_impl(
 ) -> Result<UnregisterResponse, UnregisterError> {
     let db_conn = &server.db;
     let batch = async {
-        let policy = server.shared_data.cfg().main_cfg.unregister_policy.clone();
+        let (policy, access_expire) = {
+            let cfg = server.shared_data.cfg();
+            (
+                cfg.main_cfg.unregister_policy.clone(),
+                cfg.main_cfg.access_token_expire,
+            )
+        };
         match policy {
             crate::config::UnregisterPolicy::Disable => {
                 set_account_deleted(id, &db_conn.db_pool).await?;
@@ -57,6 +64,7 @@ async fn unregister_impl(
                 delete_account(id, &db_conn.db_pool).await?;
             }
         }
+        revoke_all_tokens(id, access_expire, db_conn).await?;
         Ok(())
     };
     match batch.await {
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, time::Duration};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use base::{database::DbPool, wrapper::JobSchedulerWrapper};
 use redis::AsyncCommands;
@@ -7,6 +7,7 @@ use tokio::sync::MutexGuard;
 use tokio_cron_scheduler::Job;
 use tracing::error;
 
+use crate::leader::LeaderElection;
 use crate::webrtc::{RoomInfo, empty_room_name, room_key};
 
 pub mod accept_room_invitation;
@@ -39,11 +40,16 @@ pub async fn move_room_from_redis_to_postgres(
 pub async fn clean_rooms<'a>(
     duration: Duration,
     db_pool: DbPool,
+    leader: Arc<LeaderElection>,
     job_scheduler: MutexGuard<'a, JobSchedulerWrapper>,
 ) -> anyhow::Result<()> {
     let job = Job::new_repeated_async(duration, move |_uuid, _l| {
         let db_pool = db_pool.clone();
+        let leader = leader.clone();
         Box::pin(async move {
+            if !leader.is_leader() {
+                return;
+            }
             let logic = async move {
                 let mut conn = db_pool.redis();
                 let empty_rooms: HashSet<String> = conn.smembers(empty_room_name()).await?;
@@ -1,5 +1,5 @@
 use base::constants::ID;
-use base::rabbitmq::http_server::VERIFY_QUEUE;
+use base::rabbitmq::http_server::{PASSWORD_RESET_QUEUE, VERIFY_QUEUE};
 use deadpool_lapin::lapin::options::{ExchangeDeclareOptions, QueueDeclareOptions};
 use deadpool_lapin::lapin::types::FieldTable;
 use deadpool_lapin::lapin::{Channel, ExchangeKind};
@@ -11,6 +11,9 @@ pub const USER_MSG_BROADCAST_EXCHANGE: &str = "user_broadcast_msg";
 pub const WEBRTC_SIGNAL_EXCHANGE: &str = "webrtc_signal";
 pub const WEBRTC_FANOUT_EXCHANGE: &str = "webrtc_fanout";
 
+/// Every instance of the server is told about the changes of the server status
+pub const SERVER_STATUS_FANOUT_EXCHANGE: &str = "server_status";
+
 pub async fn create_user_message_direct_exchange(channel: &Channel) -> anyhow::Result<()> {
     channel
         .exchange_declare(
@@ -75,6 +78,22 @@ pub async fn create_webrtc_fanout_exchange(channel: &Channel) -> anyhow::Result<
     Ok(())
 }
 
+pub async fn create_server_status_fanout_exchange(channel: &Channel) -> anyhow::Result<()> {
+    channel
+        .exchange_declare(
+            SERVER_STATUS_FANOUT_EXCHANGE,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                auto_delete: false,
+                durable: false,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+    Ok(())
+}
+
 /// Init RabbitMQ
 pub async fn init(rmq: &deadpool_lapin::Pool) -> anyhow::Result<()> {
     let connection = rmq.get().await?;
@@ -83,6 +102,7 @@ pub async fn init(rmq: &deadpool_lapin::Pool) -> anyhow::Result<()> {
     create_user_message_broadcast_exchange(&channel).await?;
     create_webrtc_signal_exchange(&channel).await?;
     create_webrtc_fanout_exchange(&channel).await?;
+    create_server_status_fanout_exchange(&channel).await?;
     // Declare the verify queue
     channel
         .queue_declare(
@@ -91,6 +111,13 @@ pub async fn init(rmq: &deadpool_lapin::Pool) -> anyhow::Result<()> {
             FieldTable::default(),
         )
         .await?;
+    channel
+        .queue_declare(
+            PASSWORD_RESET_QUEUE,
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
     Ok(())
 }
 
@@ -113,8 +140,13 @@ pub async fn check_exchange_exist(
     Ok(())
 }
 
-pub fn generate_client_name(user_id: ID) -> String {
-    user_id.to_string()
+/// The queue of a connection fetching the messages, every device of the user has its own so
+/// that all of them get every message
+pub fn generate_client_name(user_id: ID, device: Option<&str>) -> String {
+    match device {
+        Some(device) => format!("{user_id}.{device}"),
+        None => user_id.to_string(),
+    }
 }
 
 pub fn generate_route_key(user_id: ID) -> String {
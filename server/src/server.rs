@@ -2,6 +2,7 @@
 
 mod ourchat_service;
 
+use crate::db::login_session::is_login_session_revoked;
 use crate::db::user::get_account_info_db;
 use crate::process::basic::get_preset_user_status::get_preset_user_status;
 use crate::process::basic::support::support;
@@ -16,7 +17,16 @@ use metrics::gauge;
 use migration::predefined::AccountStatus;
 use pb::service::auth::authorize::v1::{AuthRequest, AuthResponse};
 use pb::service::auth::email_verify::v1::{VerifyRequest, VerifyResponse};
+use pb::service::auth::password_reset::v1::{
+    RequestPasswordResetRequest, RequestPasswordResetResponse, ResetPasswordRequest,
+    ResetPasswordResponse,
+};
 use pb::service::auth::register::v1::{RegisterRequest, RegisterResponse};
+use pb::service::auth::token::v1::{
+    LogoutAllDevicesRequest, LogoutAllDevicesResponse, LogoutRequest, LogoutResponse,
+    RefreshTokenRequest, RefreshTokenResponse,
+};
+use pb::service::auth::totp::v1::AuthTotpRequest;
 use pb::service::auth::v1::auth_service_server::{self, AuthServiceServer};
 use pb::service::basic::preset_user_status::v1::{
     GetPresetUserStatusRequest, GetPresetUserStatusResponse,
@@ -50,7 +60,7 @@ use pb::service::server_manage::user_manage::v1::{
     ListServerRolePermissionsRequest, ListServerRolePermissionsResponse, ListServerRolesRequest,
     ListServerRolesResponse, ListUserServerRolesRequest, ListUserServerRolesResponse,
     ListUsersRequest, ListUsersResponse, RemoveServerRoleRequest, RemoveServerRoleResponse,
-    UnbanUserRequest, UnbanUserResponse,
+    SetUserFilesLimitRequest, SetUserFilesLimitResponse, UnbanUserRequest, UnbanUserResponse,
 };
 use pb::service::server_manage::v1::server_manage_service_server::{
     ServerManageService, ServerManageServiceServer,
@@ -58,6 +68,8 @@ use pb::service::server_manage::v1::server_manage_service_server::{
 use process::error_msg::not_found;
 use std::pin::Pin;
 use std::sync::{Arc, LazyLock};
+use std::task::Poll;
+use tonic::server::NamedService;
 use tonic::service::Routes;
 use tonic::{Request, Response, Status};
 
@@ -155,21 +167,16 @@ impl RpcServer {
             rabbitmq: self.rabbitmq.clone(),
         };
 
-        // Create service instances with interceptors for authentication and maintenance checks
-        let main_svc = OurChatServiceServer::with_interceptor(self, move |mut req| {
-            Self::check_auth(&mut req)?;
-            Ok(req)
-        });
+        // Create service instances requiring authentication
+        let db = self.db.clone();
+        let main_svc = Authenticated::new(OurChatServiceServer::new(self), db.clone());
 
         let basic_svc = BasicServiceServer::new(basic_service);
 
         let auth_svc = AuthServiceServer::new(auth_service);
 
         let server_manage_svc =
-            ServerManageServiceServer::with_interceptor(server_manage_service, move |mut req| {
-                Self::check_auth(&mut req)?;
-                Ok(req)
-            });
+            Authenticated::new(ServerManageServiceServer::new(server_manage_service), db);
 
         // enable reflection
         let reflection_service = tonic_reflection::server::Builder::configure()
@@ -188,63 +195,69 @@ impl RpcServer {
         Ok(svc)
     }
 
-    /// Verify authentication token from request metadata and extract user ID
+    /// Verify authentication token from request headers and extract user ID
     ///
     /// # Arguments
-    /// * `req` - The request to check authentication for
+    /// * `db` - Database connection pool, the revoked logins are stored in redis
+    /// * `headers` - Headers of the request to check authentication for
     ///
     /// # Returns
     /// * `Ok(ID)` - The authenticated user's ID
     /// * `Err(Status)` - Authentication error status
-    #[allow(clippy::result_large_err)]
-    fn check_auth(req: &mut Request<()>) -> Result<ID, Status> {
+    pub(crate) async fn check_auth(
+        db: &DbPool,
+        headers: &mut http::HeaderMap,
+    ) -> Result<ID, Status> {
         // Check if token exists in metadata
-        match req.metadata().get(JWT_HEADER) {
-            Some(token) => {
-                match process::check_token(
-                    token
-                        .to_str()
-                        .map_err(|_| Status::invalid_argument(error_msg::token::INVALID))?,
-                ) {
-                    Ok(jwt) => {
-                        let ret = jwt.id;
-                        // Store user ID in request metadata for later use
-                        req.metadata_mut().insert(
-                            "id",
-                            jwt.id
-                                .to_string()
-                                .parse()
-                                .map_err(|_| Status::invalid_argument(error_msg::token::INVALID))?,
-                        );
-                        tracing::info!(user_id = %jwt.id, "JWT Authentication successful");
-                        Ok(ret)
-                    }
-                    Err(e) => {
-                        tracing::info!(error = %e, "JWT Authentication failed");
-                        match e {
-                            ErrAuth::JWT(_) => {
-                                Err(Status::unauthenticated(error_msg::token::INVALID))
-                            }
-                            ErrAuth::Expire => {
-                                Err(Status::unauthenticated(error_msg::token::EXPIRED))
-                            }
-                            ErrAuth::UnsupportedAuthorizationHeader => {
-                                Err(Status::unauthenticated(
-                                    error_msg::token::UNSUPPORTED_AUTHORIZATION_HEADER,
-                                ))
-                            }
-                            ErrAuth::IncorrectFormat => {
-                                Err(Status::unauthenticated(error_msg::token::INCORRECT_FORMAT))
-                            }
-                        }
-                    }
+        let Some(token) = headers.get(JWT_HEADER) else {
+            tracing::info!("JWT Authentication failed: missing token");
+            return Err(Status::unauthenticated(error_msg::token::MISSING));
+        };
+        let token = token
+            .to_str()
+            .map_err(|_| Status::invalid_argument(error_msg::token::INVALID))?;
+        let jwt = process::check_token(token).map_err(|e| {
+            tracing::info!(error = %e, "JWT Authentication failed");
+            match e {
+                ErrAuth::JWT(_) => Status::unauthenticated(error_msg::token::INVALID),
+                ErrAuth::Expire => Status::unauthenticated(error_msg::token::EXPIRED),
+                ErrAuth::UnsupportedAuthorizationHeader => {
+                    Status::unauthenticated(error_msg::token::UNSUPPORTED_AUTHORIZATION_HEADER)
                 }
+                ErrAuth::IncorrectFormat => {
+                    Status::unauthenticated(error_msg::token::INCORRECT_FORMAT)
+                }
+            }
+        })?;
+        // The token is valid, but the login it belongs to may have been revoked
+        match is_login_session_revoked(&jwt.sid, &mut db.redis()).await {
+            Ok(false) => {}
+            Ok(true) => {
+                tracing::info!(user_id = %jwt.id, "JWT Authentication failed: token revoked");
+                return Err(Status::unauthenticated(error_msg::token::REVOKED));
             }
-            None => {
-                tracing::info!("JWT Authentication failed: missing token");
-                Err(Status::unauthenticated(error_msg::token::MISSING))
+            Err(e) => {
+                tracing::error!("Failed to check token revocation: {:?}", e);
+                return Err(Status::internal(SERVER_ERROR));
             }
         }
+        // Store user ID in request metadata for later use
+        headers.insert(
+            "id",
+            jwt.id
+                .to_string()
+                .parse()
+                .map_err(|_| Status::invalid_argument(error_msg::token::INVALID))?,
+        );
+        // and the login it belongs to, for telling the current device
+        headers.insert(
+            "sid",
+            jwt.sid
+                .parse()
+                .map_err(|_| Status::invalid_argument(error_msg::token::INVALID))?,
+        );
+        tracing::info!(user_id = %jwt.id, "JWT Authentication successful");
+        Ok(jwt.id)
     }
 
     /// Check if the user account exists and is not deleted
@@ -285,6 +298,57 @@ impl RpcServer {
     }
 }
 
+/// A grpc service whose requests are authenticated before they reach it
+///
+/// Tonic interceptors are synchronous, but checking whether a token is revoked needs redis, so
+/// the check is done in this wrapper instead.
+#[derive(Debug, Clone)]
+pub struct Authenticated<S> {
+    inner: S,
+    db: DbPool,
+}
+
+impl<S> Authenticated<S> {
+    pub fn new(inner: S, db: DbPool) -> Self {
+        Self { inner, db }
+    }
+}
+
+impl<S: NamedService> NamedService for Authenticated<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> tower::Service<http::Request<B>> for Authenticated<S>
+where
+    S: tower::Service<http::Request<B>, Response = http::Response<tonic::body::Body>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        // The service which has been polled ready handles the request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let db = self.db.clone();
+        Box::pin(async move {
+            match RpcServer::check_auth(&db, req.headers_mut()).await {
+                Ok(_) => inner.call(req).await,
+                Err(status) => Ok(status.into_http()),
+            }
+        })
+    }
+}
+
 /// Authentication service provider
 #[derive(Debug)]
 pub struct AuthServiceProvider {
@@ -325,6 +389,14 @@ impl auth_service_server::AuthService for AuthServiceProvider {
         process::auth::auth(self, request).await
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn auth_totp(
+        &self,
+        request: Request<AuthTotpRequest>,
+    ) -> Result<Response<AuthResponse>, Status> {
+        process::auth::auth_totp(self, request).await
+    }
+
     type VerifyStream = VerifyStream;
 
     #[tracing::instrument(skip(self))]
@@ -334,6 +406,47 @@ impl auth_service_server::AuthService for AuthServiceProvider {
     ) -> Result<Response<Self::VerifyStream>, Status> {
         process::verify::email_verify(self, request).await
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn refresh_token(
+        &self,
+        request: Request<RefreshTokenRequest>,
+    ) -> Result<Response<RefreshTokenResponse>, Status> {
+        process::token::refresh_token(self, request).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn logout(
+        &self,
+        request: Request<LogoutRequest>,
+    ) -> Result<Response<LogoutResponse>, Status> {
+        process::token::logout(self, request).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn logout_all_devices(
+        &self,
+        request: Request<LogoutAllDevicesRequest>,
+    ) -> Result<Response<LogoutAllDevicesResponse>, Status> {
+        process::token::logout_all_devices(self, request).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn request_password_reset(
+        &self,
+        request: Request<RequestPasswordResetRequest>,
+    ) -> Result<Response<RequestPasswordResetResponse>, Status> {
+        process::password::request_password_reset(self, request).await
+    }
+
+    // the request carries the password
+    #[tracing::instrument(skip(self, request))]
+    async fn reset_password(
+        &self,
+        request: Request<ResetPasswordRequest>,
+    ) -> Result<Response<ResetPasswordResponse>, Status> {
+        process::password::reset_password(self, request).await
+    }
 }
 
 /// Basic service implementation providing server information and utilities
@@ -366,9 +479,20 @@ impl BasicService for BasicServiceProvider {
         &self,
         _request: Request<GetServerInfoRequest>,
     ) -> Result<Response<pb::service::basic::server::v1::GetServerInfoResponse>, Status> {
+        let now = chrono::Utc::now();
+        let status = self.shared_data.server_status();
+        let maintenance =
+            status
+                .is_pending_at(now)
+                .then(|| pb::service::basic::server::v1::Maintenance {
+                    reason: status.reason.clone(),
+                    start_time: Some(status.start_time.unwrap_or(now).into()),
+                    end_time: status.end_time.map(Into::into),
+                });
         Ok(Response::new(
             pb::service::basic::server::v1::GetServerInfoResponse {
-                status: self.shared_data.get_maintaining().into(),
+                status: status.is_maintaining_at(now).into(),
+                maintenance,
                 ..SERVER_INFO_RPC.clone()
             },
         ))
@@ -427,6 +551,7 @@ static SERVER_INFO_RPC: LazyLock<pb::service::basic::server::v1::GetServerInfoRe
         status: RunningStatus::Normal as i32,
         unique_identifier: SERVER_INFO.unique_id.to_string(),
         server_name: SERVER_INFO.server_name.to_string(),
+        maintenance: None,
     });
 
 /// Server management service implementation
@@ -554,6 +679,14 @@ impl ServerManageService for ServerManageServiceProvider {
         process::list_server_role_permissions(self, request).await
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn set_user_files_limit(
+        &self,
+        request: Request<SetUserFilesLimitRequest>,
+    ) -> Result<Response<SetUserFilesLimitResponse>, Status> {
+        process::set_user_files_limit(self, request).await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn list_sessions(
         &self,
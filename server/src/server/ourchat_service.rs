@@ -1,7 +1,20 @@
 use std::pin::Pin;
 
+use pb::service::ourchat::change_password::v1::{ChangePasswordRequest, ChangePasswordResponse};
 use pb::service::ourchat::delete::v1::{DeleteFileRequest, DeleteFileResponse};
+use pb::service::ourchat::device::v1::{
+    FetchKeyBundlesRequest, FetchKeyBundlesResponse, GetKeyFingerprintRequest,
+    GetKeyFingerprintResponse, ListDevicesRequest, ListDevicesResponse, ReplenishPrekeysRequest,
+    ReplenishPrekeysResponse, RevokeDeviceRequest, RevokeDeviceResponse, UploadKeyBundleRequest,
+    UploadKeyBundleResponse,
+};
 use pb::service::ourchat::download::v1::{DownloadRequest, DownloadResponse};
+use pb::service::ourchat::file_info::v1::{GetFileInfoRequest, GetFileInfoResponse};
+use pb::service::ourchat::oauth::v1::{
+    ConfirmLinkProviderRequest, ConfirmLinkProviderResponse, LinkProviderRequest,
+    LinkProviderResponse, ListLinkedProvidersRequest, ListLinkedProvidersResponse,
+    UnlinkProviderRequest, UnlinkProviderResponse,
+};
 use pb::service::ourchat::session::e2eeize_and_dee2eeize_session::v1::{
     Dee2eeizeSessionRequest, Dee2eeizeSessionResponse, E2eeizeSessionRequest,
     E2eeizeSessionResponse,
@@ -13,6 +26,9 @@ use pb::service::ourchat::session::invite_user_to_session::v1::{
 use pb::service::ourchat::session::session_room_key::v1::{
     SendRoomKeyRequest, SendRoomKeyResponse,
 };
+use pb::service::ourchat::storage::v1::{
+    GetStorageUsageRequest, GetStorageUsageResponse, ListUserFilesRequest, ListUserFilesResponse,
+};
 use pb::service::ourchat::webrtc::room::accept_room_invitation::v1::{
     AcceptRoomInvitationRequest, AcceptRoomInvitationResponse,
 };
@@ -48,7 +64,30 @@ use pb::service::ourchat::friends::set_friend_info::v1::{
     SetFriendInfoRequest, SetFriendInfoResponse,
 };
 use pb::service::ourchat::get_account_info::v1::{GetAccountInfoRequest, GetAccountInfoResponse};
+use pb::service::ourchat::msg_delivery::edit::v1::{EditMsgRequest, EditMsgResponse};
+use pb::service::ourchat::msg_delivery::history::v1::{
+    GetSessionHistoryRequest, GetSessionHistoryResponse,
+};
+use pb::service::ourchat::msg_delivery::pin::v1::{
+    ListPinnedMsgsRequest, ListPinnedMsgsResponse, PinMsgRequest, PinMsgResponse, UnpinMsgRequest,
+    UnpinMsgResponse,
+};
+use pb::service::ourchat::msg_delivery::reaction::v1::{
+    AddReactionRequest, AddReactionResponse, RemoveReactionRequest, RemoveReactionResponse,
+};
+use pb::service::ourchat::msg_delivery::read::v1::{
+    GetUnreadCountsRequest, GetUnreadCountsResponse, MarkReadRequest, MarkReadResponse,
+};
 use pb::service::ourchat::msg_delivery::recall::v1::{RecallMsgRequest, RecallMsgResponse};
+use pb::service::ourchat::msg_delivery::scheduled::v1::{
+    CancelScheduledMsgRequest, CancelScheduledMsgResponse, ListScheduledMsgsRequest,
+    ListScheduledMsgsResponse,
+};
+use pb::service::ourchat::msg_delivery::search::v1::{
+    SearchMessagesRequest, SearchMessagesResponse,
+};
+use pb::service::ourchat::msg_delivery::thread::v1::{FetchThreadRequest, FetchThreadResponse};
+use pb::service::ourchat::msg_delivery::typing::v1::{SetTypingRequest, SetTypingResponse};
 use pb::service::ourchat::msg_delivery::v1::{
     FetchMsgsRequest, FetchMsgsResponse, SendMsgRequest, SendMsgResponse,
 };
@@ -80,6 +119,10 @@ use pb::service::ourchat::session::set_session_info::v1::{
     SetSessionInfoRequest, SetSessionInfoResponse,
 };
 use pb::service::ourchat::set_account_info::v1::{SetSelfInfoRequest, SetSelfInfoResponse};
+use pb::service::ourchat::totp::v1::{
+    ConfirmTotpRequest, ConfirmTotpResponse, DisableTotpRequest, DisableTotpResponse,
+    EnableTotpRequest, EnableTotpResponse,
+};
 use pb::service::ourchat::unregister::v1::{UnregisterRequest, UnregisterResponse};
 use pb::service::ourchat::upload::v1::{
     CancelUploadRequest, CancelUploadResponse, CompleteUploadRequest, CompleteUploadResponse,
@@ -129,6 +172,161 @@ impl OurChatService for RpcServer {
         process::set_self_info(self, id, request).await
     }
 
+    /// Start enrolling TOTP, returning the secret to be added to an authenticator
+    #[tracing::instrument(skip(self))]
+    async fn enable_totp(
+        &self,
+        request: Request<EnableTotpRequest>,
+    ) -> Result<Response<EnableTotpResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::totp::enable_totp(self, id, request).await
+    }
+
+    /// Confirm the TOTP enrollment with a code, returning the recovery codes
+    #[tracing::instrument(skip(self))]
+    async fn confirm_totp(
+        &self,
+        request: Request<ConfirmTotpRequest>,
+    ) -> Result<Response<ConfirmTotpResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::totp::confirm_totp(self, id, request).await
+    }
+
+    /// Turn off TOTP with a code or a recovery code
+    #[tracing::instrument(skip(self))]
+    async fn disable_totp(
+        &self,
+        request: Request<DisableTotpRequest>,
+    ) -> Result<Response<DisableTotpResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::totp::disable_totp(self, id, request).await
+    }
+
+    /// Change the password, logging out the other devices
+    // the request carries the password
+    #[tracing::instrument(skip(self, request))]
+    async fn change_password(
+        &self,
+        request: Request<ChangePasswordRequest>,
+    ) -> Result<Response<ChangePasswordResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::password::change_password(self, id, request).await
+    }
+
+    /// List the devices the user is logged in on
+    #[tracing::instrument(skip(self))]
+    async fn list_devices(
+        &self,
+        request: Request<ListDevicesRequest>,
+    ) -> Result<Response<ListDevicesResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::device::list_devices(self, id, request).await
+    }
+
+    /// Log out one of the devices of the user
+    #[tracing::instrument(skip(self))]
+    async fn revoke_device(
+        &self,
+        request: Request<RevokeDeviceRequest>,
+    ) -> Result<Response<RevokeDeviceResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::device::revoke_device(self, id, request).await
+    }
+
+    /// Publish the end-to-end encryption keys of the requesting device
+    #[tracing::instrument(skip(self))]
+    async fn upload_key_bundle(
+        &self,
+        request: Request<UploadKeyBundleRequest>,
+    ) -> Result<Response<UploadKeyBundleResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::device_keys::upload_key_bundle(self, id, request).await
+    }
+
+    /// Get the end-to-end encryption keys of the devices of a user
+    #[tracing::instrument(skip(self))]
+    async fn fetch_key_bundles(
+        &self,
+        request: Request<FetchKeyBundlesRequest>,
+    ) -> Result<Response<FetchKeyBundlesResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::device_keys::fetch_key_bundles(self, id, request).await
+    }
+
+    /// Add one-time prekeys to the requesting device
+    #[tracing::instrument(skip(self))]
+    async fn replenish_prekeys(
+        &self,
+        request: Request<ReplenishPrekeysRequest>,
+    ) -> Result<Response<ReplenishPrekeysResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::device_keys::replenish_prekeys(self, id, request).await
+    }
+
+    /// Get the safety number to verify the public key of a user with
+    #[tracing::instrument(skip(self))]
+    async fn get_key_fingerprint(
+        &self,
+        request: Request<GetKeyFingerprintRequest>,
+    ) -> Result<Response<GetKeyFingerprintResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::key_verification::get_key_fingerprint(self, id, request).await
+    }
+
+    /// Get a ticket for linking an external account on the http server
+    #[tracing::instrument(skip(self))]
+    async fn link_provider(
+        &self,
+        request: Request<LinkProviderRequest>,
+    ) -> Result<Response<LinkProviderResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::oauth::link_provider(self, id, request).await
+    }
+
+    /// Link the external account authorized for the link ticket of the user
+    #[tracing::instrument(skip(self))]
+    async fn confirm_link_provider(
+        &self,
+        request: Request<ConfirmLinkProviderRequest>,
+    ) -> Result<Response<ConfirmLinkProviderResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::oauth::confirm_link_provider(self, id, request).await
+    }
+
+    /// Unlink an external account, keeping at least one way of logging in
+    #[tracing::instrument(skip(self))]
+    async fn unlink_provider(
+        &self,
+        request: Request<UnlinkProviderRequest>,
+    ) -> Result<Response<UnlinkProviderResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::oauth::unlink_provider(self, id, request).await
+    }
+
+    /// List the external accounts linked to the user
+    #[tracing::instrument(skip(self))]
+    async fn list_linked_providers(
+        &self,
+        request: Request<ListLinkedProvidersRequest>,
+    ) -> Result<Response<ListLinkedProvidersResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::oauth::list_linked_providers(self, id, request).await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn set_friend_info(
         &self,
@@ -227,6 +425,16 @@ impl OurChatService for RpcServer {
         process::download(self, id, request).await
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn get_file_info(
+        &self,
+        request: Request<GetFileInfoRequest>,
+    ) -> Result<Response<GetFileInfoResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::get_file_info(self, id, request).await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn delete_file(
         &self,
@@ -237,6 +445,26 @@ impl OurChatService for RpcServer {
         process::delete_file(self, id, request).await
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn get_storage_usage(
+        &self,
+        request: Request<GetStorageUsageRequest>,
+    ) -> Result<Response<GetStorageUsageResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::get_storage_usage(self, id, request).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_user_files(
+        &self,
+        request: Request<ListUserFilesRequest>,
+    ) -> Result<Response<ListUserFilesResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::list_user_files(self, id, request).await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn accept_join_session_invitation(
         &self,
@@ -307,6 +535,161 @@ impl OurChatService for RpcServer {
         process::recall_msg(self, id, request).await
     }
 
+    /// Edit a message sent by the user itself
+    /// The previous content is kept as a revision
+    #[tracing::instrument(skip(self))]
+    async fn edit_msg(
+        &self,
+        request: Request<EditMsgRequest>,
+    ) -> Result<Response<EditMsgResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::edit_msg(self, id, request).await
+    }
+
+    /// Fetch the replies of a thread page by page
+    #[tracing::instrument(skip(self))]
+    async fn fetch_thread(
+        &self,
+        request: Request<FetchThreadRequest>,
+    ) -> Result<Response<FetchThreadResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::fetch_thread(self, id, request).await
+    }
+
+    /// React to a message of the session with an emoji
+    #[tracing::instrument(skip(self))]
+    async fn add_reaction(
+        &self,
+        request: Request<AddReactionRequest>,
+    ) -> Result<Response<AddReactionResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::add_reaction(self, id, request).await
+    }
+
+    /// Withdraw a reaction made by the user itself
+    #[tracing::instrument(skip(self))]
+    async fn remove_reaction(
+        &self,
+        request: Request<RemoveReactionRequest>,
+    ) -> Result<Response<RemoveReactionResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::remove_reaction(self, id, request).await
+    }
+
+    /// Page through the history of a single session
+    #[tracing::instrument(skip(self))]
+    async fn get_session_history(
+        &self,
+        request: Request<GetSessionHistoryRequest>,
+    ) -> Result<Response<GetSessionHistoryResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::get_session_history(self, id, request).await
+    }
+
+    /// Move the read position of the user in a session and tell the other members
+    #[tracing::instrument(skip(self))]
+    async fn mark_read(
+        &self,
+        request: Request<MarkReadRequest>,
+    ) -> Result<Response<MarkReadResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::mark_read(self, id, request).await
+    }
+
+    /// Get the number of unread messages in every session of the user
+    #[tracing::instrument(skip(self))]
+    async fn get_unread_counts(
+        &self,
+        request: Request<GetUnreadCountsRequest>,
+    ) -> Result<Response<GetUnreadCountsResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::get_unread_counts(self, id, request).await
+    }
+
+    /// Tell the other members of a session whether the user is typing
+    #[tracing::instrument(skip(self))]
+    async fn set_typing(
+        &self,
+        request: Request<SetTypingRequest>,
+    ) -> Result<Response<SetTypingResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::set_typing(self, id, request).await
+    }
+
+    /// Search the non-encrypted messages of the sessions the user is in
+    #[tracing::instrument(skip(self))]
+    async fn search_messages(
+        &self,
+        request: Request<SearchMessagesRequest>,
+    ) -> Result<Response<SearchMessagesResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::search_messages(self, id, request).await
+    }
+
+    /// Pin a message of the session, which requires the PinMsg permission
+    #[tracing::instrument(skip(self))]
+    async fn pin_msg(
+        &self,
+        request: Request<PinMsgRequest>,
+    ) -> Result<Response<PinMsgResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::pin_msg(self, id, request).await
+    }
+
+    /// Unpin a message of the session, which requires the PinMsg permission
+    #[tracing::instrument(skip(self))]
+    async fn unpin_msg(
+        &self,
+        request: Request<UnpinMsgRequest>,
+    ) -> Result<Response<UnpinMsgResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::unpin_msg(self, id, request).await
+    }
+
+    /// List the pinned messages of the session
+    #[tracing::instrument(skip(self))]
+    async fn list_pinned_msgs(
+        &self,
+        request: Request<ListPinnedMsgsRequest>,
+    ) -> Result<Response<ListPinnedMsgsResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::list_pinned_msgs(self, id, request).await
+    }
+
+    /// List the pending messages scheduled by yourself
+    #[tracing::instrument(skip(self))]
+    async fn list_scheduled_msgs(
+        &self,
+        request: Request<ListScheduledMsgsRequest>,
+    ) -> Result<Response<ListScheduledMsgsResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::list_scheduled_msgs(self, id, request).await
+    }
+
+    /// Cancel a pending message scheduled by yourself
+    #[tracing::instrument(skip(self))]
+    async fn cancel_scheduled_msg(
+        &self,
+        request: Request<CancelScheduledMsgRequest>,
+    ) -> Result<Response<CancelScheduledMsgResponse>, Status> {
+        let id = get_id_from_req_or_err(&request)?;
+        self.check_account_status(id).await?;
+        process::cancel_scheduled_msg(self, id, request).await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn set_role(
         &self,
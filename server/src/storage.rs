@@ -0,0 +1,114 @@
+//! Backends keeping the uploaded files.
+//!
+//! Files are addressed by an object name, which is what the `path` column of the `files` table
+//! holds. Uploads are received into temporary files under `files_storage_path` first, and handed
+//! to the backend once they are verified.
+
+mod local;
+pub mod migrate;
+mod s3;
+
+use std::{path::Path, pin::Pin, sync::Arc};
+
+use base::constants::ID;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::config::{MainCfg, StorageBackendKind};
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+pub type BlobReader = Pin<Box<dyn AsyncRead + Send>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("object not found")]
+    NotFound,
+    #[error("Internal IO error:{0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("http error:{0:?}")]
+    Http(#[from] reqwest::Error),
+    #[error("object storage responded {status}: {body}")]
+    Response {
+        status: http::StatusCode,
+        body: String,
+    },
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+}
+
+impl StorageError {
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            StorageError::NotFound => true,
+            StorageError::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait StorageBackend: Send + Sync + std::fmt::Debug {
+    fn kind(&self) -> StorageBackendKind;
+
+    /// Store the local file `source` as `object`, `source` may be moved away
+    async fn put_file(&self, object: &str, source: &Path) -> Result<(), StorageError>;
+
+    /// Read `length` bytes from `offset`, less if the object ends earlier
+    async fn read_range(
+        &self,
+        object: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<BlobReader, StorageError>;
+
+    /// Bytes num of the object
+    async fn size(&self, object: &str) -> Result<u64, StorageError>;
+
+    /// Delete the object, deleting a missing one is not an error
+    async fn delete(&self, object: &str) -> Result<(), StorageError>;
+
+    async fn read(&self, object: &str) -> Result<Vec<u8>, StorageError> {
+        let mut data = Vec::new();
+        self.read_range(object, 0, u64::MAX)
+            .await?
+            .read_to_end(&mut data)
+            .await?;
+        Ok(data)
+    }
+}
+
+/// Build the backend the config chooses
+pub fn build_storage(
+    cfg: &MainCfg,
+    kind: StorageBackendKind,
+) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    Ok(match kind {
+        StorageBackendKind::Local => Arc::new(LocalStorage::new(cfg.files_storage_path.clone())),
+        StorageBackendKind::S3 => {
+            let s3_cfg =
+                cfg.storage.s3.clone().ok_or_else(|| {
+                    StorageError::InvalidConfig("storage.s3 is not set".to_string())
+                })?;
+            Arc::new(S3Storage::new(s3_cfg)?)
+        }
+    })
+}
+
+/// The object name of the file `key` uploaded by the user, spread over directories by the first
+/// characters of the user id and the key for better filesystem performance
+pub fn object_name(user_id: ID, key: impl AsRef<str>) -> String {
+    let key = key.as_ref();
+    format!("{:02x}/{}/{}", user_id.0 % 256, &key[..2], key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_name() {
+        assert_eq!(object_name(ID(0x1ff), "abcdef"), "ff/ab/abcdef");
+        assert_eq!(object_name(ID(1), "xyz"), "01/xy/xyz");
+    }
+}
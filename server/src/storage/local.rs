@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::{BlobReader, StorageBackend, StorageError};
+use crate::config::StorageBackendKind;
+
+/// Files under a directory of the local filesystem
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of the object on the disk. The files uploaded before the backends existed are
+    /// recorded by their absolute paths, which are used as is.
+    pub fn resolve(&self, object: &str) -> PathBuf {
+        let path = Path::new(object);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for LocalStorage {
+    fn kind(&self) -> StorageBackendKind {
+        StorageBackendKind::Local
+    }
+
+    async fn put_file(&self, object: &str, source: &Path) -> Result<(), StorageError> {
+        let path = self.resolve(object);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        match fs::rename(source, &path).await {
+            Ok(()) => {}
+            // The temporary files may be on another filesystem
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                fs::copy(source, &path).await?;
+                fs::remove_file(source).await?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    async fn read_range(
+        &self,
+        object: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<BlobReader, StorageError> {
+        let mut file = fs::File::open(self.resolve(object)).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        Ok(Box::pin(tokio::io::BufReader::new(file).take(length)))
+    }
+
+    async fn size(&self, object: &str) -> Result<u64, StorageError> {
+        Ok(fs::metadata(self.resolve(object)).await?.len())
+    }
+
+    async fn delete(&self, object: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.resolve(object)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Moving the uploaded files between storage backends while the server is stopped
+
+use std::path::Path;
+
+use anyhow::Context;
+use base::constants::ID;
+use entities::{files, prelude::*};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ConnectionTrait, EntityTrait, PaginatorTrait, QueryOrder,
+};
+use tokio::io::AsyncWriteExt;
+
+use super::{StorageBackend, StorageError, build_storage, object_name};
+use crate::{
+    config::StorageBackendKind,
+    db::file_blobs::is_blob_object,
+    helper::generate_random_string,
+    process::files::media::{delete_file_objects, thumbnail_object, thumbnails},
+};
+
+const PAGE_SIZE: u64 = 100;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub moved: usize,
+    /// Found in the target already, by an interrupted migration before
+    pub skipped: usize,
+    /// Recorded in the database but missing from the source
+    pub missing: usize,
+}
+
+/// Copy every file recorded in the database from `source` to `target`, pointing the records to
+/// the new objects. The files are staged in `staging_dir` on the way.
+///
+/// The migration can be run again after being interrupted, the files copied already are skipped.
+pub async fn migrate_files(
+    db_conn: &impl ConnectionTrait,
+    source: &dyn StorageBackend,
+    target: &dyn StorageBackend,
+    staging_dir: &Path,
+    keep_source: bool,
+) -> anyhow::Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+    tokio::fs::create_dir_all(staging_dir).await?;
+    let mut pages = Files::find()
+        .order_by_asc(files::Column::Key)
+        .paginate(db_conn, PAGE_SIZE);
+    while let Some(page) = pages.fetch_and_next().await? {
+        for file in page {
+            // The deduplicated contents keep their names, shared by the files of the content
+            let shared = is_blob_object(&file.path);
+            let object = if shared {
+                file.path.clone()
+            } else {
+                object_name(ID::from(file.user_id), &file.key)
+            };
+            let source_size = match source.size(&file.path).await {
+                Ok(size) => size,
+                Err(e) if e.is_not_found() && shared && target.size(&object).await.is_ok() => {
+                    // Moved along with another file of the same content
+                    report.skipped += 1;
+                    continue;
+                }
+                Err(e) if e.is_not_found() => {
+                    tracing::warn!("file {} is missing from the source", file.key);
+                    report.missing += 1;
+                    continue;
+                }
+                Err(e) => Err(e).with_context(|| format!("cannot read file {}", file.key))?,
+            };
+            let copied = match target.size(&object).await {
+                Ok(size) => size == source_size,
+                Err(e) if e.is_not_found() => false,
+                Err(e) => Err(e).with_context(|| format!("cannot check file {}", file.key))?,
+            };
+            if copied {
+                report.skipped += 1;
+            } else {
+                copy_object(source, &file.path, target, &object, staging_dir)
+                    .await
+                    .with_context(|| format!("cannot copy file {}", file.key))?;
+                report.moved += 1;
+            }
+
+            for thumbnail in thumbnails(&file) {
+                let source_object = thumbnail_object(&file.path, thumbnail.max_side);
+                let target_object = thumbnail_object(&object, thumbnail.max_side);
+                match copy_object(source, &source_object, target, &target_object, staging_dir).await
+                {
+                    Ok(()) => {}
+                    // Regenerated for the new uploads only, the file is usable without them
+                    Err(e) if e.is_not_found() => {
+                        tracing::warn!("thumbnail {} is missing from the source", source_object);
+                    }
+                    Err(e) => Err(e)
+                        .with_context(|| format!("cannot copy the thumbnail of {}", file.key))?,
+                }
+            }
+
+            let old_file = file.clone();
+            if file.path != object {
+                let mut file: files::ActiveModel = file.into();
+                file.path = ActiveValue::Set(object);
+                file.update(db_conn).await?;
+            }
+            if !keep_source {
+                delete_file_objects(&old_file, source).await?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn copy_object(
+    source: &dyn StorageBackend,
+    source_object: &str,
+    target: &dyn StorageBackend,
+    target_object: &str,
+    staging_dir: &Path,
+) -> Result<(), StorageError> {
+    let staging_path = staging_dir.join(generate_random_string(16));
+    let result = async {
+        let mut reader = source.read_range(source_object, 0, u64::MAX).await?;
+        let mut staging = tokio::fs::File::create(&staging_path).await?;
+        tokio::io::copy(&mut reader, &mut staging).await?;
+        staging.flush().await?;
+        drop(staging);
+        target.put_file(target_object, &staging_path).await
+    }
+    .await;
+    // The backends moving the staged file away leave nothing to remove
+    match tokio::fs::remove_file(&staging_path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("cannot remove staged file: {}", e),
+    }
+    result
+}
+
+/// Entry of the `migrate-storage` command
+pub async fn run_migrate_storage(
+    cfg: &crate::Cfg,
+    from: StorageBackendKind,
+    to: StorageBackendKind,
+    keep_source: bool,
+) -> anyhow::Result<()> {
+    if from == to {
+        anyhow::bail!("the source and the target backend are the same");
+    }
+    let source = build_storage(&cfg.main_cfg, from)?;
+    let target = build_storage(&cfg.main_cfg, to)?;
+    let (db_conn, _) = base::database::postgres::connect_to_db(&cfg.db_cfg.url(), true).await?;
+    let staging_dir = cfg.main_cfg.files_storage_path.join(".migrate");
+    let report = migrate_files(
+        &db_conn,
+        source.as_ref(),
+        target.as_ref(),
+        &staging_dir,
+        keep_source,
+    )
+    .await?;
+    tokio::fs::remove_dir_all(&staging_dir).await.ok();
+    println!(
+        "moved {} files, skipped {} copied before, {} missing from the source",
+        report.moved, report.skipped, report.missing
+    );
+    if to != cfg.main_cfg.storage.backend {
+        println!(
+            "set storage.backend to {} before starting the server again",
+            serde_json::to_string(&to)?
+        );
+    }
+    Ok(())
+}
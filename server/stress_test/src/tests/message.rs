@@ -47,6 +47,10 @@ pub async fn test_send_msg(
                             markdown_text: format!("Test message {}", rand::random::<u32>()),
                             involved_files: vec![],
                             is_encrypted: false,
+                            reply_to_msg_id: None,
+                            thread_root_id: None,
+                            send_at: None,
+                            ttl: None,
                         })
                         .await
                     {
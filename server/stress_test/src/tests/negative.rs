@@ -50,6 +50,10 @@ pub async fn test_send_msg_invalid_session(users: &UsersGroup, report: &mut Repo
                     markdown_text: "Test message".to_string(),
                     involved_files: vec![],
                     is_encrypted: false,
+                    reply_to_msg_id: None,
+                    thread_root_id: None,
+                    send_at: None,
+                    ttl: None,
                 })
                 .await
                 .is_err()
@@ -122,6 +126,10 @@ pub async fn test_send_empty_message(users: &UsersGroup, report: &mut Report) {
                     markdown_text: "".to_string(),
                     involved_files: vec![],
                     is_encrypted: false,
+                    reply_to_msg_id: None,
+                    thread_root_id: None,
+                    send_at: None,
+                    ttl: None,
                 })
                 .await
                 .is_err()
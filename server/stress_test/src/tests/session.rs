@@ -154,6 +154,7 @@ pub async fn test_set_session_info(
                         name: Some(format!("updated_session_{}", rand::random::<u32>())),
                         description: Some("Updated by stress test".to_string()),
                         avatar_key: None,
+                        message_ttl: None,
                     })
                     .await
                     .is_ok()
@@ -804,6 +805,7 @@ pub async fn test_send_room_key(
                         session_id: session_id.0,
                         user_id: recipient_id.0,
                         room_key: Bytes::from(vec![1, 2, 3, 4]), // Dummy key
+                        device_id: None,
                     })
                     .await
                     .is_ok()
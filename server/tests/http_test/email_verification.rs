@@ -95,9 +95,10 @@ async fn test_oauth_user_email_verified() {
         account_status: sea_orm::ActiveValue::Set(1),
         deleted_at: sea_orm::ActiveValue::Set(None),
         public_key: sea_orm::ActiveValue::Set(vec![]),
-        github_id: sea_orm::ActiveValue::Set(Some("12345".to_string())),
-        oauth_provider: sea_orm::ActiveValue::Set(Some("github".to_string())),
         email_verified: sea_orm::ActiveValue::Set(true), // OAuth users are always verified
+        totp_secret: sea_orm::ActiveValue::Set(None),
+        totp_enabled: sea_orm::ActiveValue::Set(false),
+        files_limit: sea_orm::ActiveValue::Set(None),
     };
 
     user::Entity::insert(new_user)
@@ -148,9 +149,10 @@ async fn test_oauth_user_email_verified_with_verification_enabled() {
         account_status: sea_orm::ActiveValue::Set(1),
         deleted_at: sea_orm::ActiveValue::Set(None),
         public_key: sea_orm::ActiveValue::Set(vec![]),
-        github_id: sea_orm::ActiveValue::Set(Some("12346".to_string())),
-        oauth_provider: sea_orm::ActiveValue::Set(Some("github".to_string())),
         email_verified: sea_orm::ActiveValue::Set(true), // OAuth users are always verified
+        totp_secret: sea_orm::ActiveValue::Set(None),
+        totp_enabled: sea_orm::ActiveValue::Set(false),
+        files_limit: sea_orm::ActiveValue::Set(None),
     };
 
     user::Entity::insert(new_user)
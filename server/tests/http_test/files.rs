@@ -0,0 +1,136 @@
+use client::TestApp;
+use http::{StatusCode, header};
+
+#[tokio::test]
+async fn download_file_over_http() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let content: Vec<u8> = (0..=255u8).cycle().take(10000).collect();
+    let key = user.lock().await.post_file(&content, None).await.unwrap();
+    let token = user.lock().await.token.clone();
+    let url = app.http_url(format!("v1/files/{key}"));
+
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/octet-stream"
+    );
+    let etag = res.headers()[header::ETAG].clone();
+    assert_eq!(res.bytes().await.unwrap().as_ref(), content.as_slice());
+
+    // browsers can't set the header for <video src>
+    let res = app
+        .http_client
+        .get(format!("{url}?access_token={token}"))
+        .header(header::RANGE, "bytes=100-199")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 100-199/10000");
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &content[100..200]);
+
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(&token)
+        .header(header::RANGE, "bytes=-10")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.bytes().await.unwrap().as_ref(), &content[9990..]);
+
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(&token)
+        .header(header::RANGE, "bytes=10000-")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */10000");
+
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(&token)
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // the range of a changed file is not sent
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(&token)
+        .header(header::RANGE, "bytes=0-9")
+        .header(header::IF_RANGE, "\"outdated\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn download_file_over_http_permission() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_users, session) = app
+        .new_session_db_level(2, "file_session", false)
+        .await
+        .unwrap();
+    let (owner, member) = (&session_users[0], &session_users[1]);
+    let outsider = app.new_user().await.unwrap();
+    let key = owner
+        .lock()
+        .await
+        .post_file(b"session file", Some(session.session_id))
+        .await
+        .unwrap();
+    let url = app.http_url(format!("v1/files/{key}"));
+
+    let res = app.http_client.get(&url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth("forged")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let token = outsider.lock().await.token.clone();
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let token = member.lock().await.token.clone();
+    let res = app
+        .http_client
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.bytes().await.unwrap().as_ref(), b"session file");
+    app.async_drop().await;
+}
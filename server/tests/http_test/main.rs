@@ -1,6 +1,8 @@
 mod avatar;
 mod email_verification;
+mod files;
 mod http;
 mod logo;
 mod oauth;
+mod password_reset;
 mod verify;
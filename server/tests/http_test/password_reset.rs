@@ -0,0 +1,95 @@
+use base::email_client::MockEmailSender;
+use client::TestApp;
+use parking_lot::Mutex;
+use pb::service::auth::password_reset::v1::{RequestPasswordResetRequest, ResetPasswordRequest};
+use server::process::error_msg::{NOT_STRONG_PASSWORD, PASSWORD_RESET_TOKEN_INVALID};
+use std::sync::Arc;
+use std::time::Duration;
+
+const NEW_PASSWORD: &str = "Tr0ub4dor&3-correct-horse";
+
+#[tokio::test]
+async fn test_password_reset() {
+    let mut mock_smtp = MockEmailSender::new();
+    let email_body = Arc::new(Mutex::new(String::new()));
+    let mock_body = email_body.clone();
+    mock_smtp
+        .expect_send()
+        .times(1)
+        .returning(move |_to, _title, body, _html_body| {
+            *mock_body.lock() = body;
+            anyhow::Ok(())
+        });
+    let (config, args) = TestApp::get_test_config().unwrap();
+    let mut app = TestApp::new_with_launching_instance_custom_cfg((config, args), |app| {
+        app.http_launcher.as_mut().unwrap().email_client = Some(Box::new(mock_smtp));
+    })
+    .await
+    .unwrap();
+
+    let user = app.new_user().await.unwrap();
+    let email = user.lock().await.email.clone();
+    let mut auth = user.lock().await.clients.auth.clone();
+    auth.request_password_reset(RequestPasswordResetRequest {
+        email: email.clone(),
+    })
+    .await
+    .unwrap();
+    // an unknown email gets the same response
+    auth.request_password_reset(RequestPasswordResetRequest {
+        email: "nobody@ourchat.org".to_string(),
+    })
+    .await
+    .unwrap();
+    // so does a request within the interval, which sends no email
+    auth.request_password_reset(RequestPasswordResetRequest { email })
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        if !email_body.lock().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let token = {
+        let body = email_body.lock();
+        body.split('"').nth(1).unwrap().to_string()
+    };
+
+    let err = auth
+        .reset_password(ResetPasswordRequest {
+            token: "wrong token".to_string(),
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), PASSWORD_RESET_TOKEN_INVALID);
+    // a weak password doesn't use the token up
+    let err = auth
+        .reset_password(ResetPasswordRequest {
+            token: token.clone(),
+            new_password: "123".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), NOT_STRONG_PASSWORD);
+    auth.reset_password(ResetPasswordRequest {
+        token: token.clone(),
+        new_password: NEW_PASSWORD.to_string(),
+    })
+    .await
+    .unwrap();
+    let err = auth
+        .reset_password(ResetPasswordRequest {
+            token,
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), PASSWORD_RESET_TOKEN_INVALID);
+
+    user.lock().await.password = NEW_PASSWORD.to_string();
+    user.lock().await.ocid_auth().await.unwrap();
+    app.async_drop().await;
+}
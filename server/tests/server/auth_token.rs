@@ -0,0 +1,117 @@
+use client::TestApp;
+use pb::service::auth::token::v1::{LogoutAllDevicesRequest, LogoutRequest, RefreshTokenRequest};
+use pb::service::ourchat::device::v1::ListDevicesRequest;
+use pb::service::ourchat::get_account_info::v1::{GetAccountInfoRequest, QueryValues};
+use server::process::error_msg::token::{REFRESH_TOKEN_INVALID, REVOKED};
+
+fn get_self_info() -> GetAccountInfoRequest {
+    GetAccountInfoRequest {
+        id: None,
+        request_values: vec![QueryValues::Ocid.into()],
+    }
+}
+
+#[tokio::test]
+async fn refresh_token_rotation() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let old_refresh_token = user.lock().await.refresh_token.clone();
+    user.lock().await.refresh().await.unwrap();
+    user.lock()
+        .await
+        .oc()
+        .get_account_info(get_self_info())
+        .await
+        .unwrap();
+
+    // a refresh token can only be used once, reusing it revokes the whole login
+    let err = user
+        .lock()
+        .await
+        .clients
+        .auth
+        .refresh_token(RefreshTokenRequest {
+            refresh_token: old_refresh_token,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), REFRESH_TOKEN_INVALID);
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .get_account_info(get_self_info())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), REVOKED);
+    let err = user.lock().await.refresh().await.unwrap_err();
+    assert_eq!(err.unwrap_rpc_status().message(), REFRESH_TOKEN_INVALID);
+
+    // the revoked login is no longer listed as a device
+    user.lock().await.ocid_auth().await.unwrap();
+    let devices = user
+        .lock()
+        .await
+        .oc()
+        .list_devices(ListDevicesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .devices;
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].current);
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn logout() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let (first_token, first_refresh_token) = {
+        let user = user.lock().await;
+        (user.token.clone(), user.refresh_token.clone())
+    };
+    // log in on another device
+    user.lock().await.ocid_auth().await.unwrap();
+    assert_ne!(user.lock().await.token, first_token);
+
+    user.lock()
+        .await
+        .clients
+        .auth
+        .logout(LogoutRequest {
+            refresh_token: first_refresh_token,
+        })
+        .await
+        .unwrap();
+    // the other login is not affected
+    user.lock()
+        .await
+        .oc()
+        .get_account_info(get_self_info())
+        .await
+        .unwrap();
+
+    let refresh_token = user.lock().await.refresh_token.clone();
+    user.lock()
+        .await
+        .clients
+        .auth
+        .logout_all_devices(LogoutAllDevicesRequest { refresh_token })
+        .await
+        .unwrap();
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .get_account_info(get_self_info())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), REVOKED);
+
+    user.lock().await.ocid_auth().await.unwrap();
+    app.async_drop().await;
+}
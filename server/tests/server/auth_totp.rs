@@ -0,0 +1,206 @@
+use client::TestApp;
+use pb::service::auth::authorize::v1::{AuthRequest, AuthResponse, auth_request};
+use pb::service::auth::totp::v1::{AuthTotpRequest, auth_totp_request};
+use pb::service::ourchat::totp::v1::{
+    ConfirmTotpRequest, DisableTotpRequest, EnableTotpRequest, disable_totp_request,
+};
+use server::db::user::get_account_info_db;
+use server::process::error_msg::{ACCOUNT_LOCKED, WRONG_TOTP_CODE};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+fn totp(secret: &str) -> TOTP {
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap()
+}
+
+/// A code which is accepted but differs from the current one, since a code can be used only once
+fn next_code(totp: &TOTP) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp.generate(now + 30)
+}
+
+async fn password_auth(user: &client::oc_helper::user::TestUser) -> AuthResponse {
+    user.clients
+        .auth
+        .clone()
+        .auth(AuthRequest {
+            account: Some(auth_request::Account::Ocid(user.ocid.0.clone())),
+            password: user.password.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+/// Turn TOTP on for the user, returning the authenticator and the recovery codes
+async fn enroll(user: &mut client::oc_helper::user::TestUser) -> (TOTP, Vec<String>) {
+    let enroll = user
+        .oc()
+        .enable_totp(EnableTotpRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(enroll.otpauth_url.starts_with("otpauth://totp/"));
+    let totp = totp(&enroll.secret);
+    let recovery_codes = user
+        .oc()
+        .confirm_totp(ConfirmTotpRequest {
+            code: totp.generate_current().unwrap(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .recovery_codes;
+    (totp, recovery_codes)
+}
+
+#[tokio::test]
+async fn totp_login() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let mut user = user.lock().await;
+
+    user.oc().enable_totp(EnableTotpRequest {}).await.unwrap();
+    let err = user
+        .oc()
+        .confirm_totp(ConfirmTotpRequest {
+            code: "000000".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), WRONG_TOTP_CODE);
+    let (totp, recovery_codes) = enroll(&mut user).await;
+    assert_eq!(recovery_codes.len(), 10);
+    // only the encrypted secret is stored
+    let stored = get_account_info_db(user.id, app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap()
+        .totp_secret
+        .unwrap();
+    assert!(!stored.contains(&Secret::Raw(totp.secret.clone()).to_encoded().to_string()));
+
+    // the password only gives a challenge now
+    let ret = password_auth(&user).await;
+    assert!(ret.token.is_empty());
+    let challenge = ret.totp_challenge.unwrap();
+    let auth_totp = |code| AuthTotpRequest {
+        challenge: challenge.clone(),
+        code: Some(code),
+    };
+    let err = user
+        .clients
+        .auth
+        .auth_totp(auth_totp(auth_totp_request::Code::TotpCode(
+            "000000".to_string(),
+        )))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), WRONG_TOTP_CODE);
+    let code = next_code(&totp);
+    let ret = user
+        .clients
+        .auth
+        .auth_totp(auth_totp(auth_totp_request::Code::TotpCode(code.clone())))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!ret.token.is_empty());
+    assert!(ret.totp_challenge.is_none());
+
+    // neither the code nor a recovery code can be used twice
+    let challenge = password_auth(&user).await.totp_challenge.unwrap();
+    let err = user
+        .clients
+        .auth
+        .auth_totp(AuthTotpRequest {
+            challenge: challenge.clone(),
+            code: Some(auth_totp_request::Code::TotpCode(code)),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), WRONG_TOTP_CODE);
+    let recovery = auth_totp_request::Code::RecoveryCode(recovery_codes[0].clone());
+    user.clients
+        .auth
+        .auth_totp(AuthTotpRequest {
+            challenge: challenge.clone(),
+            code: Some(recovery.clone()),
+        })
+        .await
+        .unwrap();
+    let challenge = password_auth(&user).await.totp_challenge.unwrap();
+    let err = user
+        .clients
+        .auth
+        .auth_totp(AuthTotpRequest {
+            challenge,
+            code: Some(recovery),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), WRONG_TOTP_CODE);
+
+    user.oc()
+        .disable_totp(DisableTotpRequest {
+            code: Some(disable_totp_request::Code::RecoveryCode(
+                recovery_codes[1].clone(),
+            )),
+        })
+        .await
+        .unwrap();
+    let ret = password_auth(&user).await;
+    assert!(!ret.token.is_empty());
+    assert!(ret.totp_challenge.is_none());
+    drop(user);
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn totp_failures_lock_account() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let mut user = user.lock().await;
+    let (totp, _) = enroll(&mut user).await;
+
+    let challenge = password_auth(&user).await.totp_challenge.unwrap();
+    // Default config: 5 failed attempts allowed
+    for _ in 1..=5 {
+        let err = user
+            .clients
+            .auth
+            .auth_totp(AuthTotpRequest {
+                challenge: challenge.clone(),
+                code: Some(auth_totp_request::Code::TotpCode("000000".to_string())),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.message(), WRONG_TOTP_CODE);
+    }
+    let err = user
+        .clients
+        .auth
+        .auth_totp(AuthTotpRequest {
+            challenge,
+            code: Some(auth_totp_request::Code::TotpCode(next_code(&totp))),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), ACCOUNT_LOCKED);
+    drop(user);
+    app.async_drop().await;
+}
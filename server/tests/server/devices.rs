@@ -0,0 +1,379 @@
+use base::constants::DEVICE_NAME_HEADER;
+use client::TestApp;
+use client::oc_helper::user::TestUserShared;
+use pb::service::auth::authorize::v1::{AuthRequest, auth_request};
+use pb::service::auth::token::v1::RefreshTokenRequest;
+use pb::service::ourchat::device::v1::{
+    FetchKeyBundlesRequest, GetKeyFingerprintRequest, GetKeyFingerprintResponse,
+    ListDevicesRequest, OneTimePrekey, ReplenishPrekeysRequest, RevokeDeviceRequest, SignedPrekey,
+    UploadKeyBundleRequest,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::service::ourchat::session::session_room_key::v1::SendRoomKeyRequest;
+use pb::service::ourchat::set_account_info::v1::SetSelfInfoRequest;
+use pb::time::TimeStampUtc;
+use rsa::{RsaPrivateKey, RsaPublicKey, pkcs1::EncodeRsaPublicKey};
+use server::process::error_msg::token::REFRESH_TOKEN_INVALID;
+use server::process::error_msg::{PERMISSION_DENIED, invalid, not_found};
+
+#[tokio::test]
+async fn list_and_revoke_devices() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let (ocid, password, mut auth_client) = {
+        let user = user.lock().await;
+        (
+            user.ocid.0.clone(),
+            user.password.clone(),
+            user.clients.auth.clone(),
+        )
+    };
+
+    // log in on another device
+    let mut req = tonic::Request::new(AuthRequest {
+        account: Some(auth_request::Account::Ocid(ocid)),
+        password,
+    });
+    req.metadata_mut()
+        .insert(DEVICE_NAME_HEADER, "Test Phone".parse().unwrap());
+    let phone = auth_client.auth(req).await.unwrap().into_inner();
+
+    let devices = user
+        .lock()
+        .await
+        .oc()
+        .list_devices(ListDevicesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .devices;
+    assert_eq!(devices.len(), 2);
+    let current: Vec<_> = devices.iter().filter(|device| device.current).collect();
+    assert_eq!(current.len(), 1);
+    let phone_device = devices
+        .iter()
+        .find(|device| device.device_name == "Test Phone")
+        .unwrap()
+        .clone();
+    assert!(!phone_device.current);
+    assert!(phone_device.ip.is_some());
+
+    // the user is told about the new login
+    let msgs = user.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    let RespondEventType::NewLogin(ref notification) = msgs[0].clone().respond_event_type.unwrap()
+    else {
+        panic!("expected a new login notification, got {:?}", msgs[0]);
+    };
+    assert_eq!(notification.device_id, phone_device.device_id);
+    assert_eq!(notification.device_name, "Test Phone");
+
+    user.lock()
+        .await
+        .oc()
+        .revoke_device(RevokeDeviceRequest {
+            device_id: phone_device.device_id.clone(),
+        })
+        .await
+        .unwrap();
+    let err = auth_client
+        .refresh_token(RefreshTokenRequest {
+            refresh_token: phone.refresh_token,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), REFRESH_TOKEN_INVALID);
+    let devices = user
+        .lock()
+        .await
+        .oc()
+        .list_devices(ListDevicesRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .devices;
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].current);
+
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .revoke_device(RevokeDeviceRequest {
+            device_id: phone_device.device_id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::DEVICE);
+
+    app.async_drop().await;
+}
+
+fn one_time_prekeys(ids: std::ops::Range<u64>) -> Vec<OneTimePrekey> {
+    ids.map(|key_id| OneTimePrekey {
+        key_id,
+        public_key: vec![key_id as u8; 32].into(),
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn key_bundles() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app.new_session_db_level(2, "session1", true).await.unwrap();
+    let a = session_user[0].clone();
+    let b = session_user[1].clone();
+    let stranger = app.new_user().await.unwrap();
+    let (aid, bid) = (a.lock().await.id, b.lock().await.id);
+
+    let res = a
+        .lock()
+        .await
+        .oc()
+        .upload_key_bundle(UploadKeyBundleRequest {
+            identity_key: vec![1; 32].into(),
+            signed_prekey: Some(SignedPrekey {
+                key_id: 1,
+                public_key: vec![2; 32].into(),
+                signature: vec![3; 64].into(),
+            }),
+            one_time_prekeys: one_time_prekeys(0..2),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(res.one_time_prekeys_count, 2);
+
+    // each fetch takes one of the one-time prekeys
+    for key_id in 0..3 {
+        let bundles = b
+            .lock()
+            .await
+            .oc()
+            .fetch_key_bundles(FetchKeyBundlesRequest { user_id: aid.0 })
+            .await
+            .unwrap()
+            .into_inner()
+            .bundles;
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].identity_key, vec![1; 32]);
+        assert_eq!(bundles[0].signed_prekey.as_ref().unwrap().key_id, 1);
+        assert_eq!(
+            bundles[0]
+                .one_time_prekey
+                .as_ref()
+                .map(|prekey| prekey.key_id),
+            (key_id < 2).then_some(key_id)
+        );
+    }
+    // the identity key of the device is announced to the session, then a has run out of
+    // one-time prekeys
+    let msgs = a.lock().await.fetch_msgs().fetch(2).await.unwrap();
+    let RespondEventType::PublicKeyChanged(changed) = msgs[0].respond_event_type.clone().unwrap()
+    else {
+        panic!(
+            "expected a public key changed notification, got {:?}",
+            msgs[0]
+        );
+    };
+    let RespondEventType::PrekeysLow(notification) = msgs[1].respond_event_type.clone().unwrap()
+    else {
+        panic!("expected a prekeys low notification, got {:?}", msgs[1]);
+    };
+    assert_eq!(notification.one_time_prekeys_count, 0);
+    assert_eq!(changed.user_id, *aid);
+    assert_eq!(changed.public_key, vec![1; 32]);
+    assert_eq!(changed.device_id, Some(notification.device_id.clone()));
+
+    let res = a
+        .lock()
+        .await
+        .oc()
+        .replenish_prekeys(ReplenishPrekeysRequest {
+            one_time_prekeys: one_time_prekeys(2..12),
+            signed_prekey: None,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(res.one_time_prekeys_count, 10);
+    // the same id twice in one request is rejected
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .replenish_prekeys(ReplenishPrekeysRequest {
+            one_time_prekeys: [one_time_prekeys(12..14), one_time_prekeys(13..14)].concat(),
+            signed_prekey: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), invalid::KEY_BUNDLE);
+
+    // only who shares a session with a may take its prekeys
+    let err = stranger
+        .lock()
+        .await
+        .oc()
+        .fetch_key_bundles(FetchKeyBundlesRequest { user_id: aid.0 })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    assert_eq!(err.message(), PERMISSION_DENIED);
+
+    // room keys go to a device of the user
+    let err = b
+        .lock()
+        .await
+        .oc()
+        .send_room_key(SendRoomKeyRequest {
+            session_id: session.session_id.into(),
+            user_id: aid.into(),
+            room_key: vec![4; 32].into(),
+            device_id: Some("not a device".to_string()),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    b.lock()
+        .await
+        .oc()
+        .send_room_key(SendRoomKeyRequest {
+            session_id: session.session_id.into(),
+            user_id: aid.into(),
+            room_key: vec![4; 32].into(),
+            device_id: Some(notification.device_id.clone()),
+        })
+        .await
+        .unwrap();
+    let msgs = a.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    let RespondEventType::ReceiveRoomKey(room_key) = msgs[0].respond_event_type.clone().unwrap()
+    else {
+        panic!("expected a room key, got {:?}", msgs[0]);
+    };
+    assert_eq!(room_key.device_id, Some(notification.device_id));
+    assert_eq!(room_key.user_id, *bid);
+
+    app.async_drop().await;
+}
+
+async fn get_fingerprint(
+    user: &TestUserShared,
+    user_id: u64,
+) -> Result<GetKeyFingerprintResponse, tonic::Status> {
+    user.lock()
+        .await
+        .oc()
+        .get_key_fingerprint(GetKeyFingerprintRequest { user_id })
+        .await
+        .map(|res| res.into_inner())
+}
+
+#[tokio::test]
+async fn key_change_and_safety_number() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app.new_session_db_level(2, "session1", true).await.unwrap();
+    let a = session_user[0].clone();
+    let b = session_user[1].clone();
+    let (aid, bid) = (a.lock().await.id, b.lock().await.id);
+
+    let a_side = get_fingerprint(&a, bid.0).await.unwrap();
+    let b_side = get_fingerprint(&b, aid.0).await.unwrap();
+    assert_eq!(a_side.safety_number.len(), 60);
+    assert_eq!(a_side.safety_number, b_side.safety_number);
+    assert_eq!(a_side.fingerprint, b_side.peer_fingerprint);
+    assert_eq!(a_side.peer_fingerprint, b_side.fingerprint);
+
+    // a moves to a new key
+    let private_key = RsaPrivateKey::new(&mut rand::rng(), 2048).unwrap();
+    let public_key = RsaPublicKey::from(&private_key)
+        .to_pkcs1_der()
+        .unwrap()
+        .as_bytes()
+        .to_vec();
+    a.lock()
+        .await
+        .oc()
+        .set_self_info(SetSelfInfoRequest {
+            public_key: Some(public_key.clone().into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let msgs = b.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    let RespondEventType::PublicKeyChanged(notification) =
+        msgs[0].respond_event_type.clone().unwrap()
+    else {
+        panic!(
+            "expected a public key changed notification, got {:?}",
+            msgs[0]
+        );
+    };
+    assert_eq!(notification.session_id, *session.session_id);
+    assert_eq!(notification.user_id, *aid);
+    assert_eq!(notification.public_key, public_key);
+
+    let new_b_side = get_fingerprint(&b, aid.0).await.unwrap();
+    assert_ne!(new_b_side.safety_number, b_side.safety_number);
+    assert_eq!(new_b_side.fingerprint, b_side.fingerprint);
+    let changed_time: TimeStampUtc = new_b_side
+        .peer_key_changed_time
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let registered_time: TimeStampUtc = b_side.peer_key_changed_time.unwrap().try_into().unwrap();
+    assert!(changed_time > registered_time);
+    let new_a_side = get_fingerprint(&a, bid.0).await.unwrap();
+    assert_eq!(new_a_side.safety_number, new_b_side.safety_number);
+
+    // the room keys are sent to the devices as well, so a new device key changes the number
+    b.lock()
+        .await
+        .oc()
+        .upload_key_bundle(UploadKeyBundleRequest {
+            identity_key: vec![7; 32].into(),
+            signed_prekey: Some(SignedPrekey {
+                key_id: 1,
+                public_key: vec![8; 32].into(),
+                signature: vec![9; 64].into(),
+            }),
+            one_time_prekeys: one_time_prekeys(0..2),
+        })
+        .await
+        .unwrap();
+    let device_a_side = get_fingerprint(&a, bid.0).await.unwrap();
+    assert_ne!(device_a_side.safety_number, new_a_side.safety_number);
+    assert_ne!(device_a_side.peer_fingerprint, new_a_side.peer_fingerprint);
+    assert_eq!(device_a_side.fingerprint, new_a_side.fingerprint);
+    let device_b_side = get_fingerprint(&b, aid.0).await.unwrap();
+    assert_eq!(device_b_side.safety_number, device_a_side.safety_number);
+    let device_changed_time: TimeStampUtc = device_a_side
+        .peer_key_changed_time
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let registered_time: TimeStampUtc = new_a_side
+        .peer_key_changed_time
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert!(device_changed_time > registered_time);
+
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .set_self_info(SetSelfInfoRequest {
+            public_key: Some(vec![1; 64].into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), invalid::PUBLIC_KEY);
+    let err = get_fingerprint(&a, u64::MAX).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+
+    app.async_drop().await;
+}
@@ -3,6 +3,7 @@ use claims::assert_err;
 use client::TestApp;
 use client::helper::{generate_file, get_hash_from_download, get_hash_from_file};
 use client::oc_helper::user::TestUserShared;
+use sea_orm::EntityTrait;
 use size::Size;
 use tokio::fs;
 
@@ -486,6 +487,7 @@ async fn upload_chunked_cancel() {
             size,
             auto_clean: true,
             session_id: None,
+            content_type: None,
         })
         .await
         .unwrap()
@@ -531,3 +533,344 @@ async fn upload_chunked_cancel() {
 
     app.async_drop().await;
 }
+
+/// Upload `content` in one stream with the MIME type
+async fn post_file_with_content_type(
+    user: &TestUserShared,
+    content: &[u8],
+    content_type: &str,
+) -> Result<String, tonic::Status> {
+    use pb::service::ourchat::upload::v1::{Header, UploadRequest, upload_request::Data};
+    use sha3::{Digest, Sha3_256};
+
+    let header = UploadRequest {
+        data: Some(Data::Metadata(Header {
+            hash: Bytes::copy_from_slice(&Sha3_256::digest(content)),
+            size: content.len() as u64,
+            auto_clean: false,
+            session_id: None,
+            content_type: Some(content_type.to_string()),
+        })),
+    };
+    let content = UploadRequest::new_content(Bytes::copy_from_slice(content));
+    let key = user
+        .lock()
+        .await
+        .oc()
+        .upload(tokio_stream::iter([header, content]))
+        .await?
+        .into_inner()
+        .key;
+    Ok(key)
+}
+
+#[tokio::test]
+async fn download_range() {
+    use sha3::{Digest, Sha3_256};
+
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let content: Vec<u8> = generate_file(Size::from_kibibytes(100))
+        .unwrap()
+        .flatten()
+        .collect();
+    let size = content.len() as u64;
+    let key = post_file_with_content_type(&user, &content, "video/mp4")
+        .await
+        .unwrap();
+
+    let (header, data) = user
+        .lock()
+        .await
+        .download_file_range(&key, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(header.size, size);
+    assert_eq!(header.hash.as_ref(), Sha3_256::digest(&content).as_slice());
+    assert_eq!(header.content_type, "video/mp4");
+    assert_eq!(data, content);
+
+    // resume from the middle
+    let (header, data) = user
+        .lock()
+        .await
+        .download_file_range(&key, 1000, Some(5000))
+        .await
+        .unwrap();
+    assert_eq!(header.size, size);
+    assert_eq!(data, &content[1000..6000]);
+    let (_, data) = user
+        .lock()
+        .await
+        .download_file_range(&key, size - 10, Some(5000))
+        .await
+        .unwrap();
+    assert_eq!(data, &content[content.len() - 10..]);
+    let (_, data) = user
+        .lock()
+        .await
+        .download_file_range(&key, size, None)
+        .await
+        .unwrap();
+    assert!(data.is_empty());
+    let err = user
+        .lock()
+        .await
+        .download_file_range(&key, size + 1, None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::OutOfRange);
+
+    // the files uploaded without a type
+    let key = user.lock().await.post_file(&content, None).await.unwrap();
+    let (header, _) = user
+        .lock()
+        .await
+        .download_file_range(&key, 0, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(header.content_type, "application/octet-stream");
+
+    let err = post_file_with_content_type(&user, &content, "not a type\n")
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    app.async_drop().await;
+}
+
+fn encode_image(image: &image::DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+    let mut data = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut data), format)
+        .unwrap();
+    data
+}
+
+#[tokio::test]
+async fn image_thumbnails() {
+    use pb::service::ourchat::file_info::v1::GetFileInfoRequest;
+
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let other = app.new_user().await.unwrap();
+    let photo = encode_image(
+        &image::DynamicImage::new_rgb8(1200, 800),
+        image::ImageFormat::Jpeg,
+    );
+    // told as a generic type, detected from the content
+    let key = post_file_with_content_type(&user, &photo, "application/octet-stream")
+        .await
+        .unwrap();
+
+    let info = user
+        .lock()
+        .await
+        .oc()
+        .get_file_info(GetFileInfoRequest { key: key.clone() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.key, key);
+    assert_eq!(info.size, photo.len() as u64);
+    assert_eq!(info.content_type, "image/jpeg");
+    assert_eq!((info.width, info.height), (Some(1200), Some(800)));
+    let thumbnails: Vec<_> = info
+        .thumbnails
+        .iter()
+        .map(|t| (t.max_side, t.width, t.height, t.content_type.as_str()))
+        .collect();
+    assert_eq!(
+        thumbnails,
+        vec![(128, 128, 85, "image/jpeg"), (512, 512, 341, "image/jpeg")]
+    );
+
+    let (header, data) = user
+        .lock()
+        .await
+        .download_thumbnail(&key, 100)
+        .await
+        .unwrap();
+    assert_eq!((header.width, header.height), (Some(128), Some(85)));
+    assert_eq!(header.size, data.len() as u64);
+    assert!(header.hash.is_empty());
+    let thumbnail = image::load_from_memory(&data).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (128, 85));
+    // larger than every thumbnail
+    let (header, _) = user
+        .lock()
+        .await
+        .download_thumbnail(&key, 1000)
+        .await
+        .unwrap();
+    assert_eq!((header.width, header.height), (Some(512), Some(341)));
+
+    let err = other
+        .lock()
+        .await
+        .oc()
+        .get_file_info(GetFileInfoRequest { key: key.clone() })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+    // a small image with transparency is its own thumbnail
+    let icon = encode_image(
+        &image::DynamicImage::new_rgba8(64, 64),
+        image::ImageFormat::Png,
+    );
+    let icon_key = user
+        .lock()
+        .await
+        .post_file_chunked(&icon, None)
+        .await
+        .unwrap();
+    let (header, data) = user
+        .lock()
+        .await
+        .download_thumbnail(&icon_key, 128)
+        .await
+        .unwrap();
+    assert_eq!(header.content_type, "image/png");
+    assert_eq!((header.width, header.height), (Some(64), Some(64)));
+    assert_eq!(data, icon);
+
+    // the files which aren't images
+    let text_key = user.lock().await.post_file(b"hello", None).await.unwrap();
+    let info = user
+        .lock()
+        .await
+        .oc()
+        .get_file_info(GetFileInfoRequest { key: text_key })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.width, None);
+    assert!(info.thumbnails.is_empty());
+
+    // the thumbnails are deleted with the file
+    let file = entities::prelude::Files::find_by_id(&key)
+        .one(app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap();
+    let thumbnail_path = app
+        .app_shared
+        .cfg()
+        .main_cfg
+        .files_storage_path
+        .join(format!("{}.128", file.path));
+    assert!(thumbnail_path.exists());
+    user.lock().await.delete_file(&key).await.unwrap();
+    assert!(!thumbnail_path.exists());
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn deduplicated_uploads() {
+    use entities::prelude::{FileBlobs, Files};
+    use pb::service::ourchat::upload::v1::StartUploadRequest;
+    use sha3::{Digest, Sha3_256};
+
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let other = app.new_user().await.unwrap();
+    let content: Vec<u8> = generate_file(Size::from_kibibytes(100))
+        .unwrap()
+        .flatten()
+        .collect();
+    let hash = Sha3_256::digest(&content);
+    let hash_hex = format!("{hash:x}");
+    let start_request = StartUploadRequest {
+        hash: Bytes::copy_from_slice(&hash),
+        size: content.len() as u64,
+        auto_clean: true,
+        session_id: None,
+        content_type: None,
+    };
+    let ref_count = async || {
+        FileBlobs::find_by_id(&hash_hex)
+            .one(app.get_db_connection())
+            .await
+            .unwrap()
+            .map(|blob| blob.ref_count)
+    };
+
+    let key = user.lock().await.post_file(&content, None).await.unwrap();
+    let file = Files::find_by_id(&key)
+        .one(app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap();
+    let object_path = app
+        .app_shared
+        .cfg()
+        .main_cfg
+        .files_storage_path
+        .join(&file.path);
+    assert!(object_path.exists());
+    assert_eq!(ref_count().await, Some(1));
+
+    // knowing the hash isn't enough to get the file without uploading it
+    let response = other
+        .lock()
+        .await
+        .oc()
+        .start_upload(start_request.clone())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.key, None);
+    other
+        .lock()
+        .await
+        .cancel_upload(response.upload_id)
+        .await
+        .unwrap();
+    // but uploading it shares the stored content
+    let other_key = other.lock().await.post_file(&content, None).await.unwrap();
+    let other_file = Files::find_by_id(&other_key)
+        .one(app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(other_file.path, file.path);
+    assert_eq!(ref_count().await, Some(2));
+
+    // the owner uploads it again instantly
+    let response = user
+        .lock()
+        .await
+        .oc()
+        .start_upload(start_request)
+        .await
+        .unwrap()
+        .into_inner();
+    let instant_key = response.key.unwrap();
+    assert_ne!(instant_key, key);
+    assert_eq!(ref_count().await, Some(3));
+    let (_, data) = user
+        .lock()
+        .await
+        .download_file_range(&instant_key, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(data, content);
+
+    // the content is kept until the last file goes away
+    user.lock().await.delete_file(&key).await.unwrap();
+    user.lock().await.delete_file(&instant_key).await.unwrap();
+    assert_eq!(ref_count().await, Some(1));
+    assert!(object_path.exists());
+    let (_, data) = other
+        .lock()
+        .await
+        .download_file_range(&other_key, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(data, content);
+    other.lock().await.delete_file(&other_key).await.unwrap();
+    assert_eq!(ref_count().await, None);
+    assert!(!object_path.exists());
+
+    app.async_drop().await;
+}
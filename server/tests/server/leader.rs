@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use client::TestApp;
+use entities::{leader_lease, prelude::*};
+use sea_orm::{ActiveValue, EntityTrait};
+use server::SharedData;
+use server::db::leader_lease::get_leader;
+
+const LEASE: Duration = Duration::from_secs(3);
+
+async fn launch_app() -> TestApp {
+    let (mut config, args) = TestApp::get_test_config().unwrap();
+    config.main_cfg.leader_lease_duration = LEASE;
+    TestApp::new_with_launching_instance_custom_cfg((config, args), |_| {})
+        .await
+        .unwrap()
+}
+
+/// Wait until one of the nodes is the leader, returning its index
+async fn wait_for_leader(nodes: &[&Arc<SharedData>]) -> usize {
+    for _ in 0..100 {
+        if let Some(leader) = nodes.iter().position(|node| node.leader.is_leader()) {
+            return leader;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no node became the leader");
+}
+
+#[tokio::test]
+async fn leader_failover() {
+    let mut app = launch_app().await;
+    let (second, mut second_handle) = app.launch_another_instance().await.unwrap();
+    let first = app.app_shared.clone();
+
+    let leader = wait_for_leader(&[&first, &second]).await;
+    // Give the other node time to campaign, it must not take the lead too
+    tokio::time::sleep(LEASE / 2).await;
+    assert_eq!(wait_for_leader(&[&first, &second]).await, leader);
+    assert!(![&first, &second][1 - leader].leader.is_leader());
+    let lease = get_leader(app.get_db_connection()).await.unwrap().unwrap();
+    assert_eq!(lease.node_id, [&first, &second][leader].leader.node_id());
+
+    // Kill the leader, the other node takes over
+    if leader == 0 {
+        app.shutdown_handle().shutdown_all_tasks().await.unwrap();
+        assert_eq!(wait_for_leader(&[&second]).await, 0);
+        let lease = get_leader(app.get_db_connection()).await.unwrap().unwrap();
+        assert_eq!(lease.node_id, second.leader.node_id());
+        second_handle.shutdown_all_tasks().await.unwrap();
+    } else {
+        second_handle.shutdown_all_tasks().await.unwrap();
+        assert_eq!(wait_for_leader(&[&first]).await, 0);
+        let lease = get_leader(app.get_db_connection()).await.unwrap().unwrap();
+        assert_eq!(lease.node_id, first.leader.node_id());
+    }
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn expired_lease_taken_over() {
+    let mut app = launch_app().await;
+    let (second, mut second_handle) = app.launch_another_instance().await.unwrap();
+    let first = app.app_shared.clone();
+    wait_for_leader(&[&first, &second]).await;
+
+    // A leader which has died without giving the lease up
+    let now = chrono::Utc::now();
+    LeaderLease::update(leader_lease::ActiveModel {
+        id: ActiveValue::Unchanged(1),
+        node_id: ActiveValue::Set("dead node".to_string()),
+        acquired_time: ActiveValue::Set(now.into()),
+        expire_time: ActiveValue::Set((now + LEASE).into()),
+    })
+    .exec(app.get_db_connection())
+    .await
+    .unwrap();
+    // The nodes notice they have lost the lead at their next renewal
+    tokio::time::sleep(LEASE / 2).await;
+    assert!(!first.leader.is_leader());
+    assert!(!second.leader.is_leader());
+
+    // One of them takes over once the lease has expired
+    wait_for_leader(&[&first, &second]).await;
+    let lease = get_leader(app.get_db_connection()).await.unwrap().unwrap();
+    assert_ne!(lease.node_id, "dead node");
+
+    second_handle.shutdown_all_tasks().await.unwrap();
+    app.async_drop().await;
+}
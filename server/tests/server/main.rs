@@ -1,15 +1,33 @@
 mod auth_register;
+mod auth_token;
+mod auth_totp;
 mod basic;
 mod basic_services;
 mod chores;
+mod devices;
 mod files;
 mod friend;
+mod leader;
 mod log;
+mod msg_edit;
+mod msg_history;
+mod msg_pin;
+mod msg_reaction;
+mod msg_read;
 mod msg_recall;
+mod msg_scheduled;
+mod msg_search;
 mod msg_send;
+mod msg_thread;
+mod msg_ttl;
+mod msg_typing;
 mod oauth;
+mod oidc;
+mod password;
+mod rate_limit;
 mod server_manage;
 mod session;
+mod storage;
 mod tls;
 mod user_info;
 mod voip;
@@ -0,0 +1,110 @@
+use client::TestApp;
+use entities::{message_revisions, prelude::MessageRevisions};
+use pb::service::ourchat::msg_delivery::edit::v1::EditMsgRequest;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use server::process::error_msg::{ENCRYPTION_STATE_MISMATCH, PERMISSION_DENIED};
+
+#[tokio::test]
+async fn test_edit() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(3, "session1", false)
+        .await
+        .unwrap();
+    let (a, b, c) = (
+        session_user[0].clone(),
+        session_user[1].clone(),
+        session_user[2].clone(),
+    );
+    let msg_id = a
+        .lock()
+        .await
+        .send_msg(session.session_id, "helo", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    // consume the original message
+    b.lock().await.fetch_msgs().fetch(1).await.unwrap();
+
+    // only the sender can edit the message
+    let err = c
+        .lock()
+        .await
+        .oc()
+        .edit_msg(EditMsgRequest {
+            msg_id,
+            session_id: session.session_id.into(),
+            markdown_text: "hijacked".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    assert_eq!(err.message(), PERMISSION_DENIED);
+
+    // the encryption state cannot be changed
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .edit_msg(EditMsgRequest {
+            msg_id,
+            session_id: session.session_id.into(),
+            markdown_text: "hello".to_string(),
+            involved_files: vec![],
+            is_encrypted: true,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), ENCRYPTION_STATE_MISMATCH);
+
+    let edit_msg_id = a
+        .lock()
+        .await
+        .oc()
+        .edit_msg(EditMsgRequest {
+            msg_id,
+            session_id: session.session_id.into(),
+            markdown_text: "hello".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+
+    // online members receive the notification
+    let b_rec = b.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    assert_eq!(b_rec[0].msg_id, edit_msg_id);
+    let RespondEventType::Edit(data) = b_rec[0].clone().respond_event_type.unwrap() else {
+        panic!("not an edit notification")
+    };
+    assert_eq!(data.msg_id, msg_id);
+    assert_eq!(data.markdown_text, "hello");
+    assert_eq!(data.revision, 1);
+
+    // the original message is rewritten and the old content is kept as a revision
+    let c_rec = c.lock().await.fetch_msgs().fetch(2).await.unwrap();
+    let RespondEventType::Msg(ref content) = c_rec[0].clone().respond_event_type.unwrap() else {
+        panic!("not a msg")
+    };
+    assert_eq!(c_rec[0].msg_id, msg_id);
+    assert_eq!(content.markdown_text, "hello");
+    let revisions = MessageRevisions::find()
+        .filter(message_revisions::Column::MsgId.eq(msg_id as i64))
+        .all(app.get_db_connection())
+        .await
+        .unwrap();
+    assert_eq!(revisions.len(), 1);
+    let RespondEventType::Msg(old) = serde_json::from_value(revisions[0].msg_data.clone()).unwrap()
+    else {
+        panic!("not a msg")
+    };
+    assert_eq!(old.markdown_text, "helo");
+    app.async_drop().await;
+}
@@ -0,0 +1,110 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::history::v1::{
+    GetSessionHistoryRequest, HistoryDirection, get_session_history_request::Cursor,
+};
+use server::process::error_msg::not_found;
+
+#[tokio::test]
+async fn test_session_history() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (other_user, _) = app
+        .new_session_db_level(1, "session2", false)
+        .await
+        .unwrap();
+    let a = session_user[0].clone();
+    let mut msg_ids = vec![];
+    for i in 0..5 {
+        let msg_id = a
+            .lock()
+            .await
+            .send_msg(session.session_id, format!("msg{i}"), vec![], false)
+            .await
+            .unwrap()
+            .into_inner()
+            .msg_id;
+        msg_ids.push(msg_id);
+    }
+    let request = |cursor, direction: HistoryDirection, limit| GetSessionHistoryRequest {
+        session_id: session.session_id.into(),
+        cursor,
+        direction: direction.into(),
+        limit,
+    };
+
+    // the latest page
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .get_session_history(request(None, HistoryDirection::Before, 2))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.has_more);
+    let ids: Vec<_> = ret.msgs.iter().map(|msg| msg.msg_id).collect();
+    assert_eq!(ids, msg_ids[3..]);
+
+    // the page before it
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .get_session_history(request(
+            Some(Cursor::MsgId(msg_ids[3])),
+            HistoryDirection::Before,
+            10,
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!ret.has_more);
+    let ids: Vec<_> = ret.msgs.iter().map(|msg| msg.msg_id).collect();
+    assert_eq!(ids, msg_ids[..3]);
+
+    // read forward
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .get_session_history(request(
+            Some(Cursor::MsgId(msg_ids[1])),
+            HistoryDirection::After,
+            2,
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.has_more);
+    let ids: Vec<_> = ret.msgs.iter().map(|msg| msg.msg_id).collect();
+    assert_eq!(ids, msg_ids[2..4]);
+
+    // the cursor must be a message of the session
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .get_session_history(request(
+            Some(Cursor::MsgId(u64::MAX >> 1)),
+            HistoryDirection::Before,
+            0,
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::MSG);
+
+    // non-members cannot read the history
+    let err = other_user[0]
+        .lock()
+        .await
+        .oc()
+        .get_session_history(request(None, HistoryDirection::Before, 0))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    app.async_drop().await;
+}
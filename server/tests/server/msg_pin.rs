@@ -0,0 +1,84 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::pin::v1::{
+    ListPinnedMsgsRequest, PinMsgRequest, UnpinMsgRequest,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use server::process::error_msg::{PERMISSION_DENIED, exist, not_found};
+
+#[tokio::test]
+async fn test_pin() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    // the first user is the owner
+    let (owner, member) = (session_user[0].clone(), session_user[1].clone());
+    let msg_id = member
+        .lock()
+        .await
+        .send_msg(session.session_id, "important", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    let pin = PinMsgRequest {
+        session_id: session.session_id.into(),
+        msg_id,
+    };
+
+    // members don't have the permission by default
+    let err = member.lock().await.oc().pin_msg(pin).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    assert_eq!(err.message(), PERMISSION_DENIED);
+
+    owner.lock().await.oc().pin_msg(pin).await.unwrap();
+    let err = owner.lock().await.oc().pin_msg(pin).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::AlreadyExists);
+    assert_eq!(err.message(), exist::PINNED_MSG);
+
+    let member_rec = member.lock().await.fetch_msgs().fetch(2).await.unwrap();
+    let RespondEventType::Pin(ref data) = member_rec[1].clone().respond_event_type.unwrap() else {
+        panic!("not a pin notification")
+    };
+    assert_eq!(data.msg_id, msg_id);
+    assert!(data.pinned);
+
+    let ret = member
+        .lock()
+        .await
+        .oc()
+        .list_pinned_msgs(ListPinnedMsgsRequest {
+            session_id: session.session_id.into(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(ret.pinned_msgs.len(), 1);
+    assert_eq!(ret.pinned_msgs[0].msg.as_ref().unwrap().msg_id, msg_id);
+    assert_eq!(
+        ret.pinned_msgs[0].pinned_by,
+        u64::from(owner.lock().await.id)
+    );
+
+    let unpin = UnpinMsgRequest {
+        session_id: session.session_id.into(),
+        msg_id,
+    };
+    owner.lock().await.oc().unpin_msg(unpin).await.unwrap();
+    let err = owner.lock().await.oc().unpin_msg(unpin).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::PINNED_MSG);
+    let ret = member
+        .lock()
+        .await
+        .oc()
+        .list_pinned_msgs(ListPinnedMsgsRequest {
+            session_id: session.session_id.into(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.pinned_msgs.is_empty());
+    app.async_drop().await;
+}
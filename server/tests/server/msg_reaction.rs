@@ -0,0 +1,92 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::reaction::v1::{AddReactionRequest, RemoveReactionRequest};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use server::process::error_msg::{exist, invalid, not_found};
+
+#[tokio::test]
+async fn test_reaction() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(3, "session1", false)
+        .await
+        .unwrap();
+    let (a, b, c) = (
+        session_user[0].clone(),
+        session_user[1].clone(),
+        session_user[2].clone(),
+    );
+    let msg_id = a
+        .lock()
+        .await
+        .send_msg(session.session_id, "hello", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    // consume the original message
+    b.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    let add = |emoji: &str| AddReactionRequest {
+        session_id: session.session_id.into(),
+        msg_id,
+        emoji: emoji.to_string(),
+    };
+
+    b.lock().await.oc().add_reaction(add("👍")).await.unwrap();
+    let err = b
+        .lock()
+        .await
+        .oc()
+        .add_reaction(add("👍"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::AlreadyExists);
+    assert_eq!(err.message(), exist::REACTION);
+    let err = b.lock().await.oc().add_reaction(add("")).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), invalid::EMOJI);
+    c.lock().await.oc().add_reaction(add("👍")).await.unwrap();
+    c.lock().await.oc().add_reaction(add("🎉")).await.unwrap();
+
+    // online members receive the notifications
+    let b_rec = b.lock().await.fetch_msgs().fetch(3).await.unwrap();
+    let RespondEventType::Reaction(ref data) = b_rec[0].clone().respond_event_type.unwrap() else {
+        panic!("not a reaction notification")
+    };
+    assert_eq!(data.msg_id, msg_id);
+    assert_eq!(data.emoji, "👍");
+    assert!(data.added);
+
+    let err = b
+        .lock()
+        .await
+        .oc()
+        .remove_reaction(RemoveReactionRequest {
+            session_id: session.session_id.into(),
+            msg_id,
+            emoji: "🎉".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::REACTION);
+    c.lock()
+        .await
+        .oc()
+        .remove_reaction(RemoveReactionRequest {
+            session_id: session.session_id.into(),
+            msg_id,
+            emoji: "👍".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // the aggregated reactions are attached to the message in the history
+    let a_rec = a.lock().await.fetch_msgs().fetch(5).await.unwrap();
+    let msg = a_rec.iter().find(|msg| msg.msg_id == msg_id).unwrap();
+    assert_eq!(msg.reactions.len(), 2);
+    assert_eq!(msg.reactions[0].emoji, "👍");
+    assert_eq!(msg.reactions[0].count, 1);
+    assert_eq!(msg.reactions[1].emoji, "🎉");
+    assert_eq!(msg.reactions[1].count, 1);
+    app.async_drop().await;
+}
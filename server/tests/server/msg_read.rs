@@ -0,0 +1,95 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::read::v1::{
+    GetUnreadCountsRequest, MarkReadRequest, UnreadCount,
+};
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+
+#[tokio::test]
+async fn test_read_receipt() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let mut msg_ids = vec![];
+    for i in 0..3 {
+        let msg_id = a
+            .lock()
+            .await
+            .send_msg(session.session_id, format!("msg{i}"), vec![], false)
+            .await
+            .unwrap()
+            .into_inner()
+            .msg_id;
+        msg_ids.push(msg_id);
+    }
+    let unread = |counts: Vec<UnreadCount>| {
+        counts
+            .into_iter()
+            .find(|count| count.session_id == u64::from(session.session_id))
+            .unwrap()
+    };
+
+    let counts = b
+        .lock()
+        .await
+        .oc()
+        .get_unread_counts(GetUnreadCountsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .counts;
+    assert_eq!(unread(counts).unread, 3);
+    // messages sent by the user itself are never unread
+    let counts = a
+        .lock()
+        .await
+        .oc()
+        .get_unread_counts(GetUnreadCountsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .counts;
+    assert_eq!(unread(counts).unread, 0);
+
+    let mark_read = |msg_id| MarkReadRequest {
+        session_id: session.session_id.into(),
+        msg_id,
+    };
+    b.lock()
+        .await
+        .oc()
+        .mark_read(mark_read(msg_ids[1]))
+        .await
+        .unwrap();
+    // moving backward is ignored
+    b.lock()
+        .await
+        .oc()
+        .mark_read(mark_read(msg_ids[0]))
+        .await
+        .unwrap();
+    let counts = b
+        .lock()
+        .await
+        .oc()
+        .get_unread_counts(GetUnreadCountsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .counts;
+    let count = unread(counts);
+    assert_eq!(count.unread, 1);
+    assert_eq!(count.last_read_msg_id, msg_ids[1]);
+
+    // the sender knows the messages are seen
+    let a_rec = a.lock().await.fetch_msgs().fetch(4).await.unwrap();
+    let RespondEventType::ReadReceipt(ref receipt) = a_rec[3].clone().respond_event_type.unwrap()
+    else {
+        panic!("not a read receipt")
+    };
+    assert_eq!(receipt.user_id, u64::from(b.lock().await.id));
+    assert_eq!(receipt.msg_id, msg_ids[1]);
+    app.async_drop().await;
+}
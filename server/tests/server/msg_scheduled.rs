@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use client::TestApp;
+use entities::{message_records, prelude::MessageRecords};
+use pb::service::ourchat::msg_delivery::scheduled::v1::{
+    CancelScheduledMsgRequest, ListScheduledMsgsRequest,
+};
+use pb::service::ourchat::msg_delivery::v1::SendMsgRequest;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter};
+use server::process::error_msg::not_found;
+
+#[tokio::test]
+async fn test_scheduled_msg() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let schedule = |text: &str, delay: chrono::Duration| SendMsgRequest {
+        session_id: session.session_id.into(),
+        markdown_text: text.to_string(),
+        involved_files: vec![],
+        is_encrypted: false,
+        reply_to_msg_id: None,
+        thread_root_id: None,
+        send_at: Some((chrono::Utc::now() + delay).into()),
+        ttl: None,
+    };
+
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(schedule("later", chrono::Duration::seconds(2)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(ret.msg_id, 0);
+    let later_id = ret.scheduled_msg_id.unwrap();
+    let cancelled_id = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(schedule("cancelled", chrono::Duration::hours(1)))
+        .await
+        .unwrap()
+        .into_inner()
+        .scheduled_msg_id
+        .unwrap();
+
+    // only the sender can cancel the message
+    let err = b
+        .lock()
+        .await
+        .oc()
+        .cancel_scheduled_msg(CancelScheduledMsgRequest {
+            scheduled_msg_id: cancelled_id,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::SCHEDULED_MSG);
+    a.lock()
+        .await
+        .oc()
+        .cancel_scheduled_msg(CancelScheduledMsgRequest {
+            scheduled_msg_id: cancelled_id,
+        })
+        .await
+        .unwrap();
+
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .list_scheduled_msgs(ListScheduledMsgsRequest {
+            session_id: Some(session.session_id.into()),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(ret.scheduled_msgs.len(), 1);
+    assert_eq!(ret.scheduled_msgs[0].scheduled_msg_id, later_id);
+    assert_eq!(
+        ret.scheduled_msgs[0].msg.as_ref().unwrap().markdown_text,
+        "later"
+    );
+
+    // the message is delivered once its time has come
+    let b_rec = b
+        .lock()
+        .await
+        .fetch_msgs()
+        .set_timeout(Duration::from_secs(10))
+        .fetch(1)
+        .await
+        .unwrap();
+    let RespondEventType::Msg(ref msg) = b_rec[0].clone().respond_event_type.unwrap() else {
+        panic!("not a msg")
+    };
+    assert_eq!(msg.markdown_text, "later");
+    assert_eq!(msg.sender_id, u64::from(a.lock().await.id));
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .list_scheduled_msgs(ListScheduledMsgsRequest { session_id: None })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.scheduled_msgs.is_empty());
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn test_scheduled_msg_of_muted_sender_is_dropped() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let bid = b.lock().await.id;
+    b.lock()
+        .await
+        .oc()
+        .send_msg(SendMsgRequest {
+            session_id: session.session_id.into(),
+            markdown_text: "muted".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+            reply_to_msg_id: None,
+            thread_root_id: None,
+            send_at: Some((chrono::Utc::now() + chrono::Duration::seconds(2)).into()),
+            ttl: None,
+        })
+        .await
+        .unwrap();
+    // muted after scheduling the message
+    a.lock()
+        .await
+        .mute_user(vec![bid], session.session_id, None)
+        .await
+        .unwrap();
+
+    // wait until the message is taken out of the queue
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let ret = b
+            .lock()
+            .await
+            .oc()
+            .list_scheduled_msgs(ListScheduledMsgsRequest { session_id: None })
+            .await
+            .unwrap()
+            .into_inner();
+        if ret.scheduled_msgs.is_empty() {
+            break;
+        }
+        assert!(tokio::time::Instant::now() < deadline);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    let delivered = MessageRecords::find()
+        .filter(message_records::Column::SenderId.eq(i64::from(bid)))
+        .count(app.get_db_connection())
+        .await
+        .unwrap();
+    assert_eq!(delivered, 0);
+    app.async_drop().await;
+}
@@ -0,0 +1,111 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::search::v1::SearchMessagesRequest;
+use server::process::error_msg::invalid;
+
+#[tokio::test]
+async fn test_search() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (e2ee_user, e2ee_session) = app.new_session_db_level(1, "session2", true).await.unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let hello_world = a
+        .lock()
+        .await
+        .send_msg(session.session_id, "hello world", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    let hello_there = b
+        .lock()
+        .await
+        .send_msg(session.session_id, "hello there", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    e2ee_user[0]
+        .lock()
+        .await
+        .send_msg(e2ee_session.session_id, "hello", vec![], true)
+        .await
+        .unwrap();
+    let search = |query: &str| SearchMessagesRequest {
+        query: query.to_string(),
+        session_id: None,
+        sender_id: None,
+        start_time: None,
+        end_time: None,
+        before_msg_id: 0,
+        page_size: 0,
+    };
+
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .search_messages(SearchMessagesRequest {
+            page_size: 1,
+            ..search("hello")
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.has_more);
+    assert_eq!(ret.msgs.len(), 1);
+    assert_eq!(ret.msgs[0].msg_id, hello_there);
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .search_messages(SearchMessagesRequest {
+            before_msg_id: hello_there,
+            ..search("hello")
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!ret.has_more);
+    assert_eq!(ret.msgs.len(), 1);
+    assert_eq!(ret.msgs[0].msg_id, hello_world);
+
+    // filter by the sender
+    let ret = a
+        .lock()
+        .await
+        .oc()
+        .search_messages(SearchMessagesRequest {
+            sender_id: Some(b.lock().await.id.into()),
+            ..search("hello")
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(ret.msgs.len(), 1);
+    assert_eq!(ret.msgs[0].msg_id, hello_there);
+
+    // encrypted messages are never matched
+    let ret = e2ee_user[0]
+        .lock()
+        .await
+        .oc()
+        .search_messages(search("hello"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.msgs.is_empty());
+
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .search_messages(search("  "))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), invalid::SEARCH_QUERY);
+    app.async_drop().await;
+}
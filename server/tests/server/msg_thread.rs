@@ -0,0 +1,138 @@
+use client::TestApp;
+use pb::service::ourchat::msg_delivery::thread::v1::FetchThreadRequest;
+use pb::service::ourchat::msg_delivery::v1::SendMsgRequest;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use server::process::error_msg::{THREAD_ROOT_MISMATCH, not_found};
+
+#[tokio::test]
+async fn test_thread() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (_, other_session) = app
+        .new_session_db_level(1, "session2", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let root_id = a
+        .lock()
+        .await
+        .send_msg(session.session_id, "root", vec![], false)
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    let reply = |text: &str, reply_to_msg_id, thread_root_id| SendMsgRequest {
+        session_id: session.session_id.into(),
+        markdown_text: text.to_string(),
+        involved_files: vec![],
+        is_encrypted: false,
+        reply_to_msg_id,
+        thread_root_id,
+        send_at: None,
+        ttl: None,
+    };
+    let first_reply = b
+        .lock()
+        .await
+        .oc()
+        .send_msg(reply("first", Some(root_id), None))
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    // replying to a reply continues the same thread
+    let second_reply = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(reply("second", Some(first_reply), None))
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(reply("wrong", Some(first_reply), Some(first_reply)))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), THREAD_ROOT_MISMATCH);
+    // the replied message must exist in the session
+    let err = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(SendMsgRequest {
+            session_id: session.session_id.into(),
+            markdown_text: "missing".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+            reply_to_msg_id: Some(u64::MAX >> 1),
+            thread_root_id: None,
+            send_at: None,
+            ttl: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+    assert_eq!(err.message(), not_found::MSG);
+
+    let ret = b
+        .lock()
+        .await
+        .oc()
+        .fetch_thread(FetchThreadRequest {
+            session_id: session.session_id.into(),
+            thread_root_id: root_id,
+            after_msg_id: 0,
+            page_size: 1,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(ret.has_more);
+    assert_eq!(ret.replies.len(), 1);
+    assert_eq!(ret.replies[0].msg_id, first_reply);
+    let ret = b
+        .lock()
+        .await
+        .oc()
+        .fetch_thread(FetchThreadRequest {
+            session_id: session.session_id.into(),
+            thread_root_id: root_id,
+            after_msg_id: first_reply,
+            page_size: 0,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!ret.has_more);
+    assert_eq!(ret.replies.len(), 1);
+    assert_eq!(ret.replies[0].msg_id, second_reply);
+    let RespondEventType::Msg(ref msg) = ret.replies[0].clone().respond_event_type.unwrap() else {
+        panic!("not a msg")
+    };
+    assert_eq!(msg.reply_to_msg_id, Some(first_reply));
+    assert_eq!(msg.thread_root_id, Some(root_id));
+
+    // the thread cannot be read from another session
+    let err = b
+        .lock()
+        .await
+        .oc()
+        .fetch_thread(FetchThreadRequest {
+            session_id: other_session.session_id.into(),
+            thread_root_id: root_id,
+            after_msg_id: 0,
+            page_size: 0,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    app.async_drop().await;
+}
@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use client::TestApp;
+use entities::prelude::MessageRecords;
+use pb::service::ourchat::msg_delivery::edit::v1::EditMsgRequest;
+use pb::service::ourchat::msg_delivery::reaction::v1::AddReactionRequest;
+use pb::service::ourchat::msg_delivery::v1::SendMsgRequest;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use pb::service::ourchat::session::get_session_info::v1::{GetSessionInfoRequest, QueryValues};
+use pb::service::ourchat::session::set_session_info::v1::SetSessionInfoRequest;
+use sea_orm::EntityTrait;
+use server::process::error_msg::{CANNOT_SET_MESSAGE_TTL, REQUEST_INVALID_VALUE};
+
+#[tokio::test]
+async fn test_msg_ttl() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    // the first user is the owner
+    let (owner, member) = (session_user[0].clone(), session_user[1].clone());
+    let set_ttl = SetSessionInfoRequest {
+        session_id: session.session_id.into(),
+        name: None,
+        description: None,
+        avatar_key: None,
+        message_ttl: Some(3600),
+    };
+
+    // members don't have the permission by default
+    let err = member
+        .lock()
+        .await
+        .oc()
+        .set_session_info(set_ttl.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    assert_eq!(err.message(), CANNOT_SET_MESSAGE_TTL);
+    owner
+        .lock()
+        .await
+        .oc()
+        .set_session_info(set_ttl)
+        .await
+        .unwrap();
+    let info = member
+        .lock()
+        .await
+        .oc()
+        .get_session_info(GetSessionInfoRequest {
+            session_id: session.session_id.into(),
+            query_values: vec![QueryValues::MessageTtl.into()],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.message_ttl, Some(3600));
+
+    let send = |text: &str, ttl| SendMsgRequest {
+        session_id: session.session_id.into(),
+        markdown_text: text.to_string(),
+        involved_files: vec![],
+        is_encrypted: false,
+        reply_to_msg_id: None,
+        thread_root_id: None,
+        send_at: None,
+        ttl,
+    };
+    let err = owner
+        .lock()
+        .await
+        .oc()
+        .send_msg(send("never", Some(0)))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), REQUEST_INVALID_VALUE);
+
+    // the session ttl applies to messages without their own ttl
+    let kept_id = owner
+        .lock()
+        .await
+        .oc()
+        .send_msg(send("kept", None))
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    // the shorter ttl wins
+    let expired_id = owner
+        .lock()
+        .await
+        .oc()
+        .send_msg(send("gone", Some(1)))
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    let kept = MessageRecords::find_by_id(kept_id as i64)
+        .one(app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap();
+    let kept_ttl = kept.expire_time.unwrap() - kept.time;
+    assert_eq!(kept_ttl.num_seconds(), 3600);
+
+    let member_rec = member
+        .lock()
+        .await
+        .fetch_msgs()
+        .set_timeout(Duration::from_secs(30))
+        .fetch(3)
+        .await
+        .unwrap();
+    let RespondEventType::Expire(ref data) = member_rec[2].clone().respond_event_type.unwrap()
+    else {
+        panic!("not an expire notification")
+    };
+    assert_eq!(data.session_id, u64::from(session.session_id));
+    assert_eq!(data.msg_ids, vec![expired_id]);
+    assert!(
+        MessageRecords::find_by_id(expired_id as i64)
+            .one(app.get_db_connection())
+            .await
+            .unwrap()
+            .is_none()
+    );
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn test_edit_of_expiring_msg_expires_with_it() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let msg_id = a
+        .lock()
+        .await
+        .oc()
+        .send_msg(SendMsgRequest {
+            session_id: session.session_id.into(),
+            markdown_text: "secret".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+            reply_to_msg_id: None,
+            thread_root_id: None,
+            send_at: None,
+            ttl: Some(2),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .msg_id;
+    a.lock()
+        .await
+        .oc()
+        .edit_msg(EditMsgRequest {
+            msg_id,
+            session_id: session.session_id.into(),
+            markdown_text: "edited secret".to_string(),
+            involved_files: vec![],
+            is_encrypted: false,
+        })
+        .await
+        .unwrap();
+    b.lock()
+        .await
+        .oc()
+        .add_reaction(AddReactionRequest {
+            session_id: session.session_id.into(),
+            msg_id,
+            emoji: "👍".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let mut expired = false;
+    for _ in 0..60 {
+        if MessageRecords::find_by_id(msg_id as i64)
+            .one(app.get_db_connection())
+            .await
+            .unwrap()
+            .is_none()
+        {
+            expired = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(expired, "the message should expire");
+    // neither the edit nor the reaction outlives the message
+    let records = MessageRecords::find()
+        .all(app.get_db_connection())
+        .await
+        .unwrap();
+    for record in records {
+        let Ok(msg) = serde_json::from_value::<RespondEventType>(record.msg_data) else {
+            continue;
+        };
+        match msg {
+            RespondEventType::Edit(edit) => assert_ne!(edit.msg_id, msg_id),
+            RespondEventType::Reaction(reaction) => assert_ne!(reaction.msg_id, msg_id),
+            RespondEventType::Msg(content) => {
+                assert!(!content.markdown_text.contains("secret"))
+            }
+            _ => {}
+        }
+    }
+    app.async_drop().await;
+}
@@ -0,0 +1,93 @@
+use client::TestApp;
+use parking_lot::Mutex;
+use pb::service::ourchat::msg_delivery::typing::v1::SetTypingRequest;
+use pb::service::ourchat::msg_delivery::v1::fetch_msgs_response::RespondEventType;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::join;
+use tokio::sync::{Notify, oneshot};
+
+#[tokio::test]
+async fn test_typing() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let (session_user, session) = app
+        .new_session_db_level(2, "session1", false)
+        .await
+        .unwrap();
+    let (a, b) = (session_user[0].clone(), session_user[1].clone());
+    let a_id = a.lock().await.id;
+    // start a listening process, typing notifications are only sent to online members
+    let res = Arc::new(Mutex::new(None));
+    let res_clone = res.clone();
+    let b_clone = b.clone();
+    let notify = Arc::new(Notify::new());
+    let notify_clone = notify.clone();
+    let (tx, rx) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        tx.send(()).unwrap();
+        let ret = b_clone
+            .lock()
+            .await
+            .fetch_msgs()
+            .fetch_with_notify(notify_clone)
+            .await
+            .unwrap();
+        *res_clone.lock() = Some(ret);
+    });
+    rx.await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let set_typing = |typing| SetTypingRequest {
+        session_id: session.session_id.into(),
+        typing,
+    };
+    // clearing a typing state which doesn't exist notifies nobody
+    a.lock()
+        .await
+        .oc()
+        .set_typing(set_typing(false))
+        .await
+        .unwrap();
+    a.lock()
+        .await
+        .oc()
+        .set_typing(set_typing(true))
+        .await
+        .unwrap();
+    a.lock()
+        .await
+        .oc()
+        .set_typing(set_typing(false))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    notify.notify_waiters();
+    join!(task).0.unwrap();
+    let b_rec = { res.lock().clone().unwrap() };
+    assert_eq!(b_rec.len(), 2, "{b_rec:?}");
+    let RespondEventType::Typing(ref start) = b_rec[0].clone().respond_event_type.unwrap() else {
+        panic!("not a typing notification")
+    };
+    assert_eq!(b_rec[0].msg_id, 0);
+    assert_eq!(start.user_id, u64::from(a_id));
+    assert!(start.typing);
+    assert!(start.expire_time.is_some());
+    let RespondEventType::Typing(ref stop) = b_rec[1].clone().respond_event_type.unwrap() else {
+        panic!("not a typing notification")
+    };
+    assert!(!stop.typing);
+    assert!(stop.expire_time.is_none());
+
+    // typing notifications are not kept in the history
+    a.lock()
+        .await
+        .send_msg(session.session_id, "hello", vec![], false)
+        .await
+        .unwrap();
+    let a_rec = a.lock().await.fetch_msgs().fetch(1).await.unwrap();
+    assert!(matches!(
+        a_rec[0].respond_event_type,
+        Some(RespondEventType::Msg(_))
+    ));
+    app.async_drop().await;
+}
@@ -1,7 +1,38 @@
 use client::TestApp;
-use entities::user;
+use entities::{oauth_identities, user};
 use http::StatusCode;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use redis::AsyncCommands;
+use sea_orm::{EntityTrait, ModelTrait};
+use server::db::redis_mappings::map_oauth_state_to_redis;
+
+async fn link_github(app: &TestApp, user_id: i64, github_id: &str) {
+    let identity = oauth_identities::ActiveModel {
+        provider: sea_orm::ActiveValue::Set("github".to_string()),
+        subject: sea_orm::ActiveValue::Set(github_id.to_string()),
+        user_id: sea_orm::ActiveValue::Set(user_id),
+        email: sea_orm::ActiveValue::Set(None),
+        created_time: sea_orm::ActiveValue::Set(chrono::Utc::now().into()),
+    };
+    oauth_identities::Entity::insert(identity)
+        .exec(&app.db_pool.db_pool)
+        .await
+        .expect("Failed to link GitHub identity");
+}
+
+async fn find_github_user(app: &TestApp, github_id: &str) -> user::Model {
+    let identity =
+        oauth_identities::Entity::find_by_id(("github".to_string(), github_id.to_string()))
+            .one(&app.db_pool.db_pool)
+            .await
+            .expect("Failed to query OAuth identity")
+            .expect("OAuth identity not found");
+    identity
+        .find_related(user::Entity)
+        .one(&app.db_pool.db_pool)
+        .await
+        .expect("Failed to query OAuth user")
+        .expect("OAuth user not found")
+}
 
 #[tokio::test]
 async fn test_github_oauth_start() {
@@ -35,6 +66,20 @@ async fn test_github_oauth_start() {
         assert!(location.contains("redirect_uri="));
         assert!(location.contains("scope=user:email"));
         assert!(location.contains("state="));
+
+        // The state is kept in redis for whichever instance the provider redirects back to
+        let state = reqwest::Url::parse(location)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+        let ttl: i64 = app
+            .redis()
+            .ttl(map_oauth_state_to_redis(&state))
+            .await
+            .unwrap();
+        assert!((1..=600).contains(&ttl));
     } else {
         // In test mode with empty credentials, the endpoint might just exist
         assert_eq!(response.status(), StatusCode::OK);
@@ -133,26 +178,22 @@ async fn test_oauth_user_creation() {
         account_status: sea_orm::ActiveValue::Set(1),
         deleted_at: sea_orm::ActiveValue::Set(None),
         public_key: sea_orm::ActiveValue::Set(vec![]),
-        github_id: sea_orm::ActiveValue::Set(Some("99997".to_string())),
-        oauth_provider: sea_orm::ActiveValue::Set(Some("github".to_string())),
         email_verified: sea_orm::ActiveValue::Set(true),
+        totp_secret: sea_orm::ActiveValue::Set(None),
+        totp_enabled: sea_orm::ActiveValue::Set(false),
+        files_limit: sea_orm::ActiveValue::Set(None),
     };
 
     user::Entity::insert(new_user)
         .exec(&app.db_pool.db_pool)
         .await
         .expect("Failed to insert OAuth user");
+    link_github(&app, 99997, "99997").await;
 
     // Verify the OAuth user was created correctly
-    let oauth_user = user::Entity::find()
-        .filter(user::Column::GithubId.eq("99997"))
-        .one(&app.db_pool.db_pool)
-        .await
-        .expect("Failed to query OAuth user")
-        .expect("OAuth user not found");
+    let oauth_user = find_github_user(&app, "99997").await;
 
-    assert_eq!(oauth_user.github_id, Some("99997".to_string()));
-    assert_eq!(oauth_user.oauth_provider, Some("github".to_string()));
+    assert_eq!(oauth_user.id, 99997);
     assert!(oauth_user.email_verified);
     assert!(oauth_user.passwd.is_none());
     assert_eq!(
@@ -184,23 +225,20 @@ async fn test_oauth_user_update() {
         account_status: sea_orm::ActiveValue::Set(1),
         deleted_at: sea_orm::ActiveValue::Set(None),
         public_key: sea_orm::ActiveValue::Set(vec![]),
-        github_id: sea_orm::ActiveValue::Set(Some("99996".to_string())),
-        oauth_provider: sea_orm::ActiveValue::Set(Some("github".to_string())),
         email_verified: sea_orm::ActiveValue::Set(true),
+        totp_secret: sea_orm::ActiveValue::Set(None),
+        totp_enabled: sea_orm::ActiveValue::Set(false),
+        files_limit: sea_orm::ActiveValue::Set(None),
     };
 
     user::Entity::insert(new_user)
         .exec(&app.db_pool.db_pool)
         .await
         .expect("Failed to insert OAuth user");
+    link_github(&app, 99996, "99996").await;
 
     // Simulate updating the user via OAuth (e.g., user changed their GitHub profile)
-    let user_record = user::Entity::find()
-        .filter(user::Column::GithubId.eq("99996"))
-        .one(&app.db_pool.db_pool)
-        .await
-        .expect("Failed to query OAuth user")
-        .expect("OAuth user not found");
+    let user_record = find_github_user(&app, "99996").await;
 
     let mut user_active: user::ActiveModel = user_record.into();
     user_active.name = sea_orm::ActiveValue::Set("Updated Name".to_string());
@@ -215,12 +253,7 @@ async fn test_oauth_user_update() {
         .expect("Failed to update OAuth user");
 
     // Verify the user was updated correctly
-    let updated_user = user::Entity::find()
-        .filter(user::Column::GithubId.eq("99996"))
-        .one(&app.db_pool.db_pool)
-        .await
-        .expect("Failed to query updated OAuth user")
-        .expect("Updated OAuth user not found");
+    let updated_user = find_github_user(&app, "99996").await;
 
     assert_eq!(updated_user.name, "Updated Name");
     assert_eq!(updated_user.email, "updated_email@example.com");
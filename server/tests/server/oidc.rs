@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Query, State};
+use axum::response::Redirect;
+use axum::routing::{get, post};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use client::TestApp;
+use client::oc_helper::user::TestUserShared;
+use entities::{oauth_identities, user};
+use http::StatusCode;
+use jsonwebtoken::{EncodingKey, Header};
+use pb::service::ourchat::oauth::v1::{
+    ConfirmLinkProviderRequest, LinkProviderRequest, ListLinkedProvidersRequest,
+    UnlinkProviderRequest,
+};
+use rsa::RsaPrivateKey;
+use rsa::pkcs1::EncodeRsaPrivateKey;
+use rsa::traits::PublicKeyParts;
+use sea_orm::EntityTrait;
+use serde_json::json;
+use server::config::OidcProviderCfg;
+use server::process::error_msg::{OAUTH_LINK_CODE_INVALID, not_found};
+use sha2::{Digest, Sha256};
+
+const PROVIDER: &str = "mock";
+const CLIENT_ID: &str = "ourchat";
+const KEY_ID: &str = "mock-key";
+
+/// A local OpenID Connect issuer logging everyone in as `identity` without asking
+struct MockIssuer {
+    issuer: String,
+    key: EncodingKey,
+    jwk: serde_json::Value,
+    /// subject and email of the account at the issuer
+    identity: Mutex<(String, String)>,
+    /// code => (code challenge, nonce)
+    codes: Mutex<HashMap<String, (String, String)>>,
+}
+
+impl MockIssuer {
+    async fn start() -> Arc<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let private_key = RsaPrivateKey::new(&mut rand::rng(), 2048).unwrap();
+        let der = private_key.to_pkcs1_der().unwrap();
+        let issuer = Arc::new(Self {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            key: EncodingKey::from_rsa_der(der.as_bytes()),
+            jwk: json!({
+                "kty": "RSA",
+                "kid": KEY_ID,
+                "alg": "RS256",
+                "use": "sig",
+                "n": URL_SAFE_NO_PAD.encode(private_key.n_bytes()),
+                "e": URL_SAFE_NO_PAD.encode(private_key.e_bytes()),
+            }),
+            identity: Mutex::new(("mock-subject".to_string(), "oidc@example.com".to_string())),
+            codes: Mutex::new(HashMap::new()),
+        });
+        let router = axum::Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .with_state(issuer.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        issuer
+    }
+
+    fn set_identity(&self, subject: &str, email: &str) {
+        *self.identity.lock().unwrap() = (subject.to_string(), email.to_string());
+    }
+
+    fn provider_cfg(&self) -> OidcProviderCfg {
+        OidcProviderCfg {
+            name: PROVIDER.to_string(),
+            issuer: self.issuer.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            scopes: vec!["openid".to_string(), "email".to_string()],
+        }
+    }
+}
+
+async fn discovery(State(issuer): State<Arc<MockIssuer>>) -> axum::Json<serde_json::Value> {
+    axum::Json(json!({
+        "issuer": issuer.issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer.issuer),
+        "token_endpoint": format!("{}/token", issuer.issuer),
+        "jwks_uri": format!("{}/jwks", issuer.issuer),
+    }))
+}
+
+async fn jwks(State(issuer): State<Arc<MockIssuer>>) -> axum::Json<serde_json::Value> {
+    axum::Json(json!({ "keys": [issuer.jwk] }))
+}
+
+async fn authorize(
+    State(issuer): State<Arc<MockIssuer>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Redirect {
+    assert_eq!(params["client_id"], CLIENT_ID);
+    assert_eq!(params["code_challenge_method"], "S256");
+    let code = uuid::Uuid::new_v4().to_string();
+    issuer.codes.lock().unwrap().insert(
+        code.clone(),
+        (params["code_challenge"].clone(), params["nonce"].clone()),
+    );
+    Redirect::to(&format!(
+        "{}?code={}&state={}",
+        params["redirect_uri"], code, params["state"]
+    ))
+}
+
+async fn token(
+    State(issuer): State<Arc<MockIssuer>>,
+    body: String,
+) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+    let form: HashMap<String, String> = reqwest::Url::parse(&format!("http://form/?{body}"))
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect();
+    let (challenge, nonce) = issuer
+        .codes
+        .lock()
+        .unwrap()
+        .remove(&form["code"])
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes())) != challenge {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (subject, email) = issuer.identity.lock().unwrap().clone();
+    let now = chrono::Utc::now().timestamp();
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some(KEY_ID.to_string());
+    let id_token = jsonwebtoken::encode(
+        &header,
+        &json!({
+            "iss": issuer.issuer,
+            "sub": subject,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce,
+            "email": email,
+            "email_verified": true,
+            "name": "OIDC User",
+        }),
+        &issuer.key,
+    )
+    .unwrap();
+    Ok(axum::Json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "id_token": id_token,
+    })))
+}
+
+async fn app_with_issuer() -> (TestApp, Arc<MockIssuer>) {
+    let issuer = MockIssuer::start().await;
+    let (mut config, args) = TestApp::get_test_config().unwrap();
+    config.main_cfg.oauth.enable = true;
+    config.main_cfg.oauth.oidc = vec![issuer.provider_cfg()];
+    let app = TestApp::new_with_launching_instance_custom_cfg((config, args), |_| {})
+        .await
+        .unwrap();
+    (app, issuer)
+}
+
+#[tokio::test]
+async fn oidc_login_creates_user() {
+    let (mut app, issuer) = app_with_issuer().await;
+    let subject = uuid::Uuid::new_v4().to_string();
+    let email = format!("{subject}@example.com");
+    issuer.set_identity(&subject, &email);
+
+    let response = app.http_get(format!("oauth/{PROVIDER}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[http::header::CACHE_CONTROL],
+        "no-store",
+        "the tokens must not be cached"
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "logged_in");
+    assert_eq!(body["token_type"], "Bearer");
+    assert!(body["access_token"].is_string());
+
+    let identity = oauth_identities::Entity::find_by_id((PROVIDER.to_string(), subject.clone()))
+        .one(&app.db_pool.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(identity.email.as_deref(), Some(email.as_str()));
+    let user = user::Entity::find_by_id(identity.user_id)
+        .one(&app.db_pool.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.email, email);
+    assert!(user.email_verified);
+    assert!(user.passwd.is_none());
+
+    // logging in again gets the same account
+    let response = app.http_get(format!("oauth/{PROVIDER}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let users = user::Entity::find()
+        .all(&app.db_pool.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|user| user.email == email)
+        .count();
+    assert_eq!(users, 1);
+
+    // a callback without the state of a login started here is rejected
+    let response = app
+        .http_get(format!(
+            "oauth/{PROVIDER}/callback?code=forged&state=forged"
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    app.async_drop().await;
+}
+
+async fn link_ticket(user: &TestUserShared) -> String {
+    user.lock()
+        .await
+        .oc()
+        .link_provider(LinkProviderRequest {
+            provider: PROVIDER.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .link_ticket
+}
+
+/// Log in at the issuer for linking, returning the link code
+async fn open_link_url(app: &TestApp, ticket: &str) -> String {
+    let response = app
+        .http_get(format!("oauth/{PROVIDER}?link_ticket={ticket}"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "link_pending");
+    body["link_code"].as_str().unwrap().to_string()
+}
+
+fn confirm(link_code: &str) -> ConfirmLinkProviderRequest {
+    ConfirmLinkProviderRequest {
+        provider: PROVIDER.to_string(),
+        link_code: link_code.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn oidc_link_and_unlink() {
+    let (mut app, issuer) = app_with_issuer().await;
+    let user = app.new_user().await.unwrap();
+    let user_email = user.lock().await.email.clone();
+    let subject = uuid::Uuid::new_v4().to_string();
+
+    // the email belongs to an account already, which has to link the provider itself
+    issuer.set_identity(&subject, &user_email);
+    let response = app.http_get(format!("oauth/{PROVIDER}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let ticket = link_ticket(&user).await;
+    let link_code = open_link_url(&app, &ticket).await;
+    // the ticket works only once
+    let response = app
+        .http_get(format!("oauth/{PROVIDER}?link_ticket={ticket}"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    // nothing is linked until the account confirms it, and only the account which got the ticket
+    // can, so a ticket sent to someone else doesn't link their identity
+    let other = app.new_user().await.unwrap();
+    let err = other
+        .lock()
+        .await
+        .oc()
+        .confirm_link_provider(confirm(&link_code))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), OAUTH_LINK_CODE_INVALID);
+    // the code is gone once tried
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .confirm_link_provider(confirm(&link_code))
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), OAUTH_LINK_CODE_INVALID);
+
+    let ticket = link_ticket(&user).await;
+    let link_code = open_link_url(&app, &ticket).await;
+    user.lock()
+        .await
+        .oc()
+        .confirm_link_provider(confirm(&link_code))
+        .await
+        .unwrap();
+
+    let providers = user
+        .lock()
+        .await
+        .oc()
+        .list_linked_providers(ListLinkedProvidersRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .providers;
+    assert_eq!(providers.len(), 1);
+    assert_eq!(providers[0].provider, PROVIDER);
+
+    // now the identity logs into the account
+    let response = app.http_get(format!("oauth/{PROVIDER}")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .link_provider(LinkProviderRequest {
+            provider: "unknown".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), not_found::OAUTH_PROVIDER);
+
+    user.lock()
+        .await
+        .oc()
+        .unlink_provider(UnlinkProviderRequest {
+            provider: PROVIDER.to_string(),
+        })
+        .await
+        .unwrap();
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .unlink_provider(UnlinkProviderRequest {
+            provider: PROVIDER.to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), not_found::OAUTH_IDENTITY);
+    app.async_drop().await;
+}
@@ -0,0 +1,114 @@
+use client::TestApp;
+use pb::service::auth::token::v1::RefreshTokenRequest;
+use pb::service::ourchat::change_password::v1::ChangePasswordRequest;
+use server::process::error_msg::token::REFRESH_TOKEN_INVALID;
+use server::process::error_msg::{ACCOUNT_LOCKED, NOT_STRONG_PASSWORD, WRONG_PASSWORD};
+
+const NEW_PASSWORD: &str = "Tr0ub4dor&3-correct-horse";
+
+#[tokio::test]
+async fn change_password() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let old_password = user.lock().await.password.clone();
+    let other_refresh_token = user.lock().await.refresh_token.clone();
+    // change the password on another device
+    user.lock().await.ocid_auth().await.unwrap();
+
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .change_password(ChangePasswordRequest {
+            old_password: "wrong password".to_string(),
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), WRONG_PASSWORD);
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .change_password(ChangePasswordRequest {
+            old_password: old_password.clone(),
+            new_password: "123".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), NOT_STRONG_PASSWORD);
+
+    user.lock()
+        .await
+        .oc()
+        .change_password(ChangePasswordRequest {
+            old_password,
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await
+        .unwrap();
+    // the device changing the password stays logged in, the others are logged out
+    user.lock().await.refresh().await.unwrap();
+    let err = user
+        .lock()
+        .await
+        .clients
+        .auth
+        .refresh_token(RefreshTokenRequest {
+            refresh_token: other_refresh_token,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.message(), REFRESH_TOKEN_INVALID);
+
+    let err = user.lock().await.ocid_auth().await.unwrap_err();
+    assert_eq!(err.unwrap_rpc_status().message(), WRONG_PASSWORD);
+    user.lock().await.password = NEW_PASSWORD.to_string();
+    user.lock().await.ocid_auth().await.unwrap();
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn change_password_wrong_attempts_lock_account() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let old_password = user.lock().await.password.clone();
+    let max_attempts = app
+        .app_shared
+        .cfg()
+        .main_cfg
+        .lock_account_after_failed_logins;
+
+    // the old password can't be guessed with the access token alone
+    for _ in 0..max_attempts {
+        let err = user
+            .lock()
+            .await
+            .oc()
+            .change_password(ChangePasswordRequest {
+                old_password: "wrong password".to_string(),
+                new_password: NEW_PASSWORD.to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.message(), WRONG_PASSWORD);
+    }
+    let err = user
+        .lock()
+        .await
+        .oc()
+        .change_password(ChangePasswordRequest {
+            old_password,
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), ACCOUNT_LOCKED);
+    // the attempts are shared with logging in
+    let err = user.lock().await.ocid_auth().await.unwrap_err();
+    assert_eq!(err.unwrap_rpc_status().message(), ACCOUNT_LOCKED);
+    app.async_drop().await;
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use base::constants::JWT_HEADER;
+use client::TestApp;
+use pb::service::ourchat::get_account_info::v1::{GetAccountInfoRequest, QueryValues};
+use pb::service::ourchat::v1::our_chat_service_client::OurChatServiceClient;
+use server::process::error_msg::TOO_MANY_REQUESTS;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+
+const BURST: u32 = 10;
+
+fn get_self_info() -> GetAccountInfoRequest {
+    GetAccountInfoRequest {
+        id: None,
+        request_values: vec![QueryValues::Ocid.into()],
+    }
+}
+
+#[tokio::test]
+async fn grpc_rate_limit_per_user_across_instances() {
+    let (mut config, args) = TestApp::get_test_config().unwrap();
+    config.http_cfg.rate_limit.enable = true;
+    config.http_cfg.rate_limit.num_of_burst_requests = BURST;
+    config.http_cfg.rate_limit.replenish_duration = Duration::from_secs(1);
+    let mut app = TestApp::new_with_launching_instance_custom_cfg((config, args), |_| {})
+        .await
+        .unwrap();
+    let (user, other) = (app.new_user().await.unwrap(), app.new_user().await.unwrap());
+    let (second, mut second_handle) = app.launch_another_instance().await.unwrap();
+    let channel = Channel::from_shared(format!("http://localhost:{}", second.cfg().http_cfg.port))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let token: MetadataValue<_> = {
+        let user = user.lock().await;
+        format!("{} {}", user.authorization_header, user.token)
+            .parse()
+            .unwrap()
+    };
+    let mut second_client =
+        OurChatServiceClient::with_interceptor(channel, move |mut req: tonic::Request<()>| {
+            req.metadata_mut().insert(JWT_HEADER, token.clone());
+            Ok(req)
+        });
+
+    // Use the requests of the user up on the first instance
+    let mut limited = None;
+    for _ in 0..BURST * 3 {
+        if let Err(err) = user
+            .lock()
+            .await
+            .oc()
+            .get_account_info(get_self_info())
+            .await
+        {
+            limited = Some(err);
+            break;
+        }
+    }
+    let err = limited.expect("the requests of the user should be limited");
+    assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(err.message(), TOO_MANY_REQUESTS);
+
+    // The second instance shares the limit
+    let err = second_client
+        .get_account_info(get_self_info())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+    // Another user has a limit of its own
+    other
+        .lock()
+        .await
+        .oc()
+        .get_account_info(get_self_info())
+        .await
+        .unwrap();
+    // A request is replenished after a while
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    second_client
+        .get_account_info(get_self_info())
+        .await
+        .unwrap();
+
+    second_handle.shutdown_all_tasks().await.unwrap();
+    app.async_drop().await;
+}
@@ -4,3 +4,4 @@ mod bootstrap_initial_admin;
 mod maintaining;
 mod metrics;
 mod role_management;
+mod storage_quota;
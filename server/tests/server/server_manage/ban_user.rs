@@ -1,6 +1,8 @@
 use client::TestApp;
 use pb::google::protobuf::Duration;
+use pb::service::ourchat::get_account_info::v1::{GetAccountInfoRequest, QueryValues};
 use pb::service::server_manage::user_manage::v1::{BanUserRequest, UnbanUserRequest};
+use server::process::error_msg::token;
 use tonic::Request;
 
 #[tokio::test]
@@ -57,6 +59,20 @@ async fn ban_user_test() {
     // Should succeed (idempotent)
     assert!(result.is_ok(), "Duplicate ban should be idempotent");
 
+    // The tokens of the banned user are revoked
+    let err = target_user
+        .lock()
+        .await
+        .oc()
+        .get_account_info(GetAccountInfoRequest {
+            id: None,
+            request_values: vec![QueryValues::Ocid.into()],
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert_eq!(err.message(), token::REVOKED);
+
     // Test 3: Ban user without duration (permanent)
     let permanent_target = app.new_user().await.unwrap();
     let permanent_target_id = permanent_target.lock().await.id;
@@ -120,6 +136,9 @@ async fn ban_user_test() {
     // We'll accept either success or error, but we should verify behavior
     // For now, just ensure no panic.
 
+    // log in again after being unbanned
+    target_user.lock().await.ocid_auth().await.unwrap();
+    permanent_target.lock().await.ocid_auth().await.unwrap();
     app.async_drop().await;
 }
 
@@ -1,6 +1,11 @@
+use std::time::Duration;
+
 use client::TestApp;
+use pb::service::basic::v1::basic_service_client::BasicServiceClient;
+use pb::service::basic::v1::{GetServerInfoRequest, TimestampRequest};
 use pb::service::server_manage::set_server_status::v1::{ServerStatus, SetServerStatusRequest};
 use server::process::error_msg;
+use tonic::transport::Channel;
 
 #[tokio::test]
 async fn set_server_status_can_exit_maintenance_mode() {
@@ -19,6 +24,7 @@ async fn set_server_status_can_exit_maintenance_mode() {
         .set_server_status(tonic::Request::new(SetServerStatusRequest {
             server_status: ServerStatus::Maintaining as i32,
             reason: "Test maintenance".to_string(),
+            ..Default::default()
         }))
         .await
         .unwrap();
@@ -34,6 +40,7 @@ async fn set_server_status_can_exit_maintenance_mode() {
         .set_server_status(tonic::Request::new(SetServerStatusRequest {
             server_status: ServerStatus::Normal as i32,
             reason: "Exit maintenance".to_string(),
+            ..Default::default()
         }))
         .await
         .unwrap();
@@ -63,6 +70,7 @@ async fn server_manage_endpoints_blocked_during_maintenance() {
         .set_server_status(tonic::Request::new(SetServerStatusRequest {
             server_status: ServerStatus::Maintaining as i32,
             reason: "Test maintenance".to_string(),
+            ..Default::default()
         }))
         .await
         .unwrap();
@@ -91,6 +99,7 @@ async fn server_manage_endpoints_blocked_during_maintenance() {
         .set_server_status(tonic::Request::new(SetServerStatusRequest {
             server_status: ServerStatus::Normal as i32,
             reason: "Exit maintenance".to_string(),
+            ..Default::default()
         }))
         .await;
 
@@ -106,3 +115,119 @@ async fn server_manage_endpoints_blocked_during_maintenance() {
 
     app.async_drop().await;
 }
+
+#[tokio::test]
+async fn scheduled_maintenance_window() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+
+    user.lock()
+        .await
+        .promote_to_admin(app.get_db_connection())
+        .await
+        .unwrap();
+
+    let start = chrono::Utc::now() + chrono::Duration::hours(1);
+    let end = start + chrono::Duration::hours(1);
+    // The end must come after the start
+    let err = user
+        .lock()
+        .await
+        .server_manage()
+        .set_server_status(tonic::Request::new(SetServerStatusRequest {
+            server_status: ServerStatus::Maintaining as i32,
+            reason: "Upgrade".to_string(),
+            start_time: Some(end.into()),
+            end_time: Some(start.into()),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(err.message(), error_msg::invalid::MAINTENANCE_TIME);
+
+    user.lock()
+        .await
+        .server_manage()
+        .set_server_status(tonic::Request::new(SetServerStatusRequest {
+            server_status: ServerStatus::Maintaining as i32,
+            reason: "Upgrade".to_string(),
+            start_time: Some(start.into()),
+            end_time: Some(end.into()),
+        }))
+        .await
+        .unwrap();
+
+    assert!(
+        !app.app_shared.get_maintaining(),
+        "The maintenance should not have started yet"
+    );
+    let info = app
+        .basic_service()
+        .get_server_info(GetServerInfoRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.status, 0);
+    let maintenance = info.maintenance.unwrap();
+    assert_eq!(maintenance.reason, "Upgrade");
+    assert_eq!(
+        maintenance.end_time.unwrap().seconds,
+        pb::google::protobuf::Timestamp::from(end).seconds
+    );
+    // Kept in the database for the other instances
+    let status = server::db::server_status::get_server_status(app.get_db_connection())
+        .await
+        .unwrap();
+    assert_eq!(status, app.app_shared.server_status());
+
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn maintenance_applies_to_every_instance() {
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    user.lock()
+        .await
+        .promote_to_admin(app.get_db_connection())
+        .await
+        .unwrap();
+    let (second, mut second_handle) = app.launch_another_instance().await.unwrap();
+    let channel = Channel::from_shared(format!("http://localhost:{}", second.cfg().http_cfg.port))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut second_client = BasicServiceClient::new(channel);
+    second_client.timestamp(TimestampRequest {}).await.unwrap();
+
+    user.lock()
+        .await
+        .server_manage()
+        .set_server_status(tonic::Request::new(SetServerStatusRequest {
+            server_status: ServerStatus::Maintaining as i32,
+            reason: "Test maintenance".to_string(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap();
+
+    // The change is published to the other instance
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !second.get_maintaining() {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "the other instance should enter maintenance mode"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let err = second_client
+        .timestamp(TimestampRequest {})
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::Unavailable);
+    assert_eq!(err.message(), error_msg::MAINTAINING);
+
+    second_handle.shutdown_all_tasks().await.unwrap();
+    app.async_drop().await;
+}
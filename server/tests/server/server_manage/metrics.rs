@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use claims::{assert_ge, assert_gt, assert_le, assert_some};
+use claims::{assert_ge, assert_gt, assert_le, assert_lt, assert_some};
 use client::TestApp;
 use pb::google::protobuf::{Duration as ProtoDuration, Timestamp as ProtoTimestamp};
 use pb::service::server_manage::monitoring::v1::{
@@ -121,6 +121,26 @@ async fn metrics_comprehensive_test() {
         "Tokio metrics should not be included when not requested"
     );
 
+    tracing::info!("Testing the leader");
+    let response = user
+        .lock()
+        .await
+        .server_manage()
+        .get_monitoring_metrics(Request::new(GetMonitoringMetricsRequest {
+            include_system_metrics: false,
+            include_tokio_metrics: false,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.node_id, app.app_shared.leader.node_id());
+    // The only node leads
+    let leader = response.leader.unwrap();
+    assert_eq!(leader.node_id, response.node_id);
+    let acquired_time: TimeStampUtc = leader.acquired_time.unwrap().try_into().unwrap();
+    let expire_time: TimeStampUtc = leader.expire_time.unwrap().try_into().unwrap();
+    assert_lt!(acquired_time, expire_time);
+
     tracing::info!("Testing uptime increases over time");
     let uptime1 = {
         let response = user
@@ -213,6 +233,7 @@ async fn metrics_comprehensive_test() {
     }
 
     tracing::info!("Testing historical metrics with snapshots");
+    let node_id = app.app_shared.leader.node_id().to_owned();
     let before_snapshot = chrono::Utc::now() - chrono::Duration::seconds(5);
 
     tokio::time::sleep(Duration::from_millis(2500)).await;
@@ -236,6 +257,7 @@ async fn metrics_comprehensive_test() {
                 seconds: 0,
                 nanos: 0,
             }),
+            node_id: Some(node_id.clone()),
         }))
         .await
         .unwrap();
@@ -254,6 +276,7 @@ async fn metrics_comprehensive_test() {
         assert_le!(ts, after_snapshot.timestamp());
         assert_some!(dp.metrics);
         assert_ge!(dp.metrics.as_ref().unwrap().uptime_seconds, 0);
+        assert_eq!(dp.node_id.as_ref(), Some(&node_id));
 
         if i > 0 {
             let ts_prev = data_points[i - 1].timestamp.as_ref().unwrap().seconds;
@@ -282,6 +305,7 @@ async fn metrics_comprehensive_test() {
                     seconds: secs,
                     nanos,
                 }),
+                node_id: None,
             }))
             .await
             .unwrap();
@@ -295,6 +319,40 @@ async fn metrics_comprehensive_test() {
         assert_le!(points.len(), 10, "Should have reasonable number of points");
     }
 
+    tracing::info!("Testing historical metrics of every node");
+    let (second, mut second_handle) = app.launch_another_instance().await.unwrap();
+    let second_node_id = second.leader.node_id().to_owned();
+    assert_ne!(second_node_id, node_id);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let response = user
+        .lock()
+        .await
+        .server_manage()
+        .get_historical_metrics(Request::new(GetHistoricalMetricsRequest {
+            start_time: Some(ProtoTimestamp {
+                seconds: before_snapshot.timestamp(),
+                nanos: 0,
+            }),
+            end_time: Some(ProtoTimestamp {
+                seconds: chrono::Utc::now().timestamp() + 1,
+                nanos: 0,
+            }),
+            interval: None,
+            node_id: Some(second_node_id.clone()),
+        }))
+        .await
+        .unwrap();
+    let points = response.into_inner().data_points;
+    assert!(
+        !points.is_empty(),
+        "Every node should save its own snapshots"
+    );
+    for dp in &points {
+        assert_eq!(dp.node_id.as_ref(), Some(&second_node_id));
+    }
+    second_handle.shutdown_all_tasks().await.unwrap();
+
     tracing::info!("Testing historical metrics time range validation");
     let now = chrono::Utc::now().timestamp();
 
@@ -315,6 +373,7 @@ async fn metrics_comprehensive_test() {
                 seconds: 60,
                 nanos: 0,
             }),
+            node_id: None,
         }))
         .await
         .unwrap();
@@ -341,6 +400,7 @@ async fn metrics_comprehensive_test() {
                 seconds: 60,
                 nanos: 0,
             }),
+            node_id: None,
         }))
         .await
         .unwrap();
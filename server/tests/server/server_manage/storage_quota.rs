@@ -0,0 +1,158 @@
+use client::TestApp;
+use pb::service::ourchat::storage::v1::{FileOrder, GetStorageUsageRequest, ListUserFilesRequest};
+use pb::service::server_manage::user_manage::v1::SetUserFilesLimitRequest;
+use size::Size;
+use tonic::Request;
+
+#[tokio::test]
+async fn storage_usage_and_files_limit() {
+    let (mut config, args) = TestApp::get_test_config().unwrap();
+    let default_limit = Size::from_kibibytes(64);
+    config.main_cfg.user_files_limit = default_limit;
+    let mut app = TestApp::new_with_launching_instance_custom_cfg((config, args), |_| {})
+        .await
+        .unwrap();
+    let admin = app.new_user().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let user_id = user.lock().await.id;
+    admin
+        .lock()
+        .await
+        .promote_to_admin(app.get_db_connection())
+        .await
+        .unwrap();
+
+    let mut keys = Vec::new();
+    for size in [1000, 3000, 2000] {
+        keys.push(
+            user.lock()
+                .await
+                .post_file(&vec![7u8; size], None)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let usage = user
+        .lock()
+        .await
+        .oc()
+        .get_storage_usage(GetStorageUsageRequest {
+            largest_files_num: 2,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(usage.used, 6000);
+    assert_eq!(usage.limit, default_limit.bytes() as u64);
+    assert!(!usage.custom_limit);
+    let largest: Vec<_> = usage.largest_files.iter().map(|f| f.size).collect();
+    assert_eq!(largest, vec![3000, 2000]);
+    assert_eq!(usage.largest_files[0].key, keys[1]);
+
+    // paging through the files
+    let page = user
+        .lock()
+        .await
+        .oc()
+        .list_user_files(ListUserFilesRequest {
+            order: FileOrder::Largest as i32,
+            page: 0,
+            page_size: 2,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(page.total_count, 3);
+    assert!(page.has_more);
+    assert_eq!(page.files.len(), 2);
+    let page = user
+        .lock()
+        .await
+        .oc()
+        .list_user_files(ListUserFilesRequest {
+            order: FileOrder::Largest as i32,
+            page: 1,
+            page_size: 2,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!page.has_more);
+    assert_eq!(page.files.len(), 1);
+    assert_eq!(page.files[0].size, 1000);
+    // the others can't see the files
+    let page = admin
+        .lock()
+        .await
+        .oc()
+        .list_user_files(ListUserFilesRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(page.total_count, 0);
+
+    // only the admins set the limits
+    let err = user
+        .lock()
+        .await
+        .server_manage()
+        .set_user_files_limit(Request::new(SetUserFilesLimitRequest {
+            user_id: user_id.into(),
+            files_limit: Some(u64::MAX),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    admin
+        .lock()
+        .await
+        .server_manage()
+        .set_user_files_limit(Request::new(SetUserFilesLimitRequest {
+            user_id: user_id.into(),
+            files_limit: Some(256 * 1024),
+        }))
+        .await
+        .unwrap();
+    // larger than the default limit now
+    let big_file = vec![1u8; 100 * 1024];
+    user.lock().await.post_file(&big_file, None).await.unwrap();
+    let usage = user
+        .lock()
+        .await
+        .oc()
+        .get_storage_usage(GetStorageUsageRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(usage.custom_limit);
+    assert_eq!(usage.limit, 256 * 1024);
+    assert_eq!(usage.used, 6000 + 100 * 1024);
+    assert_eq!(usage.largest_files.len(), 4);
+
+    // back to the default one
+    admin
+        .lock()
+        .await
+        .server_manage()
+        .set_user_files_limit(Request::new(SetUserFilesLimitRequest {
+            user_id: user_id.into(),
+            files_limit: None,
+        }))
+        .await
+        .unwrap();
+    assert!(user.lock().await.post_file(&big_file, None).await.is_err());
+    let err = admin
+        .lock()
+        .await
+        .server_manage()
+        .set_user_files_limit(Request::new(SetUserFilesLimitRequest {
+            user_id: 0,
+            files_limit: None,
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+
+    app.async_drop().await;
+}
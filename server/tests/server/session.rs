@@ -141,6 +141,7 @@ async fn session_create() {
         name: Some("test name".to_owned()),
         description: Some("test description".to_owned()),
         avatar_key: Some("pic key".to_owned()),
+        message_ttl: None,
     };
     // accept request from owner
     user1
@@ -305,6 +306,7 @@ async fn set_session_info() {
         name: Some("test name".to_owned()),
         description: Some("test description".to_owned()),
         avatar_key: Some("pic key".to_owned()),
+        message_ttl: None,
     };
     a.lock()
         .await
@@ -56,6 +56,7 @@ async fn e2ee_update_timeout() {
             session_id: session.session_id.into(),
             user_id: bid.into(),
             room_key: encrypted_room_key,
+            device_id: None,
         })
         .await
         .unwrap();
@@ -138,6 +139,7 @@ async fn e2ee_update_member_leaving() {
             session_id: session.session_id.into(),
             user_id: bid.into(),
             room_key: encrypted_room_key,
+            device_id: None,
         })
         .await
         .unwrap();
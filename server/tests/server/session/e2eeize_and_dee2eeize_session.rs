@@ -68,6 +68,7 @@ pub async fn test_e2eeize_session() {
             session_id: session.session_id.into(),
             user_id: bid.into(),
             room_key: encrypted_room_key,
+            device_id: None,
         })
         .await
         .unwrap();
@@ -97,6 +97,7 @@ async fn invite_user_to_session_success() {
             session_id: session.session_id.into(),
             user_id: cid.into(),
             room_key: encrypted_room_key,
+            device_id: None,
         })
         .await
         .unwrap();
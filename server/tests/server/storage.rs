@@ -0,0 +1,251 @@
+//! The files stored in an S3-compatible object storage, tested against an in-memory stand-in
+//! which serves the few operations the server uses
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use client::TestApp;
+use entities::{files, prelude::Files};
+use http::{HeaderMap, StatusCode, header};
+use sea_orm::EntityTrait;
+use server::{
+    config::{S3Cfg, StorageBackendKind, StorageCfg},
+    storage::{LocalStorage, S3Storage, StorageBackend, migrate::migrate_files},
+};
+use size::Size;
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+const BUCKET: &str = "ourchat";
+
+/// Start the stand-in, returning its endpoint and the objects it holds
+async fn mock_s3() -> (String, Objects) {
+    let objects = Objects::default();
+    let app = Router::new()
+        .route(
+            "/{*path}",
+            get(get_object)
+                .head(head_object)
+                .put(put_object)
+                .delete(delete_object),
+        )
+        .with_state(objects.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}"), objects)
+}
+
+fn s3_cfg(endpoint: String) -> S3Cfg {
+    S3Cfg {
+        endpoint,
+        bucket: BUCKET.to_string(),
+        region: "us-east-1".to_string(),
+        access_key_id: "minioadmin".to_string(),
+        secret_access_key: "minioadmin".to_string(),
+        path_style: true,
+    }
+}
+
+/// The object name in the bucket, or the status rejecting the request
+fn check_request(path: &str, headers: &HeaderMap) -> Result<String, StatusCode> {
+    let signed = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256 Credential=minioadmin/"));
+    if !signed || !headers.contains_key("x-amz-date") {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match path.strip_prefix(&format!("{BUCKET}/")) {
+        Some(object) => Ok(object.to_string()),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn get_object(
+    State(objects): State<Objects>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let object = match check_request(&path, &headers) {
+        Ok(object) => object,
+        Err(status) => return status.into_response(),
+    };
+    let Some(data) = objects.lock().unwrap().get(&object).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok()?.strip_prefix("bytes="))
+        .and_then(|range| range.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?)));
+    match range {
+        Some((start, end)) => {
+            let end = end.min(data.len() - 1);
+            (StatusCode::PARTIAL_CONTENT, data[start..=end].to_vec()).into_response()
+        }
+        None => data.into_response(),
+    }
+}
+
+async fn head_object(
+    State(objects): State<Objects>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let object = match check_request(&path, &headers) {
+        Ok(object) => object,
+        Err(status) => return status.into_response(),
+    };
+    match objects.lock().unwrap().get(&object) {
+        Some(data) => [(header::CONTENT_LENGTH, data.len())].into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn put_object(
+    State(objects): State<Objects>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let object = match check_request(&path, &headers) {
+        Ok(object) => object,
+        Err(status) => return status.into_response(),
+    };
+    objects.lock().unwrap().insert(object, body.to_vec());
+    StatusCode::OK.into_response()
+}
+
+async fn delete_object(
+    State(objects): State<Objects>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let object = match check_request(&path, &headers) {
+        Ok(object) => object,
+        Err(status) => return status.into_response(),
+    };
+    objects.lock().unwrap().remove(&object);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[tokio::test]
+async fn s3_storage_backend() {
+    let (endpoint, objects) = mock_s3().await;
+    let (mut config, args) = TestApp::get_test_config().unwrap();
+    config.main_cfg.storage = StorageCfg {
+        backend: StorageBackendKind::S3,
+        s3: Some(s3_cfg(endpoint)),
+    };
+    let mut app = TestApp::new_with_launching_instance_custom_cfg((config, args), |_| {})
+        .await
+        .unwrap();
+    let user = app.new_user().await.unwrap();
+    let content: Vec<u8> = client::helper::generate_file(Size::from_kibibytes(100))
+        .unwrap()
+        .flatten()
+        .collect();
+
+    let key = user.lock().await.post_file(&content, None).await.unwrap();
+    let file = Files::find_by_id(&key)
+        .one(app.get_db_connection())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(objects.lock().unwrap().get(&file.path), Some(&content));
+    let chunked_key = user
+        .lock()
+        .await
+        .post_file_chunked(&content, None)
+        .await
+        .unwrap();
+    // the same content is stored once
+    assert_eq!(objects.lock().unwrap().len(), 1);
+
+    let (header, data) = user
+        .lock()
+        .await
+        .download_file_range(&key, 1000, Some(5000))
+        .await
+        .unwrap();
+    assert_eq!(header.size, content.len() as u64);
+    assert_eq!(data, &content[1000..6000]);
+    let (_, data) = user
+        .lock()
+        .await
+        .download_file_range(&chunked_key, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(data, content);
+
+    user.lock().await.delete_file(&key).await.unwrap();
+    assert!(objects.lock().unwrap().contains_key(&file.path));
+    user.lock().await.delete_file(&chunked_key).await.unwrap();
+    assert!(objects.lock().unwrap().is_empty());
+
+    app.async_drop().await;
+}
+
+#[tokio::test]
+async fn migrate_local_files_to_s3() {
+    let (endpoint, objects) = mock_s3().await;
+    let mut app = TestApp::new_with_launching_instance().await.unwrap();
+    let user = app.new_user().await.unwrap();
+    let mut keys = Vec::new();
+    for content in [b"first file".to_vec(), b"second file".to_vec()] {
+        keys.push(user.lock().await.post_file(&content, None).await.unwrap());
+    }
+
+    let source = LocalStorage::new(app.app_shared.cfg().main_cfg.files_storage_path.clone());
+    let target = S3Storage::new(s3_cfg(endpoint)).unwrap();
+    let staging_dir = tempfile::tempdir().unwrap();
+    let report = migrate_files(
+        app.get_db_connection(),
+        &source,
+        &target,
+        staging_dir.path(),
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.moved, keys.len());
+
+    for key in &keys {
+        let file: files::Model = Files::find_by_id(key)
+            .one(app.get_db_connection())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(objects.lock().unwrap().contains_key(&file.path));
+        assert!(source.size(&file.path).await.unwrap_err().is_not_found());
+        assert_eq!(
+            target.read(&file.path).await.unwrap(),
+            objects.lock().unwrap()[&file.path]
+        );
+    }
+
+    // the second run finds the sources removed already
+    let report = migrate_files(
+        app.get_db_connection(),
+        &source,
+        &target,
+        staging_dir.path(),
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.moved, 0);
+    assert_eq!(report.missing, keys.len());
+
+    app.async_drop().await;
+}